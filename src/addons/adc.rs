@@ -0,0 +1,274 @@
+use crate::addons;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// `ADCSRA` bits.
+pub mod adcsra {
+    pub const ADEN: u8 = 1 << 7;
+    pub const ADSC: u8 = 1 << 6;
+    pub const ADATE: u8 = 1 << 5;
+    pub const ADIF: u8 = 1 << 4;
+    pub const ADIE: u8 = 1 << 3;
+    pub const ADPS_MASK: u8 = 0b111;
+}
+
+/// `ADMUX` bits.
+pub mod admux {
+    pub const REFS_MASK: u8 = 0b1100_0000;
+    pub const ADLAR: u8 = 1 << 5;
+    pub const MUX_MASK: u8 = 0b1111;
+}
+
+/// The number of ADC clocks in a normal conversion.
+const CONVERSION_CLOCKS: u64 = 13;
+/// The number of ADC clocks in the first conversion after enabling the ADC.
+const FIRST_CONVERSION_CLOCKS: u64 = 25;
+
+/// The data space addresses of the ADC registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub adcl: u16,
+    pub adch: u16,
+    pub adcsra: u16,
+    pub adcsrb: u16,
+    pub admux: u16,
+}
+
+/// A conversion in progress.
+#[derive(Copy, Clone, Debug)]
+struct Conversion {
+    /// The cycle at which the result is ready.
+    done_at: u64,
+}
+
+/// A handle for setting the voltages an `Adc` samples.
+///
+/// Handles stay usable after the `Adc` has been attached to an `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    voltages: Rc<RefCell<[f64; 16]>>,
+}
+
+impl Handle {
+    /// Sets the voltage on a channel (volts).
+    pub fn set_voltage(&self, channel: u8, volts: f64) {
+        self.voltages.borrow_mut()[channel as usize & 0xf] = volts;
+    }
+
+    /// Gets the voltage set on a channel (volts).
+    pub fn voltage(&self, channel: u8) -> f64 {
+        self.voltages.borrow()[channel as usize & 0xf]
+    }
+}
+
+/// The analog to digital converter.
+///
+/// The host supplies the voltage on each channel, either through
+/// `set_voltage` or by a closure given to `with_source`. Conversions take
+/// 13 ADC clocks (25 for the first after enabling), where the ADC clock is
+/// the CPU clock divided by the `ADPS` prescaler.
+pub struct Adc {
+    registers: Registers,
+    vector: u8,
+
+    /// The voltage on `AVCC` (volts).
+    pub avcc: f64,
+    /// The voltage on `AREF` (volts).
+    pub aref: f64,
+    /// The voltage of the internal bandgap reference (volts).
+    pub bandgap: f64,
+
+    voltages: Rc<RefCell<[f64; 16]>>,
+    source: Option<Box<dyn FnMut(u8) -> f64>>,
+
+    conversion: Option<Conversion>,
+    /// Whether a conversion has completed since the ADC was enabled.
+    warmed_up: bool,
+    /// Whether the conversion complete interrupt has been requested.
+    raised: bool,
+    /// `ADCSRA` as it was written back after the last instruction.
+    adcsra: u8,
+}
+
+impl Adc {
+    pub fn new(registers: Registers, vector: u8) -> Self {
+        let mut voltages = [0.0; 16];
+        // Channel 14 measures the 1.1V bandgap reference.
+        voltages[14] = 1.1;
+
+        Adc {
+            registers,
+            vector,
+            avcc: 5.0,
+            aref: 5.0,
+            bandgap: 1.1,
+            voltages: Rc::new(RefCell::new(voltages)),
+            source: None,
+            conversion: None,
+            warmed_up: false,
+            raised: false,
+            adcsra: 0,
+        }
+    }
+
+    pub fn atmega328p() -> Self {
+        Self::new(
+            Registers {
+                adcl: 0x78,
+                adch: 0x79,
+                adcsra: 0x7a,
+                adcsrb: 0x7b,
+                admux: 0x7c,
+            },
+            21,
+        )
+    }
+
+    /// Uses a closure to supply the voltage on a channel whenever it is
+    /// sampled, instead of the values given to `set_voltage`.
+    pub fn with_source<F>(mut self, source: F) -> Self
+    where
+        F: FnMut(u8) -> f64 + 'static,
+    {
+        self.source = Some(Box::new(source));
+        self
+    }
+
+    /// Sets the voltage on a channel (volts).
+    pub fn set_voltage(&mut self, channel: u8, volts: f64) {
+        self.handle().set_voltage(channel, volts)
+    }
+
+    /// Gets a handle for setting the voltages on the channels.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            voltages: self.voltages.clone(),
+        }
+    }
+
+    /// Gets the voltage on a channel (volts).
+    pub fn voltage(&mut self, channel: u8) -> f64 {
+        match self.source {
+            Some(ref mut source) => source(channel),
+            None => self.voltages.borrow()[channel as usize & 0xf],
+        }
+    }
+
+    /// Converts the voltage on the selected channel to a 10-bit value.
+    fn convert(&mut self, admux: u8) -> u16 {
+        let reference = match (admux & admux::REFS_MASK) >> 6 {
+            0b00 => self.aref,
+            0b01 => self.avcc,
+            _ => self.bandgap,
+        };
+
+        let volts = self.voltage(admux & admux::MUX_MASK);
+        let value = (volts / reference * 1024.0).floor();
+        value.clamp(0.0, 1023.0) as u16
+    }
+}
+
+impl Addon for Adc {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let mut adcsra = addons::clear_written_flags(core, regs.adcsra, self.adcsra, adcsra::ADIF)?;
+
+        if adcsra & adcsra::ADEN == 0 {
+            self.conversion = None;
+            self.warmed_up = false;
+            adcsra &= !adcsra::ADSC;
+        } else {
+            let now = core.cycle_count;
+
+            if adcsra & adcsra::ADSC != 0 && self.conversion.is_none() {
+                let prescaler = match adcsra & adcsra::ADPS_MASK {
+                    0 | 1 => 2,
+                    ps => 1 << ps,
+                };
+                let clocks = if self.warmed_up {
+                    CONVERSION_CLOCKS
+                } else {
+                    FIRST_CONVERSION_CLOCKS
+                };
+
+                self.conversion = Some(Conversion {
+                    done_at: now + clocks * prescaler,
+                });
+            }
+
+            if let Some(conversion) = self.conversion {
+                if now >= conversion.done_at {
+                    let admux = core.read_data(regs.admux)?;
+                    let value = self.convert(admux);
+
+                    // ADLAR left adjusts the result.
+                    let value = if admux & admux::ADLAR != 0 {
+                        value << 6
+                    } else {
+                        value
+                    };
+                    core.write_data(regs.adcl, value as u8)?;
+                    core.write_data(regs.adch, (value >> 8) as u8)?;
+
+                    self.conversion = None;
+                    self.warmed_up = true;
+                    adcsra |= adcsra::ADIF;
+
+                    // Free running mode starts the next conversion straight away.
+                    let free_running =
+                        adcsra & adcsra::ADATE != 0 && core.read_data(regs.adcsrb)? & 0b111 == 0;
+                    if !free_running {
+                        adcsra &= !adcsra::ADSC;
+                    }
+                }
+            }
+        }
+
+        if self.raised {
+            self.raised = addons::still_raised(core, self.vector, &mut adcsra, adcsra::ADIF)?;
+        }
+
+        if adcsra & adcsra::ADIF != 0 && adcsra & adcsra::ADIE != 0 && !self.raised {
+            core.raise_interrupt(self.vector)?;
+            self.raised = true;
+        }
+
+        self.adcsra = adcsra;
+        core.write_data(regs.adcsra, adcsra)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.conversion = None;
+        self.warmed_up = false;
+        self.raised = false;
+        self.adcsra = 0;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        for &volts in self.voltages.borrow().iter() {
+            state.f64(volts);
+        }
+        state.bool(self.conversion.is_some());
+        state.u64(self.conversion.map_or(0, |c| c.done_at));
+        state.bool(self.warmed_up);
+        state.bool(self.raised);
+        state.u8(self.adcsra);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        for volts in self.voltages.borrow_mut().iter_mut() {
+            *volts = state.f64()?;
+        }
+        let converting = state.bool()?;
+        let done_at = state.u64()?;
+        self.conversion = converting.then_some(Conversion { done_at });
+        self.warmed_up = state.bool()?;
+        self.raised = state.bool()?;
+        self.adcsra = state.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,150 @@
+use crate::addons;
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// The IO address of `ACSR`.
+pub const ACSR: u8 = 0x30;
+
+/// `ACSR` bits.
+pub mod acsr {
+    pub const ACD: u8 = 1 << 7;
+    pub const ACBG: u8 = 1 << 6;
+    pub const ACO: u8 = 1 << 5;
+    pub const ACI: u8 = 1 << 4;
+    pub const ACIE: u8 = 1 << 3;
+    pub const ACIC: u8 = 1 << 2;
+    pub const ACIS_MASK: u8 = 0b11;
+}
+
+/// Which edge of the comparator output sets `ACI`, as configured by
+/// `ACIS1:ACIS0`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum InterruptMode {
+    Toggle,
+    FallingEdge,
+    RisingEdge,
+}
+
+impl InterruptMode {
+    fn from_bits(bits: u8) -> Self {
+        match bits & acsr::ACIS_MASK {
+            // `01` is reserved; treat it like toggle.
+            0b00 | 0b01 => InterruptMode::Toggle,
+            0b10 => InterruptMode::FallingEdge,
+            _ => InterruptMode::RisingEdge,
+        }
+    }
+}
+
+/// The analog comparator.
+///
+/// `ACO` is set while the positive input (`AIN0`, or the bandgap reference
+/// when `ACBG` is set) is above the negative input (`AIN1`).
+pub struct AnalogComparator {
+    register: u8,
+    vector: u8,
+
+    /// The voltage on `AIN0` (volts).
+    pub ain0: f64,
+    /// The voltage on `AIN1` (volts).
+    pub ain1: f64,
+    /// The voltage of the internal bandgap reference (volts).
+    pub bandgap: f64,
+
+    /// The last comparator output. `ACO` is read only, so this is tracked
+    /// here in case the firmware writes over it.
+    output: bool,
+    /// Whether the comparator interrupt has been requested.
+    raised: bool,
+    /// `ACSR` as it was written back after the last instruction.
+    acsr: u8,
+}
+
+impl AnalogComparator {
+    pub fn new(register: u8, vector: u8) -> Self {
+        AnalogComparator {
+            register,
+            vector,
+            ain0: 0.0,
+            ain1: 0.0,
+            bandgap: 1.1,
+            output: false,
+            raised: false,
+            acsr: 0,
+        }
+    }
+
+    pub fn atmega328p() -> Self {
+        Self::new(ACSR, 23)
+    }
+}
+
+impl Addon for AnalogComparator {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let address = SRAM_IO_OFFSET + self.register as u16;
+        let mut acsr = addons::clear_written_flags(core, address, self.acsr, acsr::ACI)?;
+
+        if self.raised {
+            self.raised = addons::still_raised(core, self.vector, &mut acsr, acsr::ACI)?;
+        }
+
+        if acsr & acsr::ACD == 0 {
+            let positive = if acsr & acsr::ACBG != 0 {
+                self.bandgap
+            } else {
+                self.ain0
+            };
+            let output = positive > self.ain1;
+            let previous = std::mem::replace(&mut self.output, output);
+
+            let triggered = match InterruptMode::from_bits(acsr) {
+                InterruptMode::Toggle => output != previous,
+                InterruptMode::FallingEdge => previous && !output,
+                InterruptMode::RisingEdge => !previous && output,
+            };
+
+            if triggered {
+                acsr |= acsr::ACI;
+            }
+        }
+
+        if self.output {
+            acsr |= acsr::ACO;
+        } else {
+            acsr &= !acsr::ACO;
+        }
+
+        if acsr & acsr::ACI != 0 && acsr & acsr::ACIE != 0 && !self.raised {
+            core.raise_interrupt(self.vector)?;
+            self.raised = true;
+        }
+
+        self.acsr = acsr;
+        core.write_data(address, acsr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.raised = false;
+        self.acsr = 0;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.f64(self.ain0);
+        state.f64(self.ain1);
+        state.bool(self.output);
+        state.bool(self.raised);
+        state.u8(self.acsr);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        self.ain0 = state.f64()?;
+        self.ain1 = state.f64()?;
+        self.output = state.bool()?;
+        self.raised = state.bool()?;
+        self.acsr = state.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use crate::reset::ResetCause;
+use crate::symbols::Symbols;
+use crate::{Addon, Core, Error, Instruction};
+use std::cell::{Ref, RefCell};
+use std::rc::Rc;
+
+/// A call or interrupt on the shadow call stack.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The byte address of the function called, or of the instruction the
+    /// interrupt vector jumped to.
+    pub function: u32,
+    /// The name of the function, if there are symbols for it.
+    pub name: Option<String>,
+    /// The byte address execution returns to.
+    pub return_address: u32,
+    /// The stack pointer after the return address was pushed.
+    pub sp: u16,
+    /// Whether the frame is an interrupt handler rather than a call.
+    pub interrupt: bool,
+}
+
+/// A handle for reading the call stack tracked by a `CallStack`.
+///
+/// Handles stay usable after the `CallStack` has been attached to an `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    frames: Rc<RefCell<Vec<Frame>>>,
+}
+
+impl Handle {
+    /// Gets the calls currently being executed, innermost first.
+    pub fn current_backtrace(&self) -> Vec<Frame> {
+        self.frames.borrow().iter().rev().cloned().collect()
+    }
+
+    /// Gets the number of calls currently being executed.
+    pub fn depth(&self) -> usize {
+        self.frames.borrow().len()
+    }
+
+    /// Forgets all frames, like after the firmware switched stacks.
+    pub fn clear(&self) {
+        self.frames.borrow_mut().clear();
+    }
+}
+
+/// Tracks the calls the firmware makes, by watching `CALL`, `RCALL`,
+/// interrupts and returns.
+///
+/// Returns pop every frame whose return address was below the new stack
+/// pointer, so that frames left behind by `longjmp` or by firmware that
+/// pops its return address are cleaned up on the next return. `RCALL .+0`
+/// is how compilers reserve two bytes of stack, so it is not a call.
+pub struct CallStack {
+    frames: Rc<RefCell<Vec<Frame>>>,
+    symbols: Symbols,
+    /// The interrupt depth after the last tick.
+    interrupt_depth: u32,
+}
+
+impl CallStack {
+    pub fn new() -> Self {
+        CallStack {
+            frames: Rc::new(RefCell::new(Vec::new())),
+            symbols: Symbols::default(),
+            interrupt_depth: 0,
+        }
+    }
+
+    /// Names frames with the symbols of a program, like `Mcu::symbols`.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = symbols;
+        self
+    }
+
+    /// Gets the calls currently being executed, innermost first.
+    pub fn current_backtrace(&self) -> Vec<Frame> {
+        self.handle().current_backtrace()
+    }
+
+    /// Gets a handle for reading the call stack.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            frames: self.frames.clone(),
+        }
+    }
+
+    /// Gets the calls currently being executed, outermost first.
+    pub(crate) fn frames(&self) -> Ref<'_, Vec<Frame>> {
+        self.frames.borrow()
+    }
+
+    fn push(&self, function: u32, return_address: u32, sp: u16, interrupt: bool) {
+        let name = self.symbols.flash(function).map(|s| s.name.clone());
+        self.frames.borrow_mut().push(Frame {
+            function,
+            name,
+            return_address,
+            sp,
+            interrupt,
+        });
+    }
+}
+
+impl Default for CallStack {
+    fn default() -> Self {
+        CallStack::new()
+    }
+}
+
+impl Addon for CallStack {
+    fn post_tick(&mut self, core: &mut Core, inst: Instruction, pc: u32) -> Result<(), Error> {
+        let sp = core.stack_pointer()?;
+        let depth = core.interrupt_depth();
+
+        // An interrupt was dispatched before the instruction at its vector
+        // was executed, so its return address is on top of the stack.
+        if depth > self.interrupt_depth {
+            let mut return_address = 0;
+            for i in 1..=core.return_address_size() as u16 {
+                return_address = (return_address << 8) | core.peek_data(sp.wrapping_add(i))? as u32;
+            }
+            self.push(core.pc, return_address * 2, sp, true);
+        }
+        self.interrupt_depth = depth;
+
+        match inst {
+            Instruction::Call(_) | Instruction::Icall | Instruction::Eicall => {
+                self.push(core.pc, pc + inst.size() as u32, sp, false)
+            }
+            Instruction::Rcall(k) if k != 0 => {
+                self.push(core.pc, pc + inst.size() as u32, sp, false)
+            }
+            Instruction::Ret | Instruction::Reti => {
+                let mut frames = self.frames.borrow_mut();
+                while frames.last().is_some_and(|f| f.sp < sp) {
+                    frames.pop();
+                }
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.frames.borrow_mut().clear();
+        self.interrupt_depth = 0;
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+use crate::dwarf::DebugInfo;
+use crate::{Addon, Core, Error, Instruction};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// How often a conditional branch or skip went each way.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Branch {
+    /// The byte address of the instruction.
+    pub pc: u32,
+    /// How often it branched or skipped.
+    pub taken: u64,
+    /// How often it fell through to the next instruction.
+    pub not_taken: u64,
+}
+
+#[derive(Default)]
+struct Counts {
+    /// Executions by the byte address of the instruction.
+    hits: HashMap<u32, u64>,
+    branches: HashMap<u32, Branch>,
+}
+
+/// The coverage of a source line.
+#[derive(Clone, Debug, Default)]
+struct Line {
+    hits: u64,
+    /// The branches on the line, by address.
+    branches: Vec<Branch>,
+}
+
+/// A handle for reading the coverage collected by `Coverage`.
+///
+/// Handles stay usable after the `Coverage` has been attached to an `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    counts: Rc<RefCell<Counts>>,
+}
+
+impl Handle {
+    /// Checks if the instruction at a byte address was executed.
+    pub fn is_executed(&self, pc: u32) -> bool {
+        self.hits(pc) > 0
+    }
+
+    /// Gets how often the instruction at a byte address was executed.
+    pub fn hits(&self, pc: u32) -> u64 {
+        self.counts.borrow().hits.get(&pc).copied().unwrap_or(0)
+    }
+
+    /// Gets the byte addresses of all executed instructions, in order.
+    pub fn executed(&self) -> Vec<u32> {
+        let mut executed: Vec<u32> = self.counts.borrow().hits.keys().copied().collect();
+        executed.sort_unstable();
+        executed
+    }
+
+    /// Gets the conditional branches and skips executed, by address.
+    pub fn branches(&self) -> Vec<Branch> {
+        let mut branches: Vec<Branch> = self.counts.borrow().branches.values().copied().collect();
+        branches.sort_by_key(|b| b.pc);
+        branches
+    }
+
+    /// Formats the coverage of the source lines in the debug information
+    /// as an lcov tracefile, as read by `genhtml` and coverage services.
+    pub fn lcov(&self, debug_info: &DebugInfo) -> String {
+        let files = self.lines(debug_info);
+        let counts = self.counts.borrow();
+        let mut out = String::new();
+
+        for (file, lines) in &files {
+            let _ = writeln!(out, "TN:");
+            let _ = writeln!(out, "SF:{}", file);
+
+            let functions: Vec<(u32, &str, u64)> = debug_info
+                .functions()
+                .iter()
+                .filter_map(|function| {
+                    let location = debug_info.symbolicate(function.low_pc)?;
+                    let hits = counts.hits.get(&function.low_pc).copied().unwrap_or(0);
+                    (&location.file == file).then_some((location.line, &function.name[..], hits))
+                })
+                .collect();
+            for &(line, name, _) in &functions {
+                let _ = writeln!(out, "FN:{},{}", line, name);
+            }
+            for &(_, name, hits) in &functions {
+                let _ = writeln!(out, "FNDA:{},{}", hits, name);
+            }
+            let _ = writeln!(out, "FNF:{}", functions.len());
+            let _ = writeln!(out, "FNH:{}", functions.iter().filter(|f| f.2 > 0).count());
+
+            let (mut found, mut hit) = (0, 0);
+            for (&number, line) in lines {
+                for (block, branch) in line.branches.iter().enumerate() {
+                    for (index, count) in [branch.taken, branch.not_taken].into_iter().enumerate() {
+                        // Branches on lines never reached are written as `-`.
+                        let taken = match line.hits {
+                            0 => "-".to_owned(),
+                            _ => count.to_string(),
+                        };
+                        let _ = writeln!(out, "BRDA:{},{},{},{}", number, block, index, taken);
+                        found += 1;
+                        hit += (count > 0) as usize;
+                    }
+                }
+            }
+            let _ = writeln!(out, "BRF:{}", found);
+            let _ = writeln!(out, "BRH:{}", hit);
+
+            for (&number, line) in lines {
+                let _ = writeln!(out, "DA:{},{}", number, line.hits);
+            }
+            let _ = writeln!(out, "LF:{}", lines.len());
+            let _ = writeln!(out, "LH:{}", lines.values().filter(|l| l.hits > 0).count());
+            let _ = writeln!(out, "end_of_record");
+        }
+        out
+    }
+
+    /// Writes the coverage of the source lines as an lcov tracefile.
+    pub fn save_lcov<P>(&self, path: P, debug_info: &DebugInfo) -> Result<(), Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        std::fs::write(path, self.lcov(debug_info)).map_err(Error::Io)
+    }
+
+    /// Formats the coverage of the source lines as a standalone HTML page,
+    /// with a summary of each file and the hit count of each line. The
+    /// source text is shown for files that can be read.
+    pub fn html(&self, debug_info: &DebugInfo) -> String {
+        let files = self.lines(debug_info);
+        let mut out = String::new();
+        out.push_str(HTML_HEADER);
+        for (file, lines) in &files {
+            let hit = lines.values().filter(|l| l.hits > 0).count();
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td>{}</td><td>{:.1}%</td></tr>",
+                escape(file),
+                lines.len(),
+                hit,
+                hit as f64 * 100.0 / lines.len().max(1) as f64
+            );
+        }
+        out.push_str("</table>\n");
+
+        for (file, lines) in &files {
+            let source = std::fs::read_to_string(file).unwrap_or_default();
+            let source: Vec<&str> = source.lines().collect();
+            let _ = writeln!(out, "<h2>{}</h2>\n<table>", escape(file));
+            for (&number, line) in lines {
+                let class = if line.hits > 0 { "hit" } else { "miss" };
+                let text = source.get(number as usize - 1).copied().unwrap_or("");
+                let _ = writeln!(
+                    out,
+                    "<tr class=\"{}\"><td>{}</td><td>{}</td><td>{}</td></tr>",
+                    class,
+                    number,
+                    line.hits,
+                    escape(text)
+                );
+            }
+            out.push_str("</table>\n");
+        }
+        out.push_str("</body></html>\n");
+        out
+    }
+
+    /// Forgets all coverage.
+    pub fn clear(&self) {
+        let mut counts = self.counts.borrow_mut();
+        counts.hits.clear();
+        counts.branches.clear();
+    }
+
+    /// Gets the coverage of each source line, by file and line.
+    ///
+    /// A line counts as executed as often as the most executed instruction
+    /// generated for it.
+    fn lines<'a>(&self, debug_info: &'a DebugInfo) -> BTreeMap<&'a str, BTreeMap<u32, Line>> {
+        let counts = self.counts.borrow();
+        let mut files: BTreeMap<&str, BTreeMap<u32, Line>> = BTreeMap::new();
+
+        for range in debug_info.line_ranges() {
+            if range.line == 0 {
+                continue;
+            }
+            let line = files
+                .entry(range.file)
+                .or_default()
+                .entry(range.line)
+                .or_default();
+            for pc in (range.low_pc..range.high_pc).step_by(2) {
+                if let Some(&hits) = counts.hits.get(&pc) {
+                    line.hits = line.hits.max(hits);
+                }
+                if let Some(&branch) = counts.branches.get(&pc) {
+                    line.branches.push(branch);
+                }
+            }
+        }
+        files
+    }
+}
+
+/// Records which instructions are executed and which way conditional
+/// branches and skips go, for code coverage.
+///
+/// Coverage by source line needs the debug information of the program,
+/// see `Mcu::debug_info`.
+pub struct Coverage {
+    counts: Rc<RefCell<Counts>>,
+    /// Whether the CPU was sleeping after the last tick.
+    sleeping: bool,
+}
+
+impl Coverage {
+    pub fn new() -> Self {
+        Coverage {
+            counts: Rc::new(RefCell::new(Counts::default())),
+            sleeping: false,
+        }
+    }
+
+    /// Gets a handle for reading the coverage.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            counts: self.counts.clone(),
+        }
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Self {
+        Coverage::new()
+    }
+}
+
+impl Addon for Coverage {
+    fn post_tick(&mut self, core: &mut Core, inst: Instruction, pc: u32) -> Result<(), Error> {
+        let parked =
+            (self.sleeping && inst == Instruction::Sleep) || core.held_in_reset().is_some();
+        self.sleeping = core.is_sleeping();
+        if parked {
+            return Ok(());
+        }
+
+        let mut counts = self.counts.borrow_mut();
+        *counts.hits.entry(pc).or_insert(0) += 1;
+
+        if inst.is_branch() || inst.is_skip() {
+            let branch = counts.branches.entry(pc).or_insert(Branch {
+                pc,
+                ..Branch::default()
+            });
+            if core.pc == pc + inst.size() as u32 {
+                branch.not_taken += 1;
+            } else {
+                branch.taken += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+const HTML_HEADER: &str = "<!DOCTYPE html>
+<html><head><meta charset=\"utf-8\"><title>Coverage</title>
+<style>td{font-family:monospace;padding:0 .5em}.hit{background:#cfc}.miss{background:#fcc}</style>
+</head><body>
+<table>
+<tr><th>File</th><th>Lines</th><th>Hit</th><th>Coverage</th></tr>
+";
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
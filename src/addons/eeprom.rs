@@ -0,0 +1,237 @@
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// `EECR` bits.
+pub mod eecr {
+    pub const EEPM_MASK: u8 = 0b0011_0000;
+    pub const EERIE: u8 = 1 << 3;
+    pub const EEMPE: u8 = 1 << 2;
+    pub const EEPE: u8 = 1 << 1;
+    pub const EERE: u8 = 1 << 0;
+}
+
+/// The number of cycles `EEMPE` stays set after being written.
+const MASTER_ENABLE_CYCLES: u64 = 4;
+
+/// The IO addresses of the EEPROM registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub eecr: u8,
+    pub eedr: u8,
+    pub eearl: u8,
+    pub eearh: u8,
+}
+
+/// A write in progress.
+#[derive(Copy, Clone, Debug)]
+struct Write {
+    address: usize,
+    value: u8,
+    /// The cycle at which the write completes.
+    done_at: u64,
+}
+
+/// The EEPROM.
+///
+/// Writes take the time given in the datasheet (3.4ms for an atomic
+/// erase and write, 1.8ms for a split erase or write), converted to CPU
+/// cycles using `cpu_frequency`. The contents live in `Core::eeprom`, and
+/// can be backed by a file with `with_file`, which is rewritten after every
+/// completed write.
+pub struct Eeprom {
+    registers: Registers,
+    vector: u8,
+    file: Option<PathBuf>,
+    /// Contents read from the file, to be loaded into the core.
+    loaded: Option<Vec<u8>>,
+
+    /// The frequency of the CPU clock (hertz).
+    pub cpu_frequency: u64,
+
+    /// The cycle at which `EEMPE` was set.
+    master_enabled_at: Option<u64>,
+    write: Option<Write>,
+    /// Whether the ready interrupt has been requested.
+    raised: bool,
+}
+
+impl Eeprom {
+    pub fn new(registers: Registers, vector: u8, cpu_frequency: u64) -> Self {
+        Eeprom {
+            registers,
+            vector,
+            file: None,
+            loaded: None,
+            cpu_frequency,
+            master_enabled_at: None,
+            write: None,
+            raised: false,
+        }
+    }
+
+    pub fn atmega328p(cpu_frequency: u64) -> Self {
+        Self::new(
+            Registers {
+                eecr: 0x1f,
+                eedr: 0x20,
+                eearl: 0x21,
+                eearh: 0x22,
+            },
+            22,
+            cpu_frequency,
+        )
+    }
+
+    /// Backs the EEPROM with a file.
+    ///
+    /// If the file exists its contents are loaded into the core on the first
+    /// tick, otherwise it is created when the first write completes.
+    pub fn with_file<P: AsRef<Path>>(mut self, path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        match fs::read(&path) {
+            Ok(contents) => self.loaded = Some(contents),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+
+        self.file = Some(path);
+        Ok(self)
+    }
+
+    /// Writes the contents to the backing file, if there is one.
+    pub fn flush(&self, core: &Core) -> io::Result<()> {
+        match self.file {
+            Some(ref path) => {
+                let contents: Vec<u8> = core.eeprom().bytes().collect();
+                fs::write(path, contents)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Converts a duration in microseconds to CPU cycles.
+    fn cycles(&self, micros: u64) -> u64 {
+        self.cpu_frequency * micros / 1_000_000
+    }
+}
+
+impl Addon for Eeprom {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let io = |a: u8| SRAM_IO_OFFSET + a as u16;
+        let now = core.cycle_count;
+        let mut eecr = core.read_data(io(regs.eecr))?;
+        let size = core.eeprom().len();
+
+        if let Some(contents) = self.loaded.take() {
+            core.eeprom_mut().load(contents.into_iter());
+        }
+
+        if let Some(write) = self.write {
+            if now >= write.done_at {
+                core.eeprom_mut().set_u8(write.address, write.value)?;
+                self.write = None;
+                eecr &= !eecr::EEPE;
+                self.flush(core).map_err(Error::Io)?;
+            }
+        }
+
+        let address = u16::from_le_bytes([
+            core.read_data(io(regs.eearl))?,
+            core.read_data(io(regs.eearh))?,
+        ]) as usize
+            % size;
+
+        if eecr & eecr::EEMPE != 0 {
+            let enabled_at = *self.master_enabled_at.get_or_insert(now);
+            if now - enabled_at >= MASTER_ENABLE_CYCLES {
+                self.master_enabled_at = None;
+                eecr &= !eecr::EEMPE;
+            }
+        } else {
+            self.master_enabled_at = None;
+        }
+
+        if eecr & eecr::EEPE != 0 && self.write.is_none() {
+            // A write only starts if `EEMPE` was set just before.
+            if self.master_enabled_at.take().is_some() {
+                let data = core.read_data(io(regs.eedr))?;
+                let old = core.eeprom().get_u8(address)?;
+                let (value, micros) = match (eecr & eecr::EEPM_MASK) >> 4 {
+                    0b00 => (data, 3400),
+                    0b01 => (0xff, 1800),
+                    // Writing without erasing can only clear bits.
+                    0b10 => (old & data, 1800),
+                    _ => (old, 0),
+                };
+
+                self.write = Some(Write {
+                    address,
+                    value,
+                    done_at: now + self.cycles(micros),
+                });
+                eecr &= !eecr::EEMPE;
+            } else {
+                eecr &= !eecr::EEPE;
+            }
+        }
+
+        if eecr & eecr::EERE != 0 {
+            // Reads are ignored while a write is in progress.
+            if self.write.is_none() {
+                let value = core.eeprom().get_u8(address)?;
+                core.write_data(io(regs.eedr), value)?;
+            }
+            eecr &= !eecr::EERE;
+        }
+
+        // The ready interrupt fires for as long as no write is in progress.
+        if self.raised && !core.interrupts().is_pending(self.vector) {
+            self.raised = false;
+        }
+        if eecr & eecr::EERIE != 0 && eecr & eecr::EEPE == 0 && !self.raised {
+            core.raise_interrupt(self.vector)?;
+            self.raised = true;
+        }
+
+        core.write_data(io(regs.eecr), eecr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        // A write in progress still completes.
+        self.master_enabled_at = None;
+        self.raised = false;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.bool(self.master_enabled_at.is_some());
+        state.u64(self.master_enabled_at.unwrap_or(0));
+        state.bool(self.write.is_some());
+        state.u32(self.write.map_or(0, |w| w.address as u32));
+        state.u8(self.write.map_or(0, |w| w.value));
+        state.u64(self.write.map_or(0, |w| w.done_at));
+        state.bool(self.raised);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        let master_enabled = state.bool()?;
+        let enabled_at = state.u64()?;
+        self.master_enabled_at = master_enabled.then_some(enabled_at);
+        let writing = state.bool()?;
+        let write = Write {
+            address: state.u32()? as usize,
+            value: state.u8()?,
+            done_at: state.u64()?,
+        };
+        self.write = writing.then_some(write);
+        self.raised = state.bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+use crate::addons;
+use crate::chips::atmega328p;
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// The data space address of `EICRA`.
+pub const EICRA: u16 = 0x69;
+/// The IO address of `EIMSK`.
+pub const EIMSK: u8 = 0x1d;
+/// The IO address of `EIFR`.
+pub const EIFR: u8 = 0x1c;
+
+/// How an external interrupt pin triggers, as configured by `ISCn1:ISCn0`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SenseMode {
+    LowLevel,
+    AnyChange,
+    FallingEdge,
+    RisingEdge,
+}
+
+impl SenseMode {
+    fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => SenseMode::LowLevel,
+            0b01 => SenseMode::AnyChange,
+            0b10 => SenseMode::FallingEdge,
+            _ => SenseMode::RisingEdge,
+        }
+    }
+}
+
+/// An external interrupt line.
+#[derive(Copy, Clone, Debug)]
+pub struct Line {
+    /// The IO address of the `PINx` register the pin is on.
+    pub pin_register: u8,
+    /// The bit in the `PINx` register.
+    pub bit: u8,
+    /// The interrupt vector number.
+    pub vector: u8,
+}
+
+/// The external interrupt unit (`INT0`, `INT1`, ...).
+///
+/// Line `n` is configured by `ISCn1:ISCn0` in `EICRA`, enabled by bit `n` in
+/// `EIMSK`, and flags bit `n` in `EIFR`.
+pub struct ExternalInterrupt {
+    lines: Vec<Line>,
+    /// The last sampled level of each line.
+    levels: Vec<bool>,
+    /// Lines which have requested an interrupt that is still pending.
+    raised: Vec<bool>,
+    /// `EIFR` as it was written back after the last instruction.
+    eifr: u8,
+}
+
+impl ExternalInterrupt {
+    pub fn new(lines: Vec<Line>) -> Self {
+        let count = lines.len();
+        ExternalInterrupt {
+            lines,
+            levels: vec![true; count],
+            raised: vec![false; count],
+            eifr: 0,
+        }
+    }
+
+    /// `INT0` on `PD2` and `INT1` on `PD3`.
+    pub fn atmega328p() -> Self {
+        Self::new(vec![
+            Line {
+                pin_register: atmega328p::PIND,
+                bit: 2,
+                vector: 1,
+            },
+            Line {
+                pin_register: atmega328p::PIND,
+                bit: 3,
+                vector: 2,
+            },
+        ])
+    }
+}
+
+impl Addon for ExternalInterrupt {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let eicra = core.read_data(EICRA)?;
+        let eimsk = core.read_data(SRAM_IO_OFFSET + EIMSK as u16)?;
+        let flags = ((1u16 << self.lines.len()) - 1) as u8;
+        let mut eifr =
+            addons::clear_written_flags(core, SRAM_IO_OFFSET + EIFR as u16, self.eifr, flags)?;
+
+        for (n, line) in self.lines.iter().enumerate() {
+            let mask = 1 << n;
+            let pins = core.read_data(SRAM_IO_OFFSET + line.pin_register as u16)?;
+            let level = pins & (1 << line.bit) != 0;
+            let previous = std::mem::replace(&mut self.levels[n], level);
+
+            let mode = SenseMode::from_bits(eicra >> (n * 2));
+            if self.raised[n] {
+                self.raised[n] = if mode == SenseMode::LowLevel {
+                    // There is no flag to clear at low level.
+                    core.interrupts().is_pending(line.vector)
+                } else {
+                    addons::still_raised(core, line.vector, &mut eifr, mask)?
+                };
+            }
+
+            let triggered = match mode {
+                // The low level interrupt has no flag and fires for as long
+                // as the pin is held low.
+                SenseMode::LowLevel => {
+                    if !level && eimsk & mask != 0 && !self.raised[n] {
+                        core.raise_interrupt(line.vector)?;
+                        self.raised[n] = true;
+                    }
+                    continue;
+                }
+                SenseMode::AnyChange => level != previous,
+                SenseMode::FallingEdge => previous && !level,
+                SenseMode::RisingEdge => !previous && level,
+            };
+
+            if triggered {
+                eifr |= mask;
+            }
+
+            if eifr & mask != 0 && eimsk & mask != 0 && !self.raised[n] {
+                core.raise_interrupt(line.vector)?;
+                self.raised[n] = true;
+            }
+        }
+
+        self.eifr = eifr;
+        core.write_data(SRAM_IO_OFFSET + EIFR as u16, eifr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.raised.iter_mut().for_each(|r| *r = false);
+        self.eifr = 0;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        let flags: Vec<u8> = self
+            .levels
+            .iter()
+            .chain(&self.raised)
+            .map(|&b| b as u8)
+            .collect();
+        state.bytes(&flags);
+        state.u8(self.eifr);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        let flags = state.bytes()?;
+        if flags.len() != self.lines.len() * 2 {
+            return Err(Error::InvalidState("line count does not match"));
+        }
+        let (levels, raised) = flags.split_at(self.lines.len());
+        self.levels = levels.iter().map(|&b| b != 0).collect();
+        self.raised = raised.iter().map(|&b| b != 0).collect();
+        self.eifr = state.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,155 @@
+use crate::{Addon, Core, Error, Instruction};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// How often the instruction at an address was executed.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Count {
+    /// The byte address of the instruction.
+    pub pc: u32,
+    pub instruction: Instruction,
+    pub count: u64,
+}
+
+/// A handle for reading the counts of an `InstructionHistogram`.
+///
+/// Handles stay usable after the `InstructionHistogram` has been attached
+/// to an `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    counts: Rc<RefCell<HashMap<(u32, Instruction), u64>>>,
+}
+
+impl Handle {
+    /// Gets the number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.counts.borrow().values().sum()
+    }
+
+    /// Gets how often the instruction at a byte address was executed.
+    pub fn count_at(&self, pc: u32) -> u64 {
+        self.counts
+            .borrow()
+            .iter()
+            .filter(|((address, _), _)| *address == pc)
+            .map(|(_, &count)| count)
+            .sum()
+    }
+
+    /// Gets the `n` most executed instructions, most executed first.
+    pub fn hottest(&self, n: usize) -> Vec<Count> {
+        let mut counts: Vec<Count> = self
+            .counts
+            .borrow()
+            .iter()
+            .map(|(&(pc, instruction), &count)| Count {
+                pc,
+                instruction,
+                count,
+            })
+            .collect();
+        counts.sort_by(|a, b| b.count.cmp(&a.count).then(a.pc.cmp(&b.pc)));
+        counts.truncate(n);
+        counts
+    }
+
+    /// Gets how often each mnemonic was executed, most executed first.
+    pub fn by_mnemonic(&self) -> Vec<(String, u64)> {
+        let mut totals: BTreeMap<String, u64> = BTreeMap::new();
+        for (&(_, instruction), &count) in self.counts.borrow().iter() {
+            *totals.entry(instruction.mnemonic()).or_insert(0) += count;
+        }
+
+        let mut totals: Vec<(String, u64)> = totals.into_iter().collect();
+        totals.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        totals
+    }
+
+    /// Formats the `n` most executed addresses and mnemonics as a table.
+    pub fn report(&self, n: usize) -> String {
+        let total = self.total().max(1) as f64;
+        let mut report = String::new();
+
+        let _ = writeln!(report, "hottest addresses:");
+        for count in self.hottest(n) {
+            let _ = writeln!(
+                report,
+                "  {:6x}  {:>10}  {:5.1}%  {}",
+                count.pc,
+                count.count,
+                count.count as f64 * 100.0 / total,
+                count.instruction
+            );
+        }
+        let _ = writeln!(report, "mnemonics:");
+        for (mnemonic, count) in self.by_mnemonic().into_iter().take(n) {
+            let _ = writeln!(
+                report,
+                "  {:6}  {:>10}  {:5.1}%",
+                mnemonic,
+                count,
+                count as f64 * 100.0 / total
+            );
+        }
+        report
+    }
+
+    /// Forgets all counts.
+    pub fn clear(&self) {
+        self.counts.borrow_mut().clear();
+    }
+}
+
+/// Counts the instructions executed, by address and by mnemonic.
+///
+/// Ticks spent sleeping or held in reset do not execute an instruction,
+/// so they are not counted.
+pub struct InstructionHistogram {
+    counts: Rc<RefCell<HashMap<(u32, Instruction), u64>>>,
+    /// Whether the CPU was sleeping after the last tick.
+    sleeping: bool,
+}
+
+impl InstructionHistogram {
+    pub fn new() -> Self {
+        InstructionHistogram {
+            counts: Rc::new(RefCell::new(HashMap::new())),
+            sleeping: false,
+        }
+    }
+
+    /// Gets a handle for reading the counts.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            counts: self.counts.clone(),
+        }
+    }
+
+    /// Formats the `n` most executed addresses and mnemonics, see
+    /// `Handle::report`.
+    pub fn report(&self, n: usize) -> String {
+        self.handle().report(n)
+    }
+}
+
+impl Default for InstructionHistogram {
+    fn default() -> Self {
+        InstructionHistogram::new()
+    }
+}
+
+impl Addon for InstructionHistogram {
+    fn post_tick(&mut self, core: &mut Core, inst: Instruction, pc: u32) -> Result<(), Error> {
+        let parked =
+            (self.sleeping && inst == Instruction::Sleep) || core.held_in_reset().is_some();
+        self.sleeping = core.is_sleeping();
+        if parked {
+            return Ok(());
+        }
+
+        *self.counts.borrow_mut().entry((pc, inst)).or_insert(0) += 1;
+        Ok(())
+    }
+}
@@ -1,10 +1,103 @@
+use crate::symbols::Symbols;
 use crate::{Error, Instruction};
+use std::io::{self, Write};
+use std::ops::Range;
 
-pub struct InstructionListener {}
+/// Prints every instruction executed, like `    4: Executing ldi r16, 0x0A`.
+///
+/// By default every instruction is printed to stdout. The builder methods
+/// send them elsewhere, only print some of them, or add the symbol each
+/// address is in.
+pub struct InstructionListener {
+    writer: Box<dyn Write>,
+    /// The byte addresses to print, or all if empty.
+    ranges: Vec<Range<u32>>,
+    /// The mnemonics to print, or all if empty.
+    mnemonics: Vec<String>,
+    symbols: Option<Symbols>,
+}
+
+impl InstructionListener {
+    pub fn new() -> Self {
+        InstructionListener {
+            writer: Box::new(io::stdout()),
+            ranges: Vec::new(),
+            mnemonics: Vec::new(),
+            symbols: None,
+        }
+    }
+
+    /// Writes to any writer rather than stdout.
+    pub fn with_writer<W>(mut self, writer: W) -> Self
+    where
+        W: Write + 'static,
+    {
+        self.writer = Box::new(writer);
+        self
+    }
+
+    /// Only prints instructions in a range of byte addresses. Several
+    /// ranges can be printed by calling this again.
+    pub fn with_range(mut self, range: Range<u32>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    /// Only prints instructions with one of the mnemonics, like `call`.
+    pub fn with_mnemonics(mut self, mnemonics: &[&str]) -> Self {
+        self.mnemonics
+            .extend(mnemonics.iter().map(|m| m.to_ascii_lowercase()));
+        self
+    }
+
+    /// Prints the symbol and offset of each address, like `main+0x4`.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.symbols = Some(symbols);
+        self
+    }
+
+    fn is_printed(&self, inst: Instruction, pc: u32) -> bool {
+        (self.ranges.is_empty() || self.ranges.iter().any(|r| r.contains(&pc)))
+            && (self.mnemonics.is_empty() || self.mnemonics.contains(&inst.mnemonic()))
+    }
+}
+
+impl Default for InstructionListener {
+    fn default() -> Self {
+        InstructionListener::new()
+    }
+}
 
 impl crate::Addon for InstructionListener {
-    fn tick(&mut self, _core: &mut crate::Core, inst: Instruction, pc: u32) -> Result<(), Error> {
-        println!("{:5X}: Executing {:?}", pc, inst);
-        Ok(())
+    fn post_tick(
+        &mut self,
+        _core: &mut crate::Core,
+        inst: Instruction,
+        pc: u32,
+    ) -> Result<(), Error> {
+        if !self.is_printed(inst, pc) {
+            return Ok(());
+        }
+
+        let symbol = self.symbols.as_ref().and_then(|s| s.flash(pc));
+        let result = match symbol {
+            Some(symbol) if pc == symbol.address => {
+                writeln!(
+                    self.writer,
+                    "{:5X} <{}>: Executing {}",
+                    pc, symbol.name, inst
+                )
+            }
+            Some(symbol) => writeln!(
+                self.writer,
+                "{:5X} <{}+{:#x}>: Executing {}",
+                pc,
+                symbol.name,
+                pc - symbol.address,
+                inst
+            ),
+            None => writeln!(self.writer, "{:5X}: Executing {}", pc, inst),
+        };
+        result.map_err(Error::Io)
     }
 }
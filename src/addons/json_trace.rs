@@ -0,0 +1,142 @@
+use crate::regs;
+use crate::{Addon, Core, Error, Instruction};
+use std::fmt::Write as _;
+use std::io::Write;
+
+/// The names of the `SREG` flags, from bit 7 to bit 0.
+const FLAGS: [char; 8] = ['I', 'T', 'H', 'S', 'V', 'N', 'Z', 'C'];
+
+/// The registers and flags after a tick.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Snapshot {
+    gprs: [u8; 32],
+    sreg: u8,
+    sp: u16,
+}
+
+/// Writes a line of JSON for every instruction executed, for diffing
+/// against other emulators and for external tools.
+///
+/// Each line is an object like
+///
+/// ```text
+/// {"cycle":12,"pc":4,"mnemonic":"ldi","operands":"r16, 0x0A","registers":{"r16":10},"flags":{},"sp":null}
+/// ```
+///
+/// with the cycle count after the instruction, its byte address, and the
+/// general purpose registers and flags that changed with their new
+/// values. `sp` is the new stack pointer, or `null` if it did not change.
+/// Changes are found by comparing with the previous tick, so on the first
+/// one the registers the instruction writes are listed instead, and no
+/// flags.
+pub struct JsonTrace {
+    writer: Box<dyn Write>,
+    previous: Option<Snapshot>,
+    /// Whether the CPU was sleeping after the last tick.
+    sleeping: bool,
+    line: String,
+}
+
+impl JsonTrace {
+    pub fn new<W>(writer: W) -> Self
+    where
+        W: Write + 'static,
+    {
+        JsonTrace {
+            writer: Box::new(writer),
+            previous: None,
+            sleeping: false,
+            line: String::new(),
+        }
+    }
+
+    fn format(&mut self, core: &Core, inst: Instruction, pc: u32, now: &Snapshot) {
+        let text = inst.to_string();
+        let (mnemonic, operands) = text.split_once(' ').unwrap_or((&text, ""));
+
+        let line = &mut self.line;
+        line.clear();
+        let _ = write!(
+            line,
+            "{{\"cycle\":{},\"pc\":{},\"mnemonic\":{},\"operands\":{},\"registers\":{{",
+            core.cycle_count,
+            pc,
+            json_string(mnemonic),
+            json_string(operands)
+        );
+
+        let changed: Vec<u8> = match self.previous {
+            Some(previous) => (0..32)
+                .filter(|&r| previous.gprs[r as usize] != now.gprs[r as usize])
+                .collect(),
+            None => inst.registers_written(),
+        };
+        for (i, r) in changed.iter().enumerate() {
+            let separator = if i > 0 { "," } else { "" };
+            let _ = write!(line, "{}\"r{}\":{}", separator, r, now.gprs[*r as usize]);
+        }
+
+        line.push_str("},\"flags\":{");
+        let old_sreg = self.previous.map_or(now.sreg, |p| p.sreg);
+        let mut first = true;
+        for (i, name) in FLAGS.iter().enumerate() {
+            let mask = 0x80 >> i;
+            if (old_sreg ^ now.sreg) & mask != 0 {
+                let separator = if first { "" } else { "," };
+                let _ = write!(line, "{}\"{}\":{}", separator, name, now.sreg & mask != 0);
+                first = false;
+            }
+        }
+
+        match self.previous {
+            Some(previous) if previous.sp != now.sp => {
+                let _ = write!(line, "}},\"sp\":{}}}", now.sp);
+            }
+            _ => line.push_str("},\"sp\":null}"),
+        }
+    }
+}
+
+impl Addon for JsonTrace {
+    fn post_tick(&mut self, core: &mut Core, inst: Instruction, pc: u32) -> Result<(), Error> {
+        let registers = core.register_file();
+        let mut gprs = [0; 32];
+        for (r, value) in gprs.iter_mut().enumerate() {
+            *value = registers.gpr(r as u8)?;
+        }
+        let now = Snapshot {
+            gprs,
+            sreg: registers.sreg.0.value,
+            sp: registers.gpr_pair_val(regs::SP_LO_NUM)?,
+        };
+
+        let parked =
+            (self.sleeping && inst == Instruction::Sleep) || core.held_in_reset().is_some();
+        self.sleeping = core.is_sleeping();
+        if !parked {
+            self.format(core, inst, pc, &now);
+            writeln!(self.writer, "{}", self.line).map_err(Error::Io)?;
+        }
+
+        self.previous = Some(now);
+        Ok(())
+    }
+}
+
+/// Quotes and escapes a string for JSON.
+fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for c in text.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(quoted, "\\u{:04x}", c as u32);
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
@@ -0,0 +1,325 @@
+use crate::core::SRAM_IO_OFFSET;
+use crate::{Addon, Core, Error, Instruction};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// A digital signal on a bit of an IO register.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Channel {
+    /// The IO address of the register, like `PINx` for pins driven from
+    /// the outside or `PORTx` for pins the firmware drives.
+    pub register: u8,
+    pub bit: u8,
+}
+
+/// The transitions of the channels of a `LogicAnalyzer`.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Capture {
+    /// The CPU frequency at the last sample (hertz).
+    pub cpu_frequency: u64,
+    /// The cycle of the first sample.
+    pub start: u64,
+    /// The cycle of the last sample.
+    pub end: u64,
+    /// The level of each channel at the first sample.
+    initial: Vec<bool>,
+    /// The cycles each channel changed level at, in order.
+    transitions: Vec<Vec<u64>>,
+}
+
+impl Capture {
+    /// Gets the level of a channel at a cycle.
+    pub fn level(&self, channel: usize, cycle: u64) -> bool {
+        let flips = self.transitions[channel].partition_point(|&c| c <= cycle);
+        self.initial[channel] ^ (flips % 2 == 1)
+    }
+
+    /// Gets the cycles a channel changed level at, in order.
+    pub fn transitions(&self, channel: usize) -> &[u64] {
+        &self.transitions[channel]
+    }
+
+    /// Gets the cycles a channel went from low to high or from high to
+    /// low, in order.
+    pub fn edges(&self, channel: usize, rising: bool) -> impl Iterator<Item = u64> + '_ {
+        self.transitions[channel]
+            .iter()
+            .copied()
+            .filter(move |&cycle| self.level(channel, cycle) == rising)
+    }
+
+    /// Converts a number of cycles into a frequency (hertz).
+    fn frequency(&self, cycles: f64) -> f64 {
+        self.cpu_frequency as f64 / cycles
+    }
+
+    /// Decodes asynchronous serial frames with 8 data bits, no parity and
+    /// an idle high line, sampling each bit in its middle.
+    pub fn decode_uart(&self, rx: usize, baud_rate: u32) -> Vec<UartFrame> {
+        let bit = self.cpu_frequency as f64 / baud_rate as f64;
+        let sample = |start: u64, n: f64| self.level(rx, start + (bit * n) as u64);
+
+        let mut frames = Vec::new();
+        let mut after = self.start;
+        for start in self.edges(rx, false) {
+            // Falling edges within a frame are data bits.
+            if start < after {
+                continue;
+            }
+            let end = start + (bit * 10.0) as u64;
+            if end > self.end {
+                break;
+            }
+
+            let byte = (0..8).fold(0, |byte, i| {
+                byte | (sample(start, 1.5 + i as f64) as u8) << i
+            });
+            frames.push(UartFrame {
+                cycle: start,
+                byte,
+                framing_error: !sample(start, 9.5),
+            });
+            after = start + (bit * 9.5) as u64;
+        }
+        frames
+    }
+
+    /// Decodes SPI transfers, most significant bit first, with 8 bits per
+    /// word. Bits are sampled on the rising edges of the clock when `cpol`
+    /// and `cpha` are the same, and on the falling edges otherwise. With
+    /// a chip select channel, words only count while it is low.
+    pub fn decode_spi(&self, channels: SpiChannels, cpol: bool, cpha: bool) -> Vec<SpiWord> {
+        let mut words = Vec::new();
+        let mut bits = 0;
+        let (mut mosi, mut miso, mut start) = (0u8, 0u8, 0);
+        let mut selected_since = None;
+
+        for cycle in self.edges(channels.sck, cpol == cpha) {
+            if let Some(cs) = channels.cs {
+                if self.level(cs, cycle) {
+                    bits = 0;
+                    continue;
+                }
+                // A word is abandoned when the device is deselected.
+                let selection = self.transitions(cs).partition_point(|&c| c <= cycle);
+                if selected_since != Some(selection) {
+                    bits = 0;
+                    selected_since = Some(selection);
+                }
+            }
+
+            if bits == 0 {
+                start = cycle;
+            }
+            mosi = (mosi << 1) | self.level(channels.mosi, cycle) as u8;
+            if let Some(channel) = channels.miso {
+                miso = (miso << 1) | self.level(channel, cycle) as u8;
+            }
+            bits += 1;
+
+            if bits == 8 {
+                words.push(SpiWord {
+                    cycle: start,
+                    mosi,
+                    miso: channels.miso.map(|_| miso),
+                    clock_frequency: self.frequency((cycle - start) as f64 / 7.0),
+                });
+                bits = 0;
+            }
+        }
+        words
+    }
+
+    /// Decodes I2C starts, stops and bytes with their acknowledge bits.
+    pub fn decode_i2c(&self, sda: usize, scl: usize) -> Vec<I2cEvent> {
+        // Every change on either line, in order.
+        let mut changes: Vec<(u64, usize)> =
+            self.transitions(sda).iter().map(|&c| (c, sda)).collect();
+        changes.extend(self.transitions(scl).iter().map(|&c| (c, scl)));
+        changes.sort_unstable();
+
+        let mut events = Vec::new();
+        let mut bits: Option<(u32, u16, u64)> = None;
+        let mut first_rise = 0;
+        for (cycle, channel) in changes {
+            if channel == sda {
+                if !self.level(scl, cycle) {
+                    continue;
+                }
+                // SDA changing while SCL is high is a start or a stop.
+                if self.level(sda, cycle) {
+                    events.push(I2cEvent::Stop { cycle });
+                    bits = None;
+                } else {
+                    events.push(I2cEvent::Start { cycle });
+                    bits = Some((0, 0, cycle));
+                }
+            } else if self.level(scl, cycle) {
+                let (count, value, start) = match bits.as_mut() {
+                    Some(bits) => bits,
+                    None => continue,
+                };
+                if *count == 0 {
+                    first_rise = cycle;
+                }
+                *value = (*value << 1) | self.level(sda, cycle) as u16;
+                *count += 1;
+
+                if *count == 9 {
+                    events.push(I2cEvent::Byte {
+                        cycle: *start,
+                        value: (*value >> 1) as u8,
+                        ack: *value & 1 == 0,
+                        clock_frequency: self.frequency((cycle - first_rise) as f64 / 8.0),
+                    });
+                    *count = 0;
+                    *value = 0;
+                    *start = cycle;
+                }
+            }
+        }
+        events
+    }
+}
+
+/// A frame decoded by `Capture::decode_uart`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UartFrame {
+    /// The cycle of the start bit.
+    pub cycle: u64,
+    pub byte: u8,
+    /// Whether the stop bit was low.
+    pub framing_error: bool,
+}
+
+/// The channels of an SPI bus, as indices into the channels of the
+/// `LogicAnalyzer`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SpiChannels {
+    pub sck: usize,
+    pub mosi: usize,
+    pub miso: Option<usize>,
+    /// The active low chip select.
+    pub cs: Option<usize>,
+}
+
+/// A word decoded by `Capture::decode_spi`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SpiWord {
+    /// The cycle of the first sampling edge.
+    pub cycle: u64,
+    pub mosi: u8,
+    pub miso: Option<u8>,
+    /// The clock frequency over the word (hertz).
+    pub clock_frequency: f64,
+}
+
+/// An event decoded by `Capture::decode_i2c`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum I2cEvent {
+    /// A start or repeated start condition.
+    Start {
+        cycle: u64,
+    },
+    /// A byte and whether it was acknowledged.
+    Byte {
+        /// The cycle of the start condition or the previous byte.
+        cycle: u64,
+        value: u8,
+        ack: bool,
+        /// The clock frequency over the data bits (hertz).
+        clock_frequency: f64,
+    },
+    Stop {
+        cycle: u64,
+    },
+}
+
+/// A handle for reading what a `LogicAnalyzer` captured.
+///
+/// Handles stay usable after the `LogicAnalyzer` has been attached to an
+/// `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    capture: Rc<RefCell<Capture>>,
+}
+
+impl Handle {
+    /// Gets a copy of everything captured so far.
+    pub fn capture(&self) -> Capture {
+        self.capture.borrow().clone()
+    }
+
+    /// Forgets everything captured, starting again at the next tick.
+    pub fn clear(&self) {
+        *self.capture.borrow_mut() = Capture::default();
+    }
+}
+
+/// Captures the transitions of digital signals, to decode the protocols
+/// firmware bit-bangs on them.
+///
+/// Channels are sampled after every tick, so transitions are timed to the
+/// end of the instruction that caused them.
+pub struct LogicAnalyzer {
+    channels: Vec<Channel>,
+    capture: Rc<RefCell<Capture>>,
+}
+
+impl LogicAnalyzer {
+    pub fn new() -> Self {
+        LogicAnalyzer {
+            channels: Vec::new(),
+            capture: Rc::new(RefCell::new(Capture::default())),
+        }
+    }
+
+    /// Adds a channel on a bit of an IO register. Channels are numbered
+    /// in the order they are added.
+    pub fn with_channel(mut self, register: u8, bit: u8) -> Self {
+        self.channels.push(Channel { register, bit });
+        self
+    }
+
+    pub fn channels(&self) -> &[Channel] {
+        &self.channels
+    }
+
+    /// Gets a handle for reading the capture.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            capture: self.capture.clone(),
+        }
+    }
+}
+
+impl Default for LogicAnalyzer {
+    fn default() -> Self {
+        LogicAnalyzer::new()
+    }
+}
+
+impl Addon for LogicAnalyzer {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let mut capture = self.capture.borrow_mut();
+        let now = core.cycle_count;
+        let first = capture.initial.is_empty();
+        if first {
+            capture.start = now;
+            capture.transitions = vec![Vec::new(); self.channels.len()];
+        }
+        capture.end = now;
+        capture.cpu_frequency = core.cpu_frequency();
+
+        for (i, channel) in self.channels.iter().enumerate() {
+            let value = core.peek_data(SRAM_IO_OFFSET + channel.register as u16)?;
+            let level = value & (1 << channel.bit) != 0;
+            if first {
+                capture.initial.push(level);
+            } else if capture.level(i, now) != level {
+                capture.transitions[i].push(now);
+            }
+        }
+        Ok(())
+    }
+}
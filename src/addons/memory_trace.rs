@@ -0,0 +1,142 @@
+use crate::core::Access;
+use crate::mem;
+use crate::{Addon, Core, Error, Instruction};
+use std::io::Write;
+use std::ops::Range;
+
+/// The size of a record in the binary format.
+pub const RECORD_SIZE: usize = 16;
+
+/// How records are written.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// A header line `cycle,pc,address,access,value` and then a line per
+    /// record, with the access as `r` or `w` and numbers in decimal.
+    Csv,
+    /// `RECORD_SIZE` bytes per record: the cycle as a `u64`, the byte
+    /// address of the instruction as a `u32`, the address as a `u16`, all
+    /// little endian, then `0` for reads or `1` for writes, and the value.
+    Binary,
+}
+
+/// A data space access made by an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Record {
+    /// The cycle count after the instruction.
+    pub cycle: u64,
+    /// The byte address of the instruction.
+    pub pc: u32,
+    pub access: Access,
+    /// The value of the byte after the instruction.
+    pub value: u8,
+}
+
+impl Record {
+    /// Encodes the record in the binary format.
+    pub fn to_bytes(&self) -> [u8; RECORD_SIZE] {
+        let (address, kind) = match self.access {
+            Access::Read(address) => (address, 0),
+            Access::Write(address) => (address, 1),
+        };
+        let mut bytes = [0; RECORD_SIZE];
+        bytes[0..8].copy_from_slice(&self.cycle.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.pc.to_le_bytes());
+        bytes[12..14].copy_from_slice(&address.to_le_bytes());
+        bytes[14] = kind;
+        bytes[15] = self.value;
+        bytes
+    }
+
+    /// Decodes a record in the binary format.
+    pub fn from_bytes(bytes: &[u8; RECORD_SIZE]) -> Self {
+        let address = u16::from_le_bytes([bytes[12], bytes[13]]);
+        Record {
+            cycle: u64::from_le_bytes(bytes[0..8].try_into().unwrap()),
+            pc: u32::from_le_bytes(bytes[8..12].try_into().unwrap()),
+            access: match bytes[14] {
+                0 => Access::Read(address),
+                _ => Access::Write(address),
+            },
+            value: bytes[15],
+        }
+    }
+}
+
+/// Writes a record of every data space access instructions make.
+///
+/// Accesses are the loads and stores of instructions, including those to
+/// IO registers, but not the pushes and pops of the stack. The value is
+/// read after the instruction, so it is the value written by writes.
+pub struct MemoryTrace {
+    writer: Box<dyn Write>,
+    format: Format,
+    /// The addresses to trace, or all if empty.
+    ranges: Vec<Range<mem::Address>>,
+    header_written: bool,
+}
+
+impl MemoryTrace {
+    pub fn new<W>(writer: W, format: Format) -> Self
+    where
+        W: Write + 'static,
+    {
+        MemoryTrace {
+            writer: Box::new(writer),
+            format,
+            ranges: Vec::new(),
+            header_written: false,
+        }
+    }
+
+    /// Only traces accesses to a range of addresses. Several ranges can be
+    /// traced by calling this again.
+    pub fn with_range(mut self, range: Range<mem::Address>) -> Self {
+        self.ranges.push(range);
+        self
+    }
+
+    fn is_traced(&self, address: mem::Address) -> bool {
+        self.ranges.is_empty() || self.ranges.iter().any(|r| r.contains(&address))
+    }
+
+    fn write(&mut self, record: Record) -> std::io::Result<()> {
+        match self.format {
+            Format::Csv => {
+                if !self.header_written {
+                    writeln!(self.writer, "cycle,pc,address,access,value")?;
+                    self.header_written = true;
+                }
+                let (address, kind) = match record.access {
+                    Access::Read(address) => (address, 'r'),
+                    Access::Write(address) => (address, 'w'),
+                };
+                writeln!(
+                    self.writer,
+                    "{},{},{},{},{}",
+                    record.cycle, record.pc, address, kind, record.value
+                )
+            }
+            Format::Binary => self.writer.write_all(&record.to_bytes()),
+        }
+    }
+}
+
+impl Addon for MemoryTrace {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, pc: u32) -> Result<(), Error> {
+        for access in core.accesses() {
+            let (Access::Read(address) | Access::Write(address)) = access;
+            if !self.is_traced(address) {
+                continue;
+            }
+
+            let record = Record {
+                cycle: core.cycle_count,
+                pc,
+                access,
+                value: core.peek_data(address)?,
+            };
+            self.write(record).map_err(Error::Io)?;
+        }
+        Ok(())
+    }
+}
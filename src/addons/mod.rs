@@ -1,8 +1,178 @@
+pub use self::adc::Adc;
+pub use self::analog_comparator::AnalogComparator;
+pub use self::call_stack::CallStack;
+pub use self::coverage::Coverage;
+pub use self::eeprom::Eeprom;
+pub use self::external_interrupt::ExternalInterrupt;
+pub use self::histogram::InstructionHistogram;
+pub use self::json_trace::JsonTrace;
+pub use self::logic_analyzer::LogicAnalyzer;
+pub use self::memory_trace::MemoryTrace;
+pub use self::pin_change_interrupt::PinChangeInterrupt;
+pub use self::profiler::Profiler;
+pub use self::pwm_probe::PwmProbe;
+pub use self::ram_usage::RamUsage;
+pub use self::serial_bridge::SerialBridge;
+pub use self::spi::{Spi, SpiSlave};
+pub use self::timer16::Timer16;
+pub use self::timer8::Timer8;
+pub use self::twi::{I2cBus, I2cDevice, Twi};
 pub use self::uart::Uart;
+pub use self::usb::Usb;
+pub use self::usi::Usi;
+pub use self::vcd::Vcd;
+pub use self::watchdog::Watchdog;
+pub use self::xmega_port::XmegaPorts;
+use crate::reset::ResetCause;
+use crate::state;
 use crate::{Core, Error, Instruction};
+use std::any::Any;
+pub mod adc;
+pub mod analog_comparator;
+pub mod call_stack;
+pub mod coverage;
+pub mod eeprom;
+pub mod external_interrupt;
+pub mod histogram;
 pub mod instruction_listener;
+pub mod json_trace;
+pub mod logic_analyzer;
+pub mod memory_trace;
+pub mod pin_change_interrupt;
+pub mod profiler;
+pub mod pwm_probe;
+pub mod ram_usage;
+pub mod serial_bridge;
+pub mod spi;
+pub mod timer16;
+pub mod timer8;
+pub mod twi;
 pub mod uart;
+pub mod usb;
+pub mod usi;
+pub mod vcd;
+pub mod watchdog;
+pub mod xmega_port;
 
-pub trait Addon {
-    fn tick(&mut self, core: &mut Core, inst: Instruction, pc: u32) -> Result<(), Error>;
+/// Converts addons to `Any`, so that `Mcu::addon` can get them back as
+/// their own type. This is implemented for every type.
+pub trait AsAny {
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Any> AsAny for T {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// A peripheral or tool attached to an `Mcu`, which calls its hooks as the
+/// simulation runs.
+///
+/// Every hook does nothing by default. Errors do not stop the `Mcu`, they
+/// are logged as `tracing` warnings with the PC and cycle count.
+pub trait Addon: AsAny {
+    /// Runs when the addon is attached to an `Mcu`, to set up the core,
+    /// like mapping its registers with `Core::map_io`.
+    fn on_attach(&mut self, _core: &mut Core) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Runs when the addon is detached with `Mcu::detach`, to undo
+    /// `on_attach`, like unmapping its registers with `Core::unmap_io`.
+    fn on_detach(&mut self, _core: &mut Core) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Runs before each instruction, or each cycle the CPU is sleeping or
+    /// held in reset.
+    fn pre_tick(&mut self, _core: &mut Core) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Runs after each instruction, with the instruction and its byte
+    /// address. Sleeping or being held in reset ticks as `SLEEP` and `NOP`.
+    fn post_tick(&mut self, _core: &mut Core, _inst: Instruction, _pc: u32) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Runs after the CPU is reset, with the cause recorded in `MCUSR`, or
+    /// `None` for `Mcu::reset`.
+    ///
+    /// Peripherals should go back to how they were at power on, besides
+    /// what they keep in IO registers.
+    fn on_reset(&mut self, _core: &mut Core, _cause: Option<ResetCause>) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Runs after an interrupt vector is dispatched. Interrupts are
+    /// dispatched at the start of a tick, so this runs before `post_tick`
+    /// for the first instruction of the handler.
+    fn on_interrupt(&mut self, _core: &mut Core, _vector: u8) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Runs after `Mcu::step_back` undid ticks. The cycle count can now be
+    /// lower than the addon last saw, so peripherals that measure time
+    /// since their last tick should count from the current cycle again.
+    fn on_rewind(&mut self, _core: &mut Core) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Saves the state of the peripheral for `Mcu::save_state`.
+    ///
+    /// Only what changes as the simulation runs needs saving, since the
+    /// state is loaded into an addon set up the same way. Addons without
+    /// any such state can leave this out.
+    fn save_state(&self, _state: &mut state::Writer) {}
+
+    /// Restores the state written by `save_state`.
+    fn load_state(&mut self, _state: &mut state::Reader) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// Gets an interrupt flag register after the last instruction, given the
+/// value the peripheral left in it.
+///
+/// Firmware clears the bits in `flags` by writing ones to them, and writing
+/// zeros leaves them alone. The other bits take the value written.
+pub(crate) fn clear_written_flags(
+    core: &Core,
+    address: u16,
+    previous: u8,
+    flags: u8,
+) -> Result<u8, Error> {
+    let value = core.peek_data(address)?;
+    if !core.was_written(address) {
+        return Ok(value);
+    }
+    Ok((value & !flags) | (previous & flags & !value))
+}
+
+/// Follows up on an interrupt a peripheral raised for `flag`, one of the
+/// bits in `flags`, and gets whether the request is still pending.
+///
+/// The flag is cleared by hardware once the vector is serviced. If firmware
+/// cleared the flag before that, the request is withdrawn.
+pub(crate) fn still_raised(
+    core: &mut Core,
+    vector: u8,
+    flags: &mut u8,
+    flag: u8,
+) -> Result<bool, Error> {
+    if !core.interrupts().is_pending(vector) {
+        *flags &= !flag;
+        return Ok(false);
+    }
+    if *flags & flag == 0 {
+        core.cancel_interrupt(vector)?;
+        return Ok(false);
+    }
+    Ok(true)
 }
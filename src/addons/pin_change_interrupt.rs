@@ -0,0 +1,128 @@
+use crate::addons;
+use crate::chips::atmega328p;
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// The data space address of `PCICR`.
+pub const PCICR: u16 = 0x68;
+/// The IO address of `PCIFR`.
+pub const PCIFR: u8 = 0x1b;
+
+/// A group of pins sharing a pin change interrupt.
+#[derive(Copy, Clone, Debug)]
+pub struct Group {
+    /// The IO address of the `PINx` register the group watches.
+    pub pin_register: u8,
+    /// The data space address of the `PCMSKn` register.
+    pub mask_register: u16,
+    /// The interrupt vector number.
+    pub vector: u8,
+}
+
+/// The pin change interrupt unit (`PCINT0`, `PCINT1`, ...).
+///
+/// Any toggle of a pin enabled in `PCMSKn` sets bit `n` in `PCIFR`, and
+/// requests an interrupt if bit `n` in `PCICR` is set.
+pub struct PinChangeInterrupt {
+    groups: Vec<Group>,
+    /// The last sampled value of each `PINx` register.
+    levels: Vec<u8>,
+    /// Groups which have requested an interrupt that is still pending.
+    raised: Vec<bool>,
+    /// `PCIFR` as it was written back after the last instruction.
+    pcifr: u8,
+}
+
+impl PinChangeInterrupt {
+    pub fn new(groups: Vec<Group>) -> Self {
+        let count = groups.len();
+        PinChangeInterrupt {
+            groups,
+            levels: vec![0; count],
+            raised: vec![false; count],
+            pcifr: 0,
+        }
+    }
+
+    /// `PCINT0` on port B, `PCINT1` on port C and `PCINT2` on port D.
+    pub fn atmega328p() -> Self {
+        Self::new(vec![
+            Group {
+                pin_register: atmega328p::PINB,
+                mask_register: 0x6b,
+                vector: 3,
+            },
+            Group {
+                pin_register: atmega328p::PINC,
+                mask_register: 0x6c,
+                vector: 4,
+            },
+            Group {
+                pin_register: atmega328p::PIND,
+                mask_register: 0x6d,
+                vector: 5,
+            },
+        ])
+    }
+}
+
+impl Addon for PinChangeInterrupt {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        // The registers are peeked, so that they are not seen as read by
+        // the firmware.
+        let pcicr = core.peek_data(PCICR)?;
+        let flags = ((1u16 << self.groups.len()) - 1) as u8;
+        let mut pcifr =
+            addons::clear_written_flags(core, SRAM_IO_OFFSET + PCIFR as u16, self.pcifr, flags)?;
+
+        for (n, group) in self.groups.iter().enumerate() {
+            let flag = 1 << n;
+            let pins = core.peek_data(SRAM_IO_OFFSET + group.pin_register as u16)?;
+            let pcmsk = core.peek_data(group.mask_register)?;
+            let previous = std::mem::replace(&mut self.levels[n], pins);
+
+            if self.raised[n] {
+                self.raised[n] = addons::still_raised(core, group.vector, &mut pcifr, flag)?;
+            }
+
+            if (pins ^ previous) & pcmsk != 0 {
+                pcifr |= flag;
+            }
+
+            if pcifr & flag != 0 && pcicr & flag != 0 && !self.raised[n] {
+                core.raise_interrupt(group.vector)?;
+                self.raised[n] = true;
+            }
+        }
+
+        self.pcifr = pcifr;
+        core.memory_mut()
+            .set_u8((SRAM_IO_OFFSET + PCIFR as u16) as usize, pcifr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.raised.iter_mut().for_each(|r| *r = false);
+        self.pcifr = 0;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.bytes(&self.levels);
+        let raised: Vec<u8> = self.raised.iter().map(|&b| b as u8).collect();
+        state.bytes(&raised);
+        state.u8(self.pcifr);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        state.bytes_into(&mut self.levels)?;
+        let raised = state.bytes()?;
+        if raised.len() != self.groups.len() {
+            return Err(Error::InvalidState("group count does not match"));
+        }
+        self.raised = raised.iter().map(|&b| b != 0).collect();
+        self.pcifr = state.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+use crate::addons::call_stack::CallStack;
+use crate::reset::ResetCause;
+use crate::symbols::Symbols;
+use crate::{Addon, Core, Error, Instruction};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+use std::rc::Rc;
+
+/// Stands for the code outside of any known function at the root of a
+/// stack.
+const UNKNOWN: u32 = u32::MAX;
+
+/// Cycles by call stack.
+#[derive(Default)]
+struct Samples {
+    /// Function addresses, outermost first, and their cycles.
+    stacks: HashMap<Vec<u32>, u64>,
+    symbols: Symbols,
+}
+
+/// A handle for reading the profile of a `Profiler`.
+///
+/// Handles stay usable after the `Profiler` has been attached to an `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    samples: Rc<RefCell<Samples>>,
+}
+
+impl Handle {
+    /// Gets the profile in the folded stack format of `flamegraph.pl` and
+    /// `inferno`, one stack per line with its functions outermost first,
+    /// separated by `;`, and then its cycles.
+    pub fn folded(&self) -> String {
+        let samples = self.samples.borrow();
+        let mut lines: Vec<String> = samples
+            .stacks
+            .iter()
+            .map(|(stack, cycles)| {
+                let mut line = String::new();
+                for (i, &function) in stack.iter().enumerate() {
+                    if i > 0 {
+                        line.push(';');
+                    }
+                    line.push_str(&samples.name(function));
+                }
+                let _ = write!(line, " {}", cycles);
+                line
+            })
+            .collect();
+        lines.sort();
+
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// Writes the profile in the folded stack format to a file.
+    pub fn save_folded<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<std::path::Path>,
+    {
+        std::fs::write(path, self.folded()).map_err(Error::Io)
+    }
+
+    /// Gets the cycles spent in each function itself, not counting the
+    /// functions it called.
+    pub fn self_cycles(&self) -> BTreeMap<String, u64> {
+        let samples = self.samples.borrow();
+        let mut result = BTreeMap::new();
+        for (stack, &cycles) in &samples.stacks {
+            let leaf = samples.name(*stack.last().unwrap());
+            *result.entry(leaf).or_insert(0) += cycles;
+        }
+        result
+    }
+
+    /// Gets the cycles spent in each function, including the functions it
+    /// called.
+    pub fn total_cycles(&self) -> BTreeMap<String, u64> {
+        let samples = self.samples.borrow();
+        let mut result = BTreeMap::new();
+        for (stack, &cycles) in &samples.stacks {
+            let mut functions: Vec<u32> = stack.clone();
+            // Recursive functions are only counted once per stack.
+            functions.sort_unstable();
+            functions.dedup();
+            for function in functions {
+                *result.entry(samples.name(function)).or_insert(0) += cycles;
+            }
+        }
+        result
+    }
+
+    /// Forgets everything profiled so far.
+    pub fn clear(&self) {
+        self.samples.borrow_mut().stacks.clear();
+    }
+}
+
+impl Samples {
+    fn name(&self, function: u32) -> String {
+        if function == UNKNOWN {
+            return "[unknown]".to_owned();
+        }
+        match self.symbols.flash(function) {
+            Some(symbol) => symbol.name.clone(),
+            None => format!("{:#06x}", function),
+        }
+    }
+}
+
+/// Accumulates the cycles spent in each function, by the call stacks
+/// tracked like `CallStack` does.
+///
+/// The root of each stack is the function the outermost call was made
+/// from, or that is executing if there are no calls, found by the symbols
+/// given to `with_symbols`.
+///
+/// By default every cycle is counted. With `with_sample_period` the stack
+/// is only sampled once per period instead, which is cheaper on long runs.
+pub struct Profiler {
+    call_stack: CallStack,
+    samples: Rc<RefCell<Samples>>,
+    /// The cycles between samples, if sampling.
+    sample_period: Option<u64>,
+    /// The cycle count at the last tick.
+    last_cycle_count: Option<u64>,
+    /// The cycle the next sample is due at.
+    next_sample: u64,
+    /// The stack being sampled, kept to avoid allocating on every tick.
+    stack: Vec<u32>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Profiler {
+            call_stack: CallStack::new(),
+            samples: Rc::new(RefCell::new(Samples::default())),
+            sample_period: None,
+            last_cycle_count: None,
+            next_sample: 0,
+            stack: Vec::new(),
+        }
+    }
+
+    /// Names functions with the symbols of a program, like `Mcu::symbols`.
+    pub fn with_symbols(mut self, symbols: Symbols) -> Self {
+        self.call_stack = self.call_stack.with_symbols(symbols.clone());
+        self.samples.borrow_mut().symbols = symbols;
+        self
+    }
+
+    /// Samples the stack once every `cycles` cycles rather than counting
+    /// every cycle.
+    pub fn with_sample_period(mut self, cycles: u64) -> Self {
+        self.sample_period = Some(cycles.max(1));
+        self
+    }
+
+    /// Gets a handle for reading the profile.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            samples: self.samples.clone(),
+        }
+    }
+
+    /// Gets the profile in the folded stack format, see `Handle::folded`.
+    pub fn folded(&self) -> String {
+        self.handle().folded()
+    }
+
+    /// Adds cycles to the current stack.
+    fn record(&mut self, pc: u32, cycles: u64) {
+        let mut samples = self.samples.borrow_mut();
+        let frames = self.call_stack.frames();
+
+        let root_pc = frames.first().map_or(pc, |f| f.return_address);
+        let root = samples
+            .symbols
+            .flash(root_pc)
+            .map_or(UNKNOWN, |s| s.address);
+        self.stack.clear();
+        self.stack.push(root);
+        self.stack.extend(frames.iter().map(|f| f.function));
+
+        match samples.stacks.get_mut(&self.stack[..]) {
+            Some(total) => *total += cycles,
+            None => {
+                samples.stacks.insert(self.stack.clone(), cycles);
+            }
+        }
+    }
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Profiler::new()
+    }
+}
+
+impl Addon for Profiler {
+    fn post_tick(&mut self, core: &mut Core, inst: Instruction, pc: u32) -> Result<(), Error> {
+        let now = core.cycle_count;
+        // Counting starts at the first tick, however long the simulation
+        // ran before the profiler was attached.
+        let last = match self.last_cycle_count {
+            Some(last) => last,
+            None => {
+                self.next_sample = now;
+                now
+            }
+        };
+        self.last_cycle_count = Some(now);
+
+        // The cycles of the instruction belong to the function it was
+        // executed in, so they are counted before calls and returns change
+        // the stack.
+        match self.sample_period {
+            None => self.record(pc, now.saturating_sub(last)),
+            Some(period) => {
+                while self.next_sample <= now {
+                    self.record(pc, period);
+                    self.next_sample += period;
+                }
+            }
+        }
+
+        self.call_stack.post_tick(core, inst, pc)
+    }
+
+    fn on_reset(&mut self, core: &mut Core, cause: Option<ResetCause>) -> Result<(), Error> {
+        self.call_stack.on_reset(core, cause)
+    }
+}
@@ -0,0 +1,112 @@
+use crate::core::SRAM_IO_OFFSET;
+use crate::{Addon, Core, Error, Instruction};
+use std::collections::VecDeque;
+
+/// A measurement of a PWM signal.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PwmMeasurement {
+    /// The frequency in Hz.
+    pub frequency: f64,
+    /// The fraction of each period that the pin is high (`0.0` to `1.0`).
+    pub duty_cycle: f64,
+    /// The average period in CPU cycles.
+    pub period_cycles: f64,
+}
+
+/// Watches an output pin and measures the frequency and duty cycle of the
+/// signal on it.
+///
+/// Only edges within the last `window` cycles are considered.
+pub struct PwmProbe {
+    pin_register: u8,
+    bit: u8,
+    /// The number of CPU ticks in a single second (ticks/second)
+    pub cpu_frequency: u64,
+    /// The number of cycles to measure over.
+    pub window: u64,
+
+    /// The last sampled level of the pin.
+    level: Option<bool>,
+    /// Edges as `(cycle, new level)`, oldest first.
+    edges: VecDeque<(u64, bool)>,
+}
+
+impl PwmProbe {
+    /// Creates a probe on a pin given by its `PINx` IO address and bit.
+    pub fn new(pin_register: u8, bit: u8, cpu_frequency: u64, window: u64) -> Self {
+        PwmProbe {
+            pin_register,
+            bit,
+            cpu_frequency,
+            window,
+            level: None,
+            edges: VecDeque::new(),
+        }
+    }
+
+    /// Samples the pin, recording an edge if its level changed.
+    ///
+    /// This is called on every tick when the probe is attached to an `Mcu`.
+    pub fn sample(&mut self, core: &Core) -> Result<(), Error> {
+        let pins = core.read_data(SRAM_IO_OFFSET + self.pin_register as u16)?;
+        let level = pins & (1 << self.bit) != 0;
+        let now = core.cycle_count;
+
+        if self.level.is_some_and(|previous| previous != level) {
+            self.edges.push_back((now, level));
+        }
+        self.level = Some(level);
+
+        while let Some(&(cycle, _)) = self.edges.front() {
+            if now - cycle > self.window {
+                self.edges.pop_front();
+            } else {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    /// Measures the signal over all complete periods in the window.
+    ///
+    /// Returns `None` if there has not been a complete period.
+    pub fn measurement(&self) -> Option<PwmMeasurement> {
+        let rising: Vec<usize> = (0..self.edges.len()).filter(|&i| self.edges[i].1).collect();
+
+        let (first, last) = (*rising.first()?, *rising.last()?);
+        if first == last {
+            return None;
+        }
+
+        let mut high_cycles = 0;
+        let mut rise = self.edges[first].0;
+        for &(cycle, level) in self.edges.range(first + 1..=last) {
+            if level {
+                rise = cycle;
+            } else {
+                high_cycles += cycle - rise;
+            }
+        }
+
+        let total_cycles = self.edges[last].0 - self.edges[first].0;
+        let periods = (rising.len() - 1) as f64;
+        let period_cycles = total_cycles as f64 / periods;
+
+        Some(PwmMeasurement {
+            frequency: self.cpu_frequency as f64 / period_cycles,
+            duty_cycle: high_cycles as f64 / total_cycles as f64,
+            period_cycles,
+        })
+    }
+
+    /// Forgets all recorded edges.
+    pub fn clear(&mut self) {
+        self.edges.clear();
+    }
+}
+
+impl Addon for PwmProbe {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        self.sample(core)
+    }
+}
@@ -0,0 +1,161 @@
+use crate::core::Access;
+use crate::{Addon, Core, Error, Instruction};
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+/// The SRAM used by a run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+    /// The lowest stack pointer seen, or `None` before the first tick.
+    pub lowest_sp: Option<u16>,
+    /// The most bytes on the stack at once.
+    pub peak_stack_depth: u16,
+    /// The number of SRAM bytes accessed, including the stack.
+    pub touched_bytes: usize,
+    /// The size of SRAM.
+    pub sram_size: usize,
+}
+
+#[derive(Default)]
+struct Usage {
+    sram_start: u16,
+    ram_end: u16,
+    lowest_sp: Option<u16>,
+    /// Whether each SRAM byte was accessed, from `sram_start`.
+    touched: Vec<bool>,
+}
+
+/// A handle for reading the SRAM usage tracked by `RamUsage`.
+///
+/// Handles stay usable after the `RamUsage` has been attached to an `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    usage: Rc<RefCell<Usage>>,
+}
+
+impl Handle {
+    pub fn report(&self) -> Report {
+        let usage = self.usage.borrow();
+        let peak_stack_depth = usage
+            .lowest_sp
+            .map_or(0, |sp| usage.ram_end.saturating_sub(sp));
+
+        // Everything between the lowest stack pointer and the end of SRAM
+        // has held the stack, even though pushes are not recorded as
+        // accesses.
+        let stack_start = usage.lowest_sp.map_or(usize::MAX, |sp| {
+            (sp as usize + 1).saturating_sub(usage.sram_start as usize)
+        });
+        let touched_bytes = usage
+            .touched
+            .iter()
+            .enumerate()
+            .filter(|&(i, &touched)| touched || i >= stack_start)
+            .count();
+
+        Report {
+            lowest_sp: usage.lowest_sp,
+            peak_stack_depth,
+            touched_bytes,
+            sram_size: usage.touched.len(),
+        }
+    }
+
+    /// Gets the SRAM addresses accessed through loads and stores, in order.
+    pub fn touched_addresses(&self) -> Vec<u16> {
+        let usage = self.usage.borrow();
+        usage
+            .touched
+            .iter()
+            .enumerate()
+            .filter(|&(_, &touched)| touched)
+            .map(|(i, _)| usage.sram_start + i as u16)
+            .collect()
+    }
+
+    /// Forgets the usage so far.
+    pub fn clear(&self) {
+        let mut usage = self.usage.borrow_mut();
+        usage.lowest_sp = None;
+        usage.touched.iter_mut().for_each(|t| *t = false);
+    }
+}
+
+/// Tracks the high-water mark of the stack and the SRAM bytes a run
+/// accesses, to see how close firmware gets to running out of RAM.
+pub struct RamUsage {
+    usage: Rc<RefCell<Usage>>,
+}
+
+impl RamUsage {
+    pub fn new() -> Self {
+        RamUsage {
+            usage: Rc::new(RefCell::new(Usage::default())),
+        }
+    }
+
+    /// Gets a handle for reading the usage.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            usage: self.usage.clone(),
+        }
+    }
+
+    pub fn report(&self) -> Report {
+        self.handle().report()
+    }
+}
+
+impl Default for RamUsage {
+    fn default() -> Self {
+        RamUsage::new()
+    }
+}
+
+impl Addon for RamUsage {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let mut usage = self.usage.borrow_mut();
+        if usage.touched.is_empty() {
+            usage.sram_start = core.sram_start();
+            usage.ram_end = core.ram_end();
+            usage.touched = vec![false; (core.ram_end() - core.sram_start()) as usize + 1];
+        }
+
+        let sp = core.stack_pointer()?;
+        if usage.lowest_sp.is_none_or(|lowest| sp < lowest) {
+            usage.lowest_sp = Some(sp);
+        }
+
+        let sram_start = usage.sram_start;
+        for access in core.accesses() {
+            let (Access::Read(address) | Access::Write(address)) = access;
+            if let Some(touched) = address
+                .checked_sub(sram_start)
+                .and_then(|i| usage.touched.get_mut(i as usize))
+            {
+                *touched = true;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let percent = |bytes: usize| bytes as f64 * 100.0 / self.sram_size.max(1) as f64;
+        writeln!(
+            fmt,
+            "peak stack depth: {} bytes ({:.1}% of SRAM)",
+            self.peak_stack_depth,
+            percent(self.peak_stack_depth as usize)
+        )?;
+        write!(
+            fmt,
+            "SRAM used: {} of {} bytes ({:.1}%)",
+            self.touched_bytes,
+            self.sram_size,
+            percent(self.touched_bytes)
+        )
+    }
+}
@@ -0,0 +1,255 @@
+use crate::addons::uart::{self, Uart};
+use crate::reset::ResetCause;
+use crate::{Addon, Core, Error, Instruction};
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver};
+
+/// Where the serial port is exposed.
+enum Endpoint {
+    Tcp {
+        listener: TcpListener,
+        /// The connected client. Only one client is served at a time.
+        client: Option<TcpStream>,
+    },
+    Pty {
+        master: File,
+        path: PathBuf,
+    },
+}
+
+/// Exposes a simulated serial port to the outside world, over a TCP
+/// listener or a pseudo-terminal.
+///
+/// The bridge owns the `Uart` it exposes and ticks it. Bytes transmitted
+/// by the firmware are forwarded to the other end, and bytes from the other
+/// end are received by the firmware. Nothing blocks, so the bridge has to
+/// be ticked for data to move.
+pub struct SerialBridge {
+    uart: Uart,
+    handle: uart::Handle,
+    transmitted: Receiver<u8>,
+    endpoint: Endpoint,
+}
+
+impl SerialBridge {
+    /// Listens for a TCP connection on `address`.
+    pub fn tcp<A>(uart: Uart, address: A) -> io::Result<Self>
+    where
+        A: ToSocketAddrs,
+    {
+        let listener = TcpListener::bind(address)?;
+        listener.set_nonblocking(true)?;
+
+        Ok(Self::new(
+            uart,
+            Endpoint::Tcp {
+                listener,
+                client: None,
+            },
+        ))
+    }
+
+    /// Creates a pseudo-terminal in raw mode.
+    ///
+    /// Its path, like `/dev/pts/3`, is given by `pty_path`.
+    #[cfg(unix)]
+    pub fn pty(uart: Uart) -> io::Result<Self> {
+        let (master, path) = pty::open()?;
+        Ok(Self::new(uart, Endpoint::Pty { master, path }))
+    }
+
+    fn new(uart: Uart, endpoint: Endpoint) -> Self {
+        let (sender, transmitted) = mpsc::channel();
+        let uart = uart.with_sink(sender);
+
+        SerialBridge {
+            handle: uart.handle(),
+            uart,
+            transmitted,
+            endpoint,
+        }
+    }
+
+    /// Gets the address being listened on, if bridged over TCP.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match self.endpoint {
+            Endpoint::Tcp { ref listener, .. } => listener.local_addr().ok(),
+            Endpoint::Pty { .. } => None,
+        }
+    }
+
+    /// Gets the path of the pseudo-terminal, if bridged over one.
+    pub fn pty_path(&self) -> Option<&Path> {
+        match self.endpoint {
+            Endpoint::Pty { ref path, .. } => Some(path),
+            Endpoint::Tcp { .. } => None,
+        }
+    }
+
+    /// Checks if a TCP client is connected. Always true for a
+    /// pseudo-terminal.
+    pub fn is_connected(&self) -> bool {
+        match self.endpoint {
+            Endpoint::Tcp { ref client, .. } => client.is_some(),
+            Endpoint::Pty { .. } => true,
+        }
+    }
+
+    pub fn uart(&self) -> &Uart {
+        &self.uart
+    }
+
+    pub fn uart_mut(&mut self) -> &mut Uart {
+        &mut self.uart
+    }
+
+    /// Moves bytes between the `Uart` and the other end.
+    fn pump(&mut self) -> io::Result<()> {
+        let transmitted: Vec<u8> = self.transmitted.try_iter().collect();
+        let mut received = [0; 256];
+
+        match self.endpoint {
+            Endpoint::Tcp {
+                ref listener,
+                ref mut client,
+            } => {
+                if client.is_none() {
+                    match listener.accept() {
+                        Ok((stream, _)) => {
+                            stream.set_nonblocking(true)?;
+                            stream.set_nodelay(true)?;
+                            *client = Some(stream);
+                        }
+                        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+                        Err(e) => return Err(e),
+                    }
+                }
+
+                // Bytes transmitted while nobody is connected are lost, like
+                // on a real unplugged serial line.
+                let disconnected = match *client {
+                    Some(ref mut stream) => match transfer(stream, &transmitted, &mut received) {
+                        Ok(Some(count)) => {
+                            self.handle.send_to_target(&received[..count]);
+                            false
+                        }
+                        Ok(None) => true,
+                        Err(ref e) if is_disconnect(e) => true,
+                        Err(e) => return Err(e),
+                    },
+                    None => false,
+                };
+                if disconnected {
+                    *client = None;
+                }
+            }
+            Endpoint::Pty { ref mut master, .. } => {
+                match transfer(master, &transmitted, &mut received) {
+                    Ok(Some(count)) => self.handle.send_to_target(&received[..count]),
+                    Ok(None) => (),
+                    // Reading the master fails while no one has the
+                    // terminal open.
+                    Err(ref e) if is_disconnect(e) => (),
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes bytes to a non-blocking stream and reads what is available.
+///
+/// Returns the number of bytes read, or `None` if the stream was closed.
+fn transfer<S>(stream: &mut S, output: &[u8], input: &mut [u8]) -> io::Result<Option<usize>>
+where
+    S: Read + Write,
+{
+    if !output.is_empty() {
+        match stream.write_all(output) {
+            Ok(()) => (),
+            // The other end is not keeping up, drop the bytes.
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => (),
+            Err(e) => return Err(e),
+        }
+    }
+
+    match stream.read(input) {
+        Ok(0) => Ok(None),
+        Ok(count) => Ok(Some(count)),
+        Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => Ok(Some(0)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Checks if an error means the other end has gone away.
+fn is_disconnect(e: &io::Error) -> bool {
+    match e.kind() {
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe => true,
+        // Linux reports `EIO` on a pseudo-terminal master with no slave.
+        _ => e.raw_os_error() == Some(5),
+    }
+}
+
+impl Addon for SerialBridge {
+    fn post_tick(&mut self, core: &mut Core, inst: Instruction, pc: u32) -> Result<(), Error> {
+        self.uart.post_tick(core, inst, pc)?;
+        self.pump().map_err(Error::Io)
+    }
+
+    fn on_reset(&mut self, core: &mut Core, cause: Option<ResetCause>) -> Result<(), Error> {
+        self.uart.on_reset(core, cause)
+    }
+}
+
+#[cfg(unix)]
+mod pty {
+    use std::ffi::CStr;
+    use std::fs::File;
+    use std::io;
+    use std::os::unix::io::FromRawFd;
+    use std::path::PathBuf;
+
+    /// Converts a `-1` return value into the last OS error.
+    fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+        if ret == -1 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(ret)
+        }
+    }
+
+    /// Opens a non-blocking pseudo-terminal master in raw mode, returning it
+    /// and the path of its slave.
+    pub fn open() -> io::Result<(File, PathBuf)> {
+        unsafe {
+            let fd = check(libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY))?;
+            // Closes the descriptor if anything below fails.
+            let master = File::from_raw_fd(fd);
+
+            check(libc::grantpt(fd))?;
+            check(libc::unlockpt(fd))?;
+
+            let name = libc::ptsname(fd);
+            if name.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let path = PathBuf::from(CStr::from_ptr(name).to_string_lossy().into_owned());
+
+            let mut termios = std::mem::zeroed::<libc::termios>();
+            check(libc::tcgetattr(fd, &mut termios))?;
+            libc::cfmakeraw(&mut termios);
+            check(libc::tcsetattr(fd, libc::TCSANOW, &termios))?;
+
+            let flags = check(libc::fcntl(fd, libc::F_GETFL))?;
+            check(libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK))?;
+
+            Ok((master, path))
+        }
+    }
+}
@@ -0,0 +1,258 @@
+use crate::chips::atmega328p;
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// `SPCR` bits.
+pub mod spcr {
+    pub const SPIE: u8 = 1 << 7;
+    pub const SPE: u8 = 1 << 6;
+    pub const DORD: u8 = 1 << 5;
+    pub const MSTR: u8 = 1 << 4;
+    pub const CPOL: u8 = 1 << 3;
+    pub const CPHA: u8 = 1 << 2;
+    pub const SPR_MASK: u8 = 0b11;
+}
+
+/// `SPSR` bits.
+pub mod spsr {
+    pub const SPIF: u8 = 1 << 7;
+    pub const WCOL: u8 = 1 << 6;
+    pub const SPI2X: u8 = 1 << 0;
+}
+
+/// A virtual device on the SPI bus.
+pub trait SpiSlave {
+    /// Called when the chip select line of the device changes.
+    ///
+    /// Chip select is active low, so `selected` is true while the line is
+    /// driven low.
+    fn select(&mut self, selected: bool) {
+        let _ = selected;
+    }
+
+    /// Exchanges a byte with the master.
+    ///
+    /// Receives the byte shifted out by the master and returns the byte
+    /// shifted back in. Only called while the device is selected.
+    fn exchange(&mut self, byte: u8) -> u8;
+}
+
+/// The IO addresses of the SPI registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub spcr: u8,
+    pub spsr: u8,
+    pub spdr: u8,
+}
+
+/// A device attached to the bus.
+struct Device {
+    /// The chip select pin, as the IO address of its `PORTx` register and
+    /// the bit in it.
+    chip_select: (u8, u8),
+    selected: bool,
+    slave: Box<dyn SpiSlave>,
+}
+
+/// A transfer in progress.
+#[derive(Copy, Clone, Debug)]
+struct Transfer {
+    byte: u8,
+    /// The cycle at which the transfer completes.
+    done_at: u64,
+}
+
+/// The SPI unit in master mode.
+///
+/// Writing `SPDR` shifts a byte out at the rate set by `SPR1:SPR0` and
+/// `SPI2X`, exchanging it with every selected device. Devices are selected
+/// by driving their chip select pin low through its `PORTx` register.
+pub struct Spi {
+    registers: Registers,
+    vector: u8,
+    devices: Vec<Device>,
+
+    transfer: Option<Transfer>,
+    /// Whether `SPSR` was read while `SPIF` was set, so that the next access
+    /// to `SPDR` clears it.
+    spif_read: bool,
+    /// Whether the transfer complete interrupt has been requested.
+    raised: bool,
+}
+
+impl Spi {
+    pub fn new(registers: Registers, vector: u8) -> Self {
+        Spi {
+            registers,
+            vector,
+            devices: Vec::new(),
+            transfer: None,
+            spif_read: false,
+            raised: false,
+        }
+    }
+
+    pub fn atmega328p() -> Self {
+        Self::new(
+            Registers {
+                spcr: 0x2c,
+                spsr: 0x2d,
+                spdr: 0x2e,
+            },
+            17,
+        )
+    }
+
+    /// Attaches a device whose chip select is bit `bit` of the `PORTx`
+    /// register at IO address `port_register`.
+    pub fn attach<S>(&mut self, port_register: u8, bit: u8, slave: S)
+    where
+        S: SpiSlave + 'static,
+    {
+        self.devices.push(Device {
+            chip_select: (port_register, bit),
+            selected: false,
+            slave: Box::new(slave),
+        });
+    }
+
+    /// Attaches a device whose chip select is the `SS` pin (`PB2`).
+    pub fn attach_atmega328p_ss<S>(&mut self, slave: S)
+    where
+        S: SpiSlave + 'static,
+    {
+        self.attach(atmega328p::PORTB, 2, slave)
+    }
+
+    /// Gets the number of CPU cycles needed to shift one bit.
+    fn clock_divider(spcr: u8, spsr: u8) -> u64 {
+        let divider = match spcr & spcr::SPR_MASK {
+            0b00 => 4,
+            0b01 => 16,
+            0b10 => 64,
+            _ => 128,
+        };
+
+        if spsr & spsr::SPI2X != 0 {
+            divider / 2
+        } else {
+            divider
+        }
+    }
+
+    /// Exchanges a byte with the selected devices.
+    ///
+    /// `MISO` floats high when nothing drives it, and several devices
+    /// driving it at once pull it low wherever any of them sends a zero.
+    fn exchange(&mut self, byte: u8) -> u8 {
+        self.devices
+            .iter_mut()
+            .filter(|device| device.selected)
+            .fold(0xff, |miso, device| miso & device.slave.exchange(byte))
+    }
+}
+
+impl Addon for Spi {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let io = |a: u8| SRAM_IO_OFFSET + a as u16;
+        let now = core.cycle_count;
+
+        for device in self.devices.iter_mut() {
+            let (port, bit) = device.chip_select;
+            let selected = core.read_data(io(port))? & (1 << bit) == 0;
+            if selected != device.selected {
+                device.selected = selected;
+                device.slave.select(selected);
+            }
+        }
+
+        let spcr = core.read_data(io(regs.spcr))?;
+        let mut spsr = core.read_data(io(regs.spsr))?;
+
+        // `SPIF` is cleared by reading `SPSR` and then accessing `SPDR`.
+        let spdr_accessed = core.was_read(io(regs.spdr)) || core.was_written(io(regs.spdr));
+        if self.spif_read && spdr_accessed {
+            spsr &= !(spsr::SPIF | spsr::WCOL);
+            self.spif_read = false;
+        }
+        if core.was_read(io(regs.spsr)) && spsr & spsr::SPIF != 0 {
+            self.spif_read = true;
+        }
+
+        if spcr & spcr::SPE == 0 {
+            self.transfer = None;
+        } else if core.was_written(io(regs.spdr)) && spcr & spcr::MSTR != 0 {
+            if self.transfer.is_some() {
+                spsr |= spsr::WCOL;
+            } else {
+                self.transfer = Some(Transfer {
+                    byte: core.read_data(io(regs.spdr))?,
+                    done_at: now + 8 * Self::clock_divider(spcr, spsr),
+                });
+            }
+        }
+
+        if let Some(transfer) = self.transfer {
+            if now >= transfer.done_at {
+                let received = self.exchange(transfer.byte);
+                core.write_data(io(regs.spdr), received)?;
+                self.transfer = None;
+                spsr |= spsr::SPIF;
+            }
+        }
+
+        // The flag is cleared by hardware once the vector is serviced.
+        if self.raised && !core.interrupts().is_pending(self.vector) {
+            self.raised = false;
+            spsr &= !spsr::SPIF;
+        }
+
+        if spsr & spsr::SPIF != 0 && spcr & spcr::SPIE != 0 && !self.raised {
+            core.raise_interrupt(self.vector)?;
+            self.raised = true;
+        }
+
+        core.write_data(io(regs.spsr), spsr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.transfer = None;
+        self.spif_read = false;
+        self.raised = false;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        let selected: Vec<u8> = self.devices.iter().map(|d| d.selected as u8).collect();
+        state.bytes(&selected);
+        state.bool(self.transfer.is_some());
+        state.u8(self.transfer.map_or(0, |t| t.byte));
+        state.u64(self.transfer.map_or(0, |t| t.done_at));
+        state.bool(self.spif_read);
+        state.bool(self.raised);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        let selected = state.bytes()?;
+        if selected.len() != self.devices.len() {
+            return Err(Error::InvalidState("device count does not match"));
+        }
+        // Devices keep their own state, so they are not told about the
+        // chip select lines changing.
+        for (device, &selected) in self.devices.iter_mut().zip(selected) {
+            device.selected = selected != 0;
+        }
+        let transferring = state.bool()?;
+        let transfer = Transfer {
+            byte: state.u8()?,
+            done_at: state.u64()?,
+        };
+        self.transfer = transferring.then_some(transfer);
+        self.spif_read = state.bool()?;
+        self.raised = state.bool()?;
+        Ok(())
+    }
+}
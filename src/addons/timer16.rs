@@ -0,0 +1,538 @@
+use crate::addons;
+use crate::chips::atmega328p;
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// Overflow flag.
+pub const TOV: u8 = 1 << 0;
+/// Output compare A match flag.
+pub const OCFA: u8 = 1 << 1;
+/// Output compare B match flag.
+pub const OCFB: u8 = 1 << 2;
+/// Input capture flag.
+pub const ICF: u8 = 1 << 5;
+
+/// Input capture edge select bit in `TCCRnB`.
+const ICES: u8 = 1 << 6;
+
+/// The data space addresses of a 16-bit timer's registers.
+///
+/// 16-bit registers are given by the address of their low byte.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub tccra: u16,
+    pub tccrb: u16,
+    pub tcnt: u16,
+    pub icr: u16,
+    pub ocra: u16,
+    pub ocrb: u16,
+    pub timsk: u16,
+    pub tifr: u16,
+}
+
+/// The interrupt vector numbers of a 16-bit timer.
+#[derive(Copy, Clone, Debug)]
+pub struct Vectors {
+    pub capt: u8,
+    pub compa: u8,
+    pub compb: u8,
+    pub ovf: u8,
+}
+
+/// The pins used by a 16-bit timer, as `(PINx IO address, bit)`.
+#[derive(Copy, Clone, Debug)]
+pub struct Pins {
+    /// `OCnA` and `OCnB`.
+    pub outputs: [(u8, u8); 2],
+    /// `ICPn`.
+    pub input_capture: (u8, u8),
+    /// `Tn`.
+    pub clock: (u8, u8),
+}
+
+/// How the counter counts, as selected by `WGMn3:0`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Normal,
+    Ctc,
+    FastPwm,
+    /// Phase correct and phase and frequency correct PWM.
+    PhaseCorrectPwm,
+}
+
+/// A 16-bit Timer/Counter (e.g. `Timer/Counter1`).
+///
+/// Like `Timer8`, with a 16-bit counter, the `ICRn` input capture register
+/// which latches `TCNTn` on edges of the `ICPn` pin, and the additional
+/// waveform generation modes using `ICRn` or fixed 8/9/10-bit values as
+/// `TOP`.
+///
+/// Firmware accesses to the 16-bit registers go through the core's `TEMP`
+/// register, so the timer itself accesses memory directly.
+pub struct Timer16 {
+    registers: Registers,
+    vectors: Vectors,
+    pins: Pins,
+
+    /// The cycle count at the last tick.
+    last_cycle: u64,
+    /// CPU cycles accumulated towards the next prescaled timer clock.
+    prescaler: u64,
+    /// The last sampled level of the external clock pin.
+    clock_level: bool,
+    /// The last sampled level of the input capture pin.
+    capture_level: bool,
+    /// Whether a phase correct PWM mode is counting down.
+    counting_down: bool,
+    /// The levels of the output compare pins.
+    output_levels: [bool; 2],
+    /// Flags which have requested an interrupt that is still pending.
+    raised: u8,
+    /// `TIFRn` as it was written back after the last instruction.
+    tifr: u8,
+}
+
+impl Timer16 {
+    pub fn new(registers: Registers, vectors: Vectors, pins: Pins) -> Self {
+        Timer16 {
+            registers,
+            vectors,
+            pins,
+            last_cycle: 0,
+            prescaler: 0,
+            clock_level: false,
+            capture_level: false,
+            counting_down: false,
+            output_levels: [false; 2],
+            raised: 0,
+            tifr: 0,
+        }
+    }
+
+    /// `Timer/Counter1` with `OC1A` on `PB1`, `OC1B` on `PB2`, `ICP1` on `PB0`
+    /// and `T1` on `PD5`.
+    pub fn atmega328p_timer1() -> Self {
+        Self::new(
+            Registers {
+                tccra: 0x80,
+                tccrb: 0x81,
+                tcnt: 0x84,
+                icr: 0x86,
+                ocra: 0x88,
+                ocrb: 0x8a,
+                timsk: 0x6f,
+                tifr: 0x36,
+            },
+            Vectors {
+                capt: 10,
+                compa: 11,
+                compb: 12,
+                ovf: 13,
+            },
+            Pins {
+                outputs: [(atmega328p::PINB, 1), (atmega328p::PINB, 2)],
+                input_capture: (atmega328p::PINB, 0),
+                clock: (atmega328p::PIND, 5),
+            },
+        )
+    }
+
+    /// Samples a pin, returning its current and previous level.
+    fn sample(
+        core: &Core,
+        (register, bit): (u8, u8),
+        last: &mut bool,
+    ) -> Result<(bool, bool), Error> {
+        let level = core
+            .memory()
+            .get_u8((SRAM_IO_OFFSET + register as u16) as usize)?
+            & (1 << bit)
+            != 0;
+        let previous = std::mem::replace(last, level);
+        Ok((level, previous))
+    }
+
+    /// Calculates how many timer clocks have elapsed since the last tick.
+    fn timer_clocks(&mut self, core: &Core, clock_select: u8) -> Result<u64, Error> {
+        let elapsed = core.cycle_count.saturating_sub(self.last_cycle);
+
+        let divisor = match clock_select {
+            0 => return Ok(0),
+            1 => 1,
+            2 => 8,
+            3 => 64,
+            4 => 256,
+            5 => 1024,
+            _ => {
+                let (level, previous) = Self::sample(core, self.pins.clock, &mut self.clock_level)?;
+                let rising = clock_select == 7;
+                return Ok((level != previous && level == rising) as u64);
+            }
+        };
+
+        self.prescaler += elapsed;
+        let clocks = self.prescaler / divisor;
+        self.prescaler %= divisor;
+        Ok(clocks)
+    }
+
+    /// The new level of output compare pin `i` (`0` for A, `1` for B) on a
+    /// compare match, or `None` if it is unaffected.
+    fn compare_match_output(&self, state: &State, i: usize) -> Option<bool> {
+        let toggled = !self.output_levels[i];
+        // Only OCnA can toggle in PWM modes, and only with a variable TOP.
+        let can_toggle = i == 0 && matches!(state.wgm, 9 | 11 | 14 | 15);
+
+        match (state.kind(), state.com(i)) {
+            (Kind::Normal, 0b01) | (Kind::Ctc, 0b01) => Some(toggled),
+            (_, 0b01) if can_toggle => Some(toggled),
+            (Kind::PhaseCorrectPwm, 0b10) => Some(self.counting_down),
+            (Kind::PhaseCorrectPwm, 0b11) => Some(!self.counting_down),
+            (_, 0b10) => Some(false),
+            (_, 0b11) => Some(true),
+            _ => None,
+        }
+    }
+
+    /// Advances the counter by a single timer clock.
+    fn step(&mut self, state: &mut State) -> [Option<bool>; 2] {
+        let mut outputs = [None; 2];
+        let top = state.top();
+
+        match state.kind() {
+            Kind::PhaseCorrectPwm => {
+                if self.counting_down {
+                    state.tcnt = state.tcnt.saturating_sub(1);
+                    if state.tcnt == 0 {
+                        self.counting_down = false;
+                        state.tifr |= TOV;
+                    }
+                } else if state.tcnt >= top {
+                    self.counting_down = true;
+                    state.tcnt = state.tcnt.saturating_sub(1);
+
+                    if state.wgm == 10 || state.wgm == 8 {
+                        state.tifr |= ICF;
+                    }
+                } else {
+                    state.tcnt += 1;
+                }
+            }
+            Kind::FastPwm => {
+                if state.tcnt >= top {
+                    state.tcnt = 0;
+                    state.tifr |= TOV;
+                    if state.wgm == 14 {
+                        state.tifr |= ICF;
+                    }
+
+                    for (i, output) in outputs.iter_mut().enumerate() {
+                        match state.com(i) {
+                            0b10 => *output = Some(true),
+                            0b11 => *output = Some(false),
+                            _ => (),
+                        }
+                    }
+                } else {
+                    state.tcnt += 1;
+                }
+            }
+            Kind::Normal | Kind::Ctc => {
+                if state.tcnt == top {
+                    state.tcnt = 0;
+                    if top == 0xffff {
+                        state.tifr |= TOV;
+                    }
+                    if state.wgm == 12 {
+                        state.tifr |= ICF;
+                    }
+                } else {
+                    state.tcnt = state.tcnt.wrapping_add(1);
+                }
+            }
+        }
+
+        for (i, (ocr, flag)) in [(state.ocra, OCFA), (state.ocrb, OCFB)].iter().enumerate() {
+            if state.tcnt != *ocr {
+                continue;
+            }
+            state.tifr |= flag;
+
+            outputs[i] = self.compare_match_output(state, i);
+        }
+
+        for (i, output) in outputs.iter().enumerate() {
+            if let Some(level) = output {
+                self.output_levels[i] = *level;
+            }
+        }
+        outputs
+    }
+}
+
+/// A snapshot of the timer's registers.
+struct State {
+    tccra: u8,
+    wgm: u8,
+    tcnt: u16,
+    icr: u16,
+    ocra: u16,
+    ocrb: u16,
+    tifr: u8,
+}
+
+impl State {
+    fn kind(&self) -> Kind {
+        match self.wgm {
+            0 | 13 => Kind::Normal,
+            4 | 12 => Kind::Ctc,
+            5 | 6 | 7 | 14 | 15 => Kind::FastPwm,
+            _ => Kind::PhaseCorrectPwm,
+        }
+    }
+
+    /// The value the counter counts up to.
+    fn top(&self) -> u16 {
+        match self.wgm {
+            1 | 5 => 0x00ff,
+            2 | 6 => 0x01ff,
+            3 | 7 => 0x03ff,
+            4 | 9 | 11 | 15 => self.ocra,
+            8 | 10 | 12 | 14 => self.icr,
+            _ => 0xffff,
+        }
+    }
+
+    /// Whether `ICRn` is used as `TOP`, which disables input capture.
+    fn icr_is_top(&self) -> bool {
+        matches!(self.wgm, 8 | 10 | 12 | 14)
+    }
+
+    /// The compare output mode for output `i` (`0` for A, `1` for B).
+    fn com(&self, i: usize) -> u8 {
+        (self.tccra >> (6 - i * 2)) & 0b11
+    }
+}
+
+/// Reads a 16-bit register without going through `TEMP`.
+fn read_u16(core: &Core, low: u16) -> Result<u16, Error> {
+    let lo = core.memory().get_u8(low as usize)? as u16;
+    let hi = core.memory().get_u8(low as usize + 1)? as u16;
+    Ok((hi << 8) | lo)
+}
+
+/// Writes a 16-bit register without going through `TEMP`.
+fn write_u16(core: &mut Core, low: u16, val: u16) -> Result<(), Error> {
+    core.memory_mut().set_u8(low as usize, val as u8)?;
+    core.memory_mut().set_u8(low as usize + 1, (val >> 8) as u8)
+}
+
+impl Addon for Timer16 {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let tccra = core.read_data(regs.tccra)?;
+        let tccrb = core.read_data(regs.tccrb)?;
+
+        let mut state = State {
+            tccra,
+            wgm: ((tccrb >> 1) & 0b1100) | (tccra & 0b11),
+            tcnt: read_u16(core, regs.tcnt)?,
+            icr: read_u16(core, regs.icr)?,
+            ocra: read_u16(core, regs.ocra)?,
+            ocrb: read_u16(core, regs.ocrb)?,
+            tifr: addons::clear_written_flags(core, regs.tifr, self.tifr, ICF | OCFA | OCFB | TOV)?,
+        };
+
+        let clocks = self.timer_clocks(core, tccrb & 0b111)?;
+        self.last_cycle = core.cycle_count;
+
+        // Pins are given back to `PORTx` when their output is disconnected.
+        for (i, &(register, bit)) in self.pins.outputs.iter().enumerate() {
+            if (tccra >> (6 - 2 * i)) & 0b11 == 0 {
+                core.override_pin(register, bit, None)?;
+            }
+        }
+
+        for _ in 0..clocks {
+            let outputs = self.step(&mut state);
+
+            for (i, output) in outputs.iter().enumerate() {
+                if let Some(level) = output {
+                    let (register, bit) = self.pins.outputs[i];
+                    core.override_pin(register, bit, Some(*level))?;
+                }
+            }
+        }
+
+        let (level, previous) =
+            Self::sample(core, self.pins.input_capture, &mut self.capture_level)?;
+        let rising = tccrb & ICES != 0;
+        if !state.icr_is_top() && level != previous && level == rising {
+            state.icr = state.tcnt;
+            state.tifr |= ICF;
+            write_u16(core, regs.icr, state.icr)?;
+        }
+
+        let timsk = core.read_data(regs.timsk)?;
+        let vectors = [
+            (ICF, self.vectors.capt),
+            (OCFA, self.vectors.compa),
+            (OCFB, self.vectors.compb),
+            (TOV, self.vectors.ovf),
+        ];
+
+        for (flag, vector) in vectors.iter() {
+            if self.raised & flag != 0
+                && !addons::still_raised(core, *vector, &mut state.tifr, *flag)?
+            {
+                self.raised &= !flag;
+            }
+
+            if state.tifr & flag != 0 && timsk & flag != 0 && self.raised & flag == 0 {
+                core.raise_interrupt(*vector)?;
+                self.raised |= flag;
+            }
+        }
+
+        write_u16(core, regs.tcnt, state.tcnt)?;
+        self.tifr = state.tifr;
+        core.write_data(regs.tifr, state.tifr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.prescaler = 0;
+        self.counting_down = false;
+        self.output_levels = [false; 2];
+        self.raised = 0;
+        self.tifr = 0;
+        Ok(())
+    }
+
+    fn on_rewind(&mut self, core: &mut Core) -> Result<(), Error> {
+        self.last_cycle = core.cycle_count;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.u64(self.last_cycle);
+        state.u64(self.prescaler);
+        state.bool(self.clock_level);
+        state.bool(self.capture_level);
+        state.bool(self.counting_down);
+        state.bool(self.output_levels[0]);
+        state.bool(self.output_levels[1]);
+        state.u8(self.raised);
+        state.u8(self.tifr);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        self.last_cycle = state.u64()?;
+        self.prescaler = state.u64()?;
+        self.clock_level = state.bool()?;
+        self.capture_level = state.bool()?;
+        self.counting_down = state.bool()?;
+        self.output_levels = [state.bool()?, state.bool()?];
+        self.raised = state.u8()?;
+        self.tifr = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Registers, Timer16};
+    use crate::chips::atmega328p;
+    use crate::{Core, Mcu};
+
+    /// An MCU running `NOP`s with `Timer/Counter1` in waveform generation
+    /// mode `wgm`, counting every CPU clock.
+    fn setup(wgm: u8) -> (Mcu, Registers) {
+        let timer = Timer16::atmega328p_timer1();
+        let regs = timer.registers;
+
+        let mut core = Core::new::<atmega328p::Chip>();
+        core.load_program_space([0; 64].into_iter());
+        let mut mcu = Mcu::new(core);
+        mcu.attach(Box::new(timer));
+        mcu.core.write_data(regs.tccra, wgm & 0b11).unwrap();
+        mcu.core
+            .write_data(regs.tccrb, ((wgm & 0b1100) << 1) | 1)
+            .unwrap();
+        (mcu, regs)
+    }
+
+    fn write_u16(mcu: &mut Mcu, low: u16, val: u16) {
+        mcu.core.write_data(low + 1, (val >> 8) as u8).unwrap();
+        mcu.core.write_data(low, val as u8).unwrap();
+    }
+
+    fn peek_u16(mcu: &Mcu, low: u16) -> u16 {
+        let lo = mcu.core.peek_data(low).unwrap() as u16;
+        let hi = mcu.core.peek_data(low + 1).unwrap() as u16;
+        (hi << 8) | lo
+    }
+
+    #[test]
+    fn writes_the_high_byte_with_the_low_byte() {
+        let (mut mcu, regs) = setup(0);
+
+        mcu.core.write_data(regs.tcnt + 1, 0x12).unwrap();
+        assert_eq!(peek_u16(&mcu, regs.tcnt), 0);
+
+        mcu.core.write_data(regs.tcnt, 0x34).unwrap();
+        assert_eq!(peek_u16(&mcu, regs.tcnt), 0x1234);
+    }
+
+    #[test]
+    fn reads_the_high_byte_latched_with_the_low_byte() {
+        let (mut mcu, regs) = setup(0);
+        write_u16(&mut mcu, regs.tcnt, 0x00ff);
+
+        assert_eq!(mcu.core.read_data(regs.tcnt).unwrap(), 0xff);
+        // The counter carries into the high byte before it is read.
+        mcu.tick().unwrap();
+        assert_eq!(peek_u16(&mcu, regs.tcnt), 0x0100);
+        assert_eq!(mcu.core.read_data(regs.tcnt + 1).unwrap(), 0x00);
+    }
+
+    #[test]
+    fn counts_up_to_top() {
+        const OCRA: u16 = 0x0123;
+        const ICR: u16 = 0x0234;
+        // Waveform generation mode, TOP, and whether the counter counts back
+        // down from TOP.
+        let modes = [
+            (0, 0xffff, false),
+            (1, 0x00ff, true),
+            (2, 0x01ff, true),
+            (3, 0x03ff, true),
+            (4, OCRA, false),
+            (5, 0x00ff, false),
+            (6, 0x01ff, false),
+            (7, 0x03ff, false),
+            (8, ICR, true),
+            (9, OCRA, true),
+            (10, ICR, true),
+            (11, OCRA, true),
+            (12, ICR, false),
+            (14, ICR, false),
+            (15, OCRA, false),
+        ];
+
+        for (wgm, top, dual_slope) in modes {
+            let (mut mcu, regs) = setup(wgm);
+            write_u16(&mut mcu, regs.ocra, OCRA);
+            write_u16(&mut mcu, regs.icr, ICR);
+            write_u16(&mut mcu, regs.tcnt, top - 1);
+
+            mcu.tick().unwrap();
+            assert_eq!(peek_u16(&mcu, regs.tcnt), top, "mode {wgm}");
+
+            mcu.tick().unwrap();
+            let after_top = if dual_slope { top - 1 } else { 0 };
+            assert_eq!(peek_u16(&mcu, regs.tcnt), after_top, "mode {wgm}");
+        }
+    }
+}
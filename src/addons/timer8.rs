@@ -0,0 +1,488 @@
+use crate::addons;
+use crate::chips::atmega328p;
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// Overflow flag.
+pub const TOV: u8 = 1 << 0;
+/// Output compare A match flag.
+pub const OCFA: u8 = 1 << 1;
+/// Output compare B match flag.
+pub const OCFB: u8 = 1 << 2;
+
+/// The data space addresses of an 8-bit timer's registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub tccra: u16,
+    pub tccrb: u16,
+    pub tcnt: u16,
+    pub ocra: u16,
+    pub ocrb: u16,
+    pub timsk: u16,
+    pub tifr: u16,
+}
+
+/// The interrupt vector numbers of an 8-bit timer.
+#[derive(Copy, Clone, Debug)]
+pub struct Vectors {
+    pub compa: u8,
+    pub compb: u8,
+    pub ovf: u8,
+}
+
+/// The `AS2` bit in `ASSR`.
+pub const AS2: u8 = 1 << 5;
+
+/// An asynchronous clock source, such as a watch crystal on `TOSC1`/`TOSC2`.
+#[derive(Copy, Clone, Debug)]
+pub struct AsyncClock {
+    /// The data space address of `ASSR`.
+    pub assr: u16,
+    /// The number of CPU ticks in a single second (ticks/second)
+    pub cpu_frequency: u64,
+    /// The frequency of the asynchronous clock (ticks/second)
+    pub crystal_frequency: u64,
+}
+
+/// The clock sources that `CSn2:0` selects between.
+#[derive(Copy, Clone, Debug)]
+pub enum ClockSource {
+    /// `clk/1`, `clk/8`, `clk/64`, `clk/256`, `clk/1024`, and falling or
+    /// rising edges on the external clock pin `Tn` as `(PINx IO address, bit)`.
+    ExternalPin((u8, u8)),
+    /// `clk/1`, `clk/8`, `clk/32`, `clk/64`, `clk/128`, `clk/256` and
+    /// `clk/1024`, where `clk` is the asynchronous clock when enabled by `AS2`.
+    Asynchronous(Option<AsyncClock>),
+}
+
+/// An 8-bit Timer/Counter (e.g. `Timer/Counter0`).
+///
+/// The timer is clocked from the core's cycle counter through the prescaler
+/// selected by `CSn2:0`, from edges on its external clock pin, or from an
+/// asynchronous clock. The normal,
+/// CTC, fast PWM and phase correct PWM waveform generation modes are
+/// supported, including driving the `OCnA`/`OCnB` output compare pins.
+pub struct Timer8 {
+    registers: Registers,
+    vectors: Vectors,
+    /// The `OCnA` and `OCnB` pins as `(PINx IO address, bit)`.
+    output_pins: [(u8, u8); 2],
+    clock_source: ClockSource,
+
+    /// The cycle count at the last tick.
+    last_cycle: u64,
+    /// Clocks accumulated towards the next prescaled timer clock.
+    prescaler: u64,
+    /// CPU cycles not yet converted into asynchronous clocks, scaled by the
+    /// asynchronous clock frequency.
+    async_remainder: u64,
+    /// The last sampled level of the external clock pin.
+    clock_level: bool,
+    /// Whether a phase correct PWM mode is counting down.
+    counting_down: bool,
+    /// The levels of the output compare pins.
+    output_levels: [bool; 2],
+    /// Flags which have requested an interrupt that is still pending.
+    raised: u8,
+    /// `TIFRn` as it was written back after the last instruction.
+    tifr: u8,
+}
+
+impl Timer8 {
+    pub fn new(
+        registers: Registers,
+        vectors: Vectors,
+        output_pins: [(u8, u8); 2],
+        clock_source: ClockSource,
+    ) -> Self {
+        Timer8 {
+            registers,
+            vectors,
+            output_pins,
+            clock_source,
+            last_cycle: 0,
+            prescaler: 0,
+            async_remainder: 0,
+            clock_level: false,
+            counting_down: false,
+            output_levels: [false; 2],
+            raised: 0,
+            tifr: 0,
+        }
+    }
+
+    /// `Timer/Counter0` with `OC0A` on `PD6`, `OC0B` on `PD5` and `T0` on `PD4`.
+    pub fn atmega328p_timer0() -> Self {
+        Self::new(
+            Registers {
+                tccra: 0x44,
+                tccrb: 0x45,
+                tcnt: 0x46,
+                ocra: 0x47,
+                ocrb: 0x48,
+                timsk: 0x6e,
+                tifr: 0x35,
+            },
+            Vectors {
+                compa: 14,
+                compb: 15,
+                ovf: 16,
+            },
+            [(atmega328p::PIND, 6), (atmega328p::PIND, 5)],
+            ClockSource::ExternalPin((atmega328p::PIND, 4)),
+        )
+    }
+
+    /// `Timer/Counter2` with `OC2A` on `PB3` and `OC2B` on `PD3`.
+    pub fn atmega328p_timer2() -> Self {
+        Self::timer2(None)
+    }
+
+    /// `Timer/Counter2` with a 32.768 kHz watch crystal that is used when
+    /// `AS2` is set in `ASSR`.
+    pub fn atmega328p_timer2_async(cpu_frequency: u64) -> Self {
+        Self::timer2(Some(AsyncClock {
+            assr: 0xb6,
+            cpu_frequency,
+            crystal_frequency: 32_768,
+        }))
+    }
+
+    fn timer2(async_clock: Option<AsyncClock>) -> Self {
+        Self::new(
+            Registers {
+                tccra: 0xb0,
+                tccrb: 0xb1,
+                tcnt: 0xb2,
+                ocra: 0xb3,
+                ocrb: 0xb4,
+                timsk: 0x70,
+                tifr: 0x37,
+            },
+            Vectors {
+                compa: 7,
+                compb: 8,
+                ovf: 9,
+            },
+            [(atmega328p::PINB, 3), (atmega328p::PIND, 3)],
+            ClockSource::Asynchronous(async_clock),
+        )
+    }
+
+    /// Calculates how many timer clocks have elapsed since the last tick.
+    fn timer_clocks(&mut self, core: &Core, clock_select: u8) -> Result<u64, Error> {
+        let mut elapsed = core.cycle_count.saturating_sub(self.last_cycle);
+
+        let divisor = match (self.clock_source, clock_select) {
+            (_, 0) => return Ok(0),
+            (ClockSource::ExternalPin(pin), cs) => match cs {
+                1 => 1,
+                2 => 8,
+                3 => 64,
+                4 => 256,
+                5 => 1024,
+                _ => return self.external_clocks(core, pin, cs == 7),
+            },
+            (ClockSource::Asynchronous(async_clock), cs) => {
+                if let Some(clock) = async_clock {
+                    if core.read_data(clock.assr)? & AS2 != 0 {
+                        elapsed = self.async_clocks(clock, elapsed);
+                    }
+                }
+
+                match cs {
+                    1 => 1,
+                    2 => 8,
+                    3 => 32,
+                    4 => 64,
+                    5 => 128,
+                    6 => 256,
+                    _ => 1024,
+                }
+            }
+        };
+
+        self.prescaler += elapsed;
+        let clocks = self.prescaler / divisor;
+        self.prescaler %= divisor;
+        Ok(clocks)
+    }
+
+    /// Converts elapsed CPU cycles into asynchronous clocks.
+    fn async_clocks(&mut self, clock: AsyncClock, cycles: u64) -> u64 {
+        self.async_remainder += cycles * clock.crystal_frequency;
+        let clocks = self.async_remainder / clock.cpu_frequency;
+        self.async_remainder %= clock.cpu_frequency;
+        clocks
+    }
+
+    /// Counts an edge on the external clock pin.
+    fn external_clocks(
+        &mut self,
+        core: &Core,
+        (register, bit): (u8, u8),
+        rising: bool,
+    ) -> Result<u64, Error> {
+        let level = core.read_data(SRAM_IO_OFFSET + register as u16)? & (1 << bit) != 0;
+        let previous = std::mem::replace(&mut self.clock_level, level);
+
+        Ok((level != previous && level == rising) as u64)
+    }
+
+    /// The new level of output compare pin `i` (`0` for A, `1` for B) on a
+    /// compare match, or `None` if it is unaffected.
+    fn compare_match_output(&self, wgm: u8, com: u8, i: usize) -> Option<bool> {
+        let toggled = !self.output_levels[i];
+
+        match wgm {
+            // Phase correct PWM: clear when up-counting, set when down-counting.
+            1 | 5 => match com {
+                0b01 if i == 0 && wgm == 5 => Some(toggled),
+                0b10 => Some(self.counting_down),
+                0b11 => Some(!self.counting_down),
+                _ => None,
+            },
+            // Fast PWM: clear on match, set at BOTTOM.
+            3 | 7 => match com {
+                0b01 if i == 0 && wgm == 7 => Some(toggled),
+                0b10 => Some(false),
+                0b11 => Some(true),
+                _ => None,
+            },
+            // Normal and CTC.
+            _ => match com {
+                0b01 => Some(toggled),
+                0b10 => Some(false),
+                0b11 => Some(true),
+                _ => None,
+            },
+        }
+    }
+
+    /// Advances the counter by a single timer clock.
+    fn step(&mut self, state: &mut State) -> [Option<bool>; 2] {
+        let mut outputs = [None; 2];
+        let top = state.top();
+
+        match state.wgm {
+            // Phase correct PWM.
+            1 | 5 => {
+                if self.counting_down {
+                    state.tcnt = state.tcnt.saturating_sub(1);
+                    if state.tcnt == 0 {
+                        self.counting_down = false;
+                        state.tifr |= TOV;
+                    }
+                } else if state.tcnt >= top {
+                    self.counting_down = true;
+                    state.tcnt = state.tcnt.saturating_sub(1);
+                } else {
+                    state.tcnt += 1;
+                }
+            }
+            // Fast PWM.
+            3 | 7 => {
+                if state.tcnt >= top {
+                    state.tcnt = 0;
+                    state.tifr |= TOV;
+
+                    for (i, output) in outputs.iter_mut().enumerate() {
+                        match state.com(i) {
+                            0b10 => *output = Some(true),
+                            0b11 => *output = Some(false),
+                            _ => (),
+                        }
+                    }
+                } else {
+                    state.tcnt += 1;
+                }
+            }
+            // Normal and CTC.
+            _ => {
+                if state.tcnt == top {
+                    state.tcnt = 0;
+                    if top == 0xff {
+                        state.tifr |= TOV;
+                    }
+                } else {
+                    state.tcnt = state.tcnt.wrapping_add(1);
+                }
+            }
+        }
+
+        for (i, (ocr, flag)) in [(state.ocra, OCFA), (state.ocrb, OCFB)].iter().enumerate() {
+            if state.tcnt != *ocr {
+                continue;
+            }
+            state.tifr |= flag;
+
+            outputs[i] = self.compare_match_output(state.wgm, state.com(i), i);
+        }
+
+        for (i, output) in outputs.iter().enumerate() {
+            if let Some(level) = output {
+                self.output_levels[i] = *level;
+            }
+        }
+        outputs
+    }
+}
+
+/// A snapshot of the timer's registers.
+struct State {
+    tccra: u8,
+    wgm: u8,
+    tcnt: u8,
+    ocra: u8,
+    ocrb: u8,
+    tifr: u8,
+}
+
+impl State {
+    /// The value the counter counts up to.
+    fn top(&self) -> u8 {
+        match self.wgm {
+            2 | 5 | 7 => self.ocra,
+            _ => 0xff,
+        }
+    }
+
+    /// The compare output mode for output `i` (`0` for A, `1` for B).
+    fn com(&self, i: usize) -> u8 {
+        (self.tccra >> (6 - i * 2)) & 0b11
+    }
+}
+
+impl Addon for Timer8 {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let tccra = core.read_data(regs.tccra)?;
+        let tccrb = core.read_data(regs.tccrb)?;
+
+        let mut state = State {
+            tccra,
+            wgm: ((tccrb >> 1) & 0b100) | (tccra & 0b11),
+            tcnt: core.read_data(regs.tcnt)?,
+            ocra: core.read_data(regs.ocra)?,
+            ocrb: core.read_data(regs.ocrb)?,
+            tifr: addons::clear_written_flags(core, regs.tifr, self.tifr, TOV | OCFA | OCFB)?,
+        };
+
+        let clocks = self.timer_clocks(core, tccrb & 0b111)?;
+        self.last_cycle = core.cycle_count;
+
+        // Pins are given back to `PORTx` when their output is disconnected.
+        for (i, &(register, bit)) in self.output_pins.iter().enumerate() {
+            if (tccra >> (6 - 2 * i)) & 0b11 == 0 {
+                core.override_pin(register, bit, None)?;
+            }
+        }
+
+        for _ in 0..clocks {
+            let outputs = self.step(&mut state);
+
+            for (i, output) in outputs.iter().enumerate() {
+                if let Some(level) = output {
+                    let (register, bit) = self.output_pins[i];
+                    core.override_pin(register, bit, Some(*level))?;
+                }
+            }
+        }
+
+        let timsk = core.read_data(regs.timsk)?;
+        let vectors = [
+            (TOV, self.vectors.ovf),
+            (OCFA, self.vectors.compa),
+            (OCFB, self.vectors.compb),
+        ];
+
+        for (flag, vector) in vectors.iter() {
+            if self.raised & flag != 0
+                && !addons::still_raised(core, *vector, &mut state.tifr, *flag)?
+            {
+                self.raised &= !flag;
+            }
+
+            if state.tifr & flag != 0 && timsk & flag != 0 && self.raised & flag == 0 {
+                core.raise_interrupt(*vector)?;
+                self.raised |= flag;
+            }
+        }
+
+        core.write_data(regs.tcnt, state.tcnt)?;
+        self.tifr = state.tifr;
+        core.write_data(regs.tifr, state.tifr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.prescaler = 0;
+        self.async_remainder = 0;
+        self.counting_down = false;
+        self.output_levels = [false; 2];
+        self.raised = 0;
+        self.tifr = 0;
+        Ok(())
+    }
+
+    fn on_rewind(&mut self, core: &mut Core) -> Result<(), Error> {
+        self.last_cycle = core.cycle_count;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.u64(self.last_cycle);
+        state.u64(self.prescaler);
+        state.u64(self.async_remainder);
+        state.bool(self.clock_level);
+        state.bool(self.counting_down);
+        state.bool(self.output_levels[0]);
+        state.bool(self.output_levels[1]);
+        state.u8(self.raised);
+        state.u8(self.tifr);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        self.last_cycle = state.u64()?;
+        self.prescaler = state.u64()?;
+        self.async_remainder = state.u64()?;
+        self.clock_level = state.bool()?;
+        self.counting_down = state.bool()?;
+        self.output_levels = [state.bool()?, state.bool()?];
+        self.raised = state.u8()?;
+        self.tifr = state.u8()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Timer8;
+    use crate::chips::atmega328p;
+    use crate::{Core, Mcu};
+
+    #[test]
+    fn counts_on_after_stepping_back() {
+        let timer = Timer8::atmega328p_timer0();
+        let tcnt = timer.registers.tcnt;
+        let tccrb = timer.registers.tccrb;
+
+        let mut core = Core::new::<atmega328p::Chip>();
+        core.load_program_space([0; 16].into_iter());
+        let mut mcu = Mcu::new(core);
+        mcu.attach(Box::new(timer));
+        // Count every CPU clock.
+        mcu.core.write_data(tccrb, 1).unwrap();
+        mcu.set_reverse_history(16);
+
+        for _ in 0..4 {
+            mcu.tick().unwrap();
+        }
+        assert_eq!(mcu.step_back(3), 3);
+        let count = mcu.core.peek_data(tcnt).unwrap();
+
+        mcu.tick().unwrap();
+        assert_eq!(mcu.core.peek_data(tcnt).unwrap(), count + 1);
+    }
+}
@@ -0,0 +1,375 @@
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// `TWCR` bits.
+pub mod twcr {
+    pub const TWINT: u8 = 1 << 7;
+    pub const TWEA: u8 = 1 << 6;
+    pub const TWSTA: u8 = 1 << 5;
+    pub const TWSTO: u8 = 1 << 4;
+    pub const TWWC: u8 = 1 << 3;
+    pub const TWEN: u8 = 1 << 2;
+    pub const TWIE: u8 = 1 << 0;
+}
+
+/// Master mode status codes reported in `TWSR`.
+pub mod status {
+    pub const START: u8 = 0x08;
+    pub const REPEATED_START: u8 = 0x10;
+    pub const SLA_W_ACK: u8 = 0x18;
+    pub const SLA_W_NACK: u8 = 0x20;
+    pub const DATA_W_ACK: u8 = 0x28;
+    pub const DATA_W_NACK: u8 = 0x30;
+    pub const SLA_R_ACK: u8 = 0x40;
+    pub const SLA_R_NACK: u8 = 0x48;
+    pub const DATA_R_ACK: u8 = 0x50;
+    pub const DATA_R_NACK: u8 = 0x58;
+    pub const NO_INFO: u8 = 0xf8;
+}
+
+/// The mask of the prescaler bits in `TWSR`.
+const TWPS_MASK: u8 = 0b11;
+
+/// A virtual device on the I2C bus.
+pub trait I2cDevice {
+    /// Called when the device is addressed after a `START`.
+    ///
+    /// `read` is whether the master wants to read from the device. Returns
+    /// whether the device acknowledges.
+    fn start(&mut self, read: bool) -> bool {
+        let _ = read;
+        true
+    }
+
+    /// Receives a byte written by the master. Returns whether the device
+    /// acknowledges.
+    fn write(&mut self, byte: u8) -> bool;
+
+    /// Sends a byte to the master.
+    fn read(&mut self) -> u8;
+
+    /// Called on a `STOP`, or a repeated `START`, ending a transaction with
+    /// the device.
+    fn stop(&mut self) {}
+}
+
+/// The devices on an I2C bus, by 7-bit address.
+#[derive(Default)]
+pub struct I2cBus {
+    devices: Vec<(u8, Box<dyn I2cDevice>)>,
+}
+
+impl I2cBus {
+    pub fn new() -> Self {
+        I2cBus::default()
+    }
+
+    /// Attaches a device at a 7-bit address, replacing any device already
+    /// there.
+    pub fn attach<D>(&mut self, address: u8, device: D)
+    where
+        D: I2cDevice + 'static,
+    {
+        let address = address & 0x7f;
+        self.devices.retain(|(a, _)| *a != address);
+        self.devices.push((address, Box::new(device)));
+    }
+
+    /// Gets the device at a 7-bit address.
+    pub fn device_mut(&mut self, address: u8) -> Option<&mut (dyn I2cDevice + 'static)> {
+        self.devices
+            .iter_mut()
+            .find(|(a, _)| *a == address)
+            .map(|(_, device)| device.as_mut())
+    }
+}
+
+/// The data space addresses of the TWI registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub twbr: u16,
+    pub twsr: u16,
+    pub twar: u16,
+    pub twdr: u16,
+    pub twcr: u16,
+}
+
+/// What the bus is doing between operations.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum State {
+    Idle,
+    /// A `START` was sent, so the next byte is an address.
+    Started,
+    Transmitting(u8),
+    Receiving(u8),
+}
+
+/// An operation started by clearing `TWINT`.
+#[derive(Copy, Clone, Debug)]
+enum Operation {
+    Start,
+    Stop,
+    Byte,
+}
+
+/// The TWI unit in master mode.
+///
+/// Operations are started by writing a one to `TWINT`, take the time of
+/// their bits on the bus at the rate set by `TWBR` and the `TWSR`
+/// prescaler, and report the status codes from the datasheet.
+pub struct Twi {
+    registers: Registers,
+    vector: u8,
+    bus: I2cBus,
+
+    state: State,
+    /// The operation in progress and the cycle at which it completes.
+    operation: Option<(Operation, u64)>,
+    /// Whether the interrupt has been requested.
+    raised: bool,
+}
+
+impl Twi {
+    pub fn new(registers: Registers, vector: u8) -> Self {
+        Twi {
+            registers,
+            vector,
+            bus: I2cBus::new(),
+            state: State::Idle,
+            operation: None,
+            raised: false,
+        }
+    }
+
+    pub fn atmega328p() -> Self {
+        Self::new(
+            Registers {
+                twbr: 0xb8,
+                twsr: 0xb9,
+                twar: 0xba,
+                twdr: 0xbb,
+                twcr: 0xbc,
+            },
+            24,
+        )
+    }
+
+    pub fn bus(&self) -> &I2cBus {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut I2cBus {
+        &mut self.bus
+    }
+
+    /// Attaches a device to the bus at a 7-bit address.
+    pub fn attach<D>(&mut self, address: u8, device: D)
+    where
+        D: I2cDevice + 'static,
+    {
+        self.bus.attach(address, device)
+    }
+
+    /// Gets the number of CPU cycles in one SCL period.
+    fn bit_cycles(twbr: u8, twsr: u8) -> u64 {
+        let prescaler = 1 << (2 * (twsr & TWPS_MASK));
+        16 + 2 * twbr as u64 * prescaler
+    }
+
+    /// Ends the transaction with the addressed device, if any.
+    fn end_transaction(&mut self) {
+        if let State::Transmitting(address) | State::Receiving(address) = self.state {
+            if let Some(device) = self.bus.device_mut(address) {
+                device.stop();
+            }
+        }
+    }
+
+    /// Completes an operation, returning the new status.
+    fn complete(&mut self, core: &mut Core, operation: Operation, twcr: u8) -> Result<u8, Error> {
+        let regs = self.registers;
+
+        let status = match operation {
+            Operation::Start => {
+                let repeated = self.state != State::Idle;
+                self.end_transaction();
+                self.state = State::Started;
+                if repeated {
+                    status::REPEATED_START
+                } else {
+                    status::START
+                }
+            }
+            Operation::Stop => {
+                self.end_transaction();
+                self.state = State::Idle;
+                status::NO_INFO
+            }
+            Operation::Byte => match self.state {
+                State::Started => {
+                    let sla = core.read_data(regs.twdr)?;
+                    let (address, read) = (sla >> 1, sla & 1 != 0);
+                    let ack = match self.bus.device_mut(address) {
+                        Some(device) => device.start(read),
+                        None => false,
+                    };
+
+                    self.state = if read {
+                        State::Receiving(address)
+                    } else {
+                        State::Transmitting(address)
+                    };
+                    match (read, ack) {
+                        (false, true) => status::SLA_W_ACK,
+                        (false, false) => status::SLA_W_NACK,
+                        (true, true) => status::SLA_R_ACK,
+                        (true, false) => status::SLA_R_NACK,
+                    }
+                }
+                State::Transmitting(address) => {
+                    let byte = core.read_data(regs.twdr)?;
+                    let ack = match self.bus.device_mut(address) {
+                        Some(device) => device.write(byte),
+                        None => false,
+                    };
+                    if ack {
+                        status::DATA_W_ACK
+                    } else {
+                        status::DATA_W_NACK
+                    }
+                }
+                State::Receiving(address) => {
+                    // Nothing driving `SDA` reads as ones.
+                    let byte = match self.bus.device_mut(address) {
+                        Some(device) => device.read(),
+                        None => 0xff,
+                    };
+                    core.write_data(regs.twdr, byte)?;
+                    if twcr & twcr::TWEA != 0 {
+                        status::DATA_R_ACK
+                    } else {
+                        status::DATA_R_NACK
+                    }
+                }
+                State::Idle => status::NO_INFO,
+            },
+        };
+        Ok(status)
+    }
+}
+
+impl Addon for Twi {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let now = core.cycle_count;
+        let mut twcr = core.read_data(regs.twcr)?;
+        let twsr = core.read_data(regs.twsr)?;
+
+        if twcr & twcr::TWEN == 0 {
+            self.end_transaction();
+            self.state = State::Idle;
+            self.operation = None;
+            return Ok(());
+        }
+
+        // Writing `TWDR` is only allowed while `TWINT` is set.
+        if core.was_written(regs.twdr) && self.operation.is_some() {
+            twcr |= twcr::TWWC;
+        }
+
+        // Writing a one to `TWINT` clears it and starts the next operation.
+        if core.was_written(regs.twcr) && twcr & twcr::TWINT != 0 {
+            twcr &= !(twcr::TWINT | twcr::TWWC);
+
+            let bit_cycles = Self::bit_cycles(core.read_data(regs.twbr)?, twsr);
+            let (operation, bits) = if twcr & twcr::TWSTA != 0 {
+                (Operation::Start, 1)
+            } else if twcr & twcr::TWSTO != 0 {
+                (Operation::Stop, 1)
+            } else {
+                // Eight data bits and the acknowledge bit.
+                (Operation::Byte, 9)
+            };
+            self.operation = Some((operation, now + bits * bit_cycles));
+        }
+
+        if let Some((operation, done_at)) = self.operation {
+            if now >= done_at {
+                self.operation = None;
+                let status = self.complete(core, operation, twcr)?;
+                core.write_data(regs.twsr, status | (twsr & TWPS_MASK))?;
+
+                match operation {
+                    // A stop does not set `TWINT`, and clears `TWSTO`.
+                    Operation::Stop => twcr &= !twcr::TWSTO,
+                    _ => twcr |= twcr::TWINT,
+                }
+            }
+        }
+
+        // `TWINT` is not cleared by servicing the vector, so the interrupt
+        // is requested again for as long as it stays set.
+        if self.raised && !core.interrupts().is_pending(self.vector) {
+            self.raised = false;
+        }
+        if twcr & twcr::TWINT != 0 && twcr & twcr::TWIE != 0 && !self.raised {
+            core.raise_interrupt(self.vector)?;
+            self.raised = true;
+        }
+
+        core.write_data(regs.twcr, twcr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.state = State::Idle;
+        self.operation = None;
+        self.raised = false;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        let (bus, address) = match self.state {
+            State::Idle => (0, 0),
+            State::Started => (1, 0),
+            State::Transmitting(address) => (2, address),
+            State::Receiving(address) => (3, address),
+        };
+        state.u8(bus);
+        state.u8(address);
+
+        let (operation, done_at) = match self.operation {
+            None => (0, 0),
+            Some((Operation::Start, done_at)) => (1, done_at),
+            Some((Operation::Stop, done_at)) => (2, done_at),
+            Some((Operation::Byte, done_at)) => (3, done_at),
+        };
+        state.u8(operation);
+        state.u64(done_at);
+        state.bool(self.raised);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        let bus = state.u8()?;
+        let address = state.u8()?;
+        self.state = match bus {
+            0 => State::Idle,
+            1 => State::Started,
+            2 => State::Transmitting(address),
+            3 => State::Receiving(address),
+            _ => return Err(Error::InvalidState("invalid bus state")),
+        };
+
+        let operation = state.u8()?;
+        let done_at = state.u64()?;
+        self.operation = match operation {
+            0 => None,
+            1 => Some((Operation::Start, done_at)),
+            2 => Some((Operation::Stop, done_at)),
+            3 => Some((Operation::Byte, done_at)),
+            _ => return Err(Error::InvalidState("invalid operation")),
+        };
+        self.raised = state.bool()?;
+        Ok(())
+    }
+}
@@ -1,53 +1,574 @@
+use crate::reset::ResetCause;
+use crate::state;
 use crate::Addon;
 use crate::Core;
-use crate::{io, Error, Instruction};
+use crate::{Error, Instruction};
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::rc::Rc;
+use std::sync::mpsc::Sender;
 
+/// `UCSRnA` bits.
+pub mod ucsra {
+    pub const RXC: u8 = 1 << 7;
+    pub const TXC: u8 = 1 << 6;
+    pub const UDRE: u8 = 1 << 5;
+    pub const FE: u8 = 1 << 4;
+    pub const DOR: u8 = 1 << 3;
+    pub const UPE: u8 = 1 << 2;
+    pub const U2X: u8 = 1 << 1;
+    pub const MPCM: u8 = 1 << 0;
+}
+
+/// `UCSRnB` bits.
+pub mod ucsrb {
+    pub const RXCIE: u8 = 1 << 7;
+    pub const TXCIE: u8 = 1 << 6;
+    pub const UDRIE: u8 = 1 << 5;
+    pub const RXEN: u8 = 1 << 4;
+    pub const TXEN: u8 = 1 << 3;
+    pub const UCSZ2: u8 = 1 << 2;
+    pub const RXB8: u8 = 1 << 1;
+    pub const TXB8: u8 = 1 << 0;
+}
+
+/// `UCSRnC` bits.
+pub mod ucsrc {
+    pub const UMSEL_MASK: u8 = 0b1100_0000;
+    pub const UPM_MASK: u8 = 0b0011_0000;
+    pub const USBS: u8 = 1 << 3;
+    pub const UCSZ_MASK: u8 = 0b0000_0110;
+    pub const UCPOL: u8 = 1 << 0;
+}
+
+/// The depth of the receive buffer.
+const RX_FIFO_DEPTH: usize = 2;
+
+/// The data space addresses of the USART registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub ucsra: u16,
+    pub ucsrb: u16,
+    pub ucsrc: u16,
+    pub ubrrl: u16,
+    pub ubrrh: u16,
+    pub udr: u16,
+}
+
+/// The USART interrupt vectors.
+#[derive(Copy, Clone, Debug)]
+pub struct Vectors {
+    pub rx: u8,
+    pub udre: u8,
+    pub tx: u8,
+}
+
+/// Where bytes transmitted by the firmware go.
+pub enum Sink {
+    /// Print to stdout.
+    Stdout,
+    /// Write to any writer.
+    Writer(Box<dyn Write>),
+    /// Send over a channel.
+    Channel(Sender<u8>),
+    /// Call a function with each byte.
+    Callback(Box<dyn FnMut(u8)>),
+    /// Throw away.
+    Discard,
+}
+
+impl Sink {
+    pub fn writer<W>(writer: W) -> Self
+    where
+        W: Write + 'static,
+    {
+        Sink::Writer(Box::new(writer))
+    }
+
+    pub fn callback<F>(callback: F) -> Self
+    where
+        F: FnMut(u8) + 'static,
+    {
+        Sink::Callback(Box::new(callback))
+    }
+
+    /// Passes a byte on.
+    ///
+    /// A channel whose receiver has hung up is not an error, the byte is
+    /// just dropped.
+    pub(crate) fn send(&mut self, byte: u8) -> io::Result<()> {
+        match *self {
+            Sink::Stdout => {
+                let mut stdout = io::stdout();
+                stdout.write_all(&[byte])?;
+                stdout.flush()
+            }
+            Sink::Writer(ref mut writer) => {
+                writer.write_all(&[byte])?;
+                writer.flush()
+            }
+            Sink::Channel(ref sender) => {
+                let _ = sender.send(byte);
+                Ok(())
+            }
+            Sink::Callback(ref mut callback) => {
+                callback(byte);
+                Ok(())
+            }
+            Sink::Discard => Ok(()),
+        }
+    }
+}
+
+impl From<Sender<u8>> for Sink {
+    fn from(sender: Sender<u8>) -> Self {
+        Sink::Channel(sender)
+    }
+}
+
+/// A handle for sending bytes to the firmware through a `Uart`.
+///
+/// Handles stay usable after the `Uart` has been attached to an `Mcu`.
+#[derive(Clone)]
+pub struct Handle {
+    rx_queue: Rc<RefCell<VecDeque<u8>>>,
+}
+
+impl Handle {
+    /// Creates a handle with nothing queued, for other peripherals that
+    /// carry a serial stream.
+    pub(crate) fn new() -> Self {
+        Handle {
+            rx_queue: Rc::new(RefCell::new(VecDeque::new())),
+        }
+    }
+
+    /// Takes up to `count` of the queued bytes.
+    pub(crate) fn take(&self, count: usize) -> Vec<u8> {
+        let mut queue = self.rx_queue.borrow_mut();
+        let count = count.min(queue.len());
+        queue.drain(..count).collect()
+    }
+
+    /// Queues bytes for the firmware to receive.
+    ///
+    /// Bytes are shifted in one frame at a time at the configured baud
+    /// rate while the receiver is enabled.
+    pub fn send_to_target(&self, bytes: &[u8]) {
+        self.rx_queue.borrow_mut().extend(bytes);
+    }
+
+    /// Gets the number of bytes queued but not yet received.
+    pub fn pending(&self) -> usize {
+        self.rx_queue.borrow().len()
+    }
+
+    /// Saves the queued bytes, for the peripherals carrying the stream.
+    pub(crate) fn save_state(&self, state: &mut state::Writer) {
+        let queue: Vec<u8> = self.rx_queue.borrow().iter().copied().collect();
+        state.bytes(&queue);
+    }
+
+    /// Replaces the queued bytes with the ones saved by `save_state`.
+    pub(crate) fn load_state(&self, state: &mut state::Reader) -> Result<(), Error> {
+        *self.rx_queue.borrow_mut() = state.bytes()?.iter().copied().collect();
+        Ok(())
+    }
+}
+
+/// A frame being shifted in or out, and the cycle at which it is done.
+#[derive(Copy, Clone, Debug)]
+struct Frame {
+    byte: u8,
+    done_at: u64,
+}
+
+impl Frame {
+    fn save(frame: Option<Frame>, state: &mut state::Writer) {
+        state.bool(frame.is_some());
+        state.u8(frame.map_or(0, |f| f.byte));
+        state.u64(frame.map_or(0, |f| f.done_at));
+    }
+
+    fn load(state: &mut state::Reader) -> Result<Option<Frame>, Error> {
+        let shifting = state.bool()?;
+        let frame = Frame {
+            byte: state.u8()?,
+            done_at: state.u64()?,
+        };
+        Ok(shifting.then_some(frame))
+    }
+}
+
+/// The USART in asynchronous mode.
+///
+/// Frames are shifted at the baud rate set by `UBRRn` and `U2Xn`, and take
+/// the time of their start, data, parity and stop bits as configured in
+/// `UCSRnB` and `UCSRnC`. Bytes transmitted by the firmware are passed to
+/// the sink, which is stdout unless another one is given to `with_sink`,
+/// and bytes given to `send_to_target` are received by the firmware. The
+/// ninth data bit is not modeled.
 pub struct Uart {
-    /// The baud rate (bits/second)
-    pub baud: u64,
-    /// The number of CPU ticks in a single second (ticks/second)
-    pub cpu_frequency: u64,
-    /// Number of ticks between each bit.
-    ticks_between_bits: u64,
+    registers: Registers,
+    vectors: Vectors,
+    sink: Sink,
 
-    ticks_until_next_bit: u64,
+    /// The transmit buffer (`UDRn` as written).
+    tx_buffer: Option<u8>,
+    /// The frame in the transmit shift register.
+    tx_shift: Option<Frame>,
+    /// Whether the transmit complete flag is set.
+    tx_complete: bool,
 
-    _tx: io::Port,
-    _rx: io::Port,
+    /// Bytes waiting to be sent to the firmware.
+    rx_queue: Rc<RefCell<VecDeque<u8>>>,
+    /// The frame in the receive shift register.
+    rx_shift: Option<Frame>,
+    /// The receive buffer (`UDRn` as read).
+    rx_fifo: VecDeque<u8>,
+    /// Whether a received frame was lost because the buffer was full.
+    data_overrun: bool,
 
-    _processed_bits: Vec<u8>,
+    /// Whether the `RX`, `UDRE` and `TX` interrupts have been requested.
+    raised: [bool; 3],
 }
 
 impl Uart {
-    pub fn new(cpu_frequency: u64, baud: u64, tx: io::Port, rx: io::Port) -> Self {
-        let ticks_between_bits = cpu_frequency / baud;
-
+    pub fn new(registers: Registers, vectors: Vectors) -> Self {
         Uart {
-            cpu_frequency,
-            baud,
-            _tx: tx,
-            _rx: rx,
+            registers,
+            vectors,
+            sink: Sink::Stdout,
+            tx_buffer: None,
+            tx_shift: None,
+            tx_complete: false,
+            rx_queue: Rc::new(RefCell::new(VecDeque::new())),
+            rx_shift: None,
+            rx_fifo: VecDeque::new(),
+            data_overrun: false,
+            raised: [false; 3],
+        }
+    }
+
+    /// `USART0`.
+    pub fn atmega328p() -> Self {
+        Self::new(
+            Registers {
+                ucsra: 0xc0,
+                ucsrb: 0xc1,
+                ucsrc: 0xc2,
+                ubrrl: 0xc4,
+                ubrrh: 0xc5,
+                udr: 0xc6,
+            },
+            Vectors {
+                rx: 18,
+                udre: 19,
+                tx: 20,
+            },
+        )
+    }
 
-            ticks_between_bits, // TODO: set this variable
-            ticks_until_next_bit: ticks_between_bits,
+    /// Sets where bytes transmitted by the firmware go.
+    pub fn with_sink<S>(mut self, sink: S) -> Self
+    where
+        S: Into<Sink>,
+    {
+        self.sink = sink.into();
+        self
+    }
 
-            _processed_bits: Vec::new(),
+    /// Queues bytes for the firmware to receive.
+    pub fn send_to_target(&self, bytes: &[u8]) {
+        self.handle().send_to_target(bytes)
+    }
+
+    /// Gets a handle for sending bytes to the firmware.
+    pub fn handle(&self) -> Handle {
+        Handle {
+            rx_queue: self.rx_queue.clone(),
         }
     }
 
-    fn process_bit(&mut self, _core: &mut Core) {
-        println!("tick");
+    /// Gets the number of CPU cycles in one frame.
+    fn frame_cycles(&self, core: &Core, ucsra: u8, ucsrb: u8) -> Result<u64, Error> {
+        let regs = self.registers;
+        let ucsrc = core.read_data(regs.ucsrc)?;
+        let ubrr = u16::from_le_bytes([
+            core.read_data(regs.ubrrl)?,
+            core.read_data(regs.ubrrh)? & 0x0f,
+        ]) as u64;
+
+        let bit_cycles = if ucsra & ucsra::U2X != 0 {
+            8 * (ubrr + 1)
+        } else {
+            16 * (ubrr + 1)
+        };
+
+        let data_bits = match (ucsrb & ucsrb::UCSZ2 != 0, (ucsrc & ucsrc::UCSZ_MASK) >> 1) {
+            (true, _) => 9,
+            (false, size) => 5 + size as u64,
+        };
+        let parity_bits = if ucsrc & ucsrc::UPM_MASK != 0 { 1 } else { 0 };
+        let stop_bits = if ucsrc & ucsrc::USBS != 0 { 2 } else { 1 };
+
+        Ok((1 + data_bits + parity_bits + stop_bits) * bit_cycles)
+    }
+
+    /// Requests or withdraws one of the interrupts.
+    fn update_interrupt(
+        &mut self,
+        core: &mut Core,
+        index: usize,
+        vector: u8,
+        requested: bool,
+    ) -> Result<(), Error> {
+        if self.raised[index] && !core.interrupts().is_pending(vector) {
+            self.raised[index] = false;
+        }
+        if requested && !self.raised[index] {
+            core.raise_interrupt(vector)?;
+            self.raised[index] = true;
+        }
+        Ok(())
     }
 }
 
 impl Addon for Uart {
-    fn tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
-        self.ticks_until_next_bit -= 1;
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let now = core.cycle_count;
+        let mut ucsra = core.read_data(regs.ucsra)?;
+        let ucsrb = core.read_data(regs.ucsrb)?;
+
+        // Writing a one to `TXC` clears it.
+        if core.was_written(regs.ucsra) && ucsra & ucsra::TXC != 0 {
+            self.tx_complete = false;
+        }
+
+        // Transmitter.
+        if ucsrb & ucsrb::TXEN == 0 {
+            self.tx_buffer = None;
+            self.tx_shift = None;
+        } else if core.was_written(regs.udr) && self.tx_buffer.is_none() {
+            self.tx_buffer = Some(core.read_data(regs.udr)?);
+        }
+
+        if let Some(frame) = self.tx_shift {
+            if now >= frame.done_at {
+                self.tx_shift = None;
+                self.sink.send(frame.byte).map_err(Error::Io)?;
+                if self.tx_buffer.is_none() {
+                    self.tx_complete = true;
+                }
+            }
+        }
+        if self.tx_shift.is_none() {
+            if let Some(byte) = self.tx_buffer.take() {
+                self.tx_shift = Some(Frame {
+                    byte,
+                    done_at: now + self.frame_cycles(core, ucsra, ucsrb)?,
+                });
+            }
+        }
+
+        // Receiver.
+        if core.was_read(regs.udr) {
+            self.rx_fifo.pop_front();
+        }
+
+        if ucsrb & ucsrb::RXEN == 0 {
+            self.rx_shift = None;
+            self.rx_fifo.clear();
+            self.data_overrun = false;
+        } else {
+            if let Some(frame) = self.rx_shift {
+                if now >= frame.done_at {
+                    self.rx_shift = None;
+                    if self.rx_fifo.len() < RX_FIFO_DEPTH {
+                        self.rx_fifo.push_back(frame.byte);
+                        self.data_overrun = false;
+                    } else {
+                        self.data_overrun = true;
+                    }
+                }
+            }
+            if self.rx_shift.is_none() {
+                let next = self.rx_queue.borrow_mut().pop_front();
+                if let Some(byte) = next {
+                    self.rx_shift = Some(Frame {
+                        byte,
+                        done_at: now + self.frame_cycles(core, ucsra, ucsrb)?,
+                    });
+                }
+            }
+        }
+
+        // `UDRn` reads the receive buffer, whatever was last written to it.
+        let udr = self.rx_fifo.front().copied().unwrap_or(0);
+        core.memory_mut().set_u8(regs.udr as usize, udr)?;
+
+        let flags = [
+            (ucsra::RXC, !self.rx_fifo.is_empty()),
+            (ucsra::TXC, self.tx_complete),
+            (ucsra::UDRE, self.tx_buffer.is_none()),
+            (ucsra::DOR, self.data_overrun),
+            (ucsra::FE, false),
+            (ucsra::UPE, false),
+        ];
+        for (flag, set) in flags {
+            if set {
+                ucsra |= flag;
+            } else {
+                ucsra &= !flag;
+            }
+        }
+
+        let vectors = self.vectors;
+        self.update_interrupt(
+            core,
+            0,
+            vectors.rx,
+            ucsra & ucsra::RXC != 0 && ucsrb & ucsrb::RXCIE != 0,
+        )?;
+        self.update_interrupt(
+            core,
+            1,
+            vectors.udre,
+            ucsra & ucsra::UDRE != 0 && ucsrb & ucsrb::UDRIE != 0,
+        )?;
+
+        // `TXC` is cleared by hardware once the vector is serviced.
+        if self.raised[2] && !core.interrupts().is_pending(vectors.tx) {
+            self.tx_complete = false;
+            ucsra &= !ucsra::TXC;
+        }
+        self.update_interrupt(
+            core,
+            2,
+            vectors.tx,
+            ucsra & ucsra::TXC != 0 && ucsrb & ucsrb::TXCIE != 0,
+        )?;
+
+        core.write_data(regs.ucsra, ucsra)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.tx_buffer = None;
+        self.tx_shift = None;
+        self.tx_complete = false;
+        self.rx_shift = None;
+        self.rx_fifo.clear();
+        self.data_overrun = false;
+        self.raised = [false; 3];
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.bool(self.tx_buffer.is_some());
+        state.u8(self.tx_buffer.unwrap_or(0));
+        Frame::save(self.tx_shift, state);
+        state.bool(self.tx_complete);
+
+        self.handle().save_state(state);
+        Frame::save(self.rx_shift, state);
+        let rx_fifo: Vec<u8> = self.rx_fifo.iter().copied().collect();
+        state.bytes(&rx_fifo);
+        state.bool(self.data_overrun);
+
+        for raised in self.raised {
+            state.bool(raised);
+        }
+    }
 
-        if self.ticks_until_next_bit == 0 {
-            self.process_bit(core);
-            self.ticks_until_next_bit = self.ticks_between_bits;
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        let buffered = state.bool()?;
+        let byte = state.u8()?;
+        self.tx_buffer = buffered.then_some(byte);
+        self.tx_shift = Frame::load(state)?;
+        self.tx_complete = state.bool()?;
+
+        self.handle().load_state(state)?;
+        self.rx_shift = Frame::load(state)?;
+        let rx_fifo = state.bytes()?;
+        if rx_fifo.len() > RX_FIFO_DEPTH {
+            return Err(Error::InvalidState("receive buffer too long"));
+        }
+        self.rx_fifo = rx_fifo.iter().copied().collect();
+        self.data_overrun = state.bool()?;
+
+        for raised in &mut self.raised {
+            *raised = state.bool()?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Uart;
+    use crate::chips::atmega328p;
+    use crate::inst::asm;
+    use crate::{Core, Mcu};
+    use std::sync::mpsc::{self, Receiver};
+
+    /// Sends `A` at 16 CPU cycles a bit.
+    const TRANSMIT: &str = "
+            ldi r16, 0
+            sts 0xc4, r16   ; UBRR0L
+            ldi r16, 0x06   ; 8 data bits
+            sts 0xc2, r16   ; UCSR0C
+            ldi r16, 0x08   ; TXEN0
+            sts 0xc1, r16   ; UCSR0B
+            ldi r16, 'A'
+            sts 0xc6, r16   ; UDR0
+    loop:   rjmp loop
+    ";
+
+    fn setup() -> (Mcu, Receiver<u8>) {
+        let mut core = Core::new::<atmega328p::Chip>();
+        core.load_program_space(asm::assemble(TRANSMIT).unwrap().into_iter());
+        let mut mcu = Mcu::new(core);
+        let (sender, receiver) = mpsc::channel();
+        mcu.attach(Box::new(Uart::atmega328p().with_sink(sender)));
+        (mcu, receiver)
+    }
+
+    /// Runs a number of instructions. `run_for_instructions` would stop at
+    /// the loop at the end, which counts as halted.
+    fn run(mcu: &mut Mcu, instructions: usize) {
+        for _ in 0..instructions {
+            mcu.tick().unwrap();
+        }
+    }
+
+    #[test]
+    fn transmits_a_frame_in_ten_bit_times() {
+        let (mut mcu, sent) = setup();
+        // Up to and including the write to `UDR0`.
+        run(&mut mcu, 8);
+        let start = mcu.core.cycle_count;
+
+        // A start bit, 8 data bits and a stop bit at 16 cycles each.
+        while mcu.core.cycle_count < start + 160 {
+            assert!(sent.try_recv().is_err());
+            mcu.tick().unwrap();
+        }
+        assert_eq!(mcu.core.cycle_count, start + 160);
+        assert_eq!(sent.try_recv(), Ok(b'A'));
+    }
+
+    #[test]
+    fn transmits_after_loading_a_state_saved_mid_frame() {
+        let (mut mcu, sent) = setup();
+        run(&mut mcu, 20);
+        assert!(sent.try_recv().is_err());
+        let state = mcu.save_state_bytes();
+
+        let (mut mcu, sent) = setup();
+        mcu.load_state_bytes(&state).unwrap();
+        run(&mut mcu, 100);
+        assert_eq!(sent.try_iter().collect::<Vec<_>>(), b"A");
+    }
+}
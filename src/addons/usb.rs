@@ -0,0 +1,1221 @@
+use crate::addons::uart::{Handle, Sink};
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+use std::collections::VecDeque;
+use std::mem;
+
+/// `PLLCSR` bits.
+pub mod pllcsr {
+    pub const PINDIV: u8 = 1 << 4;
+    pub const PLLE: u8 = 1 << 1;
+    pub const PLOCK: u8 = 1 << 0;
+}
+
+/// `USBCON` bits.
+pub mod usbcon {
+    pub const USBE: u8 = 1 << 7;
+    pub const FRZCLK: u8 = 1 << 5;
+    pub const OTGPADE: u8 = 1 << 4;
+    pub const VBUSTE: u8 = 1 << 0;
+}
+
+/// `USBSTA` bits.
+pub mod usbsta {
+    pub const ID: u8 = 1 << 1;
+    pub const VBUS: u8 = 1 << 0;
+}
+
+/// `USBINT` bits.
+pub mod usbint {
+    pub const VBUSTI: u8 = 1 << 0;
+}
+
+/// `UDCON` bits.
+pub mod udcon {
+    pub const RSTCPU: u8 = 1 << 3;
+    pub const LSM: u8 = 1 << 2;
+    pub const RMWKUP: u8 = 1 << 1;
+    pub const DETACH: u8 = 1 << 0;
+}
+
+/// `UDINT` bits. The bits of `UDIEN` enabling their interrupts are in the
+/// same places.
+pub mod udint {
+    pub const UPRSMI: u8 = 1 << 6;
+    pub const EORSMI: u8 = 1 << 5;
+    pub const WAKEUPI: u8 = 1 << 4;
+    pub const EORSTI: u8 = 1 << 3;
+    pub const SOFI: u8 = 1 << 2;
+    pub const SUSPI: u8 = 1 << 0;
+}
+
+/// `UDADDR` bits.
+pub mod udaddr {
+    pub const ADDEN: u8 = 1 << 7;
+    pub const UADD_MASK: u8 = 0b0111_1111;
+}
+
+/// `UEINTX` bits. The bits of `UEIENX` enabling their interrupts are in
+/// the same places.
+pub mod ueintx {
+    pub const FIFOCON: u8 = 1 << 7;
+    pub const NAKINI: u8 = 1 << 6;
+    pub const RWAL: u8 = 1 << 5;
+    pub const NAKOUTI: u8 = 1 << 4;
+    pub const RXSTPI: u8 = 1 << 3;
+    pub const RXOUTI: u8 = 1 << 2;
+    pub const STALLEDI: u8 = 1 << 1;
+    pub const TXINI: u8 = 1 << 0;
+    /// The flags that request the endpoint interrupt.
+    pub const INTERRUPT_FLAGS: u8 = NAKINI | NAKOUTI | RXSTPI | RXOUTI | STALLEDI | TXINI;
+}
+
+/// `UECONX` bits.
+pub mod ueconx {
+    pub const STALLRQ: u8 = 1 << 5;
+    pub const STALLRQC: u8 = 1 << 4;
+    pub const RSTDT: u8 = 1 << 3;
+    pub const EPEN: u8 = 1 << 0;
+}
+
+/// `UECFG0X` bits.
+pub mod uecfg0x {
+    pub const EPTYPE_MASK: u8 = 0b1100_0000;
+    pub const EPDIR: u8 = 1 << 0;
+}
+
+/// `UECFG1X` bits.
+pub mod uecfg1x {
+    pub const EPSIZE_MASK: u8 = 0b0111_0000;
+    pub const EPBK_MASK: u8 = 0b0000_1100;
+    pub const ALLOC: u8 = 1 << 1;
+}
+
+/// `UESTA0X` bits.
+pub mod uesta0x {
+    pub const CFGOK: u8 = 1 << 7;
+}
+
+/// The number of endpoints, counting the control endpoint 0.
+const ENDPOINTS: usize = 7;
+
+/// The address the host gives the device.
+const DEVICE_ADDRESS: u8 = 1;
+
+/// The line coding the host sets: 115200 baud, one stop bit, no parity
+/// and eight data bits.
+const LINE_CODING: [u8; 7] = [0x00, 0xc2, 0x01, 0x00, 0, 0, 8];
+
+/// How many frames the host holds the bus in reset for.
+const RESET_FRAMES: u64 = 10;
+
+/// The data space addresses of the USB controller registers.
+///
+/// The endpoint registers from `UEINTX` on show the endpoint selected by
+/// `UENUM`.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub pllcsr: u16,
+    pub usbcon: u16,
+    pub usbsta: u16,
+    pub usbint: u16,
+    pub udcon: u16,
+    pub udint: u16,
+    pub udien: u16,
+    pub udaddr: u16,
+    pub udfnuml: u16,
+    pub udfnumh: u16,
+    pub ueintx: u16,
+    pub uenum: u16,
+    pub uerst: u16,
+    pub ueconx: u16,
+    pub uecfg0x: u16,
+    pub uecfg1x: u16,
+    pub uesta0x: u16,
+    pub ueienx: u16,
+    pub uedatx: u16,
+    pub uebclx: u16,
+    pub uebchx: u16,
+    pub ueint: u16,
+}
+
+/// The USB interrupt vectors.
+#[derive(Copy, Clone, Debug)]
+pub struct Vectors {
+    /// `USB_GEN`, for the device interrupts in `UDINT` and `USBINT`.
+    pub general: u8,
+    /// `USB_COM`, for the endpoint interrupts in `UEINTX`.
+    pub endpoint: u8,
+}
+
+/// An endpoint, whose registers are shown while `UENUM` selects it.
+#[derive(Clone, Debug, Default)]
+struct Endpoint {
+    ueconx: u8,
+    uecfg0x: u8,
+    uecfg1x: u8,
+    ueienx: u8,
+    /// The flags in `UEINTX`, besides `RWAL`.
+    flags: u8,
+    /// The bank the firmware reads or writes through `UEDATX`.
+    bank: VecDeque<u8>,
+    /// A packet the firmware has released for the host to take.
+    sent: Option<Vec<u8>>,
+}
+
+impl Endpoint {
+    fn is_configured(&self) -> bool {
+        self.ueconx & ueconx::EPEN != 0 && self.uecfg1x & uecfg1x::ALLOC != 0
+    }
+
+    fn is_control(&self) -> bool {
+        self.uecfg0x & uecfg0x::EPTYPE_MASK == 0
+    }
+
+    fn is_in(&self) -> bool {
+        self.uecfg0x & uecfg0x::EPDIR != 0
+    }
+
+    fn is_stalled(&self) -> bool {
+        self.ueconx & ueconx::STALLRQ != 0
+    }
+
+    /// Gets the size of the bank in bytes.
+    fn size(&self) -> usize {
+        8 << ((self.uecfg1x & uecfg1x::EPSIZE_MASK) >> 4).min(5)
+    }
+
+    /// Empties the banks and clears the flags, keeping the configuration.
+    /// A configured IN endpoint is left ready for the firmware to fill.
+    fn reset(&mut self) {
+        self.bank.clear();
+        self.sent = None;
+        self.flags = if self.is_configured() && !self.is_control() && self.is_in() {
+            ueintx::TXINI | ueintx::FIFOCON
+        } else {
+            0
+        };
+    }
+
+    fn ueintx(&self) -> u8 {
+        let writable = if self.is_in() {
+            self.bank.len() < self.size()
+        } else {
+            !self.bank.is_empty()
+        };
+        if !self.is_control() && self.flags & ueintx::FIFOCON != 0 && writable {
+            self.flags | ueintx::RWAL
+        } else {
+            self.flags
+        }
+    }
+
+    /// Handles the firmware writing `UEINTX`, where writing a zero to a
+    /// flag clears it.
+    ///
+    /// Clearing `TXINI` of the control endpoint, or `FIFOCON` of another
+    /// one, releases the bank to the host.
+    fn write_ueintx(&mut self, value: u8) {
+        let cleared = self.flags & !value & !ueintx::RWAL;
+        self.flags &= !cleared;
+
+        if self.is_control() {
+            if cleared & ueintx::TXINI != 0 {
+                self.sent = Some(self.bank.drain(..).collect());
+            }
+            if cleared & (ueintx::RXSTPI | ueintx::RXOUTI) != 0 {
+                self.bank.clear();
+            }
+        } else if cleared & ueintx::FIFOCON != 0 {
+            if self.is_in() {
+                self.sent = Some(self.bank.drain(..).collect());
+            } else {
+                self.bank.clear();
+            }
+        }
+    }
+
+    /// Handles the firmware writing `UECONX`, where `STALLRQ` is set by
+    /// writing a one to it and cleared by writing a one to `STALLRQC`.
+    fn write_ueconx(&mut self, value: u8) {
+        let mut stall = self.ueconx & ueconx::STALLRQ;
+        if value & ueconx::STALLRQ != 0 {
+            stall = ueconx::STALLRQ;
+        }
+        if value & ueconx::STALLRQC != 0 {
+            stall = 0;
+        }
+        self.ueconx = stall | (value & ueconx::EPEN);
+        if !self.is_configured() {
+            self.reset();
+        }
+    }
+
+    fn save(&self, state: &mut state::Writer) {
+        state.u8(self.ueconx);
+        state.u8(self.uecfg0x);
+        state.u8(self.uecfg1x);
+        state.u8(self.ueienx);
+        state.u8(self.flags);
+        let bank: Vec<u8> = self.bank.iter().copied().collect();
+        state.bytes(&bank);
+        state.bool(self.sent.is_some());
+        state.bytes(self.sent.as_deref().unwrap_or(&[]));
+    }
+
+    fn load(state: &mut state::Reader) -> Result<Self, Error> {
+        let mut ep = Endpoint {
+            ueconx: state.u8()?,
+            uecfg0x: state.u8()?,
+            uecfg1x: state.u8()?,
+            ueienx: state.u8()?,
+            flags: state.u8()?,
+            bank: state.bytes()?.iter().copied().collect(),
+            sent: None,
+        };
+        let sent = state.bool()?;
+        let packet = state.bytes()?;
+        ep.sent = sent.then(|| packet.to_vec());
+        Ok(ep)
+    }
+}
+
+/// The interfaces of a CDC-ACM function, from the configuration
+/// descriptor.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+struct Cdc {
+    /// The communications interface, which takes the class requests.
+    interface: u8,
+    /// The bulk IN endpoint of the data interface.
+    data_in: usize,
+    /// The bulk OUT endpoint of the data interface.
+    data_out: usize,
+}
+
+impl Cdc {
+    /// Finds the first CDC-ACM function in a configuration descriptor.
+    fn find(descriptor: &[u8]) -> Option<Self> {
+        let mut interface = None;
+        let mut data_in = None;
+        let mut data_out = None;
+        let mut in_data_interface = false;
+
+        let mut rest = descriptor;
+        while rest.len() >= 2 {
+            let length = rest[0] as usize;
+            if length < 2 || length > rest.len() {
+                break;
+            }
+            let d = &rest[..length];
+            match d[1] {
+                // Interface descriptors, of the communications (0x02) and
+                // data (0x0a) classes.
+                0x04 if length >= 9 => {
+                    in_data_interface = d[5] == 0x0a;
+                    if d[5] == 0x02 && interface.is_none() {
+                        interface = Some(d[2]);
+                    }
+                }
+                // Bulk endpoint descriptors.
+                0x05 if length >= 7 && in_data_interface && d[3] & 0x03 == 2 => {
+                    let number = (d[2] & 0x0f) as usize;
+                    if d[2] & 0x80 != 0 {
+                        data_in.get_or_insert(number);
+                    } else {
+                        data_out.get_or_insert(number);
+                    }
+                }
+                _ => (),
+            }
+            rest = &rest[length..];
+        }
+
+        let cdc = Cdc {
+            interface: interface?,
+            data_in: data_in?,
+            data_out: data_out?,
+        };
+        let valid = |n: usize| n != 0 && n < ENDPOINTS;
+        (valid(cdc.data_in) && valid(cdc.data_out)).then_some(cdc)
+    }
+
+    fn save(self, state: &mut state::Writer) {
+        state.u8(self.interface);
+        state.u8(self.data_in as u8);
+        state.u8(self.data_out as u8);
+    }
+
+    fn load(state: &mut state::Reader) -> Result<Self, Error> {
+        let cdc = Cdc {
+            interface: state.u8()?,
+            data_in: state.u8()? as usize,
+            data_out: state.u8()? as usize,
+        };
+        if cdc.data_in >= ENDPOINTS || cdc.data_out >= ENDPOINTS {
+            return Err(Error::InvalidState("invalid CDC-ACM endpoint"));
+        }
+        Ok(cdc)
+    }
+}
+
+/// The control transfers the host makes to enumerate the device, in
+/// order.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Request {
+    SetAddress,
+    GetDeviceDescriptor,
+    GetConfigurationHeader,
+    GetConfiguration { length: u16 },
+    SetConfiguration { value: u8, cdc: Cdc },
+    SetLineCoding(Cdc),
+    SetControlLineState(Cdc),
+}
+
+impl Request {
+    /// Gets the setup packet of the request, and the data the host sends
+    /// with it.
+    fn setup(self) -> ([u8; 8], Vec<u8>) {
+        let (request_type, request, value, index, length) = match self {
+            Request::SetAddress => (0x00, 0x05, DEVICE_ADDRESS as u16, 0, 0),
+            Request::GetDeviceDescriptor => (0x80, 0x06, 0x0100, 0, 18),
+            Request::GetConfigurationHeader => (0x80, 0x06, 0x0200, 0, 9),
+            Request::GetConfiguration { length } => (0x80, 0x06, 0x0200, 0, length),
+            Request::SetConfiguration { value, .. } => (0x00, 0x09, value as u16, 0, 0),
+            Request::SetLineCoding(cdc) => (0x21, 0x20, 0, cdc.interface as u16, 7),
+            // `DTR` and `RTS`, which Arduino's `Serial` waits for.
+            Request::SetControlLineState(cdc) => (0x21, 0x22, 0x0003, cdc.interface as u16, 0),
+        };
+        let [value_lo, value_hi] = value.to_le_bytes();
+        let [index_lo, index_hi] = index.to_le_bytes();
+        let [length_lo, length_hi] = length.to_le_bytes();
+        let setup = [
+            request_type,
+            request,
+            value_lo,
+            value_hi,
+            index_lo,
+            index_hi,
+            length_lo,
+            length_hi,
+        ];
+        let data = match self {
+            Request::SetLineCoding(_) => LINE_CODING.to_vec(),
+            _ => Vec::new(),
+        };
+        (setup, data)
+    }
+
+    /// Gets the request that follows this one, given the data the device
+    /// returned, or `None` if it stalled the request.
+    fn next(self, data: Option<&[u8]>) -> Result<Option<Request>, &'static str> {
+        Ok(Some(match (self, data) {
+            (Request::SetAddress, Some(_)) => Request::GetDeviceDescriptor,
+            (Request::GetDeviceDescriptor, Some(_)) => Request::GetConfigurationHeader,
+            (Request::GetConfigurationHeader, Some(header)) if header.len() >= 4 => {
+                let length = u16::from_le_bytes([header[2], header[3]]);
+                Request::GetConfiguration { length }
+            }
+            (Request::GetConfiguration { .. }, Some(descriptor)) if descriptor.len() >= 9 => {
+                let cdc = Cdc::find(descriptor).ok_or("no CDC-ACM function")?;
+                Request::SetConfiguration {
+                    value: descriptor[5],
+                    cdc,
+                }
+            }
+            (Request::SetConfiguration { cdc, .. }, Some(_)) => Request::SetLineCoding(cdc),
+            // Not every CDC-ACM function supports the line requests.
+            (Request::SetLineCoding(cdc), _) => Request::SetControlLineState(cdc),
+            (Request::SetControlLineState(_), _) => return Ok(None),
+            (_, None) => return Err("request stalled"),
+            (_, Some(_)) => return Err("descriptor too short"),
+        }))
+    }
+
+    fn save(self, state: &mut state::Writer) {
+        match self {
+            Request::SetAddress => state.u8(0),
+            Request::GetDeviceDescriptor => state.u8(1),
+            Request::GetConfigurationHeader => state.u8(2),
+            Request::GetConfiguration { length } => {
+                state.u8(3);
+                state.u16(length);
+            }
+            Request::SetConfiguration { value, cdc } => {
+                state.u8(4);
+                state.u8(value);
+                cdc.save(state);
+            }
+            Request::SetLineCoding(cdc) => {
+                state.u8(5);
+                cdc.save(state);
+            }
+            Request::SetControlLineState(cdc) => {
+                state.u8(6);
+                cdc.save(state);
+            }
+        }
+    }
+
+    fn load(state: &mut state::Reader) -> Result<Self, Error> {
+        Ok(match state.u8()? {
+            0 => Request::SetAddress,
+            1 => Request::GetDeviceDescriptor,
+            2 => Request::GetConfigurationHeader,
+            3 => Request::GetConfiguration {
+                length: state.u16()?,
+            },
+            4 => Request::SetConfiguration {
+                value: state.u8()?,
+                cdc: Cdc::load(state)?,
+            },
+            5 => Request::SetLineCoding(Cdc::load(state)?),
+            6 => Request::SetControlLineState(Cdc::load(state)?),
+            _ => return Err(Error::InvalidState("invalid USB request")),
+        })
+    }
+}
+
+/// The stages of a control transfer.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Stage {
+    Setup,
+    /// Taking the packets the device sends.
+    DataIn,
+    /// Sending the data, with the number of bytes sent so far.
+    DataOut(usize),
+    /// Taking the zero length packet that acknowledges the data sent.
+    StatusIn,
+    /// Sending the zero length packet that acknowledges the data taken.
+    StatusOut,
+}
+
+/// How a step of a control transfer went.
+enum Outcome {
+    Pending,
+    Done(Vec<u8>),
+    Stalled,
+}
+
+/// A control transfer on endpoint 0.
+#[derive(Clone, Debug)]
+struct Transfer {
+    request: Request,
+    setup: [u8; 8],
+    stage: Stage,
+    /// The data sent, or taken so far.
+    data: Vec<u8>,
+}
+
+impl Transfer {
+    /// Puts the setup packet of a request into the bank of endpoint 0.
+    fn start(request: Request, ep0: &mut Endpoint) -> Self {
+        let (setup, data) = request.setup();
+        ep0.bank = setup.iter().copied().collect();
+        ep0.sent = None;
+        ep0.ueconx &= !ueconx::STALLRQ;
+        ep0.flags = ueintx::RXSTPI;
+
+        Transfer {
+            request,
+            setup,
+            stage: Stage::Setup,
+            data,
+        }
+    }
+
+    /// Moves the transfer on as far as the firmware has.
+    fn step(&mut self, ep0: &mut Endpoint) -> Outcome {
+        if ep0.is_stalled() {
+            ep0.flags |= ueintx::STALLEDI;
+            return Outcome::Stalled;
+        }
+
+        let length = u16::from_le_bytes([self.setup[6], self.setup[7]]) as usize;
+        match self.stage {
+            Stage::Setup => {
+                if ep0.flags & ueintx::RXSTPI == 0 {
+                    // The IN bank is free once the setup packet is taken.
+                    ep0.flags |= ueintx::TXINI;
+                    self.stage = if self.setup[0] & 0x80 != 0 && length > 0 {
+                        Stage::DataIn
+                    } else if !self.data.is_empty() {
+                        Stage::DataOut(0)
+                    } else {
+                        Stage::StatusIn
+                    };
+                }
+            }
+            Stage::DataIn => {
+                if let Some(packet) = ep0.sent.take() {
+                    ep0.flags |= ueintx::TXINI;
+                    let short = packet.len() < ep0.size();
+                    self.data.extend(packet);
+                    if short || self.data.len() >= length {
+                        self.data.truncate(length);
+                        self.stage = Stage::StatusOut;
+                    }
+                }
+            }
+            Stage::StatusOut => {
+                ep0.flags |= ueintx::RXOUTI;
+                return Outcome::Done(mem::take(&mut self.data));
+            }
+            Stage::DataOut(sent) => {
+                if ep0.flags & ueintx::RXOUTI == 0 {
+                    if sent < self.data.len() {
+                        let end = self.data.len().min(sent + ep0.size());
+                        ep0.bank = self.data[sent..end].iter().copied().collect();
+                        ep0.flags |= ueintx::RXOUTI;
+                        self.stage = Stage::DataOut(end);
+                    } else {
+                        self.stage = Stage::StatusIn;
+                    }
+                }
+            }
+            Stage::StatusIn => {
+                if ep0.sent.take().is_some() {
+                    ep0.flags |= ueintx::TXINI;
+                    return Outcome::Done(Vec::new());
+                }
+            }
+        }
+        Outcome::Pending
+    }
+
+    fn save(&self, state: &mut state::Writer) {
+        self.request.save(state);
+        state.bytes(&self.setup);
+        let (stage, sent) = match self.stage {
+            Stage::Setup => (0, 0),
+            Stage::DataIn => (1, 0),
+            Stage::DataOut(sent) => (2, sent),
+            Stage::StatusIn => (3, 0),
+            Stage::StatusOut => (4, 0),
+        };
+        state.u8(stage);
+        state.u32(sent as u32);
+        state.bytes(&self.data);
+    }
+
+    fn load(state: &mut state::Reader) -> Result<Self, Error> {
+        let request = Request::load(state)?;
+        let setup = state
+            .bytes()?
+            .try_into()
+            .map_err(|_| Error::InvalidState("invalid setup packet"))?;
+        let stage = state.u8()?;
+        let sent = state.u32()? as usize;
+        let stage = match stage {
+            0 => Stage::Setup,
+            1 => Stage::DataIn,
+            2 => Stage::DataOut(sent),
+            3 => Stage::StatusIn,
+            4 => Stage::StatusOut,
+            _ => return Err(Error::InvalidState("invalid control transfer stage")),
+        };
+        let data = state.bytes()?.to_vec();
+        if sent > data.len() {
+            return Err(Error::InvalidState("invalid control transfer stage"));
+        }
+        Ok(Transfer {
+            request,
+            setup,
+            stage,
+            data,
+        })
+    }
+}
+
+/// What the simulated host is doing.
+#[derive(Clone, Debug)]
+enum Host {
+    /// Waiting for the firmware to attach to the bus.
+    Detached,
+    /// Waiting until a cycle to make a request, once endpoint 0 is
+    /// configured.
+    Waiting {
+        request: Request,
+        until: u64,
+    },
+    Transferring(Transfer),
+    /// Passing data through the CDC-ACM data endpoints.
+    Configured(Cdc),
+    /// Gave up enumerating the device.
+    Failed,
+}
+
+/// The USB device controller, with a host that enumerates a CDC-ACM
+/// virtual serial port and bridges it to a byte stream.
+///
+/// Once the firmware enables the controller and attaches to the bus, the
+/// host resets the bus, sets the address, reads the descriptors, sets the
+/// first configuration and opens the first CDC-ACM function found in it
+/// with `DTR` and `RTS` set, making one control transfer per frame. From
+/// then on, packets the firmware sends on the bulk IN endpoint go to the
+/// sink, which is stdout unless another one is given to `with_sink`, and
+/// bytes given to `send_to_target` are sent on the bulk OUT endpoint.
+///
+/// Frames are timed by the CPU clock, and the PLL locks as soon as it is
+/// enabled. Each endpoint has a single bank, and packets move at most one
+/// per endpoint each instruction. Suspend, resume, isochronous endpoints,
+/// data toggles and the other functions of composite devices are not
+/// simulated.
+pub struct Usb {
+    registers: Registers,
+    vectors: Vectors,
+    sink: Sink,
+    handle: Handle,
+
+    endpoints: [Endpoint; ENDPOINTS],
+    /// The endpoint selected by `UENUM`.
+    selected: usize,
+    /// Whether `USBE` is set, which powers the pads and `VBUS` with them.
+    enabled: bool,
+    /// The flags in `UDINT`.
+    udint: u8,
+    /// The flags in `USBINT`.
+    usbint: u8,
+    /// The frame number in `UDFNUMH:UDFNUML`.
+    frame: u16,
+    /// The cycle the next start of frame is due at.
+    next_frame_at: u64,
+    host: Host,
+
+    /// Whether the `USB_GEN` and `USB_COM` interrupts have been requested.
+    raised: [bool; 2],
+}
+
+impl Usb {
+    pub fn new(registers: Registers, vectors: Vectors) -> Self {
+        Usb {
+            registers,
+            vectors,
+            sink: Sink::Stdout,
+            handle: Handle::new(),
+            endpoints: Default::default(),
+            selected: 0,
+            enabled: false,
+            udint: 0,
+            usbint: 0,
+            frame: 0,
+            next_frame_at: 0,
+            host: Host::Detached,
+            raised: [false; 2],
+        }
+    }
+
+    pub fn atmega32u4() -> Self {
+        Self::new(
+            Registers {
+                pllcsr: 0x49,
+                usbcon: 0xd8,
+                usbsta: 0xd9,
+                usbint: 0xda,
+                udcon: 0xe0,
+                udint: 0xe1,
+                udien: 0xe2,
+                udaddr: 0xe3,
+                udfnuml: 0xe4,
+                udfnumh: 0xe5,
+                ueintx: 0xe8,
+                uenum: 0xe9,
+                uerst: 0xea,
+                ueconx: 0xeb,
+                uecfg0x: 0xec,
+                uecfg1x: 0xed,
+                uesta0x: 0xee,
+                ueienx: 0xf0,
+                uedatx: 0xf1,
+                uebclx: 0xf2,
+                uebchx: 0xf3,
+                ueint: 0xf4,
+            },
+            Vectors {
+                general: 10,
+                endpoint: 11,
+            },
+        )
+    }
+
+    /// Sets where bytes sent by the firmware go.
+    pub fn with_sink<S>(mut self, sink: S) -> Self
+    where
+        S: Into<Sink>,
+    {
+        self.sink = sink.into();
+        self
+    }
+
+    /// Queues bytes for the firmware to receive.
+    pub fn send_to_target(&self, bytes: &[u8]) {
+        self.handle.send_to_target(bytes)
+    }
+
+    /// Gets a handle for sending bytes to the firmware.
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    /// Checks if the host has enumerated the device and opened the serial
+    /// port.
+    pub fn is_configured(&self) -> bool {
+        matches!(self.host, Host::Configured(_))
+    }
+
+    /// Resets everything but the configuration of the control endpoint,
+    /// as a reset of the bus does.
+    fn bus_reset(&mut self, core: &mut Core) -> Result<(), Error> {
+        for ep in &mut self.endpoints[1..] {
+            *ep = Endpoint::default();
+        }
+        self.endpoints[0].reset();
+        self.udint |= udint::EORSTI;
+        core.write_data(self.registers.udaddr, 0)
+    }
+
+    /// Handles what the last instruction wrote to the registers, and reads
+    /// of `UEDATX`.
+    fn handle_accesses(&mut self, core: &mut Core) -> Result<(), Error> {
+        let regs = self.registers;
+        if core.was_written(regs.udint) {
+            self.udint &= core.read_data(regs.udint)?;
+        }
+        if core.was_written(regs.usbint) {
+            self.usbint &= core.read_data(regs.usbint)?;
+        }
+        if core.was_written(regs.uerst) {
+            let uerst = core.read_data(regs.uerst)?;
+            for (n, ep) in self.endpoints.iter_mut().enumerate() {
+                if uerst & (1 << n) != 0 {
+                    ep.reset();
+                }
+            }
+        }
+
+        let ep = &mut self.endpoints[self.selected];
+        if core.was_written(regs.ueconx) {
+            ep.write_ueconx(core.read_data(regs.ueconx)?);
+        }
+        if core.was_written(regs.uecfg0x) {
+            ep.uecfg0x = core.read_data(regs.uecfg0x)?;
+        }
+        if core.was_written(regs.uecfg1x) {
+            ep.uecfg1x = core.read_data(regs.uecfg1x)?;
+            ep.reset();
+        }
+        if core.was_written(regs.ueienx) {
+            ep.ueienx = core.read_data(regs.ueienx)?;
+        }
+        if core.was_written(regs.ueintx) {
+            ep.write_ueintx(core.read_data(regs.ueintx)?);
+        }
+        if core.was_written(regs.uedatx) && ep.bank.len() < ep.size() {
+            ep.bank.push_back(core.read_data(regs.uedatx)?);
+        }
+        if core.was_read(regs.uedatx) && (ep.is_control() || !ep.is_in()) {
+            ep.bank.pop_front();
+        }
+
+        if core.was_written(regs.uenum) {
+            let uenum = core.read_data(regs.uenum)? & 0x07;
+            self.selected = (uenum as usize).min(ENDPOINTS - 1);
+        }
+        Ok(())
+    }
+
+    /// Runs the host for a tick.
+    fn run_host(&mut self, now: u64, frame_cycles: u64) -> Result<(), Error> {
+        let ep0 = &mut self.endpoints[0];
+        self.host = match mem::replace(&mut self.host, Host::Failed) {
+            Host::Waiting { request, until }
+                if now >= until && ep0.is_configured() && ep0.is_control() =>
+            {
+                Host::Transferring(Transfer::start(request, ep0))
+            }
+            Host::Transferring(mut transfer) => match transfer.step(ep0) {
+                Outcome::Pending => Host::Transferring(transfer),
+                Outcome::Done(data) => {
+                    Self::after(transfer.request, Some(&data), now + frame_cycles)
+                }
+                Outcome::Stalled => Self::after(transfer.request, None, now + frame_cycles),
+            },
+            Host::Configured(cdc) => {
+                self.bridge(cdc)?;
+                Host::Configured(cdc)
+            }
+            host => host,
+        };
+        Ok(())
+    }
+
+    /// Gets what the host does after a request, given the data the device
+    /// returned, or `None` if it stalled the request.
+    fn after(request: Request, data: Option<&[u8]>, next_frame: u64) -> Host {
+        match request.next(data) {
+            Ok(Some(request)) => Host::Waiting {
+                request,
+                until: next_frame,
+            },
+            Ok(None) => match request {
+                Request::SetControlLineState(cdc) => Host::Configured(cdc),
+                _ => Host::Failed,
+            },
+            Err(reason) => {
+                tracing::warn!(?request, reason, "USB enumeration failed");
+                Host::Failed
+            }
+        }
+    }
+
+    /// Passes packets between the CDC-ACM data endpoints and the byte
+    /// stream. Packets sent on other IN endpoints are thrown away.
+    fn bridge(&mut self, cdc: Cdc) -> Result<(), Error> {
+        for (n, ep) in self.endpoints.iter_mut().enumerate().skip(1) {
+            if !ep.is_configured() {
+                continue;
+            }
+            if ep.is_in() {
+                if let Some(packet) = ep.sent.take() {
+                    ep.flags |= ueintx::TXINI | ueintx::FIFOCON;
+                    if n == cdc.data_in {
+                        for byte in packet {
+                            self.sink.send(byte).map_err(Error::Io)?;
+                        }
+                    }
+                }
+            } else if n == cdc.data_out && ep.flags & (ueintx::RXOUTI | ueintx::FIFOCON) == 0 {
+                let packet = self.handle.take(ep.size());
+                if !packet.is_empty() {
+                    ep.bank = packet.into();
+                    ep.flags |= ueintx::RXOUTI | ueintx::FIFOCON;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Shows the state of the controller and the selected endpoint in the
+    /// registers.
+    fn update_registers(&self, core: &mut Core) -> Result<(), Error> {
+        let regs = self.registers;
+        let ep = &self.endpoints[self.selected];
+
+        let usbsta = if self.enabled {
+            usbsta::ID | usbsta::VBUS
+        } else {
+            usbsta::ID
+        };
+        let ueint = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, ep)| ep.flags & ep.ueienx & ueintx::INTERRUPT_FLAGS != 0)
+            .fold(0, |ueint, (n, _)| ueint | 1 << n);
+        let uesta0x = if ep.is_configured() {
+            uesta0x::CFGOK
+        } else {
+            0
+        };
+        let uedatx = match ep.is_in() && !ep.is_control() {
+            true => 0,
+            false => ep.bank.front().copied().unwrap_or(0),
+        };
+        let [frame_lo, frame_hi] = self.frame.to_le_bytes();
+        let [count_lo, count_hi] = (ep.bank.len() as u16).to_le_bytes();
+
+        let values = [
+            (regs.usbsta, usbsta),
+            (regs.usbint, self.usbint),
+            (regs.udint, self.udint),
+            (regs.udfnuml, frame_lo),
+            (regs.udfnumh, frame_hi),
+            (regs.ueintx, ep.ueintx()),
+            (regs.uenum, self.selected as u8),
+            (regs.ueconx, ep.ueconx),
+            (regs.uecfg0x, ep.uecfg0x),
+            (regs.uecfg1x, ep.uecfg1x),
+            (regs.uesta0x, uesta0x),
+            (regs.ueienx, ep.ueienx),
+            (regs.uedatx, uedatx),
+            (regs.uebclx, count_lo),
+            (regs.uebchx, count_hi),
+            (regs.ueint, ueint),
+        ];
+        for (address, value) in values {
+            core.write_data(address, value)?;
+        }
+        Ok(())
+    }
+
+    /// Requests or withdraws one of the interrupts.
+    fn update_interrupt(
+        &mut self,
+        core: &mut Core,
+        index: usize,
+        vector: u8,
+        requested: bool,
+    ) -> Result<(), Error> {
+        if self.raised[index] && !core.interrupts().is_pending(vector) {
+            self.raised[index] = false;
+        }
+        if requested && !self.raised[index] {
+            core.raise_interrupt(vector)?;
+            self.raised[index] = true;
+        }
+        Ok(())
+    }
+}
+
+impl Addon for Usb {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let now = core.cycle_count;
+        let frame_cycles = (core.cpu_frequency() / 1000).max(1);
+
+        // The PLL locks at once.
+        let pllcsr = core.read_data(regs.pllcsr)?;
+        let locked = (pllcsr & pllcsr::PLLE) >> 1;
+        core.write_data(regs.pllcsr, (pllcsr & !pllcsr::PLOCK) | locked)?;
+
+        self.handle_accesses(core)?;
+
+        let usbcon = core.read_data(regs.usbcon)?;
+        let enabled = usbcon & usbcon::USBE != 0;
+        if enabled != self.enabled {
+            self.enabled = enabled;
+            self.usbint |= usbint::VBUSTI;
+            if !enabled {
+                self.endpoints = Default::default();
+                self.selected = 0;
+                self.udint = 0;
+            }
+        }
+
+        let udcon = core.read_data(regs.udcon)?;
+        let attached = enabled && usbcon & usbcon::FRZCLK == 0 && udcon & udcon::DETACH == 0;
+        match (attached, &self.host) {
+            (false, _) => self.host = Host::Detached,
+            (true, Host::Detached) => {
+                self.bus_reset(core)?;
+                self.next_frame_at = now + frame_cycles;
+                self.host = Host::Waiting {
+                    request: Request::SetAddress,
+                    until: now + RESET_FRAMES * frame_cycles,
+                };
+            }
+            (true, _) => {
+                if now >= self.next_frame_at {
+                    self.next_frame_at = now + frame_cycles;
+                    self.frame = (self.frame + 1) & 0x07ff;
+                    self.udint |= udint::SOFI;
+                }
+                self.run_host(now, frame_cycles)?;
+            }
+        }
+
+        self.update_registers(core)?;
+
+        let udien = core.read_data(regs.udien)?;
+        let general = self.udint & udien != 0
+            || (self.usbint & usbint::VBUSTI != 0 && usbcon & usbcon::VBUSTE != 0);
+        let endpoint = self
+            .endpoints
+            .iter()
+            .any(|ep| ep.flags & ep.ueienx & ueintx::INTERRUPT_FLAGS != 0);
+        let vectors = self.vectors;
+        self.update_interrupt(core, 0, vectors.general, general)?;
+        self.update_interrupt(core, 1, vectors.endpoint, endpoint)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.endpoints = Default::default();
+        self.selected = 0;
+        self.enabled = false;
+        self.udint = 0;
+        self.usbint = 0;
+        self.frame = 0;
+        self.host = Host::Detached;
+        self.raised = [false; 2];
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        self.handle.save_state(state);
+        for ep in &self.endpoints {
+            ep.save(state);
+        }
+        state.u8(self.selected as u8);
+        state.bool(self.enabled);
+        state.u8(self.udint);
+        state.u8(self.usbint);
+        state.u16(self.frame);
+        state.u64(self.next_frame_at);
+
+        match self.host {
+            Host::Detached => state.u8(0),
+            Host::Waiting { request, until } => {
+                state.u8(1);
+                request.save(state);
+                state.u64(until);
+            }
+            Host::Transferring(ref transfer) => {
+                state.u8(2);
+                transfer.save(state);
+            }
+            Host::Configured(cdc) => {
+                state.u8(3);
+                cdc.save(state);
+            }
+            Host::Failed => state.u8(4),
+        }
+
+        for raised in self.raised {
+            state.bool(raised);
+        }
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        self.handle.load_state(state)?;
+        for ep in &mut self.endpoints {
+            *ep = Endpoint::load(state)?;
+        }
+        self.selected = state.u8()? as usize;
+        if self.selected >= ENDPOINTS {
+            return Err(Error::InvalidState("invalid endpoint number"));
+        }
+        self.enabled = state.bool()?;
+        self.udint = state.u8()?;
+        self.usbint = state.u8()?;
+        self.frame = state.u16()?;
+        self.next_frame_at = state.u64()?;
+
+        self.host = match state.u8()? {
+            0 => Host::Detached,
+            1 => Host::Waiting {
+                request: Request::load(state)?,
+                until: state.u64()?,
+            },
+            2 => Host::Transferring(Transfer::load(state)?),
+            3 => Host::Configured(Cdc::load(state)?),
+            4 => Host::Failed,
+            _ => return Err(Error::InvalidState("invalid USB host state")),
+        };
+
+        for raised in &mut self.raised {
+            *raised = state.bool()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Usb;
+    use crate::chips::atmega32u4;
+    use crate::inst::asm;
+    use crate::{Core, Mcu};
+
+    /// Answers `SET_ADDRESS`, `SET_CONFIGURATION` and `GET_DESCRIPTOR` with
+    /// the configuration descriptor of a CDC-ACM function on endpoint 0,
+    /// and stalls other requests.
+    const FIRMWARE: &str = "
+            ldi r16, 0x02           ; PLLE
+            sts 0x49, r16           ; PLLCSR
+            ldi r16, 0x90           ; USBE | OTGPADE
+            sts 0xd8, r16           ; USBCON
+            ldi r16, 0
+            sts 0xe0, r16           ; UDCON: attach
+            sts 0xe9, r16           ; UENUM
+            sts 0xec, r16           ; UECFG0X: control
+            ldi r16, 0x01           ; EPEN
+            sts 0xeb, r16           ; UECONX
+            ldi r16, 0x02           ; 8 bytes, ALLOC
+            sts 0xed, r16           ; UECFG1X
+
+    setup:  lds r16, 0xe8           ; UEINTX
+            sbrs r16, 3             ; RXSTPI
+            rjmp setup
+            lds r17, 0xf1           ; bmRequestType
+            lds r18, 0xf1           ; bRequest
+            lds r19, 0xf1
+            lds r19, 0xf1
+            lds r19, 0xf1
+            lds r19, 0xf1
+            lds r22, 0xf1           ; wLength
+            lds r19, 0xf1
+            ldi r16, 0xf7           ; clear RXSTPI
+            sts 0xe8, r16
+
+            cpi r18, 5              ; SET_ADDRESS
+            breq status
+            cpi r18, 9              ; SET_CONFIGURATION
+            breq status
+            cpi r18, 6              ; GET_DESCRIPTOR
+            breq descriptor
+            ldi r16, 0x21           ; STALLRQ | EPEN
+            sts 0xeb, r16
+            rjmp setup
+
+    status: lds r16, 0xe8
+            sbrs r16, 0             ; TXINI
+            rjmp status
+            ldi r16, 0xfe           ; clear TXINI
+            sts 0xe8, r16
+            rjmp setup
+
+    descriptor:
+            ldi r30, lo8(config)
+            ldi r31, hi8(config)
+            ldi r23, config_end - config
+            cp r22, r23
+            brsh packet
+            mov r23, r22
+    packet: lds r16, 0xe8
+            sbrs r16, 2             ; RXOUTI: the host has it all
+            rjmp ready
+            rjmp acked
+    ready:  sbrs r16, 0             ; TXINI
+            rjmp packet
+            ldi r24, 8
+    fill:   tst r23
+            breq send
+            lpm r16, Z+
+            sts 0xf1, r16           ; UEDATX
+            dec r23
+            dec r24
+            brne fill
+    send:   ldi r16, 0xfe           ; clear TXINI
+            sts 0xe8, r16
+            rjmp packet
+    acked:  ldi r16, 0xfb           ; clear RXOUTI
+            sts 0xe8, r16
+            rjmp setup
+
+    config: .db 9, 2, config_end - config, 0, 2, 1, 0, 0x80, 50
+            .db 9, 4, 0, 0, 0, 0x02, 2, 1, 0    ; communications
+            .db 9, 4, 1, 0, 2, 0x0a, 0, 0, 0    ; data
+            .db 7, 5, 0x83, 2, 64, 0, 0         ; bulk IN 3
+            .db 7, 5, 0x02, 2, 64, 0, 0         ; bulk OUT 2
+    config_end:
+    ";
+
+    #[test]
+    fn enumeration_configures_the_device() {
+        let mut core = Core::new::<atmega32u4::Chip>();
+        core.load_program_space(asm::assemble(FIRMWARE).unwrap().into_iter());
+        let mut mcu = Mcu::new(core);
+        // Short frames, so that the host does not wait long between
+        // requests.
+        mcu.set_clock_frequency(1_000_000);
+        mcu.attach(Box::new(Usb::atmega32u4()));
+
+        for _ in 0..100_000 {
+            if mcu.addon::<Usb>().unwrap().is_configured() {
+                return;
+            }
+            mcu.tick().unwrap();
+        }
+        panic!("the device was not configured");
+    }
+}
@@ -0,0 +1,190 @@
+use crate::chips::attiny85;
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::ResetCause;
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// `USICR` bits.
+pub mod usicr {
+    pub const USISIE: u8 = 1 << 7;
+    pub const USIOIE: u8 = 1 << 6;
+    pub const USIWM_MASK: u8 = 0b0011_0000;
+    pub const USICS_MASK: u8 = 0b0000_1100;
+    pub const USICS1: u8 = 1 << 3;
+    pub const USICS0: u8 = 1 << 2;
+    pub const USICLK: u8 = 1 << 1;
+    pub const USITC: u8 = 1 << 0;
+}
+
+/// `USISR` bits.
+pub mod usisr {
+    pub const USISIF: u8 = 1 << 7;
+    pub const USIOIF: u8 = 1 << 6;
+    pub const USIPF: u8 = 1 << 5;
+    pub const USIDC: u8 = 1 << 4;
+    pub const USICNT_MASK: u8 = 0b1111;
+    /// The flags that are cleared by writing a one to them.
+    pub const FLAGS: u8 = USISIF | USIOIF | USIPF;
+}
+
+/// The IO addresses of the USI registers.
+#[derive(Copy, Clone, Debug)]
+pub struct Registers {
+    pub usicr: u8,
+    pub usisr: u8,
+    pub usidr: u8,
+    pub usibr: u8,
+}
+
+/// The USI interrupt vectors.
+#[derive(Copy, Clone, Debug)]
+pub struct Vectors {
+    pub start: u8,
+    pub overflow: u8,
+}
+
+/// The pins of the USI, each as the IO address of its `PINx` register and
+/// the bit in it.
+#[derive(Copy, Clone, Debug)]
+pub struct Pins {
+    /// Data in (`DI`), sampled into bit 0 of `USIDR` on each shift.
+    pub data_in: (u8, u8),
+    /// Data out (`DO`), driven to bit 7 of `USIDR` in three-wire mode.
+    pub data_out: (u8, u8),
+    /// The clock (`USCK`), toggled by `USITC`.
+    pub clock: (u8, u8),
+}
+
+/// The Universal Serial Interface, as far as firmware clocks it itself.
+///
+/// This is a placeholder good enough for the common software-clocked SPI
+/// master loops: a `USICLK` strobe with `USICS1:0` clear shifts `USIDR` and
+/// counts, and a `USITC` toggle of `USCK` counts each edge when `USICLK`
+/// and `USICS1` are set, shifting on the positive edge. The counter
+/// overflow copies `USIDR` to `USIBR` and sets `USIOIF`. External clocks,
+/// the two-wire start condition detector and `USIPF` are not simulated.
+pub struct Usi {
+    registers: Registers,
+    vectors: Vectors,
+    pins: Pins,
+    /// The flags in `USISR`, which firmware clears by writing ones.
+    flags: u8,
+}
+
+impl Usi {
+    pub fn new(registers: Registers, vectors: Vectors, pins: Pins) -> Self {
+        Usi {
+            registers,
+            vectors,
+            pins,
+            flags: 0,
+        }
+    }
+
+    /// The USI with `DI` on `PB0`, `DO` on `PB1` and `USCK` on `PB2`.
+    pub fn attiny85() -> Self {
+        Self::new(
+            Registers {
+                usicr: 0x0d,
+                usisr: 0x0e,
+                usidr: 0x0f,
+                usibr: 0x10,
+            },
+            Vectors {
+                start: 13,
+                overflow: 14,
+            },
+            Pins {
+                data_in: (attiny85::PINB, 0),
+                data_out: (attiny85::PINB, 1),
+                clock: (attiny85::PINB, 2),
+            },
+        )
+    }
+
+    fn pin(core: &Core, (register, bit): (u8, u8)) -> Result<bool, Error> {
+        Ok(core.read_data(SRAM_IO_OFFSET + register as u16)? & (1 << bit) != 0)
+    }
+}
+
+impl Addon for Usi {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let regs = self.registers;
+        let io = |a: u8| SRAM_IO_OFFSET + a as u16;
+
+        let mut usisr = core.read_data(io(regs.usisr))?;
+        if core.was_written(io(regs.usisr)) {
+            self.flags &= !(usisr & usisr::FLAGS);
+        }
+        let mut counter = usisr & usisr::USICNT_MASK;
+
+        let usicr = core.read_data(io(regs.usicr))?;
+        let mut usidr = core.read_data(io(regs.usidr))?;
+        if core.was_written(io(regs.usicr)) {
+            let clock_select = usicr & usicr::USICS_MASK;
+            let strobe = usicr & usicr::USICLK != 0;
+
+            let mut edges = 0;
+            let mut shifts = 0;
+            if usicr & usicr::USITC != 0 {
+                // The clock pin is toggled through its `PORTx` bit. `PORTx`
+                // is two above `PINx`.
+                let (pin, bit) = self.pins.clock;
+                let high = !Self::pin(core, self.pins.clock)?;
+                let port = io(pin + 2);
+                let value = core.read_data(port)? ^ (1 << bit);
+                core.write_data(port, value)?;
+
+                if strobe && clock_select & usicr::USICS1 != 0 {
+                    edges = 1;
+                    let shifting_edge = clock_select & usicr::USICS0 == 0;
+                    shifts = (high == shifting_edge) as u8;
+                }
+            } else if strobe && clock_select == 0 {
+                edges = 1;
+                shifts = 1;
+            }
+
+            for _ in 0..shifts {
+                let data_in = Self::pin(core, self.pins.data_in)?;
+                usidr = (usidr << 1) | data_in as u8;
+            }
+            for _ in 0..edges {
+                counter = (counter + 1) & usisr::USICNT_MASK;
+                if counter == 0 {
+                    self.flags |= usisr::USIOIF;
+                    core.write_data(io(regs.usibr), usidr)?;
+                    if usicr & usicr::USIOIE != 0 {
+                        core.raise_interrupt(self.vectors.overflow)?;
+                    }
+                }
+            }
+            core.write_data(io(regs.usidr), usidr)?;
+
+            // The strobe bits always read as zero.
+            let usicr = usicr & !(usicr::USICLK | usicr::USITC);
+            core.write_data(io(regs.usicr), usicr)?;
+        }
+
+        let (register, bit) = self.pins.data_out;
+        let level = (usicr & usicr::USIWM_MASK != 0).then_some(usidr & 0x80 != 0);
+        core.override_pin(register, bit, level)?;
+
+        usisr = self.flags | (usisr & usisr::USIDC) | counter;
+        core.write_data(io(regs.usisr), usisr)
+    }
+
+    fn on_reset(&mut self, _: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        self.flags = 0;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.u8(self.flags);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        self.flags = state.u8()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,174 @@
+use crate::core::SRAM_IO_OFFSET;
+use crate::mem;
+use crate::{Addon, Core, Error, Instruction};
+use std::io::{self, Write};
+
+/// What a signal samples.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Source {
+    /// A bit of a `PINx` register, by IO address.
+    Pin { pin_register: u8, bit: u8 },
+    /// A byte of data space.
+    Register(mem::Address),
+}
+
+struct Signal {
+    name: String,
+    source: Source,
+    /// The identifier code of the signal in the dump.
+    code: String,
+    /// The last value written.
+    value: Option<u8>,
+}
+
+/// Writes the values of pins and IO registers to a Value Change Dump, as
+/// read by GTKWave and other waveform viewers.
+///
+/// Signals are sampled after every tick. Times are in picoseconds, going
+/// by the CPU frequency at each tick, so changes to the clock prescaler are
+/// taken into account.
+pub struct Vcd {
+    writer: Box<dyn Write>,
+    signals: Vec<Signal>,
+    header_written: bool,
+    /// The cycle count at the last tick.
+    last_cycle_count: u64,
+    /// The time of the last tick in picoseconds, as a float so that rounding
+    /// errors do not add up.
+    time: f64,
+}
+
+impl Vcd {
+    pub fn new<W>(writer: W) -> Self
+    where
+        W: Write + 'static,
+    {
+        Vcd {
+            writer: Box::new(writer),
+            signals: Vec::new(),
+            header_written: false,
+            last_cycle_count: 0,
+            time: 0.0,
+        }
+    }
+
+    /// Adds a pin, given by its `PINx` IO address and bit, as a 1-bit
+    /// signal.
+    pub fn with_pin(self, name: &str, pin_register: u8, bit: u8) -> Self {
+        self.with_signal(name, Source::Pin { pin_register, bit })
+    }
+
+    /// Adds a byte of data space, like an IO register, as an 8-bit signal.
+    pub fn with_register(self, name: &str, address: mem::Address) -> Self {
+        self.with_signal(name, Source::Register(address))
+    }
+
+    fn with_signal(mut self, name: &str, source: Source) -> Self {
+        let code = identifier_code(self.signals.len());
+        self.signals.push(Signal {
+            // Names cannot contain whitespace.
+            name: name.split_whitespace().collect::<Vec<_>>().join("_"),
+            source,
+            code,
+            value: None,
+        });
+        self
+    }
+
+    fn write_header(&mut self) -> io::Result<()> {
+        writeln!(self.writer, "$version avr $end")?;
+        writeln!(self.writer, "$timescale 1ps $end")?;
+        writeln!(self.writer, "$scope module avr $end")?;
+        for signal in &self.signals {
+            let width = match signal.source {
+                Source::Pin { .. } => 1,
+                Source::Register(_) => 8,
+            };
+            writeln!(
+                self.writer,
+                "$var wire {} {} {} $end",
+                width, signal.code, signal.name
+            )?;
+        }
+        writeln!(self.writer, "$upscope $end")?;
+        writeln!(self.writer, "$enddefinitions $end")
+    }
+
+    fn sample(&mut self, core: &Core) -> Result<(), Error> {
+        let mut changes = Vec::new();
+        for (i, signal) in self.signals.iter().enumerate() {
+            let value = match signal.source {
+                Source::Pin { pin_register, bit } => {
+                    let pins = core.peek_data(SRAM_IO_OFFSET + pin_register as u16)?;
+                    (pins >> bit) & 1
+                }
+                Source::Register(address) => core.peek_data(address)?,
+            };
+            if signal.value != Some(value) {
+                changes.push((i, value));
+            }
+        }
+        if changes.is_empty() {
+            return Ok(());
+        }
+
+        self.write_changes(&changes).map_err(Error::Io)
+    }
+
+    /// Writes changed values, with the header and the initial values on
+    /// the first call.
+    fn write_changes(&mut self, changes: &[(usize, u8)]) -> io::Result<()> {
+        let first = !self.header_written;
+        if first {
+            self.write_header()?;
+            self.header_written = true;
+        }
+
+        writeln!(self.writer, "#{}", self.time.round() as u64)?;
+        if first {
+            writeln!(self.writer, "$dumpvars")?;
+        }
+        for &(i, value) in changes {
+            let signal = &mut self.signals[i];
+            signal.value = Some(value);
+            match signal.source {
+                Source::Pin { .. } => writeln!(self.writer, "{}{}", value, signal.code)?,
+                Source::Register(_) => writeln!(self.writer, "b{:08b} {}", value, signal.code)?,
+            }
+        }
+        if first {
+            writeln!(self.writer, "$end")?;
+        }
+        Ok(())
+    }
+}
+
+impl Addon for Vcd {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        let cycles = core.cycle_count.saturating_sub(self.last_cycle_count);
+        // Time stands still while the clock is stopped.
+        if self.header_written && core.cpu_frequency() != 0 {
+            self.time += cycles as f64 * 1e12 / core.cpu_frequency() as f64;
+        }
+        self.last_cycle_count = core.cycle_count;
+
+        self.sample(core)
+    }
+}
+
+/// Gets the identifier code of the `index`th signal, made of the printable
+/// ASCII characters.
+fn identifier_code(mut index: usize) -> String {
+    const FIRST: u8 = b'!';
+    const COUNT: usize = (b'~' - b'!' + 1) as usize;
+
+    let mut code = String::new();
+    loop {
+        code.push((FIRST + (index % COUNT) as u8) as char);
+        index /= COUNT;
+        if index == 0 {
+            return code;
+        }
+        index -= 1;
+    }
+}
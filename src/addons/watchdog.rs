@@ -0,0 +1,190 @@
+use crate::core::SRAM_IO_OFFSET;
+use crate::reset::{self, ResetCause};
+use crate::state;
+use crate::{Addon, Core, Error, Instruction};
+
+/// `WDTCSR` bits.
+pub mod wdtcsr {
+    pub const WDIF: u8 = 1 << 7;
+    pub const WDIE: u8 = 1 << 6;
+    pub const WDP3: u8 = 1 << 5;
+    pub const WDCE: u8 = 1 << 4;
+    pub const WDE: u8 = 1 << 3;
+    pub const WDP_MASK: u8 = 0b111;
+}
+
+/// The frequency of the watchdog oscillator (hertz).
+pub const OSCILLATOR_FREQUENCY: u64 = 128_000;
+
+/// The number of cycles the change enable window stays open.
+const CHANGE_ENABLE_CYCLES: u64 = 4;
+
+/// The watchdog timer.
+///
+/// The timer is restarted by `WDR`. When it times out it either requests
+/// the watchdog interrupt, resets the core with `WDRF` set in `MCUSR`, or
+/// both in turn, depending on `WDIE` and `WDE`. Changing `WDE` or the
+/// prescaler needs the timed sequence through `WDCE`.
+pub struct Watchdog {
+    /// The data space address of `WDTCSR`.
+    register: u16,
+    vector: u8,
+
+    /// The frequency of the CPU clock (hertz).
+    pub cpu_frequency: u64,
+
+    /// The value of `WDTCSR` as the watchdog sees it.
+    control: u8,
+    /// The cycle until which `WDE` and the prescaler may be changed.
+    change_enabled_until: Option<u64>,
+    /// The cycle at which the timer was last restarted.
+    restarted_at: u64,
+    /// Whether the interrupt has been requested.
+    raised: bool,
+}
+
+impl Watchdog {
+    pub fn new(register: u16, vector: u8, cpu_frequency: u64) -> Self {
+        Watchdog {
+            register,
+            vector,
+            cpu_frequency,
+            control: 0,
+            change_enabled_until: None,
+            restarted_at: 0,
+            raised: false,
+        }
+    }
+
+    pub fn atmega328p(cpu_frequency: u64) -> Self {
+        Self::new(0x60, 6, cpu_frequency)
+    }
+
+    /// Gets the time-out period in CPU cycles.
+    ///
+    /// This is 2048 watchdog oscillator cycles doubled for each step of
+    /// the prescaler, from 16ms up to 8s.
+    pub fn timeout_cycles(&self) -> u64 {
+        let wdp = (self.control & wdtcsr::WDP_MASK) | ((self.control & wdtcsr::WDP3) >> 2);
+        let oscillator_cycles = 2048u64 << wdp.min(9);
+        oscillator_cycles * self.cpu_frequency / OSCILLATOR_FREQUENCY
+    }
+
+    /// Applies a write to `WDTCSR` by the firmware.
+    fn write(&mut self, value: u8, now: u64) {
+        const PROTECTED: u8 = wdtcsr::WDE | wdtcsr::WDP3 | wdtcsr::WDP_MASK;
+
+        let window_open = matches!(self.change_enabled_until, Some(until) if now <= until);
+        let mut control = self.control;
+
+        if value & (wdtcsr::WDCE | wdtcsr::WDE) == wdtcsr::WDCE | wdtcsr::WDE && !window_open {
+            // Start of the timed sequence.
+            self.change_enabled_until = Some(now + CHANGE_ENABLE_CYCLES);
+            control |= wdtcsr::WDCE | wdtcsr::WDE;
+        } else {
+            if window_open {
+                control = (control & !PROTECTED) | (value & PROTECTED);
+            } else {
+                // `WDE` can always be set, just not cleared.
+                control |= value & wdtcsr::WDE;
+            }
+            self.change_enabled_until = None;
+            control &= !wdtcsr::WDCE;
+        }
+
+        control = (control & !wdtcsr::WDIE) | (value & wdtcsr::WDIE);
+        // Writing a one to `WDIF` clears it.
+        if value & wdtcsr::WDIF != 0 {
+            control &= !wdtcsr::WDIF;
+        }
+
+        self.control = control;
+    }
+}
+
+impl Addon for Watchdog {
+    fn post_tick(&mut self, core: &mut Core, inst: Instruction, _: u32) -> Result<(), Error> {
+        let now = core.cycle_count;
+
+        if core.was_written(self.register) {
+            let value = core.read_data(self.register)?;
+            self.write(value, now);
+        }
+
+        if let Some(until) = self.change_enabled_until {
+            if now > until {
+                self.change_enabled_until = None;
+                self.control &= !wdtcsr::WDCE;
+            }
+        }
+
+        // `WDE` is forced on for as long as `WDRF` is set.
+        let mcusr = core.read_data(SRAM_IO_OFFSET + reset::MCUSR_ADDR as u16)?;
+        if mcusr & reset::WDRF != 0 {
+            self.control |= wdtcsr::WDE;
+        }
+
+        if inst == Instruction::Wdr {
+            self.restarted_at = now;
+        }
+
+        let enabled = self.control & (wdtcsr::WDE | wdtcsr::WDIE) != 0;
+        if !enabled {
+            self.restarted_at = now;
+        } else if now - self.restarted_at >= self.timeout_cycles() {
+            self.restarted_at = now;
+
+            if self.control & wdtcsr::WDIE != 0 {
+                self.control |= wdtcsr::WDIF;
+                // In interrupt and system reset mode the next time-out
+                // resets.
+                if self.control & wdtcsr::WDE != 0 {
+                    self.control &= !wdtcsr::WDIE;
+                }
+                core.raise_interrupt(self.vector)?;
+                self.raised = true;
+            } else {
+                core.reset_with(ResetCause::Watchdog)?;
+                self.control = wdtcsr::WDE;
+                self.change_enabled_until = None;
+                self.raised = false;
+            }
+        }
+
+        // The flag is cleared by hardware once the vector is serviced.
+        if self.raised && !core.interrupts().is_pending(self.vector) {
+            self.raised = false;
+            self.control &= !wdtcsr::WDIF;
+        }
+
+        core.write_data(self.register, self.control)
+    }
+
+    fn on_reset(&mut self, core: &mut Core, _: Option<ResetCause>) -> Result<(), Error> {
+        // `WDTCSR` is cleared like the other IO registers, and `WDE` is set
+        // again on the next tick if `WDRF` is.
+        self.control = 0;
+        self.change_enabled_until = None;
+        self.restarted_at = core.cycle_count;
+        self.raised = false;
+        Ok(())
+    }
+
+    fn save_state(&self, state: &mut state::Writer) {
+        state.u8(self.control);
+        state.bool(self.change_enabled_until.is_some());
+        state.u64(self.change_enabled_until.unwrap_or(0));
+        state.u64(self.restarted_at);
+        state.bool(self.raised);
+    }
+
+    fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        self.control = state.u8()?;
+        let change_enabled = state.bool()?;
+        let until = state.u64()?;
+        self.change_enabled_until = change_enabled.then_some(until);
+        self.restarted_at = state.u64()?;
+        self.raised = state.bool()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use crate::chips::atxmega128a4u;
+use crate::{Addon, Core, Error, Instruction};
+
+/// Offsets of the registers in a `PORTx` register block.
+pub mod offset {
+    pub const DIR: u16 = 0x00;
+    pub const DIRSET: u16 = 0x01;
+    pub const DIRCLR: u16 = 0x02;
+    pub const DIRTGL: u16 = 0x03;
+    pub const OUT: u16 = 0x04;
+    pub const OUTSET: u16 = 0x05;
+    pub const OUTCLR: u16 = 0x06;
+    pub const OUTTGL: u16 = 0x07;
+    pub const IN: u16 = 0x08;
+}
+
+/// Offsets of the registers of a virtual port.
+mod vport {
+    pub const DIR: u16 = 0x00;
+    pub const OUT: u16 = 0x01;
+    pub const IN: u16 = 0x02;
+}
+
+/// A `PORTx` register block and the virtual port it is mapped to, both by
+/// data space address.
+#[derive(Copy, Clone, Debug)]
+pub struct Block {
+    pub port: u16,
+    pub vport: u16,
+}
+
+/// Connects the `PORTx` register blocks of XMEGA chips to the virtual
+/// ports, which the core simulates as its GPIO ports.
+///
+/// Writes to `DIR` and `OUT`, and to their set, clear and toggle strobes,
+/// go to the virtual port. After every instruction the block reads back the
+/// direction, output and input levels of the virtual port, with the
+/// strobes reading as the register they change. Remapping the virtual
+/// ports with `PORTCFG.VPCTRLA` and `VPCTRLB` is not followed.
+///
+/// Everything the ports hold is in data space, so they have no state of
+/// their own for `Mcu::save_state`.
+pub struct XmegaPorts {
+    blocks: Vec<Block>,
+}
+
+impl XmegaPorts {
+    pub fn new(blocks: Vec<Block>) -> Self {
+        XmegaPorts { blocks }
+    }
+
+    /// `PORTA` to `PORTD`, on the virtual ports they are mapped to after
+    /// reset.
+    pub fn atxmega128a4u() -> Self {
+        let blocks = atxmega128a4u::PORTS
+            .iter()
+            .filter_map(|&(_, port, vport)| {
+                Some(Block {
+                    port,
+                    vport: vport?,
+                })
+            })
+            .collect();
+        Self::new(blocks)
+    }
+}
+
+impl Addon for XmegaPorts {
+    fn post_tick(&mut self, core: &mut Core, _: Instruction, _: u32) -> Result<(), Error> {
+        for block in &self.blocks {
+            for (register, target) in [(offset::DIR, vport::DIR), (offset::OUT, vport::OUT)] {
+                let target = block.vport + target;
+                let old = core.read_data(target)?;
+                let mut value = old;
+                for strobe in 0..4 {
+                    let address = block.port + register + strobe;
+                    if !core.was_written(address) {
+                        continue;
+                    }
+                    let written = core.read_data(address)?;
+                    value = match strobe {
+                        0 => written,
+                        1 => value | written,
+                        2 => value & !written,
+                        _ => value ^ written,
+                    };
+                }
+                if value != old {
+                    core.write_data(target, value)?;
+                }
+                for strobe in 0..4 {
+                    core.write_data(block.port + register + strobe, value)?;
+                }
+            }
+
+            let levels = core.read_data(block.vport + vport::IN)?;
+            core.write_data(block.port + offset::IN, levels)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,302 @@
+//! Control-flow graphs of decoded programs.
+
+use crate::inst::binary;
+use crate::symbols::Symbols;
+use crate::Instruction;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::fmt::Write;
+
+/// How control gets from one basic block to another.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum EdgeKind {
+    /// Execution runs on into the next block.
+    Fallthrough,
+    /// An unconditional jump.
+    Jump,
+    /// A conditional branch that is taken.
+    Branch,
+    /// A skip instruction skipping the next instruction.
+    Skip,
+    /// A call to a function.
+    Call,
+    /// The return from a call to the instruction after it.
+    Return,
+}
+
+/// An edge between the basic blocks starting at `from` and `to`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Edge {
+    pub from: u32,
+    pub to: u32,
+    pub kind: EdgeKind,
+}
+
+/// A straight-line sequence of instructions with a single entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    /// The byte address of the first instruction.
+    pub start: u32,
+    /// The byte address just past the last instruction.
+    pub end: u32,
+    /// The instructions and their byte addresses.
+    pub instructions: Vec<(u32, Instruction)>,
+    /// Whether the block ends with a word that could not be decoded, or
+    /// runs off the end of the program.
+    pub invalid_end: bool,
+}
+
+impl BasicBlock {
+    /// Gets the last instruction of the block, with its address.
+    pub fn terminator(&self) -> Option<(u32, Instruction)> {
+        self.instructions.last().copied()
+    }
+}
+
+/// The control-flow graph of a program.
+///
+/// The graph is built by following every jump, call, branch and skip from
+/// a set of entry points. Code that is only reached indirectly, for
+/// example through a function pointer, is not part of the graph unless it
+/// is given as an entry point.
+#[derive(Clone, Debug, Default)]
+pub struct Cfg {
+    blocks: BTreeMap<u32, BasicBlock>,
+    edges: Vec<Edge>,
+    /// The entry points and the targets of calls.
+    functions: BTreeSet<u32>,
+}
+
+/// The successors of a single instruction.
+struct Flow {
+    successors: Vec<(u32, EdgeKind)>,
+    /// Whether the instruction must end its basic block.
+    ends_block: bool,
+}
+
+impl Cfg {
+    /// Builds the control-flow graph of program space bytes, loaded at
+    /// address zero, from the given entry points.
+    pub fn build(bytes: &[u8], entries: &[u32]) -> Self {
+        let mut instructions: BTreeMap<u32, Option<Instruction>> = BTreeMap::new();
+        let mut flows: BTreeMap<u32, Flow> = BTreeMap::new();
+        let mut leaders: BTreeSet<u32> = entries.iter().copied().collect();
+        let mut functions: BTreeSet<u32> = leaders.clone();
+
+        let mut work: VecDeque<u32> = entries.iter().copied().collect();
+        while let Some(address) = work.pop_front() {
+            if instructions.contains_key(&address) {
+                continue;
+            }
+
+            let instruction = decode(bytes, address);
+            instructions.insert(address, instruction);
+            let flow = match instruction {
+                Some(instruction) => flow(bytes, address, instruction),
+                None => Flow {
+                    successors: Vec::new(),
+                    ends_block: true,
+                },
+            };
+
+            for &(target, kind) in &flow.successors {
+                if kind != EdgeKind::Fallthrough {
+                    leaders.insert(target);
+                }
+                if kind == EdgeKind::Call {
+                    functions.insert(target);
+                }
+                work.push_back(target);
+            }
+            flows.insert(address, flow);
+        }
+
+        // Split the instructions into blocks.
+        let mut blocks = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (&address, &instruction) in &instructions {
+            let continues = current.as_ref().is_some_and(|block| {
+                block.end == address
+                    && !leaders.contains(&address)
+                    && !block.invalid_end
+                    && !flows[&block.terminator().unwrap().0].ends_block
+            });
+            if !continues {
+                if let Some(block) = current.take() {
+                    blocks.insert(block.start, block);
+                }
+            }
+
+            let block = current.get_or_insert_with(|| BasicBlock {
+                start: address,
+                end: address,
+                instructions: Vec::new(),
+                invalid_end: false,
+            });
+            match instruction {
+                Some(instruction) => {
+                    block.instructions.push((address, instruction));
+                    block.end = address + instruction.size() as u32;
+                }
+                None => {
+                    block.end = address + 2;
+                    block.invalid_end = true;
+                }
+            }
+        }
+        if let Some(block) = current.take() {
+            blocks.insert(block.start, block);
+        }
+
+        // Connect the blocks.
+        let mut edges = Vec::new();
+        for block in blocks.values() {
+            let last = match block.terminator() {
+                Some((address, _)) if !block.invalid_end => address,
+                _ => continue,
+            };
+            for &(to, kind) in &flows[&last].successors {
+                if blocks.contains_key(&to) {
+                    edges.push(Edge {
+                        from: block.start,
+                        to,
+                        kind,
+                    });
+                }
+            }
+        }
+
+        Cfg {
+            blocks,
+            edges,
+            functions,
+        }
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> {
+        self.blocks.values()
+    }
+
+    /// Gets the block starting at a byte address.
+    pub fn block(&self, start: u32) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    /// Gets the block containing a byte address.
+    pub fn block_containing(&self, address: u32) -> Option<&BasicBlock> {
+        self.blocks
+            .range(..=address)
+            .next_back()
+            .map(|(_, block)| block)
+            .filter(|block| address < block.end)
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Gets the edges leaving the block starting at `start`.
+    pub fn successors(&self, start: u32) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |e| e.from == start)
+    }
+
+    /// Gets the edges entering the block starting at `start`.
+    pub fn predecessors(&self, start: u32) -> impl Iterator<Item = &Edge> {
+        self.edges.iter().filter(move |e| e.to == start)
+    }
+
+    /// Gets the byte addresses of the entry points and every function that
+    /// is called.
+    pub fn functions(&self) -> &BTreeSet<u32> {
+        &self.functions
+    }
+
+    /// Renders the graph in the Graphviz `dot` language, naming blocks
+    /// after the symbols at their addresses where there are any.
+    pub fn to_dot(&self, symbols: Option<&Symbols>) -> String {
+        let name = |address: u32| {
+            symbols
+                .and_then(|s| s.flash(address))
+                .filter(|s| s.address == address)
+                .map_or_else(|| format!("0x{:x}", address), |s| s.name.clone())
+        };
+
+        let mut dot = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+        for block in self.blocks.values() {
+            let mut label = format!("{}:\\l", name(block.start));
+            for (address, instruction) in &block.instructions {
+                let _ = write!(label, "{:x}: {}\\l", address, instruction);
+            }
+            if block.invalid_end {
+                label.push_str("<invalid>\\l");
+            }
+            let _ = writeln!(dot, "    b{:x} [label=\"{}\"];", block.start, label);
+        }
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Fallthrough | EdgeKind::Return => "",
+                EdgeKind::Jump => " [label=\"jump\"]",
+                EdgeKind::Branch => " [label=\"taken\"]",
+                EdgeKind::Skip => " [label=\"skip\"]",
+                EdgeKind::Call => " [label=\"call\", style=dashed]",
+            };
+            let _ = writeln!(dot, "    b{:x} -> b{:x}{};", edge.from, edge.to, style);
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// Decodes the instruction at a byte address, if there is a whole one.
+fn decode(bytes: &[u8], address: u32) -> Option<Instruction> {
+    let rest = bytes.get(address as usize..).filter(|r| r.len() >= 2)?;
+    let padded = rest.iter().copied().chain(std::iter::repeat(0)).take(4);
+
+    binary::read(padded)
+        .ok()
+        .filter(|i| i.size() as usize <= rest.len())
+}
+
+fn flow(bytes: &[u8], address: u32, instruction: Instruction) -> Flow {
+    let next = address + instruction.size() as u32;
+    let target = instruction.branch_target(address);
+
+    let (successors, ends_block) = match instruction {
+        Instruction::Jmp(..) | Instruction::Rjmp(..) => {
+            (vec![(target.unwrap(), EdgeKind::Jump)], true)
+        }
+        Instruction::Call(..) | Instruction::Rcall(..) => (
+            vec![(target.unwrap(), EdgeKind::Call), (next, EdgeKind::Return)],
+            true,
+        ),
+        // Indirect calls go somewhere unknown, and so do indirect jumps.
+        Instruction::Icall | Instruction::Eicall => (vec![(next, EdgeKind::Return)], true),
+        Instruction::Ijmp | Instruction::Eijmp => (Vec::new(), true),
+        Instruction::Ret | Instruction::Reti => (Vec::new(), true),
+        i if i.is_branch() => (
+            vec![
+                (target.unwrap(), EdgeKind::Branch),
+                (next, EdgeKind::Fallthrough),
+            ],
+            true,
+        ),
+        i if i.is_skip() => {
+            let skipped = decode(bytes, next).map_or(2, |i| i.size() as u32);
+            (
+                vec![
+                    (next, EdgeKind::Fallthrough),
+                    (next + skipped, EdgeKind::Skip),
+                ],
+                true,
+            )
+        }
+        _ => (vec![(next, EdgeKind::Fallthrough)], false),
+    };
+
+    Flow {
+        successors: successors
+            .into_iter()
+            .filter(|&(to, _)| (to as usize) < bytes.len())
+            .collect(),
+        ends_block,
+    }
+}
@@ -0,0 +1,7 @@
+//! Static analyses of programs.
+
+pub mod cfg;
+pub mod stack;
+
+pub use self::cfg::Cfg;
+pub use self::stack::StackAnalysis;
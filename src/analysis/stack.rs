@@ -0,0 +1,246 @@
+//! Static estimates of the worst-case stack depth.
+//!
+//! The depth of each function is the most bytes it pushes on any path
+//! through it, plus the deepest call it makes. Functions that adjust the
+//! stack pointer directly, like those with a frame pointer that allocate
+//! locals with `out SPL`, are flagged since those adjustments are not
+//! counted.
+
+use crate::analysis::cfg::{Cfg, EdgeKind};
+use crate::core::{PTR_SIZE, SPH_ADDR, SPL_ADDR, SRAM_IO_OFFSET};
+use crate::Instruction;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The stack usage of a function.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    /// The byte address of the function.
+    pub address: u32,
+    /// The most bytes the function itself pushes, not counting calls.
+    pub local: u32,
+    /// The most bytes on the stack at any point during a call of the
+    /// function, not counting its own return address, or `None` if there
+    /// is no bound because of recursion or a loop that keeps pushing.
+    pub total: Option<u32>,
+    /// The functions it calls.
+    pub callees: BTreeSet<u32>,
+    /// Whether the function may end up calling itself.
+    pub recursive: bool,
+    /// Whether the function has a loop that pushes more than it pops.
+    pub unbounded_loop: bool,
+    /// Whether the function writes the stack pointer directly.
+    pub adjusts_stack_pointer: bool,
+}
+
+/// The stack usage of every function in a control-flow graph.
+#[derive(Clone, Debug, Default)]
+pub struct StackAnalysis {
+    frames: BTreeMap<u32, Frame>,
+}
+
+/// What a function does within its own body.
+struct Body {
+    local: u32,
+    /// The depth before each call, by callee.
+    calls: BTreeMap<u32, u32>,
+    unbounded_loop: bool,
+    adjusts_stack_pointer: bool,
+}
+
+impl StackAnalysis {
+    /// Analyzes every function of a control-flow graph.
+    pub fn new(cfg: &Cfg) -> Self {
+        let bodies: BTreeMap<u32, Body> = cfg
+            .functions()
+            .iter()
+            .filter(|&&f| cfg.block(f).is_some())
+            .map(|&f| (f, body(cfg, f)))
+            .collect();
+
+        let mut totals = BTreeMap::new();
+        let mut recursive = BTreeSet::new();
+        for &function in bodies.keys() {
+            total(
+                function,
+                &bodies,
+                &mut Vec::new(),
+                &mut totals,
+                &mut recursive,
+            );
+        }
+
+        let frames = bodies
+            .iter()
+            .map(|(&address, body)| {
+                let frame = Frame {
+                    address,
+                    local: body.local,
+                    total: totals[&address],
+                    callees: body.calls.keys().copied().collect(),
+                    recursive: recursive.contains(&address),
+                    unbounded_loop: body.unbounded_loop,
+                    adjusts_stack_pointer: body.adjusts_stack_pointer,
+                };
+                (address, frame)
+            })
+            .collect();
+
+        StackAnalysis { frames }
+    }
+
+    pub fn frames(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.values()
+    }
+
+    /// Gets the frame of the function at a byte address.
+    pub fn frame(&self, address: u32) -> Option<&Frame> {
+        self.frames.get(&address)
+    }
+
+    /// Estimates the most bytes on the stack for the whole program.
+    ///
+    /// This is the deepest of the `main` entry points, plus the deepest
+    /// interrupt handler with its return address, assuming interrupts do
+    /// not nest. Returns `None` if any of the functions are unbounded.
+    pub fn worst_case(&self, main: &[u32], interrupts: &[u32]) -> Option<u32> {
+        let deepest = |entries: &[u32], extra: u32| {
+            entries
+                .iter()
+                .filter_map(|e| self.frames.get(e))
+                .map(|frame| frame.total.map(|t| t + extra))
+                .try_fold(0, |max, total| total.map(|t| max.max(t)))
+        };
+
+        Some(deepest(main, 0)? + deepest(interrupts, PTR_SIZE as u32)?)
+    }
+
+    /// Gets the functions that may call themselves.
+    pub fn recursive(&self) -> impl Iterator<Item = &Frame> {
+        self.frames.values().filter(|f| f.recursive)
+    }
+}
+
+/// Walks the blocks of a function, without following calls.
+fn body(cfg: &Cfg, function: u32) -> Body {
+    let mut result = Body {
+        local: 0,
+        calls: BTreeMap::new(),
+        unbounded_loop: false,
+        adjusts_stack_pointer: false,
+    };
+
+    // The deepest the stack has been seen on entry to each block.
+    let mut entry_depth: BTreeMap<u32, i64> = BTreeMap::new();
+    let mut updates: BTreeMap<u32, usize> = BTreeMap::new();
+    let limit = cfg.blocks().count();
+
+    entry_depth.insert(function, 0);
+    let mut work = vec![function];
+    while let Some(start) = work.pop() {
+        let block = match cfg.block(start) {
+            Some(block) => block,
+            None => continue,
+        };
+
+        let mut depth = entry_depth[&start];
+        for &(_, instruction) in &block.instructions {
+            match instruction {
+                Instruction::Push(_) => depth += 1,
+                Instruction::Pop(_) => depth -= 1,
+                // `rcall .+0` pushes a return address to allocate space.
+                Instruction::Rcall(0) => depth += PTR_SIZE as i64,
+                Instruction::Out(a, _) if a == SPL_ADDR || a == SPH_ADDR => {
+                    result.adjusts_stack_pointer = true;
+                }
+                Instruction::Sts(_, k) if is_stack_pointer(k) => {
+                    result.adjusts_stack_pointer = true;
+                }
+                _ => {}
+            }
+            result.local = result.local.max(depth.max(0) as u32);
+        }
+
+        let calls = match block.terminator() {
+            Some((_, Instruction::Call(_))) => true,
+            Some((_, Instruction::Rcall(k))) => k != 0,
+            _ => false,
+        };
+        if calls {
+            for edge in cfg.successors(start).filter(|e| e.kind == EdgeKind::Call) {
+                let before = result.calls.entry(edge.to).or_insert(0);
+                *before = (*before).max(depth.max(0) as u32);
+            }
+        }
+
+        for edge in cfg.successors(start) {
+            if edge.kind == EdgeKind::Call {
+                continue;
+            }
+            if entry_depth.get(&edge.to).is_some_and(|&d| d >= depth) {
+                continue;
+            }
+
+            let count = updates.entry(edge.to).or_insert(0);
+            *count += 1;
+            if *count > limit {
+                // The depth keeps growing around a loop.
+                result.unbounded_loop = true;
+                return result;
+            }
+            entry_depth.insert(edge.to, depth);
+            work.push(edge.to);
+        }
+    }
+
+    result
+}
+
+/// Checks if a data space address is `SPL` or `SPH`.
+fn is_stack_pointer(address: u16) -> bool {
+    address == SRAM_IO_OFFSET + SPL_ADDR as u16 || address == SRAM_IO_OFFSET + SPH_ADDR as u16
+}
+
+/// Works out the total depth of a function from its callees.
+fn total(
+    function: u32,
+    bodies: &BTreeMap<u32, Body>,
+    path: &mut Vec<u32>,
+    totals: &mut BTreeMap<u32, Option<u32>>,
+    recursive: &mut BTreeSet<u32>,
+) -> Option<u32> {
+    if let Some(&total) = totals.get(&function) {
+        return total;
+    }
+    if let Some(position) = path.iter().position(|&f| f == function) {
+        recursive.extend(&path[position..]);
+        return None;
+    }
+
+    let body = &bodies[&function];
+    path.push(function);
+    let mut result = if body.unbounded_loop {
+        None
+    } else {
+        Some(body.local)
+    };
+    for (&callee, &before) in &body.calls {
+        let callee_total = if bodies.contains_key(&callee) {
+            total(callee, bodies, path, totals, recursive)
+        } else {
+            Some(0)
+        };
+        result = match (result, callee_total) {
+            (Some(r), Some(c)) => Some(r.max(before + PTR_SIZE as u32 + c)),
+            _ => None,
+        };
+    }
+    path.pop();
+
+    // Functions in a cycle are unbounded, even when first reached from
+    // elsewhere in the cycle.
+    if recursive.contains(&function) {
+        result = None;
+    }
+    totals.insert(function, result);
+    result
+}
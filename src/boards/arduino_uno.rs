@@ -0,0 +1,135 @@
+//! The Arduino Uno, an ATmega328P on a 16 MHz crystal.
+
+use crate::addons::uart::{self, Sink};
+use crate::addons::{
+    Adc, AnalogComparator, Eeprom, ExternalInterrupt, PinChangeInterrupt, Spi, Timer16, Timer8,
+    Twi, Uart, Watchdog,
+};
+use crate::chips::atmega328p;
+use crate::fuses::Fuses;
+use crate::pin::Pin;
+use crate::reset::ResetCause;
+use crate::{Core, Error, Mcu};
+use std::ops::{Deref, DerefMut};
+
+/// The frequency of the crystal (hertz).
+pub const CLOCK_FREQUENCY: u64 = 16_000_000;
+
+/// The pin with the on-board LED.
+pub const LED_BUILTIN: &str = "D13";
+
+/// The fuses of an Uno, except that `BOOTRST` is unprogrammed, as sketches
+/// are loaded without the bootloader.
+pub const FUSES: Fuses = Fuses {
+    low: 0xff,
+    high: 0xdf,
+    extended: 0xfd,
+    lock: 0xff,
+};
+
+/// An Arduino Uno, running sketches like
+///
+/// ```text
+/// let mut uno = ArduinoUno::new();
+/// uno.load_elf("blink.ino.elf")?;
+/// ```
+///
+/// It dereferences to the `Mcu`, which has every peripheral of the
+/// ATmega328P attached, with `USART0` registered for `Stimulus::UartRx` and
+/// printing what the sketch writes to `Serial` to stdout.
+pub struct ArduinoUno {
+    pub mcu: Mcu,
+    serial: uart::Handle,
+}
+
+impl ArduinoUno {
+    pub fn new() -> Self {
+        ArduinoUno::with_serial(Sink::Stdout)
+    }
+
+    /// Sends what the sketch writes to `Serial` somewhere other than
+    /// stdout.
+    pub fn with_serial<S>(sink: S) -> Self
+    where
+        S: Into<Sink>,
+    {
+        let mut mcu = Mcu::new(Core::power_on::<atmega328p::Chip>());
+        mcu.set_clock_frequency(CLOCK_FREQUENCY);
+        mcu.set_fuses(FUSES);
+        mcu.reset_with(ResetCause::PowerOn)
+            .expect("a power-on reset of a new core cannot fail");
+
+        let usart = Uart::atmega328p().with_sink(sink);
+        let serial = usart.handle();
+        mcu.register_uart(serial.clone());
+
+        let adc = Adc::atmega328p();
+        mcu.register_adc(adc.handle());
+
+        mcu.attach(Box::new(Timer8::atmega328p_timer0()));
+        mcu.attach(Box::new(Timer16::atmega328p_timer1()));
+        mcu.attach(Box::new(Timer8::atmega328p_timer2()));
+        mcu.attach(Box::new(usart));
+        mcu.attach(Box::new(Spi::atmega328p()));
+        mcu.attach(Box::new(Twi::atmega328p()));
+        mcu.attach(Box::new(adc));
+        mcu.attach(Box::new(AnalogComparator::atmega328p()));
+        mcu.attach(Box::new(ExternalInterrupt::atmega328p()));
+        mcu.attach(Box::new(PinChangeInterrupt::atmega328p()));
+        mcu.attach(Box::new(Watchdog::atmega328p(CLOCK_FREQUENCY)));
+        mcu.attach(Box::new(Eeprom::atmega328p(CLOCK_FREQUENCY)));
+
+        ArduinoUno { mcu, serial }
+    }
+
+    /// Gets a handle for sending bytes to the sketch's `Serial`.
+    pub fn serial(&self) -> uart::Handle {
+        self.serial.clone()
+    }
+
+    /// Gets a pin by its Arduino name, like `D13` or `A0`, or by its AVR
+    /// name, like `PB5`.
+    pub fn pin(&mut self, name: &str) -> Result<Pin<'_>, Error> {
+        match avr_pin(name) {
+            Some((port, bit)) => self.mcu.pin(&format!("P{}{}", port, bit)),
+            None if name.starts_with('P') => self.mcu.pin(name),
+            None => Err(Error::InvalidPinName(name.to_owned())),
+        }
+    }
+}
+
+impl Default for ArduinoUno {
+    fn default() -> Self {
+        ArduinoUno::new()
+    }
+}
+
+impl Deref for ArduinoUno {
+    type Target = Mcu;
+
+    fn deref(&self) -> &Mcu {
+        &self.mcu
+    }
+}
+
+impl DerefMut for ArduinoUno {
+    fn deref_mut(&mut self) -> &mut Mcu {
+        &mut self.mcu
+    }
+}
+
+/// Gets the port and bit of an Arduino pin name.
+///
+/// `D0` to `D7` are `PD0` to `PD7`, `D8` to `D13` are `PB0` to `PB5`, and
+/// the analog inputs `A0` to `A5` are `PC0` to `PC5`, which can also be
+/// called `D14` to `D19`.
+pub fn avr_pin(name: &str) -> Option<(char, u8)> {
+    let number = name.get(1..)?.parse::<u8>().ok()?;
+    match (name.as_bytes()[0], number) {
+        (b'D', 0..=7) => Some(('D', number)),
+        (b'D', 8..=13) => Some(('B', number - 8)),
+        (b'D', 14..=19) => Some(('C', number - 14)),
+        (b'A', 0..=5) => Some(('C', number)),
+        _ => None,
+    }
+}
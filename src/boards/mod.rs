@@ -0,0 +1,6 @@
+//! Presets for development boards, which wire up a chip with its clock and
+//! the peripherals the board's software expects.
+
+pub mod arduino_uno;
+
+pub use self::arduino_uno::ArduinoUno;
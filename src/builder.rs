@@ -0,0 +1,272 @@
+//! Building cores for chips described at runtime.
+
+use crate::chips::{self, ChipDescriptor, Family};
+use crate::core::{IO_SPACE_SIZE, SRAM_DATA_OFFSET};
+use crate::fuses::Fuses;
+use crate::interrupt;
+use crate::io;
+use crate::mem::OutOfRange;
+use crate::{Core, Error};
+
+/// Builds a `Core` from settings chosen at runtime, for command line
+/// tools, GUIs and configuration files that cannot name a `Chip` type.
+///
+/// The builder starts from a known chip, or from a bare classic core
+/// with `new`, and each setting replaces part of its description:
+///
+/// ```text
+/// let core = CoreBuilder::from_chip(chips::by_name("atmega328p").unwrap())
+///     .with_flash_size(16 * 1024)
+///     .with_clock_frequency(8_000_000)
+///     .build()?;
+/// ```
+///
+/// `build` checks the description fits together before creating the core.
+#[derive(Clone, Debug)]
+pub struct CoreBuilder {
+    chip: ChipDescriptor,
+    flash_out_of_range: OutOfRange,
+    data_out_of_range: OutOfRange,
+    eeprom_out_of_range: OutOfRange,
+}
+
+impl CoreBuilder {
+    /// Starts from a classic core without flash, SRAM, ports or vectors
+    /// besides `RESET`, which have to be set.
+    pub fn new() -> Self {
+        let sram_start = SRAM_DATA_OFFSET;
+        Self::from_chip(ChipDescriptor {
+            name: "custom".to_owned(),
+            register_file: chips::register_file(sram_start),
+            io_ports: Vec::new(),
+            word_registers: Vec::new(),
+            io_registers: Vec::new(),
+            interrupt_vectors: vec![interrupt::Vector::new("RESET", 0)],
+            flash_size: 0,
+            memory_size: 0,
+            eeprom_size: 0,
+            sram_start,
+            ram_end: sram_start,
+            supports_des: false,
+            flash_page_size: 128,
+            clock_frequency: 1_000_000,
+            f_cpu_hints: Vec::new(),
+            family: Family::Classic,
+            default_fuses: Fuses::unprogrammed(),
+            min_boot_section_words: 256,
+            clkpr_address: None,
+            return_address_size: 2,
+            rampz_address: None,
+            eind_address: None,
+            mapped_flash_address: None,
+        })
+    }
+
+    /// Starts from the description of a chip, like one from
+    /// `chips::by_name` or `chips::atdf::load`.
+    pub fn from_chip(chip: ChipDescriptor) -> Self {
+        CoreBuilder {
+            chip,
+            flash_out_of_range: OutOfRange::Fault,
+            data_out_of_range: OutOfRange::Fault,
+            eeprom_out_of_range: OutOfRange::Fault,
+        }
+    }
+
+    /// Gets the description of the chip so far.
+    pub fn descriptor(&self) -> &ChipDescriptor {
+        &self.chip
+    }
+
+    pub fn with_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.chip.name = name.into();
+        self
+    }
+
+    /// Sets the core family, which decides instruction timings and the
+    /// layout of the data space.
+    pub fn with_family(mut self, family: Family) -> Self {
+        self.chip.family = family;
+        self
+    }
+
+    /// Sets the size of flash in bytes. Return addresses take three bytes
+    /// on the stack above 128KiB.
+    pub fn with_flash_size(mut self, size: usize) -> Self {
+        self.chip.flash_size = size;
+        self.chip.return_address_size = if size > 128 * 1024 { 3 } else { 2 };
+        self
+    }
+
+    /// Sets the size of a flash page in bytes, as used by `SPM`.
+    pub fn with_flash_page_size(mut self, size: usize) -> Self {
+        self.chip.flash_page_size = size;
+        self
+    }
+
+    /// Sets the first address of SRAM in the data space and its size in
+    /// bytes. The stack pointer starts at its last address.
+    pub fn with_sram(mut self, start: u16, size: usize) -> Self {
+        let ram_end = (start as usize + size.max(1) - 1) as u16;
+        self.chip.sram_start = start;
+        self.chip.memory_size = size;
+        self.chip.ram_end = ram_end;
+        self.chip.register_file = chips::register_file(ram_end);
+        self
+    }
+
+    /// Sets the size of the EEPROM in bytes.
+    pub fn with_eeprom_size(mut self, size: usize) -> Self {
+        self.chip.eeprom_size = size;
+        self
+    }
+
+    /// Sets the GPIO ports.
+    pub fn with_io_ports(mut self, ports: Vec<io::Port>) -> Self {
+        self.chip.io_ports = ports;
+        self
+    }
+
+    /// Sets the named IO registers, for debuggers and tracers.
+    pub fn with_io_registers(mut self, registers: Vec<io::Register>) -> Self {
+        self.chip.io_registers = registers;
+        self
+    }
+
+    /// Sets the 16-bit IO registers accessed through `TEMP`, by the data
+    /// space address of their low byte.
+    pub fn with_word_registers(mut self, addresses: Vec<u16>) -> Self {
+        self.chip.word_registers = addresses;
+        self
+    }
+
+    /// Sets the interrupt vector table, starting with `RESET`.
+    pub fn with_interrupt_vectors(mut self, vectors: Vec<interrupt::Vector>) -> Self {
+        self.chip.interrupt_vectors = vectors;
+        self
+    }
+
+    /// Sets the frequency of the clock source (hertz).
+    pub fn with_clock_frequency(mut self, hertz: u64) -> Self {
+        self.chip.clock_frequency = hertz;
+        self
+    }
+
+    /// Sets the fuse bytes and lock bits the core starts with.
+    pub fn with_fuses(mut self, fuses: Fuses) -> Self {
+        self.chip.default_fuses = fuses;
+        self
+    }
+
+    /// Sets whether the `DES` instruction is available.
+    pub fn with_des(mut self, supported: bool) -> Self {
+        self.chip.supports_des = supported;
+        self
+    }
+
+    /// Sets the data space address flash is mapped at, if it is.
+    pub fn with_mapped_flash(mut self, address: Option<u16>) -> Self {
+        self.chip.mapped_flash_address = address;
+        self
+    }
+
+    /// Sets what accesses past the end of flash do, like those of `LPM`
+    /// and `SPM`.
+    pub fn with_flash_out_of_range(mut self, policy: OutOfRange) -> Self {
+        self.flash_out_of_range = policy;
+        self
+    }
+
+    /// Sets what accesses past `RAMEND` in the data space do.
+    pub fn with_data_out_of_range(mut self, policy: OutOfRange) -> Self {
+        self.data_out_of_range = policy;
+        self
+    }
+
+    /// Sets what accesses past the end of the EEPROM do.
+    pub fn with_eeprom_out_of_range(mut self, policy: OutOfRange) -> Self {
+        self.eeprom_out_of_range = policy;
+        self
+    }
+
+    /// Checks the description and finishes it, for creating several cores
+    /// or registering it with a tool.
+    pub fn describe(self) -> Result<ChipDescriptor, Error> {
+        self.check()?;
+        Ok(self.chip)
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        let chip = &self.chip;
+        let invalid = |message| Err(Error::InvalidChip(message));
+
+        if chip.flash_size == 0 {
+            return invalid("the flash size is not set");
+        }
+        if !chip.flash_size.is_multiple_of(2) {
+            return invalid("the flash size is odd");
+        }
+        if chip.flash_page_size == 0 || !chip.flash_size.is_multiple_of(chip.flash_page_size) {
+            return invalid("the flash page size does not divide the flash size");
+        }
+        if chip.memory_size == 0 {
+            return invalid("the SRAM size is not set");
+        }
+        if chip.sram_start as usize + chip.memory_size > 0x10000 {
+            return invalid("SRAM does not fit in the data space");
+        }
+        if chip.sram_start < chip.family.io_offset() + IO_SPACE_SIZE {
+            return invalid("SRAM overlaps the IO space");
+        }
+        if chip
+            .mapped_flash_address
+            .is_some_and(|address| address <= chip.ram_end)
+        {
+            return invalid("mapped flash overlaps SRAM");
+        }
+        if chip.interrupt_vectors.is_empty() {
+            return invalid("there is no RESET vector");
+        }
+        if chip
+            .interrupt_vectors
+            .iter()
+            .any(|v| v.address as usize >= chip.flash_size)
+        {
+            return invalid("an interrupt vector is outside flash");
+        }
+        if chip.io_ports.iter().any(|p| {
+            [p.pin, p.ddr, p.port]
+                .iter()
+                .any(|&a| a as u16 >= IO_SPACE_SIZE)
+        }) {
+            return invalid("a port register is outside the IO space");
+        }
+        Ok(())
+    }
+
+    /// Creates the core.
+    pub fn build(self) -> Result<Core, Error> {
+        self.check()?;
+        Ok(self.apply(Core::for_chip(&self.chip)))
+    }
+
+    /// Creates the core as it is after power-on, see `Core::power_on`.
+    pub fn power_on(self) -> Result<Core, Error> {
+        self.check()?;
+        Ok(self.apply(Core::power_on_chip(&self.chip)))
+    }
+
+    fn apply(&self, mut core: Core) -> Core {
+        core.program_space_mut()
+            .set_out_of_range(self.flash_out_of_range);
+        core.memory_mut().set_out_of_range(self.data_out_of_range);
+        core.eeprom_mut().set_out_of_range(self.eeprom_out_of_range);
+        core
+    }
+}
+
+impl Default for CoreBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
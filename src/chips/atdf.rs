@@ -0,0 +1,324 @@
+//! Chip descriptions from Microchip's ATDF files, found in the `atdf`
+//! directory of the device packs.
+//!
+//! The memory sizes, IO registers, GPIO ports and interrupt vectors of the
+//! first device in the file are read. Fuse defaults are read from the
+//! `initval` of the fuse and lock bit registers where the file has them.
+//! ATDF files do not give the clock frequency, so chips shipped with
+//! `CKDIV8` programmed are taken to run from an 8 MHz internal oscillator,
+//! and others from 1 MHz.
+//!
+//! On AVRxt chips the ports are their `VPORTx` registers in the IO space,
+//! and the data space segment flash is mapped into is read.
+
+use super::{ChipDescriptor, Family};
+use crate::core::IO_SPACE_SIZE;
+use crate::fuses::{self, Fuses};
+use crate::interrupt;
+use crate::io;
+use crate::xml::{self, Element};
+use crate::Error;
+use std::fs;
+use std::path::Path;
+
+/// Reads the chip described by an ATDF file.
+pub fn load<P>(path: P) -> Result<ChipDescriptor, Error>
+where
+    P: AsRef<Path>,
+{
+    let text = fs::read_to_string(path).map_err(Error::Io)?;
+    parse(&text)
+}
+
+/// Parses the chip described by the text of an ATDF file.
+pub fn parse(text: &str) -> Result<ChipDescriptor, Error> {
+    let root = xml::parse(text).map_err(|e| Error::InvalidAtdf {
+        line: e.line,
+        message: e.message.to_owned(),
+    })?;
+    if root.name != "avr-tools-device-file" {
+        return Err(invalid(&root, "not an ATDF file"));
+    }
+    let device = root
+        .child("devices")
+        .and_then(|d| d.child("device"))
+        .ok_or_else(|| invalid(&root, "no device"))?;
+
+    let name = attribute(device, "name")?.to_owned();
+    let family = match attribute(device, "architecture")? {
+        "AVR8" => Family::Classic,
+        "AVR8_XMEGA" => Family::Xmega,
+        "AVR8X" => Family::Xt,
+        "AVR8L" => Family::Reduced,
+        _ => return Err(invalid(device, "unknown architecture")),
+    };
+
+    let spaces = device
+        .child("address-spaces")
+        .ok_or_else(|| invalid(device, "no address spaces"))?;
+    let mut flash_size = 0;
+    let mut flash_page_size = None;
+    let mut boot_sections = Vec::new();
+    let mut ram = None;
+    let mut eeprom_size = 0;
+    let mut mapped_flash_address = None;
+    for space in spaces.children("address-space") {
+        let space_name = attribute(space, "name")?;
+        if space_name == "prog" {
+            flash_size = number(space, "size")? as usize;
+        }
+
+        for segment in space.children("memory-segment") {
+            let kind = attribute(segment, "type")?;
+            let size = number(segment, "size")?;
+            match (space_name, kind) {
+                ("prog", "flash") => {
+                    if flash_page_size.is_none() && segment.attribute("pagesize").is_some() {
+                        flash_page_size = Some(number(segment, "pagesize")? as usize);
+                    }
+                    if attribute(segment, "name")?.starts_with("BOOT_SECTION") {
+                        boot_sections.push(size as u32 / 2);
+                    }
+                }
+                ("data", "ram")
+                    if ram.is_none() && segment.attribute("external") != Some("true") =>
+                {
+                    ram = Some((number(segment, "start")? as u16, size as usize));
+                }
+                (_, "eeprom") if eeprom_size == 0 => eeprom_size = size as usize,
+                ("data", "flash") if mapped_flash_address.is_none() => {
+                    mapped_flash_address = Some(number(segment, "start")? as u16);
+                }
+                _ => (),
+            }
+        }
+    }
+    if flash_size == 0 {
+        return Err(invalid(spaces, "no program space"));
+    }
+    let (sram_start, memory_size) = ram.ok_or_else(|| invalid(spaces, "no SRAM"))?;
+    let ram_end = (sram_start as usize + memory_size - 1) as u16;
+
+    let registers = Registers::read(&root, device)?;
+
+    // The IO space `IN` and `OUT` can reach, in the data space.
+    let io_space = family.io_offset()..family.io_offset() + IO_SPACE_SIZE;
+    let mut io_ports = Vec::new();
+    for (instance, group) in &registers.ports {
+        let find = |matches: &dyn Fn(&str) -> bool| {
+            group
+                .iter()
+                .find(|r| matches(&r.name) && io_space.contains(&r.address))
+                .map(|r| (r.address - io_space.start) as u8)
+        };
+        let letter = instance.chars().last().unwrap_or('?');
+        let registers = if instance.starts_with("VPORT") {
+            if !letter.is_ascii_alphabetic() {
+                continue;
+            }
+            [
+                find(&|n| n == "IN"),
+                find(&|n| n == "DIR"),
+                find(&|n| n == "OUT"),
+            ]
+        } else {
+            [
+                find(&|n| n.starts_with("PIN")),
+                find(&|n| n.starts_with("DDR")),
+                find(&|n| n.starts_with("PORT")),
+            ]
+        };
+        if let [Some(pin), Some(ddr), Some(port)] = registers {
+            io_ports.push(io::Port::new(letter, pin, ddr, port));
+        }
+    }
+
+    let address_of = |name: &str| {
+        registers
+            .data
+            .iter()
+            .find(|r| r.name == name)
+            .map(|r| r.address)
+    };
+    let clkpr_address = address_of("CLKPR");
+    let rampz_address = address_of("RAMPZ");
+    let eind_address = address_of("EIND");
+
+    // Vectors are a two word `JMP` on chips with more than 8KiB of flash,
+    // and a one word `RJMP` otherwise.
+    let vector_size = if family == Family::Xmega || flash_size > 8 * 1024 {
+        4
+    } else {
+        2
+    };
+    let mut names = Vec::new();
+    if let Some(interrupts) = device.child("interrupts") {
+        for interrupt in interrupts.children("interrupt") {
+            let index = number(interrupt, "index")? as usize;
+            if names.len() <= index {
+                names.resize(index + 1, None);
+            }
+            names[index] = Some(attribute(interrupt, "name")?.to_owned());
+        }
+    }
+    if names.is_empty() {
+        names.push(Some("RESET".to_owned()));
+    }
+    let interrupt_vectors = names
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            let name = name.unwrap_or_else(|| "RESERVED".to_owned());
+            interrupt::Vector::new(name, (i * vector_size) as u32)
+        })
+        .collect();
+
+    Ok(ChipDescriptor {
+        name,
+        register_file: super::register_file(ram_end),
+        io_ports,
+        word_registers: registers.word_registers,
+        io_registers: registers.data,
+        interrupt_vectors,
+        flash_size,
+        memory_size,
+        eeprom_size,
+        sram_start,
+        ram_end,
+        supports_des: family == Family::Xmega,
+        flash_page_size: flash_page_size.unwrap_or(128),
+        clock_frequency: if registers.fuses.low & fuses::low::CKDIV8 == 0 {
+            8_000_000
+        } else {
+            1_000_000
+        },
+        f_cpu_hints: Vec::new(),
+        family,
+        default_fuses: registers.fuses,
+        min_boot_section_words: boot_sections.into_iter().min().unwrap_or(256),
+        clkpr_address,
+        return_address_size: if flash_size > 128 * 1024 { 3 } else { 2 },
+        rampz_address,
+        eind_address,
+        mapped_flash_address,
+    })
+}
+
+/// The registers of the peripherals of a device.
+struct Registers {
+    /// The registers in the data space.
+    data: Vec<io::Register>,
+    /// The registers of each `PORT` and `VPORT` instance, by instance name.
+    ports: Vec<(String, Vec<io::Register>)>,
+    /// The low bytes of the 16-bit timer registers accessed through `TEMP`.
+    word_registers: Vec<u16>,
+    fuses: Fuses,
+}
+
+impl Registers {
+    fn read(root: &Element, device: &Element) -> Result<Self, Error> {
+        let modules: Vec<&Element> = root
+            .child("modules")
+            .map(|m| m.children("module").collect())
+            .unwrap_or_default();
+        let mut registers = Registers {
+            data: Vec::new(),
+            ports: Vec::new(),
+            word_registers: Vec::new(),
+            fuses: Fuses::unprogrammed(),
+        };
+
+        let peripherals = device.child("peripherals");
+        for module in peripherals.iter().flat_map(|p| p.children("module")) {
+            let module_name = attribute(module, "name")?;
+            let definition = modules
+                .iter()
+                .find(|m| m.attribute("name") == Some(module_name))
+                .ok_or_else(|| invalid(module, "module is not defined"))?;
+
+            for instance in module.children("instance") {
+                let instance_name = attribute(instance, "name")?;
+                for group in instance.children("register-group") {
+                    let group_name = match group.attribute("name-in-module") {
+                        Some(name) => name,
+                        None => attribute(group, "name")?,
+                    };
+                    let offset = number(group, "offset")?;
+                    let space = group.attribute("address-space").unwrap_or("data");
+                    let Some(layout) = definition
+                        .children("register-group")
+                        .find(|g| g.attribute("name") == Some(group_name))
+                    else {
+                        continue;
+                    };
+
+                    let mut found = Vec::new();
+                    for register in layout.children("register") {
+                        let name = attribute(register, "name")?;
+                        let address = (offset + number(register, "offset")?) as u16;
+                        let size = number(register, "size")? as u8;
+                        found.push(io::Register::new(name, address, size));
+
+                        if space != "data" {
+                            let initial = register.attribute("initval");
+                            let initial = match initial {
+                                Some(_) => number(register, "initval")? as u8,
+                                None => continue,
+                            };
+                            match (space, name) {
+                                ("fuses", "LOW") => registers.fuses.low = initial,
+                                ("fuses", "HIGH") => registers.fuses.high = initial,
+                                ("fuses", "EXTENDED") => registers.fuses.extended = initial,
+                                ("lockbits", "LOCKBIT") => registers.fuses.lock = initial,
+                                _ => (),
+                            }
+                        } else if size == 2
+                            && ["TC16", "TCA", "TCB"]
+                                .iter()
+                                .any(|m| module_name.starts_with(m))
+                        {
+                            registers.word_registers.push(address);
+                        }
+                    }
+
+                    if space == "data" {
+                        if module_name == "PORT" || module_name == "VPORT" {
+                            registers
+                                .ports
+                                .push((instance_name.to_owned(), found.clone()));
+                        }
+                        registers.data.extend(found);
+                    }
+                }
+            }
+        }
+
+        registers.data.sort_by_key(|r| r.address);
+        registers.word_registers.sort_unstable();
+        registers.ports.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(registers)
+    }
+}
+
+fn invalid(element: &Element, message: &str) -> Error {
+    Error::InvalidAtdf {
+        line: element.line,
+        message: format!("<{}>: {}", element.name, message),
+    }
+}
+
+fn attribute<'a>(element: &'a Element, name: &str) -> Result<&'a str, Error> {
+    element
+        .attribute(name)
+        .ok_or_else(|| invalid(element, &format!("missing attribute `{}`", name)))
+}
+
+/// Reads a decimal or `0x` hexadecimal attribute.
+fn number(element: &Element, name: &str) -> Result<u64, Error> {
+    let text = attribute(element, name)?;
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| invalid(element, &format!("`{}` is not a number", text)))
+}
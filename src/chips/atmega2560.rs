@@ -0,0 +1,334 @@
+//! The ATmega2560, with 256KiB of flash and a 22-bit program counter.
+//!
+//! Return addresses are three bytes, `ELPM` and `SPM` reach the upper
+//! flash through `RAMPZ`, and `EIJMP` and `EICALL` through `EIND`. Ports
+//! `H` to `L` are in the extended IO space, so only `LDS`, `STS` and the
+//! other data space instructions reach them.
+
+use crate::chips;
+use crate::fuses::Fuses;
+use crate::interrupt;
+use crate::io;
+
+/// `RAMPZ` data space address.
+pub const RAMPZ: u16 = 0x5b;
+/// `EIND` data space address.
+pub const EIND: u16 = 0x5c;
+
+pub struct Chip;
+
+impl chips::Chip for Chip {
+    fn name() -> &'static str {
+        "ATmega2560"
+    }
+
+    fn flash_size() -> usize {
+        256 * 1024 // 256 KB
+    }
+
+    fn memory_size() -> usize {
+        8 * 1024 // 8KB
+    }
+
+    fn eeprom_size() -> usize {
+        4 * 1024 // 4KB
+    }
+
+    fn sram_start() -> u16 {
+        0x200 // after the extended IO space
+    }
+
+    fn clock_frequency() -> u64 {
+        8_000_000 // the internal RC oscillator
+    }
+
+    fn f_cpu_hints() -> Vec<u64> {
+        vec![16_000_000, 8_000_000, 1_000_000]
+    }
+
+    fn default_fuses() -> Fuses {
+        Fuses {
+            low: 0x62,
+            high: 0x99,
+            extended: 0xff,
+            lock: 0xff,
+        }
+    }
+
+    fn min_boot_section_words() -> u32 {
+        512
+    }
+
+    fn clkpr_address() -> Option<u16> {
+        Some(0x61)
+    }
+
+    fn rampz_address() -> Option<u16> {
+        Some(RAMPZ)
+    }
+
+    fn eind_address() -> Option<u16> {
+        Some(EIND)
+    }
+
+    fn io_registers() -> Vec<io::Register> {
+        // The data space address and size in bytes of each register.
+        [
+            ("PINA", 0x20, 1),
+            ("DDRA", 0x21, 1),
+            ("PORTA", 0x22, 1),
+            ("PINB", 0x23, 1),
+            ("DDRB", 0x24, 1),
+            ("PORTB", 0x25, 1),
+            ("PINC", 0x26, 1),
+            ("DDRC", 0x27, 1),
+            ("PORTC", 0x28, 1),
+            ("PIND", 0x29, 1),
+            ("DDRD", 0x2a, 1),
+            ("PORTD", 0x2b, 1),
+            ("PINE", 0x2c, 1),
+            ("DDRE", 0x2d, 1),
+            ("PORTE", 0x2e, 1),
+            ("PINF", 0x2f, 1),
+            ("DDRF", 0x30, 1),
+            ("PORTF", 0x31, 1),
+            ("PING", 0x32, 1),
+            ("DDRG", 0x33, 1),
+            ("PORTG", 0x34, 1),
+            ("TIFR0", 0x35, 1),
+            ("TIFR1", 0x36, 1),
+            ("TIFR2", 0x37, 1),
+            ("TIFR3", 0x38, 1),
+            ("TIFR4", 0x39, 1),
+            ("TIFR5", 0x3a, 1),
+            ("PCIFR", 0x3b, 1),
+            ("EIFR", 0x3c, 1),
+            ("EIMSK", 0x3d, 1),
+            ("GPIOR0", 0x3e, 1),
+            ("EECR", 0x3f, 1),
+            ("EEDR", 0x40, 1),
+            ("EEAR", 0x41, 2),
+            ("GTCCR", 0x43, 1),
+            ("TCCR0A", 0x44, 1),
+            ("TCCR0B", 0x45, 1),
+            ("TCNT0", 0x46, 1),
+            ("OCR0A", 0x47, 1),
+            ("OCR0B", 0x48, 1),
+            ("GPIOR1", 0x4a, 1),
+            ("GPIOR2", 0x4b, 1),
+            ("SPCR", 0x4c, 1),
+            ("SPSR", 0x4d, 1),
+            ("SPDR", 0x4e, 1),
+            ("ACSR", 0x50, 1),
+            ("OCDR", 0x51, 1),
+            ("SMCR", 0x53, 1),
+            ("MCUSR", 0x54, 1),
+            ("MCUCR", 0x55, 1),
+            ("SPMCSR", 0x57, 1),
+            ("RAMPZ", 0x5b, 1),
+            ("EIND", 0x5c, 1),
+            ("SP", 0x5d, 2),
+            ("SREG", 0x5f, 1),
+            ("WDTCSR", 0x60, 1),
+            ("CLKPR", 0x61, 1),
+            ("PRR0", 0x64, 1),
+            ("PRR1", 0x65, 1),
+            ("OSCCAL", 0x66, 1),
+            ("PCICR", 0x68, 1),
+            ("EICRA", 0x69, 1),
+            ("EICRB", 0x6a, 1),
+            ("PCMSK0", 0x6b, 1),
+            ("PCMSK1", 0x6c, 1),
+            ("PCMSK2", 0x6d, 1),
+            ("TIMSK0", 0x6e, 1),
+            ("TIMSK1", 0x6f, 1),
+            ("TIMSK2", 0x70, 1),
+            ("TIMSK3", 0x71, 1),
+            ("TIMSK4", 0x72, 1),
+            ("TIMSK5", 0x73, 1),
+            ("XMCRA", 0x74, 1),
+            ("XMCRB", 0x75, 1),
+            ("ADC", 0x78, 2),
+            ("ADCSRA", 0x7a, 1),
+            ("ADCSRB", 0x7b, 1),
+            ("ADMUX", 0x7c, 1),
+            ("DIDR2", 0x7d, 1),
+            ("DIDR0", 0x7e, 1),
+            ("DIDR1", 0x7f, 1),
+            ("TCCR1A", 0x80, 1),
+            ("TCCR1B", 0x81, 1),
+            ("TCCR1C", 0x82, 1),
+            ("TCNT1", 0x84, 2),
+            ("ICR1", 0x86, 2),
+            ("OCR1A", 0x88, 2),
+            ("OCR1B", 0x8a, 2),
+            ("OCR1C", 0x8c, 2),
+            ("TCCR3A", 0x90, 1),
+            ("TCCR3B", 0x91, 1),
+            ("TCCR3C", 0x92, 1),
+            ("TCNT3", 0x94, 2),
+            ("ICR3", 0x96, 2),
+            ("OCR3A", 0x98, 2),
+            ("OCR3B", 0x9a, 2),
+            ("OCR3C", 0x9c, 2),
+            ("TCCR4A", 0xa0, 1),
+            ("TCCR4B", 0xa1, 1),
+            ("TCCR4C", 0xa2, 1),
+            ("TCNT4", 0xa4, 2),
+            ("ICR4", 0xa6, 2),
+            ("OCR4A", 0xa8, 2),
+            ("OCR4B", 0xaa, 2),
+            ("OCR4C", 0xac, 2),
+            ("TCCR2A", 0xb0, 1),
+            ("TCCR2B", 0xb1, 1),
+            ("TCNT2", 0xb2, 1),
+            ("OCR2A", 0xb3, 1),
+            ("OCR2B", 0xb4, 1),
+            ("ASSR", 0xb6, 1),
+            ("TWBR", 0xb8, 1),
+            ("TWSR", 0xb9, 1),
+            ("TWAR", 0xba, 1),
+            ("TWDR", 0xbb, 1),
+            ("TWCR", 0xbc, 1),
+            ("TWAMR", 0xbd, 1),
+            ("UCSR0A", 0xc0, 1),
+            ("UCSR0B", 0xc1, 1),
+            ("UCSR0C", 0xc2, 1),
+            ("UBRR0", 0xc4, 2),
+            ("UDR0", 0xc6, 1),
+            ("UCSR1A", 0xc8, 1),
+            ("UCSR1B", 0xc9, 1),
+            ("UCSR1C", 0xca, 1),
+            ("UBRR1", 0xcc, 2),
+            ("UDR1", 0xce, 1),
+            ("UCSR2A", 0xd0, 1),
+            ("UCSR2B", 0xd1, 1),
+            ("UCSR2C", 0xd2, 1),
+            ("UBRR2", 0xd4, 2),
+            ("UDR2", 0xd6, 1),
+            ("PINH", 0x100, 1),
+            ("DDRH", 0x101, 1),
+            ("PORTH", 0x102, 1),
+            ("PINJ", 0x103, 1),
+            ("DDRJ", 0x104, 1),
+            ("PORTJ", 0x105, 1),
+            ("PINK", 0x106, 1),
+            ("DDRK", 0x107, 1),
+            ("PORTK", 0x108, 1),
+            ("PINL", 0x109, 1),
+            ("DDRL", 0x10a, 1),
+            ("PORTL", 0x10b, 1),
+            ("TCCR5A", 0x120, 1),
+            ("TCCR5B", 0x121, 1),
+            ("TCCR5C", 0x122, 1),
+            ("TCNT5", 0x124, 2),
+            ("ICR5", 0x126, 2),
+            ("OCR5A", 0x128, 2),
+            ("OCR5B", 0x12a, 2),
+            ("OCR5C", 0x12c, 2),
+            ("UCSR3A", 0x130, 1),
+            ("UCSR3B", 0x131, 1),
+            ("UCSR3C", 0x132, 1),
+            ("UBRR3", 0x134, 2),
+            ("UDR3", 0x136, 1),
+        ]
+        .iter()
+        .map(|&(name, address, size)| io::Register::new(name, address, size))
+        .collect()
+    }
+
+    fn word_registers() -> Vec<u16> {
+        // `TCNTn`, `ICRn` and `OCRnA` to `OCRnC` of the 16-bit timers.
+        [0x84, 0x94, 0xa4, 0x124]
+            .iter()
+            .flat_map(|&tcnt| (0..5).map(move |i| tcnt + 2 * i))
+            .collect()
+    }
+
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        // Each vector is a two word `JMP`.
+        [
+            "RESET",
+            "INT0",
+            "INT1",
+            "INT2",
+            "INT3",
+            "INT4",
+            "INT5",
+            "INT6",
+            "INT7",
+            "PCINT0",
+            "PCINT1",
+            "PCINT2",
+            "WDT",
+            "TIMER2_COMPA",
+            "TIMER2_COMPB",
+            "TIMER2_OVF",
+            "TIMER1_CAPT",
+            "TIMER1_COMPA",
+            "TIMER1_COMPB",
+            "TIMER1_COMPC",
+            "TIMER1_OVF",
+            "TIMER0_COMPA",
+            "TIMER0_COMPB",
+            "TIMER0_OVF",
+            "SPI_STC",
+            "USART0_RX",
+            "USART0_UDRE",
+            "USART0_TX",
+            "ANALOG_COMP",
+            "ADC",
+            "EE_READY",
+            "TIMER3_CAPT",
+            "TIMER3_COMPA",
+            "TIMER3_COMPB",
+            "TIMER3_COMPC",
+            "TIMER3_OVF",
+            "USART1_RX",
+            "USART1_UDRE",
+            "USART1_TX",
+            "TWI",
+            "SPM_READY",
+            "TIMER4_CAPT",
+            "TIMER4_COMPA",
+            "TIMER4_COMPB",
+            "TIMER4_COMPC",
+            "TIMER4_OVF",
+            "TIMER5_CAPT",
+            "TIMER5_COMPA",
+            "TIMER5_COMPB",
+            "TIMER5_COMPC",
+            "TIMER5_OVF",
+            "USART2_RX",
+            "USART2_UDRE",
+            "USART2_TX",
+            "USART3_RX",
+            "USART3_UDRE",
+            "USART3_TX",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| interrupt::Vector::new(*name, i as u32 * 4))
+        .collect()
+    }
+
+    fn flash_page_size() -> usize {
+        256 // 128 words
+    }
+
+    fn io_ports() -> Vec<io::Port> {
+        vec![
+            io::Port::new('A', 0x00, 0x01, 0x02),
+            io::Port::new('B', 0x03, 0x04, 0x05),
+            io::Port::new('C', 0x06, 0x07, 0x08),
+            io::Port::new('D', 0x09, 0x0a, 0x0b),
+            io::Port::new('E', 0x0c, 0x0d, 0x0e),
+            io::Port::new('F', 0x0f, 0x10, 0x11),
+            io::Port::new('G', 0x12, 0x13, 0x14),
+            io::Port::new('H', 0xe0, 0xe1, 0xe2),
+            io::Port::new('J', 0xe3, 0xe4, 0xe5),
+            io::Port::new('K', 0xe6, 0xe7, 0xe8),
+            io::Port::new('L', 0xe9, 0xea, 0xeb),
+        ]
+    }
+}
@@ -1,9 +1,34 @@
 use crate::chips;
+use crate::fuses::Fuses;
+use crate::interrupt;
 use crate::io;
 
+/// `PINB` IO address.
+pub const PINB: u8 = 0x03;
+/// `DDRB` IO address.
+pub const DDRB: u8 = 0x04;
+/// `PORTB` IO address.
+pub const PORTB: u8 = 0x05;
+/// `PINC` IO address.
+pub const PINC: u8 = 0x06;
+/// `DDRC` IO address.
+pub const DDRC: u8 = 0x07;
+/// `PORTC` IO address.
+pub const PORTC: u8 = 0x08;
+/// `PIND` IO address.
+pub const PIND: u8 = 0x09;
+/// `DDRD` IO address.
+pub const DDRD: u8 = 0x0a;
+/// `PORTD` IO address.
+pub const PORTD: u8 = 0x0b;
+
 pub struct Chip;
 
 impl chips::Chip for Chip {
+    fn name() -> &'static str {
+        "ATmega328P"
+    }
+
     fn flash_size() -> usize {
         32 * 1024 // 32 KB
     }
@@ -12,17 +37,177 @@ impl chips::Chip for Chip {
         2 * 1024 // 2KB
     }
 
+    fn eeprom_size() -> usize {
+        1024 // 1KB
+    }
+
+    fn sram_start() -> u16 {
+        0x100 // after the extended IO space
+    }
+
+    fn clock_frequency() -> u64 {
+        8_000_000 // the internal RC oscillator
+    }
+
+    fn default_fuses() -> Fuses {
+        Fuses {
+            low: 0x62,
+            high: 0xd9,
+            extended: 0xff,
+            lock: 0xff,
+        }
+    }
+
+    fn clkpr_address() -> Option<u16> {
+        Some(0x61)
+    }
+
+    fn f_cpu_hints() -> Vec<u64> {
+        vec![16_000_000, 8_000_000, 1_000_000, 20_000_000]
+    }
+
+    fn io_registers() -> Vec<io::Register> {
+        // The data space address and size in bytes of each register.
+        [
+            ("PINB", 0x23, 1),
+            ("DDRB", 0x24, 1),
+            ("PORTB", 0x25, 1),
+            ("PINC", 0x26, 1),
+            ("DDRC", 0x27, 1),
+            ("PORTC", 0x28, 1),
+            ("PIND", 0x29, 1),
+            ("DDRD", 0x2a, 1),
+            ("PORTD", 0x2b, 1),
+            ("TIFR0", 0x35, 1),
+            ("TIFR1", 0x36, 1),
+            ("TIFR2", 0x37, 1),
+            ("PCIFR", 0x3b, 1),
+            ("EIFR", 0x3c, 1),
+            ("EIMSK", 0x3d, 1),
+            ("GPIOR0", 0x3e, 1),
+            ("EECR", 0x3f, 1),
+            ("EEDR", 0x40, 1),
+            ("EEAR", 0x41, 2),
+            ("GTCCR", 0x43, 1),
+            ("TCCR0A", 0x44, 1),
+            ("TCCR0B", 0x45, 1),
+            ("TCNT0", 0x46, 1),
+            ("OCR0A", 0x47, 1),
+            ("OCR0B", 0x48, 1),
+            ("GPIOR1", 0x4a, 1),
+            ("GPIOR2", 0x4b, 1),
+            ("SPCR", 0x4c, 1),
+            ("SPSR", 0x4d, 1),
+            ("SPDR", 0x4e, 1),
+            ("ACSR", 0x50, 1),
+            ("SMCR", 0x53, 1),
+            ("MCUSR", 0x54, 1),
+            ("MCUCR", 0x55, 1),
+            ("SPMCSR", 0x57, 1),
+            ("SP", 0x5d, 2),
+            ("SREG", 0x5f, 1),
+            ("WDTCSR", 0x60, 1),
+            ("CLKPR", 0x61, 1),
+            ("PRR", 0x64, 1),
+            ("OSCCAL", 0x66, 1),
+            ("PCICR", 0x68, 1),
+            ("EICRA", 0x69, 1),
+            ("PCMSK0", 0x6b, 1),
+            ("PCMSK1", 0x6c, 1),
+            ("PCMSK2", 0x6d, 1),
+            ("TIMSK0", 0x6e, 1),
+            ("TIMSK1", 0x6f, 1),
+            ("TIMSK2", 0x70, 1),
+            ("ADC", 0x78, 2),
+            ("ADCSRA", 0x7a, 1),
+            ("ADCSRB", 0x7b, 1),
+            ("ADMUX", 0x7c, 1),
+            ("DIDR0", 0x7e, 1),
+            ("DIDR1", 0x7f, 1),
+            ("TCCR1A", 0x80, 1),
+            ("TCCR1B", 0x81, 1),
+            ("TCCR1C", 0x82, 1),
+            ("TCNT1", 0x84, 2),
+            ("ICR1", 0x86, 2),
+            ("OCR1A", 0x88, 2),
+            ("OCR1B", 0x8a, 2),
+            ("TCCR2A", 0xb0, 1),
+            ("TCCR2B", 0xb1, 1),
+            ("TCNT2", 0xb2, 1),
+            ("OCR2A", 0xb3, 1),
+            ("OCR2B", 0xb4, 1),
+            ("ASSR", 0xb6, 1),
+            ("TWBR", 0xb8, 1),
+            ("TWSR", 0xb9, 1),
+            ("TWAR", 0xba, 1),
+            ("TWDR", 0xbb, 1),
+            ("TWCR", 0xbc, 1),
+            ("TWAMR", 0xbd, 1),
+            ("UCSR0A", 0xc0, 1),
+            ("UCSR0B", 0xc1, 1),
+            ("UCSR0C", 0xc2, 1),
+            ("UBRR0", 0xc4, 2),
+            ("UDR0", 0xc6, 1),
+        ]
+        .iter()
+        .map(|&(name, address, size)| io::Register::new(name, address, size))
+        .collect()
+    }
+
+    fn word_registers() -> Vec<u16> {
+        vec![
+            0x84, // TCNT1
+            0x86, // ICR1
+            0x88, // OCR1A
+            0x8a, // OCR1B
+        ]
+    }
+
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        // Each vector is a two word `JMP`.
+        [
+            "RESET",
+            "INT0",
+            "INT1",
+            "PCINT0",
+            "PCINT1",
+            "PCINT2",
+            "WDT",
+            "TIMER2_COMPA",
+            "TIMER2_COMPB",
+            "TIMER2_OVF",
+            "TIMER1_CAPT",
+            "TIMER1_COMPA",
+            "TIMER1_COMPB",
+            "TIMER1_OVF",
+            "TIMER0_COMPA",
+            "TIMER0_COMPB",
+            "TIMER0_OVF",
+            "SPI_STC",
+            "USART_RX",
+            "USART_UDRE",
+            "USART_TX",
+            "ADC",
+            "EE_READY",
+            "ANALOG_COMP",
+            "TWI",
+            "SPM_READY",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| interrupt::Vector::new(*name, i as u32 * 4))
+        .collect()
+    }
+
+    fn flash_page_size() -> usize {
+        128 // 64 words
+    }
+
     fn io_ports() -> Vec<io::Port> {
         vec![
-            io::Port::new(0x03), // PINB
-            io::Port::new(0x04), // DDRB
-            io::Port::new(0x05), // PORTB
-            io::Port::new(0x06), // PINC
-            io::Port::new(0x07), // DDRC
-            io::Port::new(0x08), // PORTC
-            io::Port::new(0x09), // PIND
-            io::Port::new(0x0a), // DDRD
-            io::Port::new(0x0b), // PORTD
+            io::Port::new('B', PINB, DDRB, PORTB),
+            io::Port::new('C', PINC, DDRC, PORTC),
+            io::Port::new('D', PIND, DDRD, PORTD),
         ]
     }
 }
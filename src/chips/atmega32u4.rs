@@ -0,0 +1,271 @@
+//! The ATmega32U4, with 32KiB of flash and a full speed USB device
+//! controller, as on the Arduino Leonardo and Micro.
+
+use crate::chips;
+use crate::fuses::Fuses;
+use crate::interrupt;
+use crate::io;
+
+pub struct Chip;
+
+impl chips::Chip for Chip {
+    fn name() -> &'static str {
+        "ATmega32U4"
+    }
+
+    fn flash_size() -> usize {
+        32 * 1024 // 32 KB
+    }
+
+    fn memory_size() -> usize {
+        2560 // 2.5KB
+    }
+
+    fn eeprom_size() -> usize {
+        1024 // 1KB
+    }
+
+    fn sram_start() -> u16 {
+        0x100 // after the extended IO space
+    }
+
+    fn clock_frequency() -> u64 {
+        16_000_000 // the crystal the default fuses select
+    }
+
+    fn f_cpu_hints() -> Vec<u64> {
+        vec![16_000_000, 8_000_000]
+    }
+
+    fn default_fuses() -> Fuses {
+        Fuses {
+            low: 0x5e,
+            high: 0x99,
+            extended: 0xf3,
+            lock: 0xff,
+        }
+    }
+
+    fn clkpr_address() -> Option<u16> {
+        Some(0x61)
+    }
+
+    fn io_registers() -> Vec<io::Register> {
+        // The data space address and size in bytes of each register.
+        [
+            ("PINB", 0x23, 1),
+            ("DDRB", 0x24, 1),
+            ("PORTB", 0x25, 1),
+            ("PINC", 0x26, 1),
+            ("DDRC", 0x27, 1),
+            ("PORTC", 0x28, 1),
+            ("PIND", 0x29, 1),
+            ("DDRD", 0x2a, 1),
+            ("PORTD", 0x2b, 1),
+            ("PINE", 0x2c, 1),
+            ("DDRE", 0x2d, 1),
+            ("PORTE", 0x2e, 1),
+            ("PINF", 0x2f, 1),
+            ("DDRF", 0x30, 1),
+            ("PORTF", 0x31, 1),
+            ("TIFR0", 0x35, 1),
+            ("TIFR1", 0x36, 1),
+            ("TIFR3", 0x38, 1),
+            ("TIFR4", 0x39, 1),
+            ("PCIFR", 0x3b, 1),
+            ("EIFR", 0x3c, 1),
+            ("EIMSK", 0x3d, 1),
+            ("GPIOR0", 0x3e, 1),
+            ("EECR", 0x3f, 1),
+            ("EEDR", 0x40, 1),
+            ("EEAR", 0x41, 2),
+            ("GTCCR", 0x43, 1),
+            ("TCCR0A", 0x44, 1),
+            ("TCCR0B", 0x45, 1),
+            ("TCNT0", 0x46, 1),
+            ("OCR0A", 0x47, 1),
+            ("OCR0B", 0x48, 1),
+            ("PLLCSR", 0x49, 1),
+            ("GPIOR1", 0x4a, 1),
+            ("GPIOR2", 0x4b, 1),
+            ("SPCR", 0x4c, 1),
+            ("SPSR", 0x4d, 1),
+            ("SPDR", 0x4e, 1),
+            ("ACSR", 0x50, 1),
+            ("OCDR", 0x51, 1),
+            ("PLLFRQ", 0x52, 1),
+            ("SMCR", 0x53, 1),
+            ("MCUSR", 0x54, 1),
+            ("MCUCR", 0x55, 1),
+            ("SPMCSR", 0x57, 1),
+            ("RAMPZ", 0x5b, 1),
+            ("SP", 0x5d, 2),
+            ("SREG", 0x5f, 1),
+            ("WDTCSR", 0x60, 1),
+            ("CLKPR", 0x61, 1),
+            ("PRR0", 0x64, 1),
+            ("PRR1", 0x65, 1),
+            ("OSCCAL", 0x66, 1),
+            ("RCCTRL", 0x67, 1),
+            ("PCICR", 0x68, 1),
+            ("EICRA", 0x69, 1),
+            ("EICRB", 0x6a, 1),
+            ("PCMSK0", 0x6b, 1),
+            ("TIMSK0", 0x6e, 1),
+            ("TIMSK1", 0x6f, 1),
+            ("TIMSK3", 0x71, 1),
+            ("TIMSK4", 0x72, 1),
+            ("ADC", 0x78, 2),
+            ("ADCSRA", 0x7a, 1),
+            ("ADCSRB", 0x7b, 1),
+            ("ADMUX", 0x7c, 1),
+            ("DIDR2", 0x7d, 1),
+            ("DIDR0", 0x7e, 1),
+            ("DIDR1", 0x7f, 1),
+            ("TCCR1A", 0x80, 1),
+            ("TCCR1B", 0x81, 1),
+            ("TCCR1C", 0x82, 1),
+            ("TCNT1", 0x84, 2),
+            ("ICR1", 0x86, 2),
+            ("OCR1A", 0x88, 2),
+            ("OCR1B", 0x8a, 2),
+            ("OCR1C", 0x8c, 2),
+            ("TCCR3A", 0x90, 1),
+            ("TCCR3B", 0x91, 1),
+            ("TCCR3C", 0x92, 1),
+            ("TCNT3", 0x94, 2),
+            ("ICR3", 0x96, 2),
+            ("OCR3A", 0x98, 2),
+            ("OCR3B", 0x9a, 2),
+            ("OCR3C", 0x9c, 2),
+            ("TWBR", 0xb8, 1),
+            ("TWSR", 0xb9, 1),
+            ("TWAR", 0xba, 1),
+            ("TWDR", 0xbb, 1),
+            ("TWCR", 0xbc, 1),
+            ("TWAMR", 0xbd, 1),
+            ("TCNT4", 0xbe, 1),
+            ("TC4H", 0xbf, 1),
+            ("TCCR4A", 0xc0, 1),
+            ("TCCR4B", 0xc1, 1),
+            ("TCCR4C", 0xc2, 1),
+            ("TCCR4D", 0xc3, 1),
+            ("TCCR4E", 0xc4, 1),
+            ("CLKSEL0", 0xc5, 1),
+            ("CLKSEL1", 0xc6, 1),
+            ("CLKSTA", 0xc7, 1),
+            ("UCSR1A", 0xc8, 1),
+            ("UCSR1B", 0xc9, 1),
+            ("UCSR1C", 0xca, 1),
+            ("UCSR1D", 0xcb, 1),
+            ("UBRR1", 0xcc, 2),
+            ("UDR1", 0xce, 1),
+            ("OCR4A", 0xcf, 1),
+            ("OCR4B", 0xd0, 1),
+            ("OCR4C", 0xd1, 1),
+            ("OCR4D", 0xd2, 1),
+            ("DT4", 0xd4, 1),
+            ("UHWCON", 0xd7, 1),
+            ("USBCON", 0xd8, 1),
+            ("USBSTA", 0xd9, 1),
+            ("USBINT", 0xda, 1),
+            ("UDCON", 0xe0, 1),
+            ("UDINT", 0xe1, 1),
+            ("UDIEN", 0xe2, 1),
+            ("UDADDR", 0xe3, 1),
+            ("UDFNUM", 0xe4, 2),
+            ("UDMFN", 0xe6, 1),
+            ("UEINTX", 0xe8, 1),
+            ("UENUM", 0xe9, 1),
+            ("UERST", 0xea, 1),
+            ("UECONX", 0xeb, 1),
+            ("UECFG0X", 0xec, 1),
+            ("UECFG1X", 0xed, 1),
+            ("UESTA0X", 0xee, 1),
+            ("UESTA1X", 0xef, 1),
+            ("UEIENX", 0xf0, 1),
+            ("UEDATX", 0xf1, 1),
+            ("UEBCLX", 0xf2, 1),
+            ("UEBCHX", 0xf3, 1),
+            ("UEINT", 0xf4, 1),
+        ]
+        .iter()
+        .map(|&(name, address, size)| io::Register::new(name, address, size))
+        .collect()
+    }
+
+    fn word_registers() -> Vec<u16> {
+        // `TCNTn`, `ICRn` and `OCRnA` to `OCRnC` of the 16-bit timers.
+        [0x84, 0x94]
+            .iter()
+            .flat_map(|&tcnt| (0..5).map(move |i| tcnt + 2 * i))
+            .collect()
+    }
+
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        // Each vector is a two word `JMP`.
+        [
+            "RESET",
+            "INT0",
+            "INT1",
+            "INT2",
+            "INT3",
+            "RESERVED",
+            "RESERVED",
+            "INT6",
+            "RESERVED",
+            "PCINT0",
+            "USB_GEN",
+            "USB_COM",
+            "WDT",
+            "RESERVED",
+            "RESERVED",
+            "RESERVED",
+            "TIMER1_CAPT",
+            "TIMER1_COMPA",
+            "TIMER1_COMPB",
+            "TIMER1_COMPC",
+            "TIMER1_OVF",
+            "TIMER0_COMPA",
+            "TIMER0_COMPB",
+            "TIMER0_OVF",
+            "SPI_STC",
+            "USART1_RX",
+            "USART1_UDRE",
+            "USART1_TX",
+            "ANALOG_COMP",
+            "ADC",
+            "EE_READY",
+            "TIMER3_CAPT",
+            "TIMER3_COMPA",
+            "TIMER3_COMPB",
+            "TIMER3_COMPC",
+            "TIMER3_OVF",
+            "TWI",
+            "SPM_READY",
+            "TIMER4_COMPA",
+            "TIMER4_COMPB",
+            "TIMER4_COMPD",
+            "TIMER4_OVF",
+            "TIMER4_FPF",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| interrupt::Vector::new(*name, i as u32 * 4))
+        .collect()
+    }
+
+    fn flash_page_size() -> usize {
+        128 // 64 words
+    }
+
+    fn io_ports() -> Vec<io::Port> {
+        vec![
+            io::Port::new('B', 0x03, 0x04, 0x05),
+            io::Port::new('C', 0x06, 0x07, 0x08),
+            io::Port::new('D', 0x09, 0x0a, 0x0b),
+            io::Port::new('E', 0x0c, 0x0d, 0x0e),
+            io::Port::new('F', 0x0f, 0x10, 0x11),
+        ]
+    }
+}
@@ -0,0 +1,143 @@
+//! The ATtiny10, a reduced core (AVRrc) chip with 1KiB of flash, 32 bytes
+//! of SRAM and one port.
+//!
+//! The core only has the registers `r16` to `r31`. The IO space is at the
+//! bottom of the data space, followed by SRAM at `0x40`, and flash is
+//! mapped into the data space at `0x4000` for `LD`, as there is no `LPM`.
+
+use crate::chips::{self, Family};
+use crate::interrupt;
+use crate::io;
+
+/// `PINB` IO address.
+pub const PINB: u8 = 0x00;
+/// `DDRB` IO address.
+pub const DDRB: u8 = 0x01;
+/// `PORTB` IO address.
+pub const PORTB: u8 = 0x02;
+
+/// The data space address flash is mapped at.
+pub const MAPPED_FLASH: u16 = 0x4000;
+
+pub struct Chip;
+
+impl chips::Chip for Chip {
+    fn name() -> &'static str {
+        "ATtiny10"
+    }
+
+    fn flash_size() -> usize {
+        1024 // 1 KB
+    }
+
+    fn memory_size() -> usize {
+        32
+    }
+
+    fn sram_start() -> u16 {
+        0x40
+    }
+
+    fn clock_frequency() -> u64 {
+        1_000_000 // the 8 MHz oscillator, divided by 8 by `CLKPSR` at reset
+    }
+
+    fn f_cpu_hints() -> Vec<u64> {
+        vec![1_000_000, 8_000_000]
+    }
+
+    fn family() -> Family {
+        Family::Reduced
+    }
+
+    fn flash_page_size() -> usize {
+        16 // 8 words
+    }
+
+    fn mapped_flash_address() -> Option<u16> {
+        Some(MAPPED_FLASH)
+    }
+
+    fn io_registers() -> Vec<io::Register> {
+        // The data space address and size in bytes of each register.
+        [
+            ("PINB", 0x00, 1),
+            ("DDRB", 0x01, 1),
+            ("PORTB", 0x02, 1),
+            ("PUEB", 0x03, 1),
+            ("PORTCR", 0x0c, 1),
+            ("PCMSK", 0x10, 1),
+            ("PCIFR", 0x11, 1),
+            ("PCICR", 0x12, 1),
+            ("EIMSK", 0x13, 1),
+            ("EIFR", 0x14, 1),
+            ("EICRA", 0x15, 1),
+            ("DIDR0", 0x17, 1),
+            ("ADCL", 0x19, 1),
+            ("ADMUX", 0x1b, 1),
+            ("ADCSRB", 0x1c, 1),
+            ("ADCSRA", 0x1d, 1),
+            ("ACSR", 0x1f, 1),
+            ("ICR0", 0x22, 2),
+            ("OCR0B", 0x24, 2),
+            ("OCR0A", 0x26, 2),
+            ("TCNT0", 0x28, 2),
+            ("TIFR0", 0x2a, 1),
+            ("TIMSK0", 0x2b, 1),
+            ("TCCR0C", 0x2c, 1),
+            ("TCCR0B", 0x2d, 1),
+            ("TCCR0A", 0x2e, 1),
+            ("GTCCR", 0x2f, 1),
+            ("WDTCSR", 0x31, 1),
+            ("NVMCSR", 0x32, 1),
+            ("NVMCMD", 0x33, 1),
+            ("VLMCSR", 0x34, 1),
+            ("PRR", 0x35, 1),
+            ("CLKPSR", 0x36, 1),
+            ("CLKMSR", 0x37, 1),
+            ("OSCCAL", 0x39, 1),
+            ("SMCR", 0x3a, 1),
+            ("RSTFLR", 0x3b, 1),
+            ("CCP", 0x3c, 1),
+            ("SP", 0x3d, 2),
+            ("SREG", 0x3f, 1),
+        ]
+        .iter()
+        .map(|&(name, address, size)| io::Register::new(name, address, size))
+        .collect()
+    }
+
+    fn word_registers() -> Vec<u16> {
+        vec![
+            0x22, // ICR0
+            0x24, // OCR0B
+            0x26, // OCR0A
+            0x28, // TCNT0
+        ]
+    }
+
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        // Each vector is a one word `RJMP`.
+        [
+            "RESET",
+            "INT0",
+            "PCINT0",
+            "TIM0_CAPT",
+            "TIM0_OVF",
+            "TIM0_COMPA",
+            "TIM0_COMPB",
+            "ANA_COMP",
+            "WDT",
+            "VLM",
+            "ADC",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| interrupt::Vector::new(*name, i as u32 * 2))
+        .collect()
+    }
+
+    fn io_ports() -> Vec<io::Port> {
+        vec![io::Port::new('B', PINB, DDRB, PORTB)]
+    }
+}
@@ -0,0 +1,291 @@
+//! The ATtiny1614, a tinyAVR 1-series (AVRxt) chip with 16KiB of flash,
+//! 2KiB of SRAM and two ports.
+//!
+//! The peripherals are in the data space from address 0, with the virtual
+//! ports, `CPU` and the general purpose registers in the IO space `IN` and
+//! `OUT` reach. Flash is mapped into the data space at `0x8000`.
+
+use crate::chips::{self, Family};
+use crate::interrupt;
+use crate::io;
+
+/// The data space base addresses of the peripheral modules.
+pub mod base {
+    pub const VPORTA: u16 = 0x0000;
+    pub const VPORTB: u16 = 0x0004;
+    pub const GPIO: u16 = 0x001c;
+    pub const CPU: u16 = 0x0030;
+    pub const RSTCTRL: u16 = 0x0040;
+    pub const SLPCTRL: u16 = 0x0050;
+    pub const CLKCTRL: u16 = 0x0060;
+    pub const BOD: u16 = 0x0080;
+    pub const VREF: u16 = 0x00a0;
+    pub const WDT: u16 = 0x0100;
+    pub const CPUINT: u16 = 0x0110;
+    pub const CRCSCAN: u16 = 0x0120;
+    pub const RTC: u16 = 0x0140;
+    pub const EVSYS: u16 = 0x0180;
+    pub const CCL: u16 = 0x01c0;
+    pub const PORTMUX: u16 = 0x0200;
+    pub const PORTA: u16 = 0x0400;
+    pub const PORTB: u16 = 0x0420;
+    pub const ADC0: u16 = 0x0600;
+    pub const AC0: u16 = 0x0680;
+    pub const DAC0: u16 = 0x06a0;
+    pub const USART0: u16 = 0x0800;
+    pub const TWI0: u16 = 0x0810;
+    pub const SPI0: u16 = 0x0820;
+    pub const TCA0: u16 = 0x0a00;
+    pub const TCB0: u16 = 0x0a40;
+    pub const TCB1: u16 = 0x0a50;
+    pub const TCD0: u16 = 0x0a80;
+    pub const SYSCFG: u16 = 0x0f00;
+    pub const NVMCTRL: u16 = 0x1000;
+    pub const SIGROW: u16 = 0x1100;
+    pub const FUSE: u16 = 0x1280;
+    pub const USERROW: u16 = 0x1300;
+    pub const EEPROM: u16 = 0x1400;
+    pub const SRAM: u16 = 0x3800;
+    pub const MAPPED_FLASH: u16 = 0x8000;
+}
+
+/// `VPORTA.DIR` IO address.
+pub const VPORTA_DIR: u8 = 0x00;
+/// `VPORTA.OUT` IO address.
+pub const VPORTA_OUT: u8 = 0x01;
+/// `VPORTA.IN` IO address.
+pub const VPORTA_IN: u8 = 0x02;
+/// `VPORTB.DIR` IO address.
+pub const VPORTB_DIR: u8 = 0x04;
+/// `VPORTB.OUT` IO address.
+pub const VPORTB_OUT: u8 = 0x05;
+/// `VPORTB.IN` IO address.
+pub const VPORTB_IN: u8 = 0x06;
+
+pub struct Chip;
+
+impl chips::Chip for Chip {
+    fn name() -> &'static str {
+        "ATtiny1614"
+    }
+
+    fn flash_size() -> usize {
+        16 * 1024 // 16 KB
+    }
+
+    fn memory_size() -> usize {
+        2 * 1024
+    }
+
+    fn sram_start() -> u16 {
+        base::SRAM
+    }
+
+    fn eeprom_size() -> usize {
+        256
+    }
+
+    fn clock_frequency() -> u64 {
+        3_333_333 // the 20 MHz oscillator divided by 6
+    }
+
+    fn f_cpu_hints() -> Vec<u64> {
+        vec![3_333_333, 20_000_000, 10_000_000, 16_000_000]
+    }
+
+    fn family() -> Family {
+        Family::Xt
+    }
+
+    fn flash_page_size() -> usize {
+        64 // 32 words
+    }
+
+    fn mapped_flash_address() -> Option<u16> {
+        Some(base::MAPPED_FLASH)
+    }
+
+    fn io_registers() -> Vec<io::Register> {
+        // The data space address and size in bytes of each register.
+        [
+            ("VPORTA_DIR", 0x00, 1),
+            ("VPORTA_OUT", 0x01, 1),
+            ("VPORTA_IN", 0x02, 1),
+            ("VPORTA_INTFLAGS", 0x03, 1),
+            ("VPORTB_DIR", 0x04, 1),
+            ("VPORTB_OUT", 0x05, 1),
+            ("VPORTB_IN", 0x06, 1),
+            ("VPORTB_INTFLAGS", 0x07, 1),
+            ("GPIOR0", 0x1c, 1),
+            ("GPIOR1", 0x1d, 1),
+            ("GPIOR2", 0x1e, 1),
+            ("GPIOR3", 0x1f, 1),
+            ("CCP", 0x34, 1),
+            ("SP", 0x3d, 2),
+            ("SREG", 0x3f, 1),
+            ("RSTCTRL_RSTFR", 0x40, 1),
+            ("RSTCTRL_SWRR", 0x41, 1),
+            ("SLPCTRL_CTRLA", 0x50, 1),
+            ("CLKCTRL_MCLKCTRLA", 0x60, 1),
+            ("CLKCTRL_MCLKCTRLB", 0x61, 1),
+            ("CLKCTRL_MCLKLOCK", 0x62, 1),
+            ("CLKCTRL_MCLKSTATUS", 0x63, 1),
+            ("WDT_CTRLA", 0x100, 1),
+            ("WDT_STATUS", 0x101, 1),
+            ("CPUINT_CTRLA", 0x110, 1),
+            ("CPUINT_STATUS", 0x111, 1),
+            ("CPUINT_LVL0PRI", 0x112, 1),
+            ("CPUINT_LVL1VEC", 0x113, 1),
+            ("RTC_CTRLA", 0x140, 1),
+            ("RTC_STATUS", 0x141, 1),
+            ("RTC_INTCTRL", 0x142, 1),
+            ("RTC_INTFLAGS", 0x143, 1),
+            ("RTC_CNT", 0x148, 2),
+            ("RTC_PER", 0x14a, 2),
+            ("RTC_CMP", 0x14c, 2),
+            ("PORTMUX_CTRLA", 0x200, 1),
+            ("PORTMUX_CTRLB", 0x201, 1),
+            ("PORTA_DIR", 0x400, 1),
+            ("PORTA_DIRSET", 0x401, 1),
+            ("PORTA_DIRCLR", 0x402, 1),
+            ("PORTA_DIRTGL", 0x403, 1),
+            ("PORTA_OUT", 0x404, 1),
+            ("PORTA_OUTSET", 0x405, 1),
+            ("PORTA_OUTCLR", 0x406, 1),
+            ("PORTA_OUTTGL", 0x407, 1),
+            ("PORTA_IN", 0x408, 1),
+            ("PORTA_INTFLAGS", 0x409, 1),
+            ("PORTB_DIR", 0x420, 1),
+            ("PORTB_DIRSET", 0x421, 1),
+            ("PORTB_DIRCLR", 0x422, 1),
+            ("PORTB_DIRTGL", 0x423, 1),
+            ("PORTB_OUT", 0x424, 1),
+            ("PORTB_OUTSET", 0x425, 1),
+            ("PORTB_OUTCLR", 0x426, 1),
+            ("PORTB_OUTTGL", 0x427, 1),
+            ("PORTB_IN", 0x428, 1),
+            ("PORTB_INTFLAGS", 0x429, 1),
+            ("ADC0_CTRLA", 0x600, 1),
+            ("ADC0_COMMAND", 0x608, 1),
+            ("ADC0_INTFLAGS", 0x60b, 1),
+            ("ADC0_RES", 0x610, 2),
+            ("USART0_RXDATAL", 0x800, 1),
+            ("USART0_RXDATAH", 0x801, 1),
+            ("USART0_TXDATAL", 0x802, 1),
+            ("USART0_TXDATAH", 0x803, 1),
+            ("USART0_STATUS", 0x804, 1),
+            ("USART0_CTRLA", 0x805, 1),
+            ("USART0_CTRLB", 0x806, 1),
+            ("USART0_CTRLC", 0x807, 1),
+            ("USART0_BAUD", 0x808, 2),
+            ("TWI0_MCTRLA", 0x813, 1),
+            ("TWI0_MSTATUS", 0x815, 1),
+            ("TWI0_MBAUD", 0x816, 1),
+            ("TWI0_MADDR", 0x817, 1),
+            ("TWI0_MDATA", 0x818, 1),
+            ("SPI0_CTRLA", 0x820, 1),
+            ("SPI0_CTRLB", 0x821, 1),
+            ("SPI0_INTCTRL", 0x822, 1),
+            ("SPI0_INTFLAGS", 0x823, 1),
+            ("SPI0_DATA", 0x824, 1),
+            ("TCA0_CTRLA", 0xa00, 1),
+            ("TCA0_CTRLB", 0xa01, 1),
+            ("TCA0_INTCTRL", 0xa0a, 1),
+            ("TCA0_INTFLAGS", 0xa0b, 1),
+            ("TCA0_TEMP", 0xa0f, 1),
+            ("TCA0_CNT", 0xa20, 2),
+            ("TCA0_PER", 0xa26, 2),
+            ("TCA0_CMP0", 0xa28, 2),
+            ("TCA0_CMP1", 0xa2a, 2),
+            ("TCA0_CMP2", 0xa2c, 2),
+            ("TCB0_CTRLA", 0xa40, 1),
+            ("TCB0_CTRLB", 0xa41, 1),
+            ("TCB0_INTCTRL", 0xa45, 1),
+            ("TCB0_INTFLAGS", 0xa46, 1),
+            ("TCB0_TEMP", 0xa49, 1),
+            ("TCB0_CNT", 0xa4a, 2),
+            ("TCB0_CCMP", 0xa4c, 2),
+            ("TCB1_CTRLA", 0xa50, 1),
+            ("TCB1_CTRLB", 0xa51, 1),
+            ("TCB1_INTCTRL", 0xa55, 1),
+            ("TCB1_INTFLAGS", 0xa56, 1),
+            ("TCB1_TEMP", 0xa59, 1),
+            ("TCB1_CNT", 0xa5a, 2),
+            ("TCB1_CCMP", 0xa5c, 2),
+            ("SYSCFG_REVID", 0xf01, 1),
+            ("NVMCTRL_CTRLA", 0x1000, 1),
+            ("NVMCTRL_CTRLB", 0x1001, 1),
+            ("NVMCTRL_STATUS", 0x1002, 1),
+            ("NVMCTRL_DATA", 0x1006, 2),
+            ("NVMCTRL_ADDR", 0x1008, 2),
+            ("SIGROW_DEVICEID0", 0x1100, 1),
+            ("SIGROW_DEVICEID1", 0x1101, 1),
+            ("SIGROW_DEVICEID2", 0x1102, 1),
+        ]
+        .iter()
+        .map(|&(name, address, size)| io::Register::new(name, address, size))
+        .collect()
+    }
+
+    fn word_registers() -> Vec<u16> {
+        vec![
+            0xa20, // TCA0.CNT
+            0xa26, // TCA0.PER
+            0xa28, // TCA0.CMP0
+            0xa2a, // TCA0.CMP1
+            0xa2c, // TCA0.CMP2
+            0xa4a, // TCB0.CNT
+            0xa4c, // TCB0.CCMP
+            0xa5a, // TCB1.CNT
+            0xa5c, // TCB1.CCMP
+        ]
+    }
+
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        // Each vector is a two word `JMP`.
+        [
+            "RESET",
+            "CRCSCAN_NMI",
+            "BOD_VLM",
+            "PORTA_PORT",
+            "PORTB_PORT",
+            "RESERVED",
+            "RTC_CNT",
+            "RTC_PIT",
+            "TCA0_OVF",
+            "TCA0_HUNF",
+            "TCA0_CMP0",
+            "TCA0_CMP1",
+            "TCA0_CMP2",
+            "TCB0_INT",
+            "TCB1_INT",
+            "TCD0_OVF",
+            "TCD0_TRIG",
+            "AC0_AC",
+            "AC1_AC",
+            "AC2_AC",
+            "ADC0_RESRDY",
+            "ADC0_WCOMP",
+            "ADC1_RESRDY",
+            "ADC1_WCOMP",
+            "TWI0_TWIS",
+            "TWI0_TWIM",
+            "SPI0_INT",
+            "USART0_RXC",
+            "USART0_DRE",
+            "USART0_TXC",
+            "NVMCTRL_EE",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| interrupt::Vector::new(*name, i as u32 * 4))
+        .collect()
+    }
+
+    fn io_ports() -> Vec<io::Port> {
+        vec![
+            io::Port::new('A', VPORTA_IN, VPORTA_DIR, VPORTA_OUT),
+            io::Port::new('B', VPORTB_IN, VPORTB_DIR, VPORTB_OUT),
+        ]
+    }
+}
@@ -0,0 +1,145 @@
+//! The ATtiny85, with 8KiB of flash, 512 bytes of SRAM and one port.
+
+use crate::chips;
+use crate::fuses::Fuses;
+use crate::interrupt;
+use crate::io;
+
+/// `PINB` IO address.
+pub const PINB: u8 = 0x16;
+/// `DDRB` IO address.
+pub const DDRB: u8 = 0x17;
+/// `PORTB` IO address.
+pub const PORTB: u8 = 0x18;
+
+pub struct Chip;
+
+impl chips::Chip for Chip {
+    fn name() -> &'static str {
+        "ATtiny85"
+    }
+
+    fn flash_size() -> usize {
+        8 * 1024 // 8 KB
+    }
+
+    fn memory_size() -> usize {
+        512
+    }
+
+    fn eeprom_size() -> usize {
+        512
+    }
+
+    fn clock_frequency() -> u64 {
+        8_000_000 // the internal RC oscillator
+    }
+
+    fn f_cpu_hints() -> Vec<u64> {
+        vec![1_000_000, 8_000_000, 16_000_000]
+    }
+
+    fn default_fuses() -> Fuses {
+        Fuses {
+            low: 0x62,
+            high: 0xdf,
+            extended: 0xff,
+            lock: 0xff,
+        }
+    }
+
+    fn clkpr_address() -> Option<u16> {
+        Some(0x46)
+    }
+
+    fn io_registers() -> Vec<io::Register> {
+        // The data space address and size in bytes of each register.
+        [
+            ("ADCSRB", 0x23, 1),
+            ("ADC", 0x24, 2),
+            ("ADCSRA", 0x26, 1),
+            ("ADMUX", 0x27, 1),
+            ("ACSR", 0x28, 1),
+            ("USICR", 0x2d, 1),
+            ("USISR", 0x2e, 1),
+            ("USIDR", 0x2f, 1),
+            ("USIBR", 0x30, 1),
+            ("GPIOR0", 0x31, 1),
+            ("GPIOR1", 0x32, 1),
+            ("GPIOR2", 0x33, 1),
+            ("DIDR0", 0x34, 1),
+            ("PCMSK", 0x35, 1),
+            ("PINB", 0x36, 1),
+            ("DDRB", 0x37, 1),
+            ("PORTB", 0x38, 1),
+            ("EECR", 0x3c, 1),
+            ("EEDR", 0x3d, 1),
+            ("EEAR", 0x3e, 2),
+            ("PRR", 0x40, 1),
+            ("WDTCR", 0x41, 1),
+            ("DWDR", 0x42, 1),
+            ("DTPS1", 0x43, 1),
+            ("DT1B", 0x44, 1),
+            ("DT1A", 0x45, 1),
+            ("CLKPR", 0x46, 1),
+            ("PLLCSR", 0x47, 1),
+            ("OCR0B", 0x48, 1),
+            ("OCR0A", 0x49, 1),
+            ("TCCR0A", 0x4a, 1),
+            ("OCR1B", 0x4b, 1),
+            ("GTCCR", 0x4c, 1),
+            ("OCR1C", 0x4d, 1),
+            ("OCR1A", 0x4e, 1),
+            ("TCNT1", 0x4f, 1),
+            ("TCCR1", 0x50, 1),
+            ("OSCCAL", 0x51, 1),
+            ("TCNT0", 0x52, 1),
+            ("TCCR0B", 0x53, 1),
+            ("MCUSR", 0x54, 1),
+            ("MCUCR", 0x55, 1),
+            ("SPMCSR", 0x57, 1),
+            ("TIFR", 0x58, 1),
+            ("TIMSK", 0x59, 1),
+            ("GIFR", 0x5a, 1),
+            ("GIMSK", 0x5b, 1),
+            ("SP", 0x5d, 2),
+            ("SREG", 0x5f, 1),
+        ]
+        .iter()
+        .map(|&(name, address, size)| io::Register::new(name, address, size))
+        .collect()
+    }
+
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        // Each vector is a one word `RJMP`.
+        [
+            "RESET",
+            "INT0",
+            "PCINT0",
+            "TIMER1_COMPA",
+            "TIMER1_OVF",
+            "TIMER0_OVF",
+            "EE_READY",
+            "ANALOG_COMP",
+            "ADC",
+            "TIMER1_COMPB",
+            "TIMER0_COMPA",
+            "TIMER0_COMPB",
+            "WDT",
+            "USI_START",
+            "USI_OVF",
+        ]
+        .iter()
+        .enumerate()
+        .map(|(i, name)| interrupt::Vector::new(*name, i as u32 * 2))
+        .collect()
+    }
+
+    fn flash_page_size() -> usize {
+        64 // 32 words
+    }
+
+    fn io_ports() -> Vec<io::Port> {
+        vec![io::Port::new('B', PINB, DDRB, PORTB)]
+    }
+}
@@ -0,0 +1,355 @@
+//! The ATxmega128A4U, an XMEGA chip with 128KiB of application flash, an
+//! 8KiB boot section, 8KiB of SRAM and five ports.
+//!
+//! The peripherals are blocks of registers in the data space from address
+//! 0. Only the four virtual ports in the IO space are GPIO ports of the
+//! core, mapped to `PORTA` to `PORTD` as after reset. The `PORTx` register
+//! blocks reach them through the `XmegaPorts` addon.
+
+use crate::chips::{self, Family};
+use crate::interrupt;
+use crate::io;
+
+/// The data space base addresses of the peripheral modules.
+pub mod base {
+    pub const GPIO: u16 = 0x0000;
+    pub const VPORT0: u16 = 0x0010;
+    pub const VPORT1: u16 = 0x0014;
+    pub const VPORT2: u16 = 0x0018;
+    pub const VPORT3: u16 = 0x001c;
+    pub const CPU: u16 = 0x0030;
+    pub const CLK: u16 = 0x0040;
+    pub const SLEEP: u16 = 0x0048;
+    pub const OSC: u16 = 0x0050;
+    pub const DFLLRC32M: u16 = 0x0060;
+    pub const DFLLRC2M: u16 = 0x0068;
+    pub const PR: u16 = 0x0070;
+    pub const RST: u16 = 0x0078;
+    pub const WDT: u16 = 0x0080;
+    pub const MCU: u16 = 0x0090;
+    pub const PMIC: u16 = 0x00a0;
+    pub const PORTCFG: u16 = 0x00b0;
+    pub const AES: u16 = 0x00c0;
+    pub const CRC: u16 = 0x00d0;
+    pub const DMA: u16 = 0x0100;
+    pub const EVSYS: u16 = 0x0180;
+    pub const NVM: u16 = 0x01c0;
+    pub const ADCA: u16 = 0x0200;
+    pub const DACB: u16 = 0x0320;
+    pub const ACA: u16 = 0x0380;
+    pub const RTC: u16 = 0x0400;
+    pub const TWIC: u16 = 0x0480;
+    pub const TWIE: u16 = 0x04a0;
+    pub const USB: u16 = 0x04c0;
+    pub const PORTA: u16 = 0x0600;
+    pub const PORTB: u16 = 0x0620;
+    pub const PORTC: u16 = 0x0640;
+    pub const PORTD: u16 = 0x0660;
+    pub const PORTE: u16 = 0x0680;
+    pub const PORTR: u16 = 0x07e0;
+    pub const TCC0: u16 = 0x0800;
+    pub const TCC1: u16 = 0x0840;
+    pub const AWEXC: u16 = 0x0880;
+    pub const HIRESC: u16 = 0x0890;
+    pub const USARTC0: u16 = 0x08a0;
+    pub const USARTC1: u16 = 0x08b0;
+    pub const SPIC: u16 = 0x08c0;
+    pub const IRCOM: u16 = 0x08f8;
+    pub const TCD0: u16 = 0x0900;
+    pub const TCD1: u16 = 0x0940;
+    pub const HIRESD: u16 = 0x0990;
+    pub const USARTD0: u16 = 0x09a0;
+    pub const USARTD1: u16 = 0x09b0;
+    pub const SPID: u16 = 0x09c0;
+    pub const TCE0: u16 = 0x0a00;
+    pub const USARTE0: u16 = 0x0aa0;
+    pub const SRAM: u16 = 0x2000;
+}
+
+/// The ports with their register blocks, and the virtual port each is
+/// mapped to after reset.
+pub const PORTS: [(char, u16, Option<u16>); 6] = [
+    ('A', base::PORTA, Some(base::VPORT0)),
+    ('B', base::PORTB, Some(base::VPORT1)),
+    ('C', base::PORTC, Some(base::VPORT2)),
+    ('D', base::PORTD, Some(base::VPORT3)),
+    ('E', base::PORTE, None),
+    ('R', base::PORTR, None),
+];
+
+pub struct Chip;
+
+impl chips::Chip for Chip {
+    fn name() -> &'static str {
+        "ATxmega128A4U"
+    }
+
+    fn flash_size() -> usize {
+        136 * 1024 // 128 KB and the 8 KB boot section
+    }
+
+    fn memory_size() -> usize {
+        8 * 1024
+    }
+
+    fn sram_start() -> u16 {
+        base::SRAM
+    }
+
+    fn eeprom_size() -> usize {
+        2 * 1024
+    }
+
+    fn supports_des() -> bool {
+        true
+    }
+
+    fn flash_page_size() -> usize {
+        256 // 128 words
+    }
+
+    fn clock_frequency() -> u64 {
+        2_000_000 // the internal 2 MHz RC oscillator
+    }
+
+    fn f_cpu_hints() -> Vec<u64> {
+        vec![2_000_000, 32_000_000, 16_000_000]
+    }
+
+    fn family() -> Family {
+        Family::Xmega
+    }
+
+    fn min_boot_section_words() -> u32 {
+        4 * 1024
+    }
+
+    fn rampz_address() -> Option<u16> {
+        Some(0x3b)
+    }
+
+    fn eind_address() -> Option<u16> {
+        Some(0x3c)
+    }
+
+    fn io_registers() -> Vec<io::Register> {
+        // The data space address and size in bytes of each register.
+        let mut registers: Vec<io::Register> = [
+            ("GPIO_GPIOR0", 0x00, 1),
+            ("GPIO_GPIOR1", 0x01, 1),
+            ("GPIO_GPIOR2", 0x02, 1),
+            ("GPIO_GPIOR3", 0x03, 1),
+            ("CPU_CCP", 0x34, 1),
+            ("CPU_RAMPD", 0x38, 1),
+            ("CPU_RAMPX", 0x39, 1),
+            ("CPU_RAMPY", 0x3a, 1),
+            ("CPU_RAMPZ", 0x3b, 1),
+            ("CPU_EIND", 0x3c, 1),
+            ("CPU_SP", 0x3d, 2),
+            ("CPU_SREG", 0x3f, 1),
+            ("CLK_CTRL", 0x40, 1),
+            ("CLK_PSCTRL", 0x41, 1),
+            ("CLK_LOCK", 0x42, 1),
+            ("SLEEP_CTRL", 0x48, 1),
+            ("OSC_CTRL", 0x50, 1),
+            ("OSC_STATUS", 0x51, 1),
+            ("RST_STATUS", 0x78, 1),
+            ("RST_CTRL", 0x79, 1),
+            ("WDT_CTRL", 0x80, 1),
+            ("WDT_WINCTRL", 0x81, 1),
+            ("WDT_STATUS", 0x82, 1),
+            ("MCU_DEVID0", 0x90, 1),
+            ("MCU_DEVID1", 0x91, 1),
+            ("MCU_DEVID2", 0x92, 1),
+            ("MCU_REVID", 0x93, 1),
+            ("PMIC_STATUS", 0xa0, 1),
+            ("PMIC_INTPRI", 0xa1, 1),
+            ("PMIC_CTRL", 0xa2, 1),
+            ("PORTCFG_MPCMASK", 0xb0, 1),
+            ("PORTCFG_VPCTRLA", 0xb2, 1),
+            ("PORTCFG_VPCTRLB", 0xb3, 1),
+            ("PORTCFG_CLKEVOUT", 0xb4, 1),
+            ("NVM_ADDR0", 0x1c0, 1),
+            ("NVM_ADDR1", 0x1c1, 1),
+            ("NVM_ADDR2", 0x1c2, 1),
+            ("NVM_DATA0", 0x1c4, 1),
+            ("NVM_DATA1", 0x1c5, 1),
+            ("NVM_DATA2", 0x1c6, 1),
+            ("NVM_CMD", 0x1ca, 1),
+            ("NVM_CTRLA", 0x1cb, 1),
+            ("NVM_CTRLB", 0x1cc, 1),
+            ("NVM_INTCTRL", 0x1cd, 1),
+            ("NVM_STATUS", 0x1cf, 1),
+            ("NVM_LOCKBITS", 0x1d0, 1),
+            ("RTC_CTRL", 0x400, 1),
+            ("RTC_STATUS", 0x401, 1),
+            ("RTC_INTCTRL", 0x402, 1),
+            ("RTC_INTFLAGS", 0x403, 1),
+            ("RTC_CNT", 0x408, 2),
+            ("RTC_PER", 0x40a, 2),
+            ("RTC_COMP", 0x40c, 2),
+        ]
+        .iter()
+        .map(|&(name, address, size)| io::Register::new(name, address, size))
+        .collect();
+
+        for (n, vport) in [base::VPORT0, base::VPORT1, base::VPORT2, base::VPORT3]
+            .into_iter()
+            .enumerate()
+        {
+            for (offset, register) in ["DIR", "OUT", "IN", "INTFLAGS"].iter().enumerate() {
+                let name = format!("VPORT{}_{}", n, register);
+                registers.push(io::Register::new(name, vport + offset as u16, 1));
+            }
+        }
+        for (letter, port, _) in PORTS {
+            let block = [
+                ("DIR", 0x00),
+                ("DIRSET", 0x01),
+                ("DIRCLR", 0x02),
+                ("DIRTGL", 0x03),
+                ("OUT", 0x04),
+                ("OUTSET", 0x05),
+                ("OUTCLR", 0x06),
+                ("OUTTGL", 0x07),
+                ("IN", 0x08),
+                ("INTCTRL", 0x09),
+                ("INT0MASK", 0x0a),
+                ("INT1MASK", 0x0b),
+                ("INTFLAGS", 0x0c),
+            ];
+            for (register, offset) in block {
+                let name = format!("PORT{}_{}", letter, register);
+                registers.push(io::Register::new(name, port + offset, 1));
+            }
+        }
+        for (timer, base, channels) in [
+            ("TCC0", base::TCC0, 4),
+            ("TCC1", base::TCC1, 2),
+            ("TCD0", base::TCD0, 4),
+            ("TCD1", base::TCD1, 2),
+            ("TCE0", base::TCE0, 4),
+        ] {
+            let block = [
+                ("CTRLA", 0x00, 1),
+                ("CTRLB", 0x01, 1),
+                ("INTCTRLA", 0x06, 1),
+                ("INTCTRLB", 0x07, 1),
+                ("INTFLAGS", 0x0c, 1),
+                ("TEMP", 0x0f, 1),
+                ("CNT", 0x20, 2),
+                ("PER", 0x26, 2),
+            ];
+            for (register, offset, size) in block {
+                let name = format!("{}_{}", timer, register);
+                registers.push(io::Register::new(name, base + offset, size));
+            }
+            for channel in 0..channels {
+                let name = format!("{}_CC{}", timer, (b'A' + channel) as char);
+                registers.push(io::Register::new(name, base + 0x28 + 2 * channel as u16, 2));
+            }
+        }
+        for (usart, base) in [
+            ("USARTC0", base::USARTC0),
+            ("USARTC1", base::USARTC1),
+            ("USARTD0", base::USARTD0),
+            ("USARTD1", base::USARTD1),
+            ("USARTE0", base::USARTE0),
+        ] {
+            let block = [
+                ("DATA", 0x00),
+                ("STATUS", 0x01),
+                ("CTRLA", 0x03),
+                ("CTRLB", 0x04),
+                ("CTRLC", 0x05),
+                ("BAUDCTRLA", 0x06),
+                ("BAUDCTRLB", 0x07),
+            ];
+            for (register, offset) in block {
+                let name = format!("{}_{}", usart, register);
+                registers.push(io::Register::new(name, base + offset, 1));
+            }
+        }
+
+        registers.sort_by_key(|r| r.address);
+        registers
+    }
+
+    fn word_registers() -> Vec<u16> {
+        let mut registers = Vec::new();
+        for (base, channels) in [
+            (base::TCC0, 4),
+            (base::TCC1, 2),
+            (base::TCD0, 4),
+            (base::TCD1, 2),
+            (base::TCE0, 4),
+        ] {
+            // CNT, PER and the compare or capture channels.
+            registers.push(base + 0x20);
+            registers.push(base + 0x26);
+            registers.extend((0..channels).map(|channel| base + 0x28 + 2 * channel));
+        }
+        registers
+    }
+
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        // Each vector is a two word `JMP`. The gaps are the vectors of
+        // peripherals other XMEGA A chips have.
+        let groups: &[(usize, &str, &[&str])] = &[
+            (0, "", &["RESET"]),
+            (1, "OSC", &["OSCF"]),
+            (2, "PORTC", &["INT0", "INT1"]),
+            (4, "PORTR", &["INT0", "INT1"]),
+            (6, "DMA", &["CH0", "CH1", "CH2", "CH3"]),
+            (10, "RTC", &["OVF", "COMP"]),
+            (12, "TWIC", &["TWIS", "TWIM"]),
+            (14, "TCC0", &["OVF", "ERR", "CCA", "CCB", "CCC", "CCD"]),
+            (20, "TCC1", &["OVF", "ERR", "CCA", "CCB"]),
+            (24, "SPIC", &["INT"]),
+            (25, "USARTC0", &["RXC", "DRE", "TXC"]),
+            (28, "USARTC1", &["RXC", "DRE", "TXC"]),
+            (31, "AES", &["INT"]),
+            (32, "NVM", &["EE", "SPM"]),
+            (34, "PORTB", &["INT0", "INT1"]),
+            (43, "PORTE", &["INT0", "INT1"]),
+            (45, "TWIE", &["TWIS", "TWIM"]),
+            (47, "TCE0", &["OVF", "ERR", "CCA", "CCB", "CCC", "CCD"]),
+            (58, "USARTE0", &["RXC", "DRE", "TXC"]),
+            (64, "PORTD", &["INT0", "INT1"]),
+            (66, "PORTA", &["INT0", "INT1"]),
+            (68, "ACA", &["AC0", "AC1", "ACW"]),
+            (71, "ADCA", &["CH0", "CH1", "CH2", "CH3"]),
+            (77, "TCD0", &["OVF", "ERR", "CCA", "CCB", "CCC", "CCD"]),
+            (83, "TCD1", &["OVF", "ERR", "CCA", "CCB"]),
+            (87, "SPID", &["INT"]),
+            (88, "USARTD0", &["RXC", "DRE", "TXC"]),
+            (91, "USARTD1", &["RXC", "DRE", "TXC"]),
+            (125, "USB", &["BUSEVENT", "TRNCOMPL"]),
+        ];
+
+        let mut names = vec!["RESERVED".to_owned(); 127];
+        for &(first, module, sources) in groups {
+            for (i, source) in sources.iter().enumerate() {
+                names[first + i] = match module {
+                    "" => source.to_string(),
+                    _ => format!("{}_{}", module, source),
+                };
+            }
+        }
+        names
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| interrupt::Vector::new(name, i as u32 * 4))
+            .collect()
+    }
+
+    fn io_ports() -> Vec<io::Port> {
+        PORTS
+            .iter()
+            .filter_map(|&(letter, _, vport)| {
+                let vport = vport? as u8;
+                Some(io::Port::new(letter, vport + 2, vport, vport + 1))
+            })
+            .collect()
+    }
+}
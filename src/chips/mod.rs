@@ -1,41 +1,305 @@
+pub mod atdf;
+pub mod atmega2560;
 pub mod atmega328p;
+pub mod atmega32u4;
+pub mod attiny10;
+pub mod attiny1614;
+pub mod attiny85;
+pub mod atxmega128a4u;
 
+use crate::core;
+use crate::fuses::Fuses;
+use crate::interrupt;
 use crate::io;
 use crate::regs::{Register, RegisterFile};
 
+/// The core family of a chip, which decides instruction timings.
+///
+/// The names in brackets are those used by the AVR instruction set manual.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Family {
+    /// Classic megaAVR and tinyAVR cores (AVRe, AVRe+).
+    Classic,
+    /// XMEGA cores (AVRxm).
+    Xmega,
+    /// Newer tinyAVR and megaAVR 0-series cores (AVRxt).
+    Xt,
+    /// The reduced tinyAVR cores with 16 registers (AVRrc).
+    Reduced,
+}
+
+impl Family {
+    /// The data space address of the IO space `IN` and `OUT` reach.
+    ///
+    /// Classic cores map the general purpose registers below it, so it
+    /// starts at `0x20`. The other cores have the IO space at the bottom of
+    /// the data space.
+    pub fn io_offset(self) -> u16 {
+        match self {
+            Family::Classic => core::SRAM_IO_OFFSET,
+            Family::Xmega | Family::Xt | Family::Reduced => 0,
+        }
+    }
+}
+
 /// A microcontroller.
 pub trait Chip {
+    /// The part name, like `ATmega328P`.
+    fn name() -> &'static str;
+
     fn register_file() -> RegisterFile {
-        let mut file = Vec::new();
-
-        // Create GPRs (r0-r31).
-        for number in 0..32 {
-            file.push(Register {
-                name: format!("r{}", number),
-                value: 0,
-            });
+        register_file(Self::ram_end())
+    }
+
+    /// The GPIO ports.
+    fn io_ports() -> Vec<io::Port>;
+
+    /// The named IO registers, for debuggers and tracers.
+    fn io_registers() -> Vec<io::Register> {
+        Vec::new()
+    }
+
+    /// The 16-bit IO registers which are accessed through the shared `TEMP`
+    /// register, given by the data space address of their low byte.
+    fn word_registers() -> Vec<u16> {
+        Vec::new()
+    }
+
+    /// The interrupt vector table, starting with `RESET`.
+    fn interrupt_vectors() -> Vec<interrupt::Vector> {
+        vec![interrupt::Vector::new("RESET", 0)]
+    }
+
+    fn flash_size() -> usize;
+    fn memory_size() -> usize;
+
+    /// The size of the EEPROM in bytes.
+    fn eeprom_size() -> usize {
+        0
+    }
+
+    /// The first address of SRAM in the data space.
+    fn sram_start() -> u16 {
+        core::SRAM_DATA_OFFSET
+    }
+
+    /// The last address of SRAM in the data space (`RAMEND`).
+    fn ram_end() -> u16 {
+        (Self::sram_start() as usize + Self::memory_size() - 1) as u16
+    }
+
+    /// Whether the chip implements the `DES` instruction.
+    fn supports_des() -> bool {
+        false
+    }
+
+    /// The size of a flash page in bytes, as used by `SPM`.
+    fn flash_page_size() -> usize {
+        128
+    }
+
+    /// The frequency of the clock source the chip runs from by default, before
+    /// any division by the clock prescaler (hertz).
+    fn clock_frequency() -> u64 {
+        1_000_000
+    }
+
+    /// The `F_CPU` values firmware for the chip is commonly built with,
+    /// most common first, for guessing the clock of a program.
+    fn f_cpu_hints() -> Vec<u64> {
+        Vec::new()
+    }
+
+    /// The core family, which decides instruction timings.
+    fn family() -> Family {
+        Family::Classic
+    }
+
+    /// The fuse bytes and lock bits as shipped from the factory.
+    fn default_fuses() -> Fuses {
+        Fuses::unprogrammed()
+    }
+
+    /// The smallest selectable boot section, in words.
+    fn min_boot_section_words() -> u32 {
+        256
+    }
+
+    /// The data space address of `CLKPR`, if the chip has one.
+    fn clkpr_address() -> Option<u16> {
+        None
+    }
+
+    /// The size of return addresses on the stack in bytes, which is 3 on
+    /// chips with a 22-bit program counter.
+    fn return_address_size() -> u8 {
+        if Self::flash_size() > 128 * 1024 {
+            3
+        } else {
+            2
         }
+    }
 
-        let memory_end = Self::memory_size() - 1;
-        let memory_size_hi = memory_end & 0x00ff;
-        let memory_size_lo = (memory_end & 0xff00) >> 8;
+    /// The data space address of `RAMPZ`, if the chip has one for `ELPM`
+    /// and `SPM` to reach flash above 64KiB.
+    fn rampz_address() -> Option<u16> {
+        None
+    }
 
-        // Innitialize SP
-        file.push(Register {
-            name: "SPH".into(),
-            value: memory_size_hi as u8,
-        });
+    /// The data space address of `EIND`, if the chip has one for `EIJMP`
+    /// and `EICALL`.
+    fn eind_address() -> Option<u16> {
+        None
+    }
+
+    /// The data space address flash is mapped at for `LD`, if it is.
+    fn mapped_flash_address() -> Option<u16> {
+        None
+    }
+}
 
+/// Creates the general purpose registers and the stack pointer, which
+/// starts at `RAMEND`.
+pub(crate) fn register_file(ram_end: u16) -> RegisterFile {
+    let mut file = Vec::new();
+
+    // Create GPRs (r0-r31).
+    for number in 0..32 {
         file.push(Register {
-            name: "SPL".into(),
-            value: memory_size_lo as u8,
+            name: format!("r{}", number),
+            value: 0,
         });
+    }
 
-        RegisterFile::new(file)
+    let ram_end_lo = ram_end & 0x00ff;
+    let ram_end_hi = (ram_end & 0xff00) >> 8;
+
+    // Innitialize SP to RAMEND
+    file.push(Register {
+        name: "SPL".into(),
+        value: ram_end_lo as u8,
+    });
+
+    file.push(Register {
+        name: "SPH".into(),
+        value: ram_end_hi as u8,
+    });
+
+    RegisterFile::new(file)
+}
+
+/// Everything needed to create a `Core` for a chip, for choosing the chip
+/// at runtime rather than through the `Chip` type parameter.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChipDescriptor {
+    /// The part name, like `ATmega328P`.
+    pub name: String,
+    pub register_file: RegisterFile,
+    /// The GPIO ports.
+    pub io_ports: Vec<io::Port>,
+    /// The data space addresses of the low bytes of the 16-bit IO registers
+    /// accessed through `TEMP`.
+    pub word_registers: Vec<u16>,
+    /// The named IO registers, by address.
+    pub io_registers: Vec<io::Register>,
+    /// The interrupt vector table, starting with `RESET`.
+    pub interrupt_vectors: Vec<interrupt::Vector>,
+    /// The size of flash in bytes.
+    pub flash_size: usize,
+    /// The size of SRAM in bytes.
+    pub memory_size: usize,
+    /// The size of the EEPROM in bytes.
+    pub eeprom_size: usize,
+    /// The first address of SRAM in the data space.
+    pub sram_start: u16,
+    /// The last address of SRAM in the data space (`RAMEND`).
+    pub ram_end: u16,
+    pub supports_des: bool,
+    /// The size of a flash page in bytes.
+    pub flash_page_size: usize,
+    /// The default frequency of the clock source (hertz).
+    pub clock_frequency: u64,
+    /// The `F_CPU` values firmware is commonly built with.
+    pub f_cpu_hints: Vec<u64>,
+    pub family: Family,
+    /// The fuse bytes and lock bits as shipped from the factory.
+    pub default_fuses: Fuses,
+    /// The smallest selectable boot section, in words.
+    pub min_boot_section_words: u32,
+    /// The data space address of `CLKPR`, if the chip has one.
+    pub clkpr_address: Option<u16>,
+    /// The size of return addresses on the stack in bytes.
+    pub return_address_size: u8,
+    /// The data space address of `RAMPZ`, if the chip has one.
+    pub rampz_address: Option<u16>,
+    /// The data space address of `EIND`, if the chip has one.
+    pub eind_address: Option<u16>,
+    /// The data space address flash is mapped at, if it is.
+    pub mapped_flash_address: Option<u16>,
+}
+
+impl ChipDescriptor {
+    /// Describes a chip type.
+    pub fn of<C>() -> Self
+    where
+        C: Chip,
+    {
+        ChipDescriptor {
+            name: C::name().to_owned(),
+            register_file: C::register_file(),
+            io_ports: C::io_ports(),
+            word_registers: C::word_registers(),
+            io_registers: C::io_registers(),
+            interrupt_vectors: C::interrupt_vectors(),
+            flash_size: C::flash_size(),
+            memory_size: C::memory_size(),
+            eeprom_size: C::eeprom_size(),
+            sram_start: C::sram_start(),
+            ram_end: C::ram_end(),
+            supports_des: C::supports_des(),
+            flash_page_size: C::flash_page_size(),
+            clock_frequency: C::clock_frequency(),
+            f_cpu_hints: C::f_cpu_hints(),
+            family: C::family(),
+            default_fuses: C::default_fuses(),
+            min_boot_section_words: C::min_boot_section_words(),
+            clkpr_address: C::clkpr_address(),
+            return_address_size: C::return_address_size(),
+            rampz_address: C::rampz_address(),
+            eind_address: C::eind_address(),
+            mapped_flash_address: C::mapped_flash_address(),
+        }
     }
+}
 
-    fn io_ports() -> Vec<io::Port>;
+impl ChipDescriptor {
+    /// Gets an IO register by name, like `TCNT1`.
+    pub fn io_register(&self, name: &str) -> Option<&io::Register> {
+        self.io_registers.iter().find(|r| r.name == name)
+    }
+}
 
-    fn flash_size() -> usize;
-    fn memory_size() -> usize;
+/// The chips that can be looked up by name.
+const REGISTRY: &[fn() -> ChipDescriptor] = &[
+    ChipDescriptor::of::<atmega328p::Chip>,
+    ChipDescriptor::of::<atmega2560::Chip>,
+    ChipDescriptor::of::<atmega32u4::Chip>,
+    ChipDescriptor::of::<attiny85::Chip>,
+    ChipDescriptor::of::<attiny1614::Chip>,
+    ChipDescriptor::of::<attiny10::Chip>,
+    ChipDescriptor::of::<atxmega128a4u::Chip>,
+];
+
+/// Looks up a chip by its part name, ignoring case, like `atmega328p`.
+pub fn by_name(name: &str) -> Option<ChipDescriptor> {
+    REGISTRY
+        .iter()
+        .map(|describe| describe())
+        .find(|chip| chip.name.eq_ignore_ascii_case(name))
+}
+
+/// Gets the part names of every chip `by_name` knows.
+pub fn names() -> Vec<String> {
+    REGISTRY.iter().map(|describe| describe().name).collect()
 }
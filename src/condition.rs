@@ -0,0 +1,356 @@
+//! Conditions on the machine state, like `r24 == 0x7F && sram[0x120] != 0`,
+//! for conditional breakpoints.
+//!
+//! Conditions use the operators of C with the same precedence. Values are
+//! 64-bit signed integers, and comparisons and logical operators give 1
+//! or 0. The operands are
+//!
+//! ```text
+//! 42, 0x2A, 0b101010, '*'   numbers and characters
+//! r0 .. r31                 general purpose registers
+//! x, y, z                   pointer registers
+//! sp, sreg, pc              the stack pointer, status register and the
+//!                           byte address of the next instruction
+//! cycles                    the cycle count
+//! sram[a], data[a]          a byte of data space
+//! flash[a], eeprom[a]       a byte of program space or EEPROM
+//! ```
+
+use crate::regs;
+use crate::{Core, Error};
+use std::fmt;
+
+/// A parsed condition.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Operand {
+    Gpr(u8),
+    Pair(u8),
+    Sp,
+    Sreg,
+    Pc,
+    Cycles,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Memory {
+    Data,
+    Flash,
+    Eeprom,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Unary {
+    Not,
+    Negate,
+    Complement,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Binary {
+    Or,
+    And,
+    BitOr,
+    BitXor,
+    BitAnd,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+    Number(i64),
+    Operand(Operand),
+    Memory(Memory, Box<Expr>),
+    Unary(Unary, Box<Expr>),
+    Binary(Binary, Box<Expr>, Box<Expr>),
+}
+
+/// The binary operators from the lowest to the highest precedence.
+const PRECEDENCE: &[&[(&str, Binary)]] = &[
+    &[("||", Binary::Or)],
+    &[("&&", Binary::And)],
+    &[("|", Binary::BitOr)],
+    &[("^", Binary::BitXor)],
+    &[("&", Binary::BitAnd)],
+    &[("==", Binary::Eq), ("!=", Binary::Ne)],
+    &[
+        ("<=", Binary::Le),
+        (">=", Binary::Ge),
+        ("<", Binary::Lt),
+        (">", Binary::Gt),
+    ],
+    &[("<<", Binary::Shl), (">>", Binary::Shr)],
+    &[("+", Binary::Add), ("-", Binary::Sub)],
+    &[("*", Binary::Mul)],
+];
+
+impl Condition {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut parser = Parser { text: source };
+        let expr = parser
+            .binary(0)
+            .and_then(|expr| match parser.text.trim() {
+                "" => Ok(expr),
+                rest => Err(format!("unexpected '{}'", rest)),
+            })
+            .map_err(|message| Error::InvalidCondition {
+                condition: source.to_owned(),
+                message,
+            })?;
+
+        Ok(Condition {
+            source: source.to_owned(),
+            expr,
+        })
+    }
+
+    /// Checks if the condition holds, that is its value is not zero.
+    pub fn evaluate(&self, core: &Core) -> Result<bool, Error> {
+        Ok(self.value(core)? != 0)
+    }
+
+    /// Gets the value of the condition.
+    pub fn value(&self, core: &Core) -> Result<i64, Error> {
+        self.expr.value(core)
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{}", self.source)
+    }
+}
+
+impl Expr {
+    fn value(&self, core: &Core) -> Result<i64, Error> {
+        Ok(match self {
+            Expr::Number(n) => *n,
+            Expr::Operand(operand) => {
+                let registers = core.register_file();
+                match *operand {
+                    Operand::Gpr(r) => registers.gpr(r)? as i64,
+                    Operand::Pair(r) => registers.gpr_pair_val(r)? as i64,
+                    Operand::Sp => registers.gpr_pair_val(regs::SP_LO_NUM)? as i64,
+                    Operand::Sreg => registers.sreg.0.value as i64,
+                    Operand::Pc => core.pc as i64,
+                    Operand::Cycles => core.cycle_count as i64,
+                }
+            }
+            Expr::Memory(memory, address) => {
+                let address = address.value(core)?;
+                let address = usize::try_from(address).unwrap_or(usize::MAX);
+                let byte = match memory {
+                    Memory::Data => {
+                        let address =
+                            u16::try_from(address).map_err(|_| Error::SegmentationFault {
+                                address,
+                                history: Vec::new(),
+                            })?;
+                        core.peek_data(address)?
+                    }
+                    Memory::Flash => core.program_space().get_u8(address)?,
+                    Memory::Eeprom => core.eeprom().get_u8(address)?,
+                };
+                byte as i64
+            }
+            Expr::Unary(op, operand) => {
+                let value = operand.value(core)?;
+                match op {
+                    Unary::Not => (value == 0) as i64,
+                    Unary::Negate => value.wrapping_neg(),
+                    Unary::Complement => !value,
+                }
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let lhs = lhs.value(core)?;
+                // `&&` and `||` only evaluate their right side when needed.
+                match op {
+                    Binary::Or if lhs != 0 => return Ok(1),
+                    Binary::And if lhs == 0 => return Ok(0),
+                    _ => (),
+                }
+                let rhs = rhs.value(core)?;
+
+                match op {
+                    Binary::Or | Binary::And => (rhs != 0) as i64,
+                    Binary::BitOr => lhs | rhs,
+                    Binary::BitXor => lhs ^ rhs,
+                    Binary::BitAnd => lhs & rhs,
+                    Binary::Eq => (lhs == rhs) as i64,
+                    Binary::Ne => (lhs != rhs) as i64,
+                    Binary::Lt => (lhs < rhs) as i64,
+                    Binary::Le => (lhs <= rhs) as i64,
+                    Binary::Gt => (lhs > rhs) as i64,
+                    Binary::Ge => (lhs >= rhs) as i64,
+                    Binary::Shl => lhs.wrapping_shl(rhs as u32),
+                    Binary::Shr => lhs.wrapping_shr(rhs as u32),
+                    Binary::Add => lhs.wrapping_add(rhs),
+                    Binary::Sub => lhs.wrapping_sub(rhs),
+                    Binary::Mul => lhs.wrapping_mul(rhs),
+                }
+            }
+        })
+    }
+}
+
+/// Parses conditions by recursive descent.
+struct Parser<'a> {
+    text: &'a str,
+}
+
+impl Parser<'_> {
+    /// Parses operators of the given precedence level and higher.
+    fn binary(&mut self, level: usize) -> Result<Expr, String> {
+        let operators = match PRECEDENCE.get(level) {
+            Some(operators) => operators,
+            None => return self.unary(),
+        };
+
+        let mut lhs = self.binary(level + 1)?;
+        'operators: loop {
+            self.text = self.text.trim_start();
+            for &(token, op) in operators.iter() {
+                if self.is_operator(token) {
+                    self.text = &self.text[token.len()..];
+                    let rhs = self.binary(level + 1)?;
+                    lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+                    continue 'operators;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    /// Checks if the text starts with an operator, and not a longer one
+    /// that starts the same, like `&` and `&&`.
+    fn is_operator(&self, token: &str) -> bool {
+        let rest = match self.text.strip_prefix(token) {
+            Some(rest) => rest,
+            None => return false,
+        };
+        match token {
+            "|" => !rest.starts_with('|'),
+            "&" => !rest.starts_with('&'),
+            "<" => !rest.starts_with(['<', '=']),
+            ">" => !rest.starts_with(['>', '=']),
+            _ => true,
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.text = self.text.trim_start();
+
+        let op = match self.text.chars().next() {
+            Some('!') if !self.text.starts_with("!=") => Some(Unary::Not),
+            Some('-') => Some(Unary::Negate),
+            Some('~') => Some(Unary::Complement),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.text = &self.text[1..];
+            return Ok(Expr::Unary(op, Box::new(self.unary()?)));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        if let Some(rest) = self.text.strip_prefix('(') {
+            self.text = rest;
+            let expr = self.binary(0)?;
+            self.expect(')')?;
+            return Ok(expr);
+        }
+        if let Some(rest) = self.text.strip_prefix('\'') {
+            let mut chars = rest.chars();
+            let c = chars.next().ok_or("unterminated character")?;
+            if chars.next() != Some('\'') {
+                return Err("unterminated character".to_owned());
+            }
+            self.text = chars.as_str();
+            return Ok(Expr::Number(c as i64));
+        }
+
+        let end = self
+            .text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(self.text.len());
+        let token = &self.text[..end];
+        self.text = &self.text[end..];
+
+        if token.is_empty() {
+            return Err("expected a value".to_owned());
+        }
+        if token.starts_with(|c: char| c.is_ascii_digit()) {
+            return parse_number(token).map(Expr::Number);
+        }
+
+        let name = token.to_ascii_lowercase();
+        let memory = match &name[..] {
+            "sram" | "data" => Some(Memory::Data),
+            "flash" => Some(Memory::Flash),
+            "eeprom" => Some(Memory::Eeprom),
+            _ => None,
+        };
+        if let Some(memory) = memory {
+            self.text = self.text.trim_start();
+            self.expect('[')?;
+            let address = self.binary(0)?;
+            self.expect(']')?;
+            return Ok(Expr::Memory(memory, Box::new(address)));
+        }
+
+        let operand = match &name[..] {
+            "x" => Operand::Pair(26),
+            "y" => Operand::Pair(28),
+            "z" => Operand::Pair(30),
+            "sp" => Operand::Sp,
+            "sreg" => Operand::Sreg,
+            "pc" => Operand::Pc,
+            "cycles" => Operand::Cycles,
+            _ => match name.strip_prefix('r').and_then(|n| n.parse::<u8>().ok()) {
+                Some(r) if r < 32 => Operand::Gpr(r),
+                _ => return Err(format!("unknown operand '{}'", token)),
+            },
+        };
+        Ok(Expr::Operand(operand))
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        match self.text.trim_start().strip_prefix(c) {
+            Some(rest) => {
+                self.text = rest;
+                Ok(())
+            }
+            None => Err(format!("expected '{}'", c)),
+        }
+    }
+}
+
+fn parse_number(token: &str) -> Result<i64, String> {
+    let lower = token.to_ascii_lowercase();
+    let result = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+
+    result.map_err(|_| format!("invalid number '{}'", token))
+}
@@ -1,30 +1,199 @@
+use crate::chips::{Chip, ChipDescriptor, Family};
+use crate::des;
+use crate::events::Event;
+use crate::fuses::{self, Fuses, Section};
 use crate::inst;
+use crate::interrupt::{self, Level};
 use crate::mem;
+use crate::mmio;
+use crate::pmic;
 use crate::regs::{self, RegisterFile};
+use crate::reset::{self, ResetCause};
+use crate::reverse::{Checkpoint, CpuState, Delta};
+use crate::sleep;
 use crate::sreg;
+use crate::state;
+use crate::watch::{self, Watchpoint};
 use crate::Error;
-use crate::{chips::Chip, Instruction};
+use crate::{Instruction, SReg};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
+use std::rc::Rc;
 
 /// The address that register space is mapped to in SRAM.
 pub const SRAM_REGISTER_OFFSET: u16 = 0;
-/// The address that IO space is mapped to in SRAM.
+/// The address that IO space is mapped to in SRAM on classic cores. See
+/// `Family::io_offset`.
 pub const SRAM_IO_OFFSET: u16 = 0x20;
 /// The address that data space is mapped to in SRAM.
 pub const SRAM_DATA_OFFSET: u16 = 0x60;
 
 pub const PTR_SIZE: u16 = 2;
 
+/// The number of IO addresses `IN` and `OUT` reach.
+pub const IO_SPACE_SIZE: u16 = 0x40;
+
+/// The IO address of the `SPL` register.
+pub const SPL_ADDR: u8 = 0x3d;
+/// The IO address of the `SPH` register.
+pub const SPH_ADDR: u8 = 0x3e;
+/// The IO address of the `SREG` register.
+pub const SREG_ADDR: u8 = 0x3f;
+
+/// The IO address of the `SPMCSR` register.
+pub const SPMCSR_ADDR: u8 = 0x37;
+
+/// The number of recently executed instructions kept by default, see
+/// `Core::pc_history`.
+pub const DEFAULT_PC_HISTORY_CAPACITY: usize = 16;
+
+/// `SPMCSR` bits.
+pub mod spmcsr {
+    /// Store program memory enable.
+    pub const SPMEN: u8 = 1 << 0;
+    /// Page erase.
+    pub const PGERS: u8 = 1 << 1;
+    /// Page write.
+    pub const PGWRT: u8 = 1 << 2;
+    /// Boot lock bit set.
+    pub const BLBSET: u8 = 1 << 3;
+    /// Read-while-write section read enable.
+    pub const RWWSRE: u8 = 1 << 4;
+    /// Read-while-write section busy.
+    pub const RWWSB: u8 = 1 << 6;
+}
+
+/// A byte of a 16-bit register.
+enum WordByte {
+    Low,
+    High,
+}
+
+/// What writes to flash mapped into the data space do, see
+/// `Core::set_mapped_flash_writes`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum MappedFlashWrites {
+    /// The write is ignored.
+    #[default]
+    Ignore,
+    /// The write is ignored and reported as `Event::StrayFlashWrite`.
+    Report,
+    /// The write fails with `Error::StrayFlashWrite`.
+    Fault,
+}
+
+/// A data space access made by an instruction.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Access {
+    Read(mem::Address),
+    Write(mem::Address),
+}
+
 /// The AVR CPU.
+///
+/// With the `serde` feature the whole state of the CPU can be serialized,
+/// to snapshot it and restore it later. Watchpoints are debugging aids
+/// rather than state, and IO mappings belong to the peripherals, so neither
+/// are part of snapshots.
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Core {
     register_file: RegisterFile,
 
     program_space: mem::Space,
     memory: mem::Space,
+    eeprom: mem::Space,
+    /// The temporary page buffer filled by `SPM`.
+    page_buffer: Vec<u8>,
     pub io_ports: Vec<crate::io::Port>,
 
     /// The program counter.
     pub pc: u32,
 
+    /// The number of CPU cycles executed so far.
+    ///
+    /// Each instruction adds its cycles for the chip's family, including
+    /// taken branches and skips. Sleeping or being held in reset counts one
+    /// cycle per tick.
+    pub cycle_count: u64,
+
+    interrupts: interrupt::Controller,
+    /// The number of interrupt handlers currently being executed.
+    interrupt_depth: u32,
+    /// Set by `RETI` and `SEI` so that one more instruction is executed
+    /// before the next interrupt is dispatched.
+    interrupts_inhibited: bool,
+
+    /// The named IO registers, by address.
+    io_registers: Vec<crate::io::Register>,
+    /// The low byte addresses of 16-bit registers accessed through `TEMP`.
+    word_registers: Vec<u16>,
+    /// The temporary register shared by all 16-bit register accesses.
+    temp: Cell<u8>,
+
+    /// The address of the instruction currently being executed.
+    executing_pc: u32,
+    /// Whether data space accesses are being recorded in `accesses`.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    recording_accesses: bool,
+    /// The data space accesses made by the last executed instruction.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    accesses: RefCell<Vec<Access>>,
+    /// The data space ranges that instructions stop runs on accessing.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchpoints: Vec<Watchpoint>,
+    /// The watchpoints hit by the last executed instruction.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    watchpoint_hits: RefCell<Vec<watch::Hit>>,
+    /// The data space ranges handled by peripherals.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    io_mappings: Vec<mmio::Mapping>,
+    /// The most recently executed instructions and their addresses, newest
+    /// last, for post-mortem diagnostics.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    pc_history: VecDeque<(u32, Instruction)>,
+    /// The most instructions kept in `pc_history`.
+    pc_history_capacity: usize,
+    /// What writes to mapped flash do.
+    mapped_flash_writes: MappedFlashWrites,
+
+    /// The first address of SRAM.
+    sram_start: u16,
+    /// The last address of SRAM (`RAMEND`).
+    ram_end: u16,
+
+    /// The active sleep mode, or `None` if the CPU is awake.
+    sleep_mode: Option<sleep::SleepMode>,
+    /// The reset source holding the CPU in reset, if any.
+    held_in_reset: Option<ResetCause>,
+    /// The events since the last tick, until the `Mcu` reports them to
+    /// its addons and subscribers.
+    #[cfg_attr(feature = "serde", serde(skip))]
+    events: Vec<Event>,
+
+    /// Whether the `DES` instruction is available.
+    supports_des: bool,
+    /// The core family, which decides instruction timings.
+    family: Family,
+
+    fuses: Fuses,
+    /// The frequency of the clock source, before the clock prescaler
+    /// (hertz).
+    clock_frequency: u64,
+    /// The smallest selectable boot section, in words.
+    min_boot_section_words: u32,
+    /// The data space address of `CLKPR`, if the chip has one.
+    clkpr_address: Option<u16>,
+    /// The size of return addresses on the stack in bytes.
+    return_address_size: u8,
+    /// The data space address of `RAMPZ`, if the chip has one.
+    rampz_address: Option<u16>,
+    /// The data space address of `EIND`, if the chip has one.
+    eind_address: Option<u16>,
+    /// The data space address flash is mapped to, if it is.
+    mapped_flash_address: Option<u16>,
+
     size_of_next_instruction: u8,
 }
 
@@ -33,14 +202,86 @@ impl Core {
     where
         M: Chip,
     {
+        Self::for_chip(&ChipDescriptor::of::<M>())
+    }
+
+    /// Creates a CPU for a chip chosen at runtime, like one from
+    /// `chips::by_name`.
+    pub fn for_chip(chip: &ChipDescriptor) -> Self {
         Core {
-            register_file: M::register_file(),
-            program_space: mem::Space::new(M::flash_size()),
-            memory: mem::Space::new(M::memory_size()),
-            io_ports: M::io_ports(),
+            register_file: chip.register_file.clone(),
+            program_space: mem::Space::new(chip.flash_size),
+            memory: mem::Space::new(chip.ram_end as usize + 1),
+            eeprom: erased(mem::Space::new(chip.eeprom_size)),
+            page_buffer: vec![0xff; chip.flash_page_size],
+            io_ports: chip.io_ports.clone(),
             pc: 0,
+            cycle_count: 0,
+            interrupts: interrupt::Controller::new(chip.interrupt_vectors.clone()),
+            interrupt_depth: 0,
+            interrupts_inhibited: false,
+            io_registers: chip.io_registers.clone(),
+            word_registers: chip.word_registers.clone(),
+            temp: Cell::new(0),
+            executing_pc: 0,
+            recording_accesses: false,
+            accesses: RefCell::new(Vec::new()),
+            watchpoints: Vec::new(),
+            watchpoint_hits: RefCell::new(Vec::new()),
+            io_mappings: Vec::new(),
+            pc_history: VecDeque::with_capacity(DEFAULT_PC_HISTORY_CAPACITY),
+            pc_history_capacity: DEFAULT_PC_HISTORY_CAPACITY,
+            mapped_flash_writes: MappedFlashWrites::Ignore,
+            sram_start: chip.sram_start,
+            ram_end: chip.ram_end,
+            sleep_mode: None,
+            held_in_reset: None,
+            events: Vec::new(),
+            supports_des: chip.supports_des,
+            family: chip.family,
+            fuses: chip.default_fuses,
+            clock_frequency: chip.clock_frequency,
+            min_boot_section_words: chip.min_boot_section_words,
+            clkpr_address: chip.clkpr_address,
+            return_address_size: chip.return_address_size,
+            rampz_address: chip.rampz_address,
+            eind_address: chip.eind_address,
+            mapped_flash_address: chip.mapped_flash_address,
             size_of_next_instruction: 0,
         }
+        .with_reset_clock_prescaler()
+    }
+
+    /// Sets `CLKPR` to its value at reset, which depends on `CKDIV8`.
+    fn with_reset_clock_prescaler(mut self) -> Self {
+        let _ = self.reset_clock_prescaler();
+        self
+    }
+
+    fn reset_clock_prescaler(&mut self) -> Result<(), Error> {
+        if let Some(clkpr) = self.clkpr_address {
+            let clkps = self.fuses.clock_divider().trailing_zeros() as u8;
+            self.memory.set_u8(clkpr as usize, clkps)?;
+        }
+        Ok(())
+    }
+
+    /// Creates a CPU as it is after power-on.
+    ///
+    /// This is `new` with `PORF` set in `MCUSR`.
+    pub fn power_on<M>() -> Self
+    where
+        M: Chip,
+    {
+        Self::power_on_chip(&ChipDescriptor::of::<M>())
+    }
+
+    /// Creates a CPU for a chip chosen at runtime as it is after power-on.
+    pub fn power_on_chip(chip: &ChipDescriptor) -> Self {
+        let mut core = Self::for_chip(chip);
+        let flags = ResetCause::PowerOn.update_flags(core.family, 0);
+        let _ = core.memory.set_u8(core.reset_flags_address(), flags);
+        core
     }
 
     pub fn load_program_space<I>(&mut self, bytes: I)
@@ -50,16 +291,809 @@ impl Core {
         self.program_space.load(bytes);
     }
 
+    /// Loads bytes into program space starting at byte address `address`.
+    pub fn load_program_space_at(&mut self, address: u32, bytes: &[u8]) -> Result<(), Error> {
+        for (i, &byte) in bytes.iter().enumerate() {
+            self.program_space.set_u8(address as usize + i, byte)?;
+        }
+        Ok(())
+    }
+
+    /// Resets the CPU.
+    ///
+    /// This sets `SP` to `RAMEND`, clears `SREG` and jumps to the reset
+    /// vector. Memory is left untouched.
+    pub fn reset(&mut self) {
+        self.register_file
+            .set_gpr_pair(regs::SP_LO_NUM, self.ram_end);
+        self.register_file.sreg = SReg::new();
+        self.page_buffer.iter_mut().for_each(|b| *b = 0xff);
+        self.sleep_mode = None;
+        self.interrupts.clear_all();
+        self.interrupt_depth = 0;
+        self.interrupts_inhibited = false;
+        self.pc = self.reset_vector();
+    }
+
+    /// Resets the CPU, recording the cause in `MCUSR`, or `RSTCTRL.RSTFR`
+    /// on AVRxt chips.
+    ///
+    /// Unlike `reset`, this also clears the IO registers like the hardware
+    /// does, except for `MCUSR` itself.
+    pub fn reset_with(&mut self, cause: ResetCause) -> Result<(), Error> {
+        tracing::debug!(pc = self.pc, cycle = self.cycle_count, ?cause, "reset");
+        let flags_addr = self.reset_flags_address();
+        let flags = cause.update_flags(self.family, self.memory.get_u8(flags_addr)?);
+
+        for addr in self.io_offset()..self.sram_start {
+            self.memory.set_u8(addr as usize, 0)?;
+        }
+        self.memory.set_u8(flags_addr, flags)?;
+        self.reset_clock_prescaler()?;
+        // Pins driven from the outside keep their levels.
+        for index in 0..self.io_ports.len() {
+            self.io_ports[index].clear_overrides();
+            self.update_port(index)?;
+        }
+
+        self.reset();
+        self.events.push(Event::Reset { cause: Some(cause) });
+        Ok(())
+    }
+
+    /// Takes the events since this was last called, or since the start of
+    /// the last tick, in order.
+    pub(crate) fn take_events(&mut self) -> Vec<Event> {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Starts or stops journaling writes to program space, data space and
+    /// the EEPROM, which reverse execution needs.
+    pub(crate) fn set_journaling(&mut self, enabled: bool) {
+        self.program_space.set_journaling(enabled);
+        self.memory.set_journaling(enabled);
+        self.eeprom.set_journaling(enabled);
+    }
+
+    /// Records the state before a tick, to work out what it changed with
+    /// `delta`. Journaling has to be enabled.
+    pub(crate) fn checkpoint(&mut self) -> Checkpoint {
+        // Anything written outside of ticks is not part of this tick.
+        self.program_space.take_journal();
+        self.memory.take_journal();
+        self.eeprom.take_journal();
+
+        Checkpoint {
+            registers: self.register_file.registers().map(|r| r.value).collect(),
+            page_buffer: self.page_buffer.clone(),
+            cpu: self.cpu_state(),
+        }
+    }
+
+    /// Works out what changed since a checkpoint.
+    pub(crate) fn delta(&mut self, checkpoint: Checkpoint) -> Delta {
+        let registers = self
+            .register_file
+            .registers()
+            .zip(checkpoint.registers)
+            .enumerate()
+            .filter(|(_, (now, before))| now.value != *before)
+            .map(|(number, (_, before))| (number as u8, before))
+            .collect();
+
+        Delta {
+            registers,
+            memory: self.memory.take_journal(),
+            eeprom: self.eeprom.take_journal(),
+            program_space: self.program_space.take_journal(),
+            page_buffer: (checkpoint.page_buffer != self.page_buffer)
+                .then_some(checkpoint.page_buffer),
+            cpu: checkpoint.cpu,
+        }
+    }
+
+    /// Undoes the changes of a tick, returning to the state before it.
+    pub(crate) fn undo(&mut self, delta: &Delta) {
+        for &(number, value) in &delta.registers {
+            if let Ok(register) = self.register_file.gpr_mut(number) {
+                *register = value;
+            }
+        }
+        self.memory.undo(&delta.memory);
+        self.eeprom.undo(&delta.eeprom);
+        self.program_space.undo(&delta.program_space);
+        if let Some(page_buffer) = &delta.page_buffer {
+            self.page_buffer.clone_from(page_buffer);
+        }
+
+        let cpu = &delta.cpu;
+        self.pc = cpu.pc;
+        self.cycle_count = cpu.cycle_count;
+        self.register_file.sreg.0.value = cpu.sreg;
+        self.interrupts.clear_all();
+        for (number, &pending) in cpu.pending.iter().enumerate() {
+            if pending {
+                self.interrupts.raise(number as u8);
+            }
+        }
+        self.interrupt_depth = cpu.interrupt_depth;
+        self.interrupts_inhibited = cpu.interrupts_inhibited;
+        self.temp.set(cpu.temp);
+        self.sleep_mode = cpu.sleep_mode;
+        self.held_in_reset = cpu.held_in_reset;
+        self.executing_pc = cpu.executing_pc;
+        self.size_of_next_instruction = cpu.size_of_next_instruction;
+    }
+
+    fn cpu_state(&self) -> CpuState {
+        CpuState {
+            pc: self.pc,
+            cycle_count: self.cycle_count,
+            sreg: self.register_file.sreg.0.value,
+            pending: (0..self.interrupts.vectors().len())
+                .map(|n| self.interrupts.is_pending(n as u8))
+                .collect(),
+            interrupt_depth: self.interrupt_depth,
+            interrupts_inhibited: self.interrupts_inhibited,
+            temp: self.temp.get(),
+            sleep_mode: self.sleep_mode,
+            held_in_reset: self.held_in_reset,
+            executing_pc: self.executing_pc,
+            size_of_next_instruction: self.size_of_next_instruction,
+        }
+    }
+
+    /// Saves everything that changes as the CPU runs, for `Mcu::save_state`.
+    ///
+    /// What is fixed by the chip, like the interrupt vectors, is not saved,
+    /// so the state can only be loaded into a core of the same chip.
+    pub fn save_state(&self, state: &mut state::Writer) {
+        self.register_file.save_state(state);
+        self.program_space.save_state(state);
+        self.memory.save_state(state);
+        self.eeprom.save_state(state);
+        state.bytes(&self.page_buffer);
+
+        state.u32(self.pc);
+        state.u64(self.cycle_count);
+        let pending: Vec<u8> = (0..self.interrupts.vectors().len())
+            .map(|n| self.interrupts.is_pending(n as u8) as u8)
+            .collect();
+        state.bytes(&pending);
+        state.u32(self.interrupt_depth);
+        state.bool(self.interrupts_inhibited);
+        state.u8(self.temp.get());
+
+        match self.sleep_mode {
+            Some(mode) => {
+                state.bool(true);
+                state.u8(mode.to_smcr());
+            }
+            None => state.bool(false),
+        }
+        state.u8(self.held_in_reset.map_or(0, |cause| cause.flag()));
+        for port in &self.io_ports {
+            state.bytes(&port.drive_state());
+        }
+
+        state.u8(self.fuses.low);
+        state.u8(self.fuses.high);
+        state.u8(self.fuses.extended);
+        state.u8(self.fuses.lock);
+        state.u64(self.clock_frequency);
+        state.u8(self.size_of_next_instruction);
+    }
+
+    /// Restores the state saved by `save_state`.
+    pub fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        self.register_file.load_state(state)?;
+        self.program_space.load_state(state)?;
+        self.memory.load_state(state)?;
+        self.eeprom.load_state(state)?;
+        state.bytes_into(&mut self.page_buffer)?;
+
+        self.pc = state.u32()?;
+        self.cycle_count = state.u64()?;
+        let pending = state.bytes()?;
+        if pending.len() != self.interrupts.vectors().len() {
+            return Err(Error::InvalidState("vector count does not match the chip"));
+        }
+        self.interrupts.clear_all();
+        for (number, &pending) in pending.iter().enumerate() {
+            if pending != 0 {
+                self.interrupts.raise(number as u8);
+            }
+        }
+        self.interrupt_depth = state.u32()?;
+        self.interrupts_inhibited = state.bool()?;
+        self.temp.set(state.u8()?);
+
+        self.sleep_mode = match state.bool()? {
+            true => Some(sleep::SleepMode::from_smcr(state.u8()?)),
+            false => None,
+        };
+        self.held_in_reset = match state.u8()? {
+            0 => None,
+            flag => Some(
+                ResetCause::from_flag(flag).ok_or(Error::InvalidState("invalid reset cause"))?,
+            ),
+        };
+        for port in self.io_ports.iter_mut() {
+            let drive_state = state.bytes()?.try_into();
+            port.set_drive_state(
+                drive_state.map_err(|_| Error::InvalidState("invalid port state"))?,
+            );
+        }
+
+        self.fuses = Fuses {
+            low: state.u8()?,
+            high: state.u8()?,
+            extended: state.u8()?,
+            lock: state.u8()?,
+        };
+        self.clock_frequency = state.u64()?;
+        self.size_of_next_instruction = state.u8()?;
+        Ok(())
+    }
+
+    pub fn fuses(&self) -> Fuses {
+        self.fuses
+    }
+
+    /// Sets the fuse bytes and lock bits.
+    ///
+    /// Like on a real chip, most fuses only take effect at the next reset.
+    pub fn set_fuses(&mut self, fuses: Fuses) {
+        self.fuses = fuses;
+    }
+
+    /// Gets the byte address of the start of the boot section.
+    pub fn boot_section_start(&self) -> u32 {
+        let words = self.fuses.boot_section_words(self.min_boot_section_words);
+        (self.program_space.len() as u32).saturating_sub(words * 2)
+    }
+
+    /// Gets the section of flash a byte address is in.
+    pub fn flash_section(&self, addr: u32) -> Section {
+        if addr >= self.boot_section_start() {
+            Section::Boot
+        } else {
+            Section::Application
+        }
+    }
+
+    /// Gets the byte address execution starts at after a reset, which is
+    /// the start of the boot section if `BOOTRST` is programmed.
+    pub fn reset_vector(&self) -> u32 {
+        if self.fuses.boot_reset() {
+            self.boot_section_start()
+        } else {
+            0
+        }
+    }
+
+    /// Gets the frequency of the clock source, before the clock prescaler
+    /// (hertz).
+    pub fn clock_frequency(&self) -> u64 {
+        self.clock_frequency
+    }
+
+    /// Sets the frequency of the clock source (hertz), like fitting a
+    /// different crystal.
+    pub fn set_clock_frequency(&mut self, frequency: u64) {
+        self.clock_frequency = frequency;
+    }
+
+    /// Gets the frequency the CPU runs at (hertz), which is the clock
+    /// source divided by the clock prescaler.
+    pub fn cpu_frequency(&self) -> u64 {
+        self.clock_frequency / self.clock_divider() as u64
+    }
+
+    /// Gets the factor the system clock is divided by.
+    ///
+    /// This is set by `CLKPR` if the chip has one, which starts out
+    /// dividing by 8 if `CKDIV8` is programmed.
+    pub fn clock_divider(&self) -> u32 {
+        match self.clkpr_address {
+            Some(clkpr) => {
+                let clkps = self.memory.get_u8(clkpr as usize).unwrap_or(0) & 0x0f;
+                1 << clkps.min(8)
+            }
+            None => self.fuses.clock_divider(),
+        }
+    }
+
+    /// Resets the CPU and holds it in reset until `release_reset`, like
+    /// the `RESET` pin being held low or the supply voltage staying below
+    /// the brown-out level.
+    ///
+    /// No instructions are executed while held, but ticking still advances
+    /// the cycle counter.
+    pub fn assert_reset(&mut self, cause: ResetCause) -> Result<(), Error> {
+        self.reset_with(cause)?;
+        self.held_in_reset = Some(cause);
+        Ok(())
+    }
+
+    /// Lets the CPU start executing from the reset vector again.
+    pub fn release_reset(&mut self) {
+        self.held_in_reset = None;
+    }
+
+    /// Gets the reset source holding the CPU in reset, if any.
+    pub fn held_in_reset(&self) -> Option<ResetCause> {
+        self.held_in_reset
+    }
+
     pub fn tick(&mut self) -> Result<(Instruction, u32), Error> {
-        let inst = self.fetch()?;
+        self.accesses.borrow_mut().clear();
+        self.watchpoint_hits.borrow_mut().clear();
+        self.events.clear();
+
+        if self.held_in_reset.is_some() {
+            self.cycle_count += 1;
+            return Ok((Instruction::Nop, self.pc));
+        }
+
+        if !self.interrupts_inhibited {
+            let pc = self.pc;
+            self.dispatch_pending_interrupt()
+                .map_err(|e| e.at_pc(pc).with_history(self.pc_history.iter().copied()))?;
+        }
+        self.interrupts_inhibited = false;
+
+        // No instructions are fetched while sleeping, the CPU stays
+        // parked on the `SLEEP` instruction until it is woken.
+        if self.is_sleeping() {
+            let pc = self.pc - Instruction::Sleep.size() as u32;
+            self.cycle_count += 1;
+            return Ok((Instruction::Sleep, pc));
+        }
+
+        let inst = self
+            .fetch()
+            .map_err(|e| e.with_history(self.pc_history.iter().copied()))?;
         let pc = self.pc;
+        self.executing_pc = pc;
 
-        self.update_clock()?;
+        if self.pc_history_capacity > 0 {
+            if self.pc_history.len() == self.pc_history_capacity {
+                self.pc_history.pop_front();
+            }
+            self.pc_history.push_back((pc, inst));
+        }
+
+        self.recording_accesses = true;
+        let result = self.execute(inst);
+        self.recording_accesses = false;
+        result.map_err(|e| e.at_pc(pc).with_history(self.pc_history.iter().copied()))?;
 
-        self.execute(inst)?;
         Ok((inst, pc))
     }
 
+    /// Gets the most recently executed instructions and their byte
+    /// addresses, oldest first.
+    ///
+    /// Errors like `Error::SegmentationFault` include these as well, to
+    /// show how the program got into a bad state.
+    pub fn pc_history(&self) -> impl Iterator<Item = (u32, Instruction)> + '_ {
+        self.pc_history.iter().copied()
+    }
+
+    /// Sets how many of the most recently executed instructions are kept
+    /// by `pc_history`, or stops keeping them if it is zero.
+    ///
+    /// By default the last `DEFAULT_PC_HISTORY_CAPACITY` are kept.
+    pub fn set_pc_history_capacity(&mut self, capacity: usize) {
+        self.pc_history_capacity = capacity;
+        while self.pc_history.len() > capacity {
+            self.pc_history.pop_front();
+        }
+    }
+
+    /// Sets what writes to flash mapped into the data space do, like an
+    /// `ST` through a pointer meant for `LD` on an AVRxt or AVRrc core.
+    ///
+    /// Writes to mapped flash would go to the page buffer of the NVM
+    /// controller, which is not simulated, so they are ignored by default.
+    /// Reporting or failing on them catches firmware that corrupts its
+    /// program through a stray pointer, but also flags the page buffer
+    /// writes of bootloaders.
+    pub fn set_mapped_flash_writes(&mut self, policy: MappedFlashWrites) {
+        self.mapped_flash_writes = policy;
+    }
+
+    /// Gets the data space accesses made by the last executed instruction.
+    ///
+    /// Peripherals use this to react to registers being read or written,
+    /// like a flag being cleared by writing a one to it. Accesses made
+    /// outside of instruction execution are not recorded.
+    pub fn accesses(&self) -> Vec<Access> {
+        self.accesses.borrow().clone()
+    }
+
+    /// Checks if the last executed instruction read from `addr`.
+    pub fn was_read(&self, addr: mem::Address) -> bool {
+        self.accesses.borrow().contains(&Access::Read(addr))
+    }
+
+    /// Checks if the last executed instruction wrote to `addr`.
+    pub fn was_written(&self, addr: mem::Address) -> bool {
+        self.accesses.borrow().contains(&Access::Write(addr))
+    }
+
+    /// Watches a range of data space addresses, so that instructions
+    /// accessing them in the way given by `kind` are reported by
+    /// `watchpoint_hits` and stop runs of `Mcu`.
+    ///
+    /// Accesses made through the stack by `PUSH`, `POP`, calls and returns
+    /// are watched as well.
+    pub fn add_watchpoint(&mut self, range: std::ops::Range<mem::Address>, kind: watch::Kind) {
+        let watchpoint = Watchpoint { range, kind };
+        if !self.watchpoints.contains(&watchpoint) {
+            self.watchpoints.push(watchpoint);
+        }
+    }
+
+    /// Removes a watchpoint, returning whether there was one.
+    pub fn remove_watchpoint(
+        &mut self,
+        range: std::ops::Range<mem::Address>,
+        kind: watch::Kind,
+    ) -> bool {
+        let watchpoint = Watchpoint { range, kind };
+        let len = self.watchpoints.len();
+        self.watchpoints.retain(|w| *w != watchpoint);
+        self.watchpoints.len() != len
+    }
+
+    pub fn clear_watchpoints(&mut self) {
+        self.watchpoints.clear();
+    }
+
+    pub fn watchpoints(&self) -> &[Watchpoint] {
+        &self.watchpoints
+    }
+
+    /// Gets the watched accesses made by the last executed instruction.
+    pub fn watchpoint_hits(&self) -> Vec<watch::Hit> {
+        self.watchpoint_hits.borrow().clone()
+    }
+
+    /// Maps a range of data space addresses to a handler, which sees the
+    /// reads and writes instructions make to them.
+    ///
+    /// Fails if part of the range is already mapped.
+    pub fn map_io(
+        &mut self,
+        range: std::ops::Range<mem::Address>,
+        handler: Rc<RefCell<dyn mmio::Handler>>,
+    ) -> Result<(), Error> {
+        if self.io_mappings.iter().any(|m| m.overlaps(&range)) {
+            return Err(Error::IoAlreadyMapped(range));
+        }
+
+        self.io_mappings.push(mmio::Mapping { range, handler });
+        Ok(())
+    }
+
+    /// Removes the mappings sharing an address with a range, returning
+    /// whether there were any.
+    pub fn unmap_io(&mut self, range: std::ops::Range<mem::Address>) -> bool {
+        let count = self.io_mappings.len();
+        self.io_mappings.retain(|m| !m.overlaps(&range));
+        self.io_mappings.len() < count
+    }
+
+    pub fn io_mappings(&self) -> &[mmio::Mapping] {
+        &self.io_mappings
+    }
+
+    /// Gets the handler an address is mapped to while an instruction is
+    /// executing.
+    fn io_handler(&self, addr: mem::Address) -> Option<&Rc<RefCell<dyn mmio::Handler>>> {
+        if !self.recording_accesses {
+            return None;
+        }
+
+        self.io_mappings
+            .iter()
+            .find(|m| m.range.contains(&addr))
+            .map(|m| &m.handler)
+    }
+
+    /// Records an access made by an instruction if it is watched.
+    fn check_watchpoints(&self, access: Access, old: u8, new: u8) {
+        if !self.recording_accesses || !self.watchpoints.iter().any(|w| w.matches(access)) {
+            return;
+        }
+
+        self.watchpoint_hits.borrow_mut().push(watch::Hit {
+            pc: self.executing_pc,
+            access,
+            old,
+            new,
+        });
+    }
+
+    /// Gets the interrupt controller.
+    pub fn interrupts(&self) -> &interrupt::Controller {
+        &self.interrupts
+    }
+
+    /// Gets the interrupt controller mutably.
+    pub fn interrupts_mut(&mut self) -> &mut interrupt::Controller {
+        &mut self.interrupts
+    }
+
+    /// Requests service of interrupt vector `number`.
+    ///
+    /// The highest priority pending interrupt is dispatched on the next
+    /// instruction boundary once interrupts are enabled.
+    pub fn raise_interrupt(&mut self, number: u8) -> Result<(), Error> {
+        if self.interrupts.raise(number) {
+            Ok(())
+        } else {
+            Err(Error::InterruptDoesNotExist(number))
+        }
+    }
+
+    /// Withdraws a pending request of interrupt vector `number`, like
+    /// peripherals do when firmware clears the flag that raised it before
+    /// it was serviced.
+    pub fn cancel_interrupt(&mut self, number: u8) -> Result<(), Error> {
+        if self.interrupts.vector(number).is_none() {
+            return Err(Error::InterruptDoesNotExist(number));
+        }
+        self.interrupts.clear(number);
+        Ok(())
+    }
+
+    /// Services interrupt vector `number` if interrupts are enabled.
+    ///
+    /// The return address is pushed, the `I` flag is cleared, and execution
+    /// continues at the vector. A sleeping CPU is woken up. This takes four
+    /// cycles, or five on chips with a 22-bit program counter.
+    ///
+    /// AVRxt cores do not clear `I`. They hold off further interrupts until
+    /// the handler returns instead. XMEGA cores do not clear it either, and
+    /// hold off interrupts by their level, see `pmic`.
+    ///
+    /// Returns whether the interrupt was dispatched.
+    pub fn interrupt(&mut self, number: u8) -> Result<bool, Error> {
+        let address = self
+            .interrupts
+            .vector(number)
+            .ok_or(Error::InterruptDoesNotExist(number))?
+            .address;
+        let level = self.interrupts.level(number).unwrap_or(Level::Low);
+
+        if !self.accepts_interrupt(level)? {
+            return Ok(false);
+        }
+
+        self.wake();
+        self.push_return_address()?;
+        match self.family {
+            Family::Classic | Family::Reduced => {
+                self.register_file.sreg_flag_clear(sreg::INTERRUPT_FLAG)
+            }
+            Family::Xmega => {
+                let status = self.memory.get_u8(pmic::STATUS_ADDR as usize)?;
+                let status = status | pmic::executing_flag(level);
+                self.memory.set_u8(pmic::STATUS_ADDR as usize, status)?;
+            }
+            Family::Xt => (),
+        }
+        self.interrupt_depth += 1;
+        self.pc = address;
+        // The interrupt response takes four cycles, and one more to push
+        // a third byte of the return address.
+        self.cycle_count += 4 + self.return_address_size as u64 - 2;
+        self.events
+            .push(Event::InterruptDispatched { vector: number });
+        Ok(true)
+    }
+
+    /// Checks if an interrupt of a level would be serviced now.
+    fn accepts_interrupt(&self, level: Level) -> Result<bool, Error> {
+        let enabled = self.register_file.sreg.is_set(sreg::INTERRUPT_FLAG);
+        Ok(match self.family {
+            Family::Classic | Family::Reduced => enabled,
+            Family::Xt => enabled && self.interrupt_depth == 0,
+            Family::Xmega => {
+                let status = self.memory.get_u8(pmic::STATUS_ADDR as usize)?;
+                let ctrl = self.memory.get_u8(pmic::CTRL_ADDR as usize)?;
+                pmic::accepts(level, status, ctrl, enabled)
+            }
+        })
+    }
+
+    /// Gets the number of interrupt handlers currently being executed.
+    ///
+    /// This is greater than one when handlers re-enable interrupts and
+    /// get interrupted themselves.
+    pub fn interrupt_depth(&self) -> u32 {
+        self.interrupt_depth
+    }
+
+    /// Gets the stack pointer.
+    pub fn stack_pointer(&self) -> Result<u16, Error> {
+        self.register_file.gpr_pair_val(regs::SP_LO_NUM)
+    }
+
+    /// Gets the first address of SRAM.
+    pub fn sram_start(&self) -> u16 {
+        self.sram_start
+    }
+
+    /// Gets the last address of SRAM (`RAMEND`), where the stack starts.
+    pub fn ram_end(&self) -> u16 {
+        self.ram_end
+    }
+
+    /// Gets the size of return addresses on the stack in bytes, which is 3
+    /// on chips with a 22-bit program counter.
+    pub fn return_address_size(&self) -> u8 {
+        self.return_address_size
+    }
+
+    /// Gets the core family, which decides instruction timings and the
+    /// layout of the data space.
+    pub fn family(&self) -> Family {
+        self.family
+    }
+
+    /// Gets the data space address of IO address 0.
+    pub fn io_offset(&self) -> u16 {
+        self.family.io_offset()
+    }
+
+    /// Gets the data space address of the register recording what caused
+    /// resets, `MCUSR`, `RSTCTRL.RSTFR`, `RSTFLR` or `RST.STATUS`.
+    fn reset_flags_address(&self) -> usize {
+        match self.family {
+            Family::Xt => reset::RSTFR_ADDR as usize,
+            Family::Reduced => reset::RSTFLR_ADDR as usize,
+            Family::Xmega => reset::RST_STATUS_ADDR as usize,
+            Family::Classic => (self.io_offset() + reset::MCUSR_ADDR as u16) as usize,
+        }
+    }
+
+    /// Gets the flash address a data space address reads on chips that map
+    /// flash into the data space.
+    fn mapped_flash_at(&self, addr: mem::Address) -> Option<usize> {
+        let start = self.mapped_flash_address?;
+        (addr >= start).then(|| (addr - start) as usize)
+    }
+
+    /// Gets the named IO registers of the chip, by address.
+    pub fn io_registers(&self) -> &[crate::io::Register] {
+        &self.io_registers
+    }
+
+    /// Gets an IO register by name, like `TCNT1`.
+    pub fn io_register(&self, name: &str) -> Option<&crate::io::Register> {
+        self.io_registers.iter().find(|r| r.name == name)
+    }
+
+    /// Gets the IO register a data space address is part of.
+    pub fn io_register_containing(&self, address: u16) -> Option<&crate::io::Register> {
+        self.io_registers
+            .iter()
+            .find(|r| (r.address..r.address + r.size as u16).contains(&address))
+    }
+
+    /// Checks if the CPU is executing an interrupt handler.
+    pub fn in_interrupt(&self) -> bool {
+        self.interrupt_depth > 0
+    }
+
+    /// Drives a pin from the outside world, given by the IO address of its
+    /// `PINx` register.
+    ///
+    /// The pin only reads as `high` while it is an input. Registers that
+    /// are not part of a port have the bit set directly.
+    pub fn drive_pin(&mut self, pin_register: u8, bit: u8, high: bool) -> Result<(), Error> {
+        if let Some(index) = self.io_ports.iter().position(|p| p.pin == pin_register) {
+            self.io_ports[index].drive(bit, Some(high));
+            return self.update_port(index);
+        }
+
+        let address = self.io_offset() + pin_register as u16;
+        let current = self.read_data(address)?;
+        let new = if high {
+            current | (1 << bit)
+        } else {
+            current & !(1 << bit)
+        };
+        self.write_data(address, new)
+    }
+
+    /// Drives pin `pin` of a port like `'B'` from the outside world, or
+    /// leaves it floating if `level` is `None`.
+    pub fn set_pin_input(&mut self, port: char, pin: u8, level: Option<bool>) -> Result<(), Error> {
+        let index = self
+            .io_ports
+            .iter()
+            .position(|p| p.name == port)
+            .filter(|_| pin < 8)
+            .ok_or(Error::PinDoesNotExist { port, pin })?;
+
+        self.io_ports[index].drive(pin, level);
+        self.update_port(index)
+    }
+
+    /// Lets a peripheral drive an output pin, given by the IO address of
+    /// its `PINx` register, instead of `PORTx`. If `level` is `None` the
+    /// pin is given back to `PORTx`.
+    pub fn override_pin(
+        &mut self,
+        pin_register: u8,
+        bit: u8,
+        level: Option<bool>,
+    ) -> Result<(), Error> {
+        match self.io_ports.iter().position(|p| p.pin == pin_register) {
+            Some(index) => {
+                self.io_ports[index].set_override(bit, level);
+                self.update_port(index)
+            }
+            None => match level {
+                Some(high) => self.drive_pin(pin_register, bit, high),
+                None => Ok(()),
+            },
+        }
+    }
+
+    /// Gets the port with a register at an IO address.
+    fn port_index(&self, io_address: u8) -> Option<usize> {
+        self.io_ports
+            .iter()
+            .position(|p| p.has_register(io_address))
+    }
+
+    /// Writes a register of a port. Writing ones to `PINx` toggles the bits
+    /// of `PORTx`.
+    fn write_port(&mut self, index: usize, addr: mem::Address, val: u8) -> Result<(), Error> {
+        let offset = self.io_offset();
+        let port = &self.io_ports[index];
+        if addr == offset + port.pin as u16 {
+            let port_addr = (offset + port.port as u16) as usize;
+            let toggled = self.memory.get_u8(port_addr)? ^ val;
+            self.memory.set_u8(port_addr, toggled)?;
+        } else {
+            self.memory.set_u8(addr as usize, val)?;
+        }
+        self.update_port(index)
+    }
+
+    /// Updates `PINx` to the levels of the pins of a port.
+    fn update_port(&mut self, index: usize) -> Result<(), Error> {
+        let offset = self.io_offset();
+        let port = &self.io_ports[index];
+        let ddr = self.memory.get_u8((offset + port.ddr as u16) as usize)?;
+        let value = self.memory.get_u8((offset + port.port as u16) as usize)?;
+        let levels = port.levels(ddr, value);
+        self.memory
+            .set_u8((offset + port.pin as u16) as usize, levels)
+    }
+
+    /// Checks if the CPU is currently sleeping.
+    pub fn is_sleeping(&self) -> bool {
+        self.sleep_mode.is_some()
+    }
+
+    /// Gets the active sleep mode, or `None` if the CPU is awake.
+    pub fn sleep_mode(&self) -> Option<sleep::SleepMode> {
+        self.sleep_mode
+    }
+
+    /// Wakes the CPU up from sleep.
+    pub fn wake(&mut self) {
+        self.sleep_mode = None;
+    }
+
     pub fn register_file(&self) -> &RegisterFile {
         &self.register_file
     }
@@ -74,6 +1108,16 @@ impl Core {
         &mut self.program_space
     }
 
+    /// Gets the EEPROM contents.
+    ///
+    /// The EEPROM registers are implemented by `addons::Eeprom`.
+    pub fn eeprom(&self) -> &mem::Space {
+        &self.eeprom
+    }
+    pub fn eeprom_mut(&mut self) -> &mut mem::Space {
+        &mut self.eeprom
+    }
+
     pub fn memory(&self) -> &mem::Space {
         &self.memory
     }
@@ -81,58 +1125,201 @@ impl Core {
         &mut self.memory
     }
 
+    /// Reads a byte from the data space.
+    ///
+    /// `SPL`, `SPH` and `SREG` are backed by the register file rather than
+    /// by memory. Reading the low byte of a 16-bit register latches its high
+    /// byte into the `TEMP` register, which is what reading the high byte
+    /// returns. On chips that map flash into the data space, addresses from
+    /// there on read flash.
+    pub fn read_data(&self, addr: mem::Address) -> Result<u8, Error> {
+        if self.recording_accesses {
+            self.accesses.borrow_mut().push(Access::Read(addr));
+        }
+
+        let mut val = self.load_data(addr)?;
+        if let Some(handler) = self.io_handler(addr) {
+            val = handler.borrow_mut().read(self.cycle_count, addr, val)?;
+        }
+        self.check_watchpoints(Access::Read(addr), val, val);
+        Ok(val)
+    }
+
+    fn load_data(&self, addr: mem::Address) -> Result<u8, Error> {
+        match self.word_register_at(addr) {
+            Some(WordByte::Low) => {
+                self.temp.set(self.memory.get_u8(addr as usize + 1)?);
+                return self.memory.get_u8(addr as usize);
+            }
+            Some(WordByte::High) => return Ok(self.temp.get()),
+            None => (),
+        }
+
+        if let Some(flash_addr) = self.mapped_flash_at(addr) {
+            return self.program_space.get_u8(flash_addr);
+        }
+        match self.io_register_at(addr) {
+            Some(SPL_ADDR) => self.register_file.gpr(regs::SP_LO_NUM),
+            Some(SPH_ADDR) => self.register_file.gpr(regs::SP_HI_NUM),
+            Some(SREG_ADDR) => Ok(self.register_file.sreg.0.value),
+            _ => self.memory.get_u8(addr as usize),
+        }
+    }
+
+    /// Writes a byte to the data space.
+    ///
+    /// `SPL`, `SPH` and `SREG` are backed by the register file rather than
+    /// by memory. Writing the high byte of a 16-bit register only stores it
+    /// in the `TEMP` register, and both bytes are written together when the
+    /// low byte is written. Writes to mapped flash are ignored, unless
+    /// `set_mapped_flash_writes` says otherwise.
+    pub fn write_data(&mut self, addr: mem::Address, mut val: u8) -> Result<(), Error> {
+        if self.recording_accesses {
+            self.accesses.borrow_mut().push(Access::Write(addr));
+        }
+        if let Some(handler) = self.io_handler(addr) {
+            val = handler.borrow_mut().write(self.cycle_count, addr, val)?;
+        }
+        if self.recording_accesses && (self.io_offset()..self.sram_start).contains(&addr) {
+            self.events.push(Event::IoWrite {
+                address: addr,
+                value: val,
+            });
+        }
+        if !self.watchpoints.is_empty() {
+            let old = self.peek_data(addr)?;
+            self.check_watchpoints(Access::Write(addr), old, val);
+        }
+
+        match self.word_register_at(addr) {
+            Some(WordByte::Low) => {
+                self.memory.set_u8(addr as usize + 1, self.temp.get())?;
+                return self.memory.set_u8(addr as usize, val);
+            }
+            Some(WordByte::High) => {
+                self.temp.set(val);
+                return Ok(());
+            }
+            None => (),
+        }
+
+        // Writes to mapped flash would go to the page buffer of the NVM
+        // controller, which is not simulated.
+        if self.mapped_flash_at(addr).is_some() {
+            return self.stray_flash_write(addr, val);
+        }
+
+        // Ports may be in the extended IO space, like those of the
+        // ATmega2560 from `PORTH` up.
+        let port = addr
+            .checked_sub(self.io_offset())
+            .and_then(|a| u8::try_from(a).ok())
+            .and_then(|a| self.port_index(a));
+        if let Some(index) = port {
+            return self.write_port(index, addr, val);
+        }
+
+        match self.io_register_at(addr) {
+            Some(SPL_ADDR) => *self.register_file.gpr_mut(regs::SP_LO_NUM)? = val,
+            Some(SPH_ADDR) => *self.register_file.gpr_mut(regs::SP_HI_NUM)? = val,
+            Some(SREG_ADDR) => self.register_file.sreg.0.value = val,
+            _ => self.memory.set_u8(addr as usize, val)?,
+        }
+        Ok(())
+    }
+
+    fn stray_flash_write(&mut self, addr: mem::Address, val: u8) -> Result<(), Error> {
+        let pc = self.executing_pc;
+        match self.mapped_flash_writes {
+            MappedFlashWrites::Ignore => (),
+            MappedFlashWrites::Report => self.events.push(Event::StrayFlashWrite {
+                address: addr,
+                value: val,
+                pc,
+            }),
+            MappedFlashWrites::Fault => {
+                return Err(Error::StrayFlashWrite {
+                    address: addr,
+                    value: val,
+                    pc,
+                    history: Vec::new(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads a byte from the data space without the side effects of
+    /// `read_data`, like latching `TEMP` or being recorded as an access.
+    /// Addresses protected by `mem::Space::guard` can be read.
+    pub fn peek_data(&self, addr: mem::Address) -> Result<u8, Error> {
+        if let Some(flash_addr) = self.mapped_flash_at(addr) {
+            return self.program_space.peek_u8(flash_addr);
+        }
+        match self.io_register_at(addr) {
+            Some(SPL_ADDR) => self.register_file.gpr(regs::SP_LO_NUM),
+            Some(SPH_ADDR) => self.register_file.gpr(regs::SP_HI_NUM),
+            Some(SREG_ADDR) => Ok(self.register_file.sreg.0.value),
+            _ => self.memory.peek_u8(addr as usize),
+        }
+    }
+
     /// lhs = lhs + rhs
     pub fn add(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
-        let sum = self.do_rdrr(lhs, rhs, |a, b| a + b)?;
-        self.update_sreg_arithmetic(sum)
+        let rhs_val = self.register_file.gpr(rhs)?;
+        self.do_add(lhs, rhs_val, false)
     }
 
+    /// lhs = lhs + rhs + C
     pub fn adc(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
-        let carry = self.register_file.sreg_flag(sreg::CARRY_FLAG);
-        let constant = if carry { 1 } else { 0 };
-
-        let sum = self.do_rdrr(lhs, rhs, |a, b| a + b + constant)?;
-        self.update_sreg_arithmetic(sum)
+        let rhs_val = self.register_file.gpr(rhs)?;
+        self.do_add(lhs, rhs_val, true)
     }
 
     /// lhs = lhs + rhs
     pub fn adiw(&mut self, rd: u8, imm: u8) -> Result<(), Error> {
-        let val = self.register_file.gpr_pair_val(rd)? + imm as u16;
-        self.register_file.set_gpr_pair(rd, val);
-        self.update_sreg_arithmetic(val)
+        let rd_val = self.register_file.gpr_pair_val(rd)?;
+        let result = rd_val.wrapping_add(imm as u16);
+        self.register_file.set_gpr_pair(rd, result);
+
+        let rdh7 = rd_val & 0x8000 != 0;
+        let r15 = result & 0x8000 != 0;
+        self.update_sreg_word(result, !rdh7 && r15, rdh7 && !r15);
+        Ok(())
     }
 
     /// lhs = lhs - rhs
     pub fn sub(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
-        let diff = self.do_rdrr(lhs, rhs, |a, b| a - b)?;
-        self.update_sreg_arithmetic(diff)
+        let rhs_val = self.register_file.gpr(rhs)?;
+        self.do_sub(lhs, rhs_val, false)
     }
 
+    /// lhs = lhs - rhs - C
     pub fn sbc(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
-        let carry = self.register_file.sreg_flag(sreg::CARRY_FLAG);
-        let constant = if carry { 1 } else { 0 };
-
-        let diff = self.do_rdrr(lhs, rhs, |a, b| a.wrapping_sub(b).wrapping_sub(constant))?;
-        self.update_sreg_arithmetic(diff)
+        let rhs_val = self.register_file.gpr(rhs)?;
+        self.do_sub(lhs, rhs_val, true)
     }
 
+    /// rd = rd - imm
     pub fn subi(&mut self, rd: u8, imm: u8) -> Result<(), Error> {
-        let diff = self.do_rdi(rd, |d| d.wrapping_sub(imm as _))?;
-        self.update_sreg_arithmetic(diff)
+        self.do_sub(rd, imm, false)
     }
 
+    /// rd = rd - imm - C
     pub fn sbci(&mut self, rd: u8, imm: u8) -> Result<(), Error> {
-        let carry = self.register_file.sreg_flag(sreg::CARRY_FLAG);
-        let constant = if carry { 1 } else { 0 };
-
-        let diff = self.do_rdi(rd, |d| d.wrapping_sub(imm as _).wrapping_sub(constant))?;
-        self.update_sreg_arithmetic(diff)
+        self.do_sub(rd, imm, true)
     }
 
+    /// lhs = lhs - rhs
     pub fn sbiw(&mut self, rd: u8, imm: u8) -> Result<(), Error> {
-        let val = self.register_file.gpr_pair_val(rd)?.wrapping_sub(imm as _);
-        self.register_file.set_gpr_pair(rd, val);
-        self.update_sreg_arithmetic(val)
+        let rd_val = self.register_file.gpr_pair_val(rd)?;
+        let result = rd_val.wrapping_sub(imm as u16);
+        self.register_file.set_gpr_pair(rd, result);
+
+        let rdh7 = rd_val & 0x8000 != 0;
+        let r15 = result & 0x8000 != 0;
+        self.update_sreg_word(result, rdh7 && !r15, r15 && !rdh7);
+        Ok(())
     }
 
     /// R1:R0 = Rd * Rr
@@ -151,39 +1338,49 @@ impl Core {
 
     pub fn and(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
         let result = self.do_rdrr(lhs, rhs, |a, b| a & b)?;
-
-        self.update_zero_flag(result);
-        self.update_negative_flag(result);
-        self.register_file.sreg_flag_clear(sreg::OVERFLOW_FLAG);
+        self.update_sreg_logical(result as u8);
         Ok(())
     }
 
     pub fn andi(&mut self, rd: u8, imm: u8) -> Result<(), Error> {
-        self.do_rdi(rd, |d| d & imm as u16)?;
+        let result = self.do_rdi(rd, |d| d & imm as u16)?;
+        self.update_sreg_logical(result as u8);
         Ok(())
     }
 
     pub fn or(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
-        self.do_rdrr(lhs, rhs, |a, b| a | b)?;
+        let result = self.do_rdrr(lhs, rhs, |a, b| a | b)?;
+        self.update_sreg_logical(result as u8);
         Ok(())
     }
 
     pub fn ori(&mut self, rd: u8, imm: u8) -> Result<(), Error> {
-        self.do_rdi(rd, |d| d & imm as u16)?;
+        let result = self.do_rdi(rd, |d| d | imm as u16)?;
+        self.update_sreg_logical(result as u8);
         Ok(())
     }
 
     pub fn eor(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
-        self.do_rdrr(lhs, rhs, |a, b| a ^ b)?;
+        let result = self.do_rdrr(lhs, rhs, |a, b| a ^ b)?;
+        self.update_sreg_logical(result as u8);
         Ok(())
     }
 
     pub fn com(&mut self, rd: u8) -> Result<(), Error> {
-        self.do_rd(rd, |a| 0xff - a)
+        let result = self.do_rdi(rd, |a| 0xff - a)?;
+        self.update_sreg_logical(result as u8);
+        self.register_file.sreg.set(sreg::CARRY_FLAG, true);
+        Ok(())
     }
 
+    /// rd = 0 - rd
     pub fn neg(&mut self, rd: u8) -> Result<(), Error> {
-        self.do_rd(rd, |a| -(a as i8) as u8)
+        let rd_val = self.register_file.gpr(rd)?;
+        let result = rd_val.wrapping_neg();
+        *self.register_file.gpr_mut(rd)? = result;
+
+        self.update_sreg_sub(0, rd_val, result);
+        Ok(())
     }
 
     pub fn mov(&mut self, lhs: u8, rhs: u8) -> Result<(), Error> {
@@ -204,34 +1401,26 @@ impl Core {
     }
 
     pub fn inc(&mut self, rd: u8) -> Result<(), Error> {
-        self.do_rd(rd, |d| d.wrapping_add(1))
+        let result = self.do_rdi(rd, |d| d + 1)? as u8;
+        self.update_sreg_inc_dec(result, result == 0x80);
+        Ok(())
     }
 
     pub fn dec(&mut self, rd: u8) -> Result<(), Error> {
-        self.do_rd(rd, |d| d.wrapping_sub(1))
+        let result = self.do_rdi(rd, |d| d.wrapping_sub(1))? as u8;
+        self.update_sreg_inc_dec(result, result == 0x7f);
+        Ok(())
     }
 
     pub fn push(&mut self, rd: u8) -> Result<(), Error> {
         let rd_val = self.register_file.gpr(rd)?;
-        let sp = self.register_file.gpr_mut(regs::SP_LO_NUM)?;
-
-        assert!(*sp > 0, "stack overflow");
-
-        self.memory.set_u8(*sp as usize, rd_val)?;
-
-        *sp -= 1;
-        Ok(())
+        self.push_u8(rd_val)
     }
 
     pub fn pop(&mut self, rd: u8) -> Result<(), Error> {
-        let rd_val = self.register_file.gpr(rd)?;
-
-        let sp = self.register_file.gpr_mut(regs::SP_LO_NUM)?;
-        *sp += 1;
-
-        assert!(*sp > 0, "stack overflow");
-
-        self.memory.set_u8(*sp as usize, rd_val)
+        let val = self.pop_u8()?;
+        *self.register_file.gpr_mut(rd)? = val;
+        Ok(())
     }
 
     pub fn swap(&mut self, rd: u8) -> Result<(), Error> {
@@ -243,21 +1432,21 @@ impl Core {
         })
     }
 
+    /// Compares `rd` with `rr`.
     pub fn cp(&mut self, rd: u8, rr: u8) -> Result<(), Error> {
-        let rd_val = self.register_file.gpr(rd)? as u16;
-        let rr_val = self.register_file.gpr(rr)? as u16;
+        let rd_val = self.register_file.gpr(rd)?;
+        let rr_val = self.register_file.gpr(rr)?;
 
-        self.update_sreg_cp(rd_val, rr_val);
+        self.do_compare(rd_val, rr_val, false);
         Ok(())
     }
 
+    /// Compares `rd` with `rr` and the carry.
     pub fn cpc(&mut self, rd: u8, rr: u8) -> Result<(), Error> {
-        let rd_val = self.register_file.gpr(rd)? as u16;
-        let rr_val = self.register_file.gpr(rr)? as u16;
-        let c = self.register_file.sreg.is_set(sreg::CARRY_FLAG);
-        let c = if c { 1 } else { 0 };
-        let value = rd_val.wrapping_sub(rr_val).wrapping_sub(c);
-        self.update_sreg_arithmetic(value)?;
+        let rd_val = self.register_file.gpr(rd)?;
+        let rr_val = self.register_file.gpr(rr)?;
+
+        self.do_compare(rd_val, rr_val, true);
         Ok(())
     }
 
@@ -270,7 +1459,11 @@ impl Core {
         Ok(())
     }
 
-    pub fn cpi(&mut self, _rd: u8, _imm: u8) -> Result<(), Error> {
+    /// Compares `rd` with an immediate.
+    pub fn cpi(&mut self, rd: u8, imm: u8) -> Result<(), Error> {
+        let rd_val = self.register_file.gpr(rd)?;
+
+        self.do_compare(rd_val, imm, false);
         Ok(())
     }
 
@@ -284,16 +1477,7 @@ impl Core {
     }
 
     pub fn call(&mut self, k: u32) -> Result<(), Error> {
-        let return_addr = self.pc as u16; // after CALL instruction.
-
-        // push return address onto stack
-        let mut sp = self.register_file.gpr_pair_val(regs::SP_LO_NUM).unwrap();
-        self.memory.set_u16((sp - 1) as usize, return_addr)?;
-
-        // post-decrement
-        sp -= 2;
-
-        self.register_file.set_gpr_pair(regs::SP_LO_NUM, sp);
+        self.push_return_address()?;
 
         self.pc = k;
         Ok(())
@@ -305,112 +1489,170 @@ impl Core {
         Ok(())
     }
 
-    pub fn rcall(&mut self, _k: i16) -> Result<(), Error> {
+    pub fn rcall(&mut self, k: i16) -> Result<(), Error> {
+        self.push_return_address()?;
+
+        self.rjmp(k)
+    }
+
+    /// Jumps to the word address in `Z`.
+    pub fn ijmp(&mut self) -> Result<(), Error> {
+        let z = self.register_file.gpr_pair_val(30)? as u32;
+        self.pc = z << 1;
+        Ok(())
+    }
+
+    /// Calls the word address in `Z`.
+    pub fn icall(&mut self) -> Result<(), Error> {
+        self.push_return_address()?;
+        self.ijmp()
+    }
+
+    /// Jumps to the word address in `EIND:Z`.
+    pub fn eijmp(&mut self) -> Result<(), Error> {
+        let eind = self
+            .eind_address
+            .ok_or(Error::UnsupportedInstruction(Instruction::Eijmp))?;
+        let z = self.register_file.gpr_pair_val(30)? as u32;
+        let high = self.memory.get_u8(eind as usize)? as u32;
+        self.pc = ((high << 16) | z) << 1;
         Ok(())
     }
 
-    pub fn brne(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::ZERO_FLAG))
+    /// Calls the word address in `EIND:Z`.
+    pub fn eicall(&mut self) -> Result<(), Error> {
+        if self.eind_address.is_none() {
+            return Err(Error::UnsupportedInstruction(Instruction::Eicall));
+        }
+        self.push_return_address()?;
+        self.eijmp()
+    }
+
+    pub fn brne(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::ZERO_BIT, k)
     }
 
-    pub fn breq(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::ZERO_FLAG))
+    pub fn breq(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::ZERO_BIT, k)
     }
 
-    pub fn brbs(&mut self, _flag: u8, _k: i8) -> Result<(), Error> {
-        unimplemented!();
+    /// Branches if bit `s` in SREG is set, returning whether it did.
+    pub fn brbs(&mut self, s: u8, k: i8) -> Result<bool, Error> {
+        self.do_sreg_branch(k, |sreg| sreg.is_set(1 << s))
     }
 
-    pub fn brbc(&mut self, _flag: u8, _k: i8) -> Result<(), Error> {
-        unimplemented!();
+    /// Branches if bit `s` in SREG is cleared, returning whether it did.
+    pub fn brbc(&mut self, s: u8, k: i8) -> Result<bool, Error> {
+        self.do_sreg_branch(k, |sreg| sreg.is_clear(1 << s))
     }
 
-    pub fn brcs(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::CARRY_FLAG))
+    pub fn brcs(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::CARRY_BIT, k)
     }
 
-    pub fn brcc(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::CARRY_FLAG))
+    pub fn brcc(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::CARRY_BIT, k)
     }
 
-    pub fn brsh(&mut self, k: i8) -> Result<(), Error> {
+    pub fn brsh(&mut self, k: i8) -> Result<bool, Error> {
         self.brcc(k)
     }
 
-    pub fn brlo(&mut self, k: i8) -> Result<(), Error> {
+    pub fn brlo(&mut self, k: i8) -> Result<bool, Error> {
         self.brcs(k)
     }
 
-    pub fn brmi(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::NEGATIVE_FLAG))
+    pub fn brmi(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::NEGATIVE_BIT, k)
     }
 
-    pub fn brpl(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::NEGATIVE_FLAG))
+    pub fn brpl(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::NEGATIVE_BIT, k)
     }
 
-    pub fn brge(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::S_FLAG))
+    pub fn brge(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::S_BIT, k)
     }
 
-    pub fn brlt(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::S_FLAG))
+    pub fn brlt(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::S_BIT, k)
     }
 
-    pub fn brhs(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::HALF_CARRY_FLAG))
+    pub fn brhs(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::HALF_CARRY_BIT, k)
     }
 
-    pub fn brhc(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::HALF_CARRY_FLAG))
+    pub fn brhc(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::HALF_CARRY_BIT, k)
     }
 
-    pub fn brts(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::TRANSFER_FLAG))
+    pub fn brts(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::TRANSFER_BIT, k)
     }
 
-    pub fn brtc(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::TRANSFER_FLAG))
+    pub fn brtc(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::TRANSFER_BIT, k)
     }
 
-    pub fn brvs(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::OVERFLOW_FLAG))
+    pub fn brvs(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::OVERFLOW_BIT, k)
     }
 
-    pub fn brvc(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::OVERFLOW_FLAG))
+    pub fn brvc(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::OVERFLOW_BIT, k)
     }
 
-    pub fn brie(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_set(sreg::INTERRUPT_FLAG))
+    pub fn brie(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbs(sreg::INTERRUPT_BIT, k)
     }
 
-    pub fn brid(&mut self, k: i8) -> Result<(), Error> {
-        self.do_sreg_branch(k, |sreg| sreg.is_clear(sreg::INTERRUPT_FLAG))
+    pub fn brid(&mut self, k: i8) -> Result<bool, Error> {
+        self.brbc(sreg::INTERRUPT_BIT, k)
     }
 
     pub fn ret(&mut self) -> Result<(), Error> {
-        let mut sp = self.register_file.gpr_pair_val(regs::SP_LO_NUM).unwrap();
-
-        // pre-increment
-        sp += 2;
-
-        let return_addr = self.memory.get_u16((sp - 1) as usize)?;
-        self.register_file.set_gpr_pair(regs::SP_LO_NUM, sp);
+        let mut return_addr = 0;
+        for _ in 0..self.return_address_size {
+            return_addr = (return_addr << 8) | self.pop_u8()? as u32;
+        }
 
-        self.pc = return_addr as u32;
+        // The stack holds word addresses.
+        self.pc = return_addr << 1;
         Ok(())
     }
 
+    /// Returns from an interrupt handler and re-enables interrupts, which
+    /// AVRxt and XMEGA cores never disabled. XMEGA cores clear the highest
+    /// level executing in `PMIC.STATUS` instead.
+    ///
+    /// One more instruction is always executed before another interrupt
+    /// is serviced.
     pub fn reti(&mut self) -> Result<(), Error> {
         self.ret()?;
 
-        self.register_file.sreg_flag_set(sreg::INTERRUPT_FLAG);
+        match self.family {
+            Family::Classic | Family::Reduced => {
+                self.register_file.sreg_flag_set(sreg::INTERRUPT_FLAG)
+            }
+            Family::Xmega => {
+                let status = self.memory.get_u8(pmic::STATUS_ADDR as usize)?;
+                self.memory
+                    .set_u8(pmic::STATUS_ADDR as usize, pmic::after_reti(status))?;
+            }
+            Family::Xt => (),
+        }
+        self.interrupt_depth = self.interrupt_depth.saturating_sub(1);
+        self.interrupts_inhibited = true;
         Ok(())
     }
 
+    /// Enables interrupts.
+    ///
+    /// The instruction following `SEI` is executed before any pending
+    /// interrupt.
     pub fn sei(&mut self) -> Result<(), Error> {
         self.register_file.sreg_flag_set(sreg::INTERRUPT_FLAG);
+        self.interrupts_inhibited = true;
         Ok(())
     }
 
@@ -419,6 +1661,68 @@ impl Core {
         Ok(())
     }
 
+    pub fn sleep(&mut self) -> Result<(), Error> {
+        let (control, decode): (_, fn(u8) -> sleep::SleepMode) = match self.family {
+            Family::Xt => (sleep::SLPCTRL_CTRLA_ADDR, sleep::SleepMode::from_slpctrl),
+            Family::Reduced => (
+                sleep::REDUCED_SMCR_ADDR as u16,
+                sleep::SleepMode::from_reduced_smcr,
+            ),
+            Family::Xmega => (sleep::SLEEP_CTRL_ADDR, sleep::SleepMode::from_smcr),
+            Family::Classic => (
+                self.io_offset() + sleep::SMCR_ADDR as u16,
+                sleep::SleepMode::from_smcr,
+            ),
+        };
+        let control = self.memory.get_u8(control as usize)?;
+
+        // The CPU only goes to sleep if the sleep enable bit is set.
+        if control & sleep::SE != 0 {
+            self.sleep_mode = Some(decode(control));
+        }
+        Ok(())
+    }
+
+    /// Raises `Error::Break` so that debuggers and test harnesses can
+    /// use `BREAK` as a software breakpoint.
+    pub fn brk(&mut self) -> Result<(), Error> {
+        Err(Error::Break {
+            pc: self.executing_pc,
+        })
+    }
+
+    /// Resets the watchdog timer.
+    ///
+    /// The watchdog itself is the `addons::Watchdog` addon, which watches
+    /// for this instruction, so there is nothing to do here.
+    pub fn wdr(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Performs DES round `k` on the data block in R7:R0 with the key in R15:R8.
+    ///
+    /// The half carry flag selects decryption.
+    pub fn des(&mut self, k: u8) -> Result<(), Error> {
+        if !self.supports_des {
+            return Err(Error::UnsupportedInstruction(Instruction::Des(k)));
+        }
+
+        let mut block = 0u64;
+        let mut key = 0u64;
+        for i in (0..8).rev() {
+            block = (block << 8) | self.register_file.gpr(i)? as u64;
+            key = (key << 8) | self.register_file.gpr(i + 8)? as u64;
+        }
+
+        let decrypt = self.register_file.sreg.is_set(sreg::HALF_CARRY_FLAG);
+        let block = des::round(block, key, k, decrypt);
+
+        for i in 0..8 {
+            *self.register_file.gpr_mut(i)? = (block >> (i * 8)) as u8;
+        }
+        Ok(())
+    }
+
     pub fn sbrs(&mut self, r: u8, b: u8) -> Result<(), Error> {
         let value = self.register_file.gpr(r)?;
         if value & (1 << b) != 0 {
@@ -429,12 +1733,11 @@ impl Core {
 
     pub fn sts(&mut self, rd: u8, k: u16) -> Result<(), Error> {
         let value = self.register_file.gpr(rd).expect("Could not find register");
-        self.memory.set_u8(k as usize, value)?;
-        Ok(())
+        self.write_data(k, value)
     }
 
     pub fn lds(&mut self, rd: u8, k: u16) -> Result<(), Error> {
-        let value = self.memory().get_u8(k as usize)?;
+        let value = self.read_data(k)?;
         *self
             .register_file
             .gpr_mut(rd)
@@ -442,18 +1745,147 @@ impl Core {
         Ok(())
     }
 
+    /// Load program memory.
+    ///
+    /// With `BLBSET` and `SPMEN` set in `SPMCSR` this reads the fuse and
+    /// lock bits instead. Reads that the boot lock bits forbid give `0xff`.
     pub fn lpm(&mut self, rd: u8, rz: u8, postinc: bool) -> Result<(), Error> {
         assert_eq!(rz, 30);
         let z = self.register_file.gpr_pair_val(rz)?;
-        let value = self.program_space.get_u8(z as _)?;
-        *self.register_file.gpr_mut(rd)? = value;
+        self.load_program_memory(rd, z as u32)?;
+
         if postinc {
-            let z = z + 1;
+            let z = z.wrapping_add(1);
             self.register_file.set_gpr_pair(rz, z);
         }
         Ok(())
     }
 
+    /// Extended load program memory, from `RAMPZ:Z`.
+    pub fn elpm(&mut self, rd: u8, rz: u8, postinc: bool) -> Result<(), Error> {
+        assert_eq!(rz, 30);
+        let rampz = self
+            .rampz_address
+            .ok_or(Error::UnsupportedInstruction(Instruction::Elpm(
+                rd, rz, postinc,
+            )))?;
+        let z = self.register_file.gpr_pair_val(rz)? as u32;
+        let address = (self.memory.get_u8(rampz as usize)? as u32) << 16 | z;
+        self.load_program_memory(rd, address)?;
+
+        if postinc {
+            let address = address.wrapping_add(1);
+            self.register_file.set_gpr_pair(rz, address as u16);
+            self.memory.set_u8(rampz as usize, (address >> 16) as u8)?;
+        }
+        Ok(())
+    }
+
+    /// Loads a byte of program memory for `LPM` and `ELPM`.
+    fn load_program_memory(&mut self, rd: u8, address: u32) -> Result<(), Error> {
+        let spmcsr_addr = (self.io_offset() + SPMCSR_ADDR as u16) as usize;
+        let control = self.memory.get_u8(spmcsr_addr)?;
+
+        let value = if control & (spmcsr::BLBSET | spmcsr::SPMEN) == spmcsr::BLBSET | spmcsr::SPMEN
+        {
+            self.memory
+                .set_u8(spmcsr_addr, control & !(spmcsr::BLBSET | spmcsr::SPMEN))?;
+            match address {
+                0x0000 => self.fuses.low,
+                0x0001 => self.fuses.lock,
+                0x0002 => self.fuses.extended,
+                0x0003 => self.fuses.high,
+                _ => 0xff,
+            }
+        } else if self.lpm_allowed(address) {
+            self.program_space.get_u8(address as _)?
+        } else {
+            0xff
+        };
+
+        *self.register_file.gpr_mut(rd)? = value;
+        Ok(())
+    }
+
+    /// Checks if the boot lock bits allow the executing code to read a
+    /// flash address.
+    fn lpm_allowed(&self, addr: u32) -> bool {
+        let target = self.flash_section(addr);
+        target == self.flash_section(self.executing_pc) || self.fuses.section_lock(target).lpm_read
+    }
+
+    /// Store program memory.
+    ///
+    /// The operation performed depends on the bits set in `SPMCSR`.
+    pub fn spm(&mut self, postinc: bool) -> Result<(), Error> {
+        let spmcsr_addr = (self.io_offset() + SPMCSR_ADDR as u16) as usize;
+        let control = self.memory.get_u8(spmcsr_addr)?;
+
+        // SPM is ignored unless it has been enabled.
+        if control & spmcsr::SPMEN == 0 {
+            return Ok(());
+        }
+
+        let z = self.register_file.gpr_pair_val(30)? as usize;
+        let rampz = match self.rampz_address {
+            Some(rampz) => self.memory.get_u8(rampz as usize)? as usize,
+            None => 0,
+        };
+        let address = rampz << 16 | z;
+        let page_size = self.page_buffer.len();
+        let page_start = address & !(page_size - 1);
+        let offset = address & (page_size - 1) & !1;
+
+        let mut new_control = control & !(spmcsr::SPMEN | spmcsr::PGERS | spmcsr::PGWRT);
+        let writable = self
+            .fuses
+            .section_lock(self.flash_section(page_start as u32))
+            .spm_write;
+
+        if control & (spmcsr::PGERS | spmcsr::PGWRT) != 0 && !writable {
+            // The boot lock bits protect the page.
+        } else if control & spmcsr::PGERS != 0 {
+            for addr in page_start..page_start + page_size {
+                self.program_space.set_u8(addr, 0xff)?;
+            }
+            self.events.push(Event::FlashWrite {
+                page: page_start as u32,
+                erased: true,
+            });
+            new_control |= spmcsr::RWWSB;
+        } else if control & spmcsr::PGWRT != 0 {
+            for (i, &byte) in self.page_buffer.iter().enumerate() {
+                self.program_space.set_u8(page_start + i, byte)?;
+            }
+            self.events.push(Event::FlashWrite {
+                page: page_start as u32,
+                erased: false,
+            });
+            self.page_buffer.iter_mut().for_each(|b| *b = 0xff);
+            new_control |= spmcsr::RWWSB;
+        } else if control & spmcsr::RWWSRE != 0 {
+            new_control &= !(spmcsr::RWWSB | spmcsr::RWWSRE);
+        } else if control & spmcsr::BLBSET != 0 {
+            // Only the boot lock bits can be programmed, from R0.
+            let r0 = self.register_file.gpr(0)?;
+            self.fuses.lock &= r0 | !(fuses::lock::BLB0_MASK | fuses::lock::BLB1_MASK);
+            new_control &= !spmcsr::BLBSET;
+        } else {
+            // Fill the temporary page buffer with R1:R0.
+            let (lo, hi) = self.register_file.gpr_pair(0)?;
+            self.page_buffer[offset] = lo;
+            self.page_buffer[offset + 1] = hi;
+        }
+
+        self.memory.set_u8(spmcsr_addr, new_control)?;
+
+        if postinc {
+            self.register_file
+                .set_gpr_pair(30, (z as u16).wrapping_add(2));
+        }
+        Ok(())
+    }
+
     pub fn nop(&mut self) -> Result<(), Error> {
         Ok(())
     }
@@ -462,8 +1894,7 @@ impl Core {
         // There should only be 6-bits.
         assert!(a <= 0b111111);
 
-        let offset = SRAM_IO_OFFSET + a as u16;
-        let io_val = self.memory.get_u8(offset as usize)?;
+        let io_val = self.read_data(self.io_offset() + a as u16)?;
 
         *self.register_file.gpr_mut(rd).unwrap() = io_val;
         Ok(())
@@ -473,87 +1904,142 @@ impl Core {
         // There should only be 6-bits.
         assert!(a <= 0b111111);
 
-        let offset = SRAM_IO_OFFSET + a as u16;
         let reg_val = self.register_file.gpr(rd)?;
 
-        self.memory.set_u8(offset as usize, reg_val)
+        self.write_data(self.io_offset() + a as u16, reg_val)
     }
 
     pub fn sbi(&mut self, a: u8, b: u8) -> Result<(), Error> {
-        self.do_io_ab(a, b, |_, current, b| current | (1 << b))
+        self.do_io_ab(a, b, |current, b| current | (1 << b))
     }
 
     pub fn sbis(&mut self, a: u8, b: u8) -> Result<(), Error> {
-        // TODO: Not so sure about this implementation.
-        self.do_io_ab(a, b, |s, current, b| {
-            if current == b {
-                s.pc += s.size_of_next_instruction as u32;
-            }
-            current
-        })
+        let value = self.read_data(self.io_offset() + a as u16)?;
+        if value & (1 << b) != 0 {
+            self.pc += self.size_of_next_instruction as u32;
+        }
+        Ok(())
     }
 
     pub fn cbi(&mut self, a: u8, b: u8) -> Result<(), Error> {
-        self.do_io_ab(a, b, |_, current, b| current & !(1 << b))
+        self.do_io_ab(a, b, |current, b| current & !(1 << b))
     }
 
     fn st(&mut self, ptr: u8, reg: u8, variant: inst::Variant) -> Result<(), Error> {
-        let addr = self.register_file.gpr_pair_val(ptr)?;
         let val = self.register_file.gpr(reg)?;
+        let addr = self.pointer_address(ptr, variant)?;
 
-        self.memory.set_u8(addr as usize, val)?;
-
-        self.handle_ld_st_variant(ptr, variant);
-        Ok(())
+        self.write_data(addr, val)
     }
 
     fn ld(&mut self, reg: u8, ptr: u8, variant: inst::Variant) -> Result<(), Error> {
-        let addr = self.register_file.gpr_pair_val(ptr)?;
+        let addr = self.pointer_address(ptr, variant)?;
 
-        // Load from data spacself.brid(k),
-        let val = self.memory.get_u8(addr as usize)?;
+        // Load from data space.
+        let val = self.read_data(addr)?;
         // Store to register.
         *self.register_file.gpr_mut(reg)? = val;
-
-        self.handle_ld_st_variant(ptr, variant);
         Ok(())
     }
 
+    /// (Z) = Rd, Rd = (Z)
+    pub fn xch(&mut self, rz: u8, rd: u8) -> Result<(), Error> {
+        self.do_z_atomic(rz, rd, |_, rd_val| rd_val)
+    }
+
+    /// (Z) = Rd | (Z), Rd = (Z)
+    pub fn las(&mut self, rz: u8, rd: u8) -> Result<(), Error> {
+        self.do_z_atomic(rz, rd, |z_val, rd_val| z_val | rd_val)
+    }
+
+    /// (Z) = ($FF - Rd) & (Z), Rd = (Z)
+    pub fn lac(&mut self, rz: u8, rd: u8) -> Result<(), Error> {
+        self.do_z_atomic(rz, rd, |z_val, rd_val| (0xff - rd_val) & z_val)
+    }
+
+    /// (Z) = Rd ^ (Z), Rd = (Z)
+    pub fn lat(&mut self, rz: u8, rd: u8) -> Result<(), Error> {
+        self.do_z_atomic(rz, rd, |z_val, rd_val| z_val ^ rd_val)
+    }
+
     fn std(&mut self, ptr: u8, imm: u8, reg: u8) -> Result<(), Error> {
-        let addr = self.register_file.gpr_pair_val(ptr)? + imm as u16;
+        let addr = self
+            .register_file
+            .gpr_pair_val(ptr)?
+            .wrapping_add(imm as u16);
         let val = self.register_file.gpr(reg)?;
 
-        self.memory.set_u8(addr as usize, val)
+        self.write_data(addr, val)
     }
 
     fn ldd(&mut self, reg: u8, ptr: u8, imm: u8) -> Result<(), Error> {
-        let addr = self.register_file.gpr_pair_val(ptr)? + imm as u16;
+        let addr = self
+            .register_file
+            .gpr_pair_val(ptr)?
+            .wrapping_add(imm as u16);
 
-        let val = self.memory.get_u8(addr as usize)?;
+        let val = self.read_data(addr)?;
 
         *self.register_file.gpr_mut(reg)? = val;
         Ok(())
     }
 
     fn fetch(&mut self) -> Result<inst::Instruction, Error> {
-        // println!("PC = {:3X}", self.pc);
-
-        let mut bytes = self.program_space.bytes().skip(self.pc as usize).copied();
-
-        let instruction = inst::binary::read(&mut bytes)?;
-
-        // println!("PC = {:3X}", self.pc + instruction.size() as u32);
-
-        let possible_next_instruction = inst::binary::read(&mut bytes)?;
-        self.size_of_next_instruction = possible_next_instruction.size();
+        let space = &self.program_space;
+        let mut bytes = (self.pc as usize..space.len()).filter_map(|addr| space.get_u8(addr).ok());
+
+        let instruction = inst::binary::read_for(&mut bytes, self.family)?;
+        if !instruction.is_supported_by(self.family) {
+            return Err(Error::NotInInstructionSet {
+                instruction,
+                family: self.family,
+            });
+        }
+        tracing::trace!(
+            pc = self.pc,
+            cycle = self.cycle_count,
+            next_pc = self.pc + instruction.size() as u32,
+            %instruction,
+            "fetched"
+        );
+
+        // The next instruction is only decoded for skips to know its size.
+        // It can be data, or past the end of flash, which only fails if it
+        // is executed.
+        self.size_of_next_instruction =
+            inst::binary::read_for(&mut bytes, self.family).map_or(2, |next| next.size());
 
         Ok(instruction)
     }
 
     fn execute(&mut self, inst: inst::Instruction) -> Result<(), Error> {
         self.pc += inst.size() as u32;
+        let next_pc = self.pc;
 
-        match inst {
+        let branched = self.execute_operation(inst)?;
+
+        let mut cycles = inst.cycles(self.family);
+        if inst.is_call() || matches!(inst, Instruction::Ret | Instruction::Reti) {
+            // A third byte of the return address takes a cycle to push or
+            // pop.
+            cycles += self.return_address_size as u64 - 2;
+        }
+        // A taken branch takes an extra cycle, even one to the next
+        // instruction that leaves the PC where it would be anyway.
+        if branched {
+            cycles += 1;
+        } else if inst.is_skip() {
+            // One cycle per word skipped.
+            cycles += (self.pc - next_pc) as u64 / 2;
+        }
+        self.cycle_count += cycles;
+        Ok(())
+    }
+
+    /// Executes an instruction, returning whether it was a conditional
+    /// branch that was taken.
+    fn execute_operation(&mut self, inst: inst::Instruction) -> Result<bool, Error> {
+        let result = match inst {
             Instruction::Inc(rd) => self.inc(rd),
             Instruction::Dec(rd) => self.dec(rd),
             Instruction::Com(rd) => self.com(rd),
@@ -587,6 +2073,10 @@ impl Core {
             Instruction::Reti => self.reti(),
             Instruction::Sei => self.sei(),
             Instruction::Cli => self.cli(),
+            Instruction::Sleep => self.sleep(),
+            Instruction::Break => self.brk(),
+            Instruction::Wdr => self.wdr(),
+            Instruction::Des(k) => self.des(k),
             Instruction::Sbrs(r, b) => self.sbrs(r, b),
             Instruction::In(rd, a) => self._in(rd, a),
             Instruction::Out(a, rd) => self.out(a, rd),
@@ -597,34 +2087,163 @@ impl Core {
             Instruction::Call(k) => self.call(k),
             Instruction::Rjmp(k) => self.rjmp(k),
             Instruction::Rcall(k) => self.rcall(k),
-            Instruction::Brbs(s, k) => self.brbs(s, k),
-            Instruction::Brbc(s, k) => self.brbc(s, k),
-            Instruction::Breq(k) => self.breq(k),
-            Instruction::Brne(k) => self.brne(k),
-            Instruction::Brcs(k) => self.brcs(k),
-            Instruction::Brcc(k) => self.brcc(k),
-            Instruction::Brsh(k) => self.brsh(k),
-            Instruction::Brlo(k) => self.brlo(k),
-            Instruction::Brmi(k) => self.brmi(k),
-            Instruction::Brpl(k) => self.brpl(k),
-            Instruction::Brge(k) => self.brge(k),
-            Instruction::Brlt(k) => self.brlt(k),
-            Instruction::Brhs(k) => self.brhs(k),
-            Instruction::Brhc(k) => self.brhc(k),
-            Instruction::Brts(k) => self.brts(k),
-            Instruction::Brtc(k) => self.brtc(k),
-            Instruction::Brvs(k) => self.brvs(k),
-            Instruction::Brvc(k) => self.brvc(k),
-            Instruction::Brie(k) => self.brie(k),
-            Instruction::Brid(k) => self.brid(k),
-            Instruction::Sts(rd, k) => self.sts(rd, k),
-            Instruction::Lds(rd, k) => self.lds(rd, k),
+            Instruction::Ijmp => self.ijmp(),
+            Instruction::Icall => self.icall(),
+            Instruction::Eijmp => self.eijmp(),
+            Instruction::Eicall => self.eicall(),
+            Instruction::Brbs(s, k) => return self.brbs(s, k),
+            Instruction::Brbc(s, k) => return self.brbc(s, k),
+            Instruction::Breq(k) => return self.breq(k),
+            Instruction::Brne(k) => return self.brne(k),
+            Instruction::Brcs(k) => return self.brcs(k),
+            Instruction::Brcc(k) => return self.brcc(k),
+            Instruction::Brsh(k) => return self.brsh(k),
+            Instruction::Brlo(k) => return self.brlo(k),
+            Instruction::Brmi(k) => return self.brmi(k),
+            Instruction::Brpl(k) => return self.brpl(k),
+            Instruction::Brge(k) => return self.brge(k),
+            Instruction::Brlt(k) => return self.brlt(k),
+            Instruction::Brhs(k) => return self.brhs(k),
+            Instruction::Brhc(k) => return self.brhc(k),
+            Instruction::Brts(k) => return self.brts(k),
+            Instruction::Brtc(k) => return self.brtc(k),
+            Instruction::Brvs(k) => return self.brvs(k),
+            Instruction::Brvc(k) => return self.brvc(k),
+            Instruction::Brie(k) => return self.brie(k),
+            Instruction::Brid(k) => return self.brid(k),
+            Instruction::Sts(rd, k) | Instruction::Sts16(rd, k) => self.sts(rd, k),
+            Instruction::Lds(rd, k) | Instruction::Lds16(rd, k) => self.lds(rd, k),
             Instruction::Lpm(rd, z, postinc) => self.lpm(rd, z, postinc),
+            Instruction::Elpm(rd, z, postinc) => self.elpm(rd, z, postinc),
+            Instruction::Spm(postinc) => self.spm(postinc),
             Instruction::St(ptr, reg, variant) => self.st(ptr, reg, variant),
+            Instruction::Xch(z, rd) => self.xch(z, rd),
+            Instruction::Las(z, rd) => self.las(z, rd),
+            Instruction::Lac(z, rd) => self.lac(z, rd),
+            Instruction::Lat(z, rd) => self.lat(z, rd),
             Instruction::Std(ptr, imm, reg) => self.std(ptr, imm, reg),
             Instruction::Ld(reg, ptr, variant) => self.ld(reg, ptr, variant),
             Instruction::Ldd(reg, ptr, imm) => self.ldd(reg, ptr, imm),
+        };
+        result.map(|()| false)
+    }
+
+    /// rd = rd + rr_val (+ C if `with_carry`)
+    fn do_add(&mut self, rd: u8, rr_val: u8, with_carry: bool) -> Result<(), Error> {
+        let carry = with_carry && self.register_file.sreg.is_set(sreg::CARRY_FLAG);
+
+        let rd_reg = self.register_file.gpr_mut(rd)?;
+        let rd_val = *rd_reg;
+        let result = rd_val.wrapping_add(rr_val).wrapping_add(carry as u8);
+        *rd_reg = result;
+
+        self.update_sreg_add(rd_val, rr_val, result);
+        Ok(())
+    }
+
+    /// rd = rd - rr_val (- C if `with_carry`)
+    fn do_sub(&mut self, rd: u8, rr_val: u8, with_carry: bool) -> Result<(), Error> {
+        let rd_val = self.register_file.gpr(rd)?;
+        let result = self.do_compare(rd_val, rr_val, with_carry);
+
+        *self.register_file.gpr_mut(rd)? = result;
+        Ok(())
+    }
+
+    /// Computes `rd_val - rr_val` (- C if `with_carry`) and updates the flags,
+    /// without writing the result anywhere.
+    ///
+    /// When subtracting with carry, `Z` is only kept set if it was already
+    /// set, so that multi-byte subtractions test the whole value.
+    fn do_compare(&mut self, rd_val: u8, rr_val: u8, with_carry: bool) -> u8 {
+        let carry = with_carry && self.register_file.sreg.is_set(sreg::CARRY_FLAG);
+        let zero = self.register_file.sreg.is_set(sreg::ZERO_FLAG);
+
+        let result = rd_val.wrapping_sub(rr_val).wrapping_sub(carry as u8);
+
+        self.update_sreg_sub(rd_val, rr_val, result);
+        if with_carry {
+            self.register_file
+                .sreg
+                .set(sreg::ZERO_FLAG, zero && result == 0);
+        }
+        result
+    }
+
+    /// Dispatches the highest priority pending interrupt, if any.
+    fn dispatch_pending_interrupt(&mut self) -> Result<(), Error> {
+        let mut accepted = [false; 4];
+        for (level, accepted) in Level::ALL.into_iter().zip(&mut accepted) {
+            *accepted = self.accepts_interrupt(level)?;
+        }
+        let pending = self
+            .interrupts
+            .highest_pending_where(|level| accepted[level as usize]);
+
+        if let Some(number) = pending {
+            tracing::debug!(
+                pc = self.pc,
+                cycle = self.cycle_count,
+                number,
+                "dispatching interrupt"
+            );
+            if self.interrupt(number)? {
+                self.interrupts.clear(number);
+            }
+        }
+        Ok(())
+    }
+
+    /// Pushes the current PC (the instruction after the call) onto the stack.
+    fn push_return_address(&mut self) -> Result<(), Error> {
+        // The stack holds word addresses, low byte first.
+        let return_addr = self.pc >> 1;
+
+        for i in 0..self.return_address_size {
+            self.push_u8((return_addr >> (8 * i)) as u8)?;
+        }
+        Ok(())
+    }
+
+    /// Pushes a byte onto the stack.
+    fn push_u8(&mut self, val: u8) -> Result<(), Error> {
+        let sp = self.register_file.gpr_pair_val(regs::SP_LO_NUM)?;
+
+        if sp < self.sram_start || sp > self.ram_end {
+            return Err(Error::StackOverflow {
+                sp,
+                pc: self.executing_pc,
+                history: Vec::new(),
+            });
+        }
+
+        let old = self.memory.peek_u8(sp as usize)?;
+        self.check_watchpoints(Access::Write(sp), old, val);
+        self.memory.set_u8(sp as usize, val)?;
+
+        // post-decrement
+        self.register_file.set_gpr_pair(regs::SP_LO_NUM, sp - 1);
+        Ok(())
+    }
+
+    /// Pops a byte off the stack.
+    fn pop_u8(&mut self) -> Result<u8, Error> {
+        let sp = self.register_file.gpr_pair_val(regs::SP_LO_NUM)?;
+
+        // pre-increment
+        if sp >= self.ram_end {
+            return Err(Error::StackUnderflow {
+                sp,
+                pc: self.executing_pc,
+                history: Vec::new(),
+            });
         }
+        let sp = sp + 1;
+
+        let val = self.memory.get_u8(sp as usize)?;
+        self.check_watchpoints(Access::Read(sp), val, val);
+        self.register_file.set_gpr_pair(regs::SP_LO_NUM, sp);
+        Ok(val)
     }
 
     fn do_rd<F>(&mut self, rd: u8, mut f: F) -> Result<(), Error>
@@ -668,7 +2287,10 @@ impl Core {
     where
         F: FnMut(u16, u16) -> u16,
     {
-        assert!(rd % 2 == 0 && rr % 2 == 0, "GPR pairs must be even numbers");
+        assert!(
+            rd.is_multiple_of(2) && rr.is_multiple_of(2),
+            "GPR pairs must be even numbers"
+        );
 
         let rr_val_lo = self.register_file.gpr(rr).unwrap() as u16;
         let rr_val_hi = self.register_file.gpr(rr + 1).unwrap() as u16;
@@ -689,107 +2311,297 @@ impl Core {
 
     fn do_io_ab<F>(&mut self, a: u8, b: u8, mut f: F) -> Result<(), Error>
     where
-        F: FnMut(&mut Self, u8, u8) -> u8,
+        F: FnMut(u8, u8) -> u8,
     {
-        let memory_address = (SRAM_IO_OFFSET + a as u16) as usize;
-        let current_value = self.memory.get_u8(memory_address)?;
-        let new_value = f(self, current_value, b);
+        let address = self.io_offset() + a as u16;
+        let current_value = self.read_data(address)?;
+        let new_value = f(current_value, b);
 
-        self.memory.set_u8(memory_address, new_value)
+        self.write_data(address, new_value)
     }
 
-    fn do_sreg_branch<F>(&mut self, k: i8, mut f: F) -> Result<(), Error>
+    /// Performs a read-modify-write of the data space byte pointed to by `Z`,
+    /// storing the old value in `rd`.
+    fn do_z_atomic<F>(&mut self, rz: u8, rd: u8, mut f: F) -> Result<(), Error>
+    where
+        F: FnMut(u8, u8) -> u8,
+    {
+        let addr = self.register_file.gpr_pair_val(rz)?;
+        let z_val = self.read_data(addr)?;
+        let rd_val = self.register_file.gpr(rd)?;
+
+        self.write_data(addr, f(z_val, rd_val))?;
+        *self.register_file.gpr_mut(rd)? = z_val;
+        Ok(())
+    }
+
+    fn do_sreg_branch<F>(&mut self, k: i8, mut f: F) -> Result<bool, Error>
     where
         F: FnMut(sreg::SReg) -> bool,
     {
         let sreg = self.register_file.sreg.clone();
-        if f(sreg) {
+        let taken = f(sreg);
+        if taken {
             self.rjmp(k as i16)?
         };
-        Ok(())
+        Ok(taken)
+    }
+
+    /// Updates the `S`, `V`, `N` and `Z` flags for a logical operation.
+    fn update_sreg_logical(&mut self, result: u8) {
+        let r7 = result & 0x80 != 0;
+
+        let sreg = &mut self.register_file.sreg;
+        sreg.set(sreg::OVERFLOW_FLAG, false);
+        sreg.set(sreg::NEGATIVE_FLAG, r7);
+        sreg.set(sreg::ZERO_FLAG, result == 0);
+    }
+
+    /// Updates the `S`, `V`, `N` and `Z` flags for `INC` and `DEC`.
+    fn update_sreg_inc_dec(&mut self, result: u8, overflow: bool) {
+        let sreg = &mut self.register_file.sreg;
+        sreg.set(sreg::OVERFLOW_FLAG, overflow);
+        sreg.set(sreg::NEGATIVE_FLAG, result & 0x80 != 0);
+        sreg.set(sreg::ZERO_FLAG, result == 0);
+    }
+
+    /// Updates the `S`, `V`, `N`, `Z` and `C` flags for a 16-bit word
+    /// operation, given its overflow and carry.
+    fn update_sreg_word(&mut self, result: u16, overflow: bool, carry: bool) {
+        let r15 = result & 0x8000 != 0;
+
+        let sreg = &mut self.register_file.sreg;
+        sreg.set(sreg::OVERFLOW_FLAG, overflow);
+        sreg.set(sreg::NEGATIVE_FLAG, r15);
+        sreg.set(sreg::ZERO_FLAG, result == 0);
+        sreg.set(sreg::CARRY_FLAG, carry);
+    }
+
+    /// Updates the `H`, `S`, `V`, `N`, `Z` and `C` flags for the 8-bit
+    /// addition `rd + rr = result`.
+    fn update_sreg_add(&mut self, rd: u8, rr: u8, result: u8) {
+        // Bit n of `carries` is set if bit n carried into bit n+1.
+        let carries = (rd & rr) | (rr & !result) | (!result & rd);
+        let overflows = (rd & rr & !result) | (!rd & !rr & result);
+
+        let half_carry = carries & 0x08 != 0;
+        let carry = carries & 0x80 != 0;
+        let overflow = overflows & 0x80 != 0;
+        let r7 = result & 0x80 != 0;
+
+        let sreg = &mut self.register_file.sreg;
+        sreg.set(sreg::HALF_CARRY_FLAG, half_carry);
+        sreg.set(sreg::OVERFLOW_FLAG, overflow);
+        sreg.set(sreg::NEGATIVE_FLAG, r7);
+        sreg.set(sreg::ZERO_FLAG, result == 0);
+        sreg.set(sreg::CARRY_FLAG, carry);
+    }
+
+    /// Updates the `H`, `S`, `V`, `N`, `Z` and `C` flags for the 8-bit
+    /// subtraction `rd - rr = result`.
+    fn update_sreg_sub(&mut self, rd: u8, rr: u8, result: u8) {
+        // Bit n of `borrows` is set if bit n borrowed from bit n+1.
+        let borrows = (!rd & rr) | (rr & result) | (result & !rd);
+        let overflows = (rd & !rr & !result) | (!rd & rr & result);
+
+        let half_carry = borrows & 0x08 != 0;
+        let carry = borrows & 0x80 != 0;
+        let overflow = overflows & 0x80 != 0;
+        let r7 = result & 0x80 != 0;
+
+        let sreg = &mut self.register_file.sreg;
+        sreg.set(sreg::HALF_CARRY_FLAG, half_carry);
+        sreg.set(sreg::OVERFLOW_FLAG, overflow);
+        sreg.set(sreg::NEGATIVE_FLAG, r7);
+        sreg.set(sreg::ZERO_FLAG, result == 0);
+        sreg.set(sreg::CARRY_FLAG, carry);
+    }
+
+    /// Checks if a data space address is part of a 16-bit register.
+    fn word_register_at(&self, addr: mem::Address) -> Option<WordByte> {
+        self.word_registers.iter().find_map(|&low| {
+            if addr == low {
+                Some(WordByte::Low)
+            } else if addr == low + 1 {
+                Some(WordByte::High)
+            } else {
+                None
+            }
+        })
     }
 
-    /// Updates the `V`, `C`, `H`, `N`, `Z`, and `S` status flags.
-    fn update_sreg_arithmetic(&mut self, val: u16) -> Result<(), Error> {
-        self.update_overflow_flag(val);
-        self.update_carry_flag(val);
-        self.update_half_carry_flag(val);
-        self.update_negative_flag(val);
-        self.update_zero_flag(val);
-        Ok(())
+    /// Gets the IO address that a data space address maps to.
+    fn io_register_at(&self, addr: mem::Address) -> Option<u8> {
+        let offset = self.io_offset();
+        if (offset..offset + IO_SPACE_SIZE).contains(&addr) {
+            Some((addr - offset) as u8)
+        } else {
+            None
+        }
     }
 
-    /// Updates the `V`, `C`, `H`, `N`, `Z`, and `S` status flags.
-    fn update_sreg_cp(&mut self, rd_val: u16, rr_val: u16) {
-        let val = rd_val - rr_val;
+    /// Gets the address an `LD` or `ST` through a pointer register accesses,
+    /// and steps the pointer by a byte before or after the access.
+    fn pointer_address(&mut self, ptr: u8, variant: inst::Variant) -> Result<u16, Error> {
+        let val = self.register_file.gpr_pair_val(ptr)?;
 
-        self.update_overflow_flag(val);
-        self.update_negative_flag(val);
-        self.update_zero_flag(val);
-
-        let is_carry = (rr_val as i16).abs() > (rd_val as i16).abs();
-        self.register_file.sreg.set(sreg::CARRY_FLAG, is_carry);
+        let (addr, next) = match variant {
+            inst::Variant::Normal => (val, val),
+            inst::Variant::Predecrement => (val.wrapping_sub(1), val.wrapping_sub(1)),
+            inst::Variant::Postincrement => (val, val.wrapping_add(1)),
+        };
 
-        // TODO: Set half carry flag
+        self.register_file.set_gpr_pair(ptr, next);
+        Ok(addr)
     }
+}
 
-    /// Sets the overflow flag if `val` overflows a `u8`.
-    fn update_overflow_flag(&mut self, val: u16) {
-        let overflowed = val > 0xff;
-        self.register_file.sreg.set(sreg::OVERFLOW_FLAG, overflowed);
+/// Fills a memory space with `0xff`, like erased flash or EEPROM.
+fn erased(mut space: mem::Space) -> mem::Space {
+    space.fill(0xff);
+    space
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Core;
+    use crate::chips::atmega328p;
+    use crate::sreg;
+
+    fn ldi(d: u16, k: u16) -> u16 {
+        0xe000 | (k & 0xf0) << 4 | (d - 16) << 4 | (k & 0x0f)
     }
 
-    /// Sets the carry flag if necessary.
-    fn update_carry_flag(&mut self, val: u16) {
-        let is_carry = (val & 0b100000000) > 0;
-        self.register_file.sreg.set(sreg::CARRY_FLAG, is_carry);
+    fn cpi(d: u16, k: u16) -> u16 {
+        0x3000 | (k & 0xf0) << 4 | (d - 16) << 4 | (k & 0x0f)
     }
 
-    /// Sets the half carry flag if necessary.
-    fn update_half_carry_flag(&mut self, val: u16) {
-        let is_hcarry = (val & 0b1000) > 0;
-        self.register_file
-            .sreg
-            .set(sreg::HALF_CARRY_FLAG, is_hcarry);
+    fn cp(d: u16, r: u16) -> u16 {
+        0x1400 | (r & 0x10) << 5 | d << 4 | (r & 0x0f)
     }
 
-    /// Sets the negative flag based on `val`.
-    fn update_negative_flag(&mut self, val: u16) {
-        let is_negative = (val & 0b10000000) > 0;
-        self.register_file
-            .sreg
-            .set(sreg::NEGATIVE_FLAG, is_negative);
-        self.register_file.sreg.set(sreg::S_FLAG, !is_negative);
+    fn cpc(d: u16, r: u16) -> u16 {
+        0x0400 | (r & 0x10) << 5 | d << 4 | (r & 0x0f)
     }
 
-    fn update_zero_flag(&mut self, val: u16) {
-        let is_zero = val == 0;
-        self.register_file.sreg.set(sreg::ZERO_FLAG, is_zero);
+    fn sub(d: u16, r: u16) -> u16 {
+        0x1800 | (r & 0x10) << 5 | d << 4 | (r & 0x0f)
     }
 
-    fn handle_ld_st_variant(&mut self, ptr: u8, variant: inst::Variant) {
-        let mut val = self.register_file.gpr_pair_val(ptr).unwrap();
+    const BRLT: u16 = 0xf004;
+    const BRGE: u16 = 0xf404;
+
+    fn run(words: &[u16], ticks: usize) -> Core {
+        let mut core = Core::new::<atmega328p::Chip>();
+        core.load_program_space(words.iter().flat_map(|w| w.to_le_bytes()));
+        for _ in 0..ticks {
+            core.tick().unwrap();
+        }
+        core
+    }
+
+    /// Runs a comparison followed by a branch, and gets whether the branch
+    /// was taken.
+    fn taken(comparison: &[u16], branch: u16) -> bool {
+        let mut words = comparison.to_vec();
+        words.extend([
+            branch | 2 << 3,
+            ldi(20, 0),
+            0xc001, // rjmp .+2
+            ldi(20, 1),
+            0xcfff, // rjmp .-2
+        ]);
+        let core = run(&words, comparison.len() + 3);
+        core.register_file().gpr(20).unwrap() == 1
+    }
+
+    fn compare(instruction: fn(u16, u16) -> u16, a: u8, b: u8) -> Vec<u16> {
+        vec![ldi(16, a as u16), ldi(17, b as u16), instruction(16, 17)]
+    }
+
+    fn compare_immediate(a: u8, b: u8) -> Vec<u16> {
+        vec![ldi(16, a as u16), cpi(16, b as u16)]
+    }
+
+    fn compare_word(a: u16, b: u16) -> Vec<u16> {
+        vec![
+            ldi(16, a & 0xff),
+            ldi(17, a >> 8),
+            ldi(18, b & 0xff),
+            ldi(19, b >> 8),
+            cp(16, 18),
+            cpc(17, 19),
+        ]
+    }
+
+    fn comparisons(a: u8, b: u8) -> [Vec<u16>; 3] {
+        [
+            compare(cp, a, b),
+            compare(sub, a, b),
+            compare_immediate(a, b),
+        ]
+    }
+
+    #[test]
+    fn signed_compare_with_overflow() {
+        // -128 - 1 overflows to +127, so N is clear and only V tells the
+        // result is negative.
+        for comparison in comparisons(0x80, 0x01) {
+            assert!(taken(&comparison, BRLT));
+            assert!(!taken(&comparison, BRGE));
+        }
+
+        // 127 - -1 overflows to -128.
+        for comparison in comparisons(0x7f, 0xff) {
+            assert!(taken(&comparison, BRGE));
+            assert!(!taken(&comparison, BRLT));
+        }
+    }
 
-        match variant {
-            inst::Variant::Normal => (),
-            inst::Variant::Predecrement => val -= PTR_SIZE,
-            inst::Variant::Postincrement => val += PTR_SIZE,
+    #[test]
+    fn signed_compare_without_overflow() {
+        for comparison in comparisons(0xff, 0x01) {
+            assert!(taken(&comparison, BRLT));
         }
+        for comparison in comparisons(0x01, 0xff) {
+            assert!(taken(&comparison, BRGE));
+        }
+        for comparison in comparisons(0x05, 0x05) {
+            assert!(taken(&comparison, BRGE));
+            assert!(!taken(&comparison, BRLT));
+        }
+    }
 
-        self.register_file.set_gpr_pair(ptr, val);
+    #[test]
+    fn signed_compare_with_carry() {
+        // -32768 < 1 and 32767 > -1, both overflowing in the high byte.
+        assert!(taken(&compare_word(0x8000, 0x0001), BRLT));
+        assert!(!taken(&compare_word(0x8000, 0x0001), BRGE));
+        assert!(taken(&compare_word(0x7fff, 0xffff), BRGE));
+        assert!(!taken(&compare_word(0x7fff, 0xffff), BRLT));
+        assert!(taken(&compare_word(0x0100, 0x00ff), BRGE));
     }
 
-    /// This is like the hackiest clock, ever!
-    fn update_clock(&mut self) -> Result<(), Error> {
-        let clk_lo = self.memory().get_u16(0x105)? as u32;
-        let clk_hi = self.memory().get_u16(0x107)? as u32;
-        let clk = (clk_hi << 8) | clk_lo;
+    #[test]
+    fn s_is_n_xor_v() {
+        let core = run(&compare(sub, 0x80, 0x01), 3);
+        let flag = |mask| core.register_file().sreg_flag(mask);
+        assert!(!flag(sreg::NEGATIVE_FLAG));
+        assert!(flag(sreg::OVERFLOW_FLAG));
+        assert!(flag(sreg::S_FLAG));
+    }
 
-        let clk = clk.wrapping_add(1);
-        let clk_lo = (clk & 0xff) as u16;
-        let clk_hi = (clk >> 8) as u16;
-        self.memory.set_u16(0x105, clk_lo)?;
-        self.memory.set_u16(0x107, clk_hi)?;
-        Ok(())
+    #[test]
+    fn data_after_the_last_instruction() {
+        // `rjmp .-2` followed by a word that does not decode.
+        let core = run(&[0xcfff, 0xffff], 3);
+        assert_eq!(core.pc, 0);
+    }
+
+    #[test]
+    fn branch_to_the_next_instruction_takes_a_cycle_when_taken() {
+        // `S` starts out clear, so `brge .+0` is taken and `brlt .+0` is not.
+        assert_eq!(run(&[BRGE], 1).cycle_count, 2);
+        assert_eq!(run(&[BRLT], 1).cycle_count, 1);
     }
 }
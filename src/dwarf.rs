@@ -0,0 +1,767 @@
+//! Line and function information from DWARF debug sections.
+//!
+//! Only what is needed to map program counter values back to source is
+//! read: the line number programs in `.debug_line` and the subprograms in
+//! `.debug_info`. DWARF versions 2 to 5 are supported, in the 32-bit
+//! format that `avr-gcc` produces.
+
+use crate::elf::Elf;
+use crate::Error;
+use std::collections::HashMap;
+use std::fmt;
+
+const DW_TAG_SUBPROGRAM: u64 = 0x2e;
+
+const DW_AT_NAME: u64 = 0x03;
+const DW_AT_LOW_PC: u64 = 0x11;
+const DW_AT_HIGH_PC: u64 = 0x12;
+const DW_AT_ABSTRACT_ORIGIN: u64 = 0x31;
+const DW_AT_SPECIFICATION: u64 = 0x47;
+
+const DW_FORM_ADDR: u64 = 0x01;
+const DW_FORM_BLOCK2: u64 = 0x03;
+const DW_FORM_BLOCK4: u64 = 0x04;
+const DW_FORM_DATA2: u64 = 0x05;
+const DW_FORM_DATA4: u64 = 0x06;
+const DW_FORM_DATA8: u64 = 0x07;
+const DW_FORM_STRING: u64 = 0x08;
+const DW_FORM_BLOCK: u64 = 0x09;
+const DW_FORM_BLOCK1: u64 = 0x0a;
+const DW_FORM_DATA1: u64 = 0x0b;
+const DW_FORM_FLAG: u64 = 0x0c;
+const DW_FORM_SDATA: u64 = 0x0d;
+const DW_FORM_STRP: u64 = 0x0e;
+const DW_FORM_UDATA: u64 = 0x0f;
+const DW_FORM_REF_ADDR: u64 = 0x10;
+const DW_FORM_REF1: u64 = 0x11;
+const DW_FORM_REF2: u64 = 0x12;
+const DW_FORM_REF4: u64 = 0x13;
+const DW_FORM_REF8: u64 = 0x14;
+const DW_FORM_REF_UDATA: u64 = 0x15;
+const DW_FORM_INDIRECT: u64 = 0x16;
+const DW_FORM_SEC_OFFSET: u64 = 0x17;
+const DW_FORM_EXPRLOC: u64 = 0x18;
+const DW_FORM_FLAG_PRESENT: u64 = 0x19;
+const DW_FORM_STRX: u64 = 0x1a;
+const DW_FORM_ADDRX: u64 = 0x1b;
+const DW_FORM_REF_SUP4: u64 = 0x1c;
+const DW_FORM_STRP_SUP: u64 = 0x1d;
+const DW_FORM_DATA16: u64 = 0x1e;
+const DW_FORM_LINE_STRP: u64 = 0x1f;
+const DW_FORM_REF_SIG8: u64 = 0x20;
+const DW_FORM_IMPLICIT_CONST: u64 = 0x21;
+const DW_FORM_LOCLISTX: u64 = 0x22;
+const DW_FORM_RNGLISTX: u64 = 0x23;
+const DW_FORM_REF_SUP8: u64 = 0x24;
+const DW_FORM_STRX1: u64 = 0x25;
+const DW_FORM_STRX2: u64 = 0x26;
+const DW_FORM_STRX3: u64 = 0x27;
+const DW_FORM_STRX4: u64 = 0x28;
+const DW_FORM_ADDRX1: u64 = 0x29;
+const DW_FORM_ADDRX2: u64 = 0x2a;
+const DW_FORM_ADDRX3: u64 = 0x2b;
+const DW_FORM_ADDRX4: u64 = 0x2c;
+
+const DW_UT_COMPILE: u8 = 0x01;
+const DW_UT_PARTIAL: u8 = 0x03;
+
+const DW_LNS_COPY: u8 = 1;
+const DW_LNS_ADVANCE_PC: u8 = 2;
+const DW_LNS_ADVANCE_LINE: u8 = 3;
+const DW_LNS_SET_FILE: u8 = 4;
+const DW_LNS_CONST_ADD_PC: u8 = 8;
+const DW_LNS_FIXED_ADVANCE_PC: u8 = 9;
+
+const DW_LNE_END_SEQUENCE: u8 = 1;
+const DW_LNE_SET_ADDRESS: u8 = 2;
+const DW_LNE_DEFINE_FILE: u8 = 3;
+
+const DW_LNCT_PATH: u64 = 1;
+
+/// A source location for a program counter value.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Location {
+    /// The function containing the address, if known.
+    pub function: Option<String>,
+    pub file: String,
+    pub line: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self.function {
+            Some(ref function) => write!(fmt, "{}() at {}:{}", function, self.file, self.line),
+            None => write!(fmt, "{}:{}", self.file, self.line),
+        }
+    }
+}
+
+/// A function and the byte addresses of its code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Function {
+    pub name: String,
+    pub low_pc: u32,
+    /// The address just past the end of the function.
+    pub high_pc: u32,
+}
+
+/// The byte addresses generated for a source line.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LineRange<'a> {
+    pub file: &'a str,
+    pub line: u32,
+    pub low_pc: u32,
+    /// The address just past the end of the range.
+    pub high_pc: u32,
+}
+
+/// A row of the line number table.
+#[derive(Copy, Clone, Debug)]
+struct Row {
+    address: u32,
+    /// An index into `DebugInfo::files`.
+    file: usize,
+    line: u32,
+    end_sequence: bool,
+}
+
+/// The line and function information of a program.
+#[derive(Clone, Debug, Default)]
+pub struct DebugInfo {
+    files: Vec<String>,
+    /// Sorted by address, with end of sequence rows before other rows at
+    /// the same address.
+    rows: Vec<Row>,
+    functions: Vec<Function>,
+}
+
+impl DebugInfo {
+    /// Reads the debug information from an ELF file.
+    ///
+    /// A file without debug sections gives empty debug information.
+    pub fn parse(elf: &Elf) -> Result<Self, Error> {
+        let section = |name| elf.section(name).map(|s| &s.data[..]).unwrap_or(&[]);
+        let strings = Strings {
+            debug_str: section(".debug_str"),
+            debug_line_str: section(".debug_line_str"),
+        };
+
+        let mut info = DebugInfo::default();
+        info.parse_lines(section(".debug_line"), &strings)?;
+        info.parse_functions(section(".debug_info"), section(".debug_abbrev"), &strings)?;
+
+        info.rows
+            .sort_by_key(|row| (row.address, !row.end_sequence));
+        info.functions.sort_by_key(|f| f.low_pc);
+        Ok(info)
+    }
+
+    pub fn functions(&self) -> &[Function] {
+        &self.functions
+    }
+
+    /// Gets the innermost function containing a byte address.
+    pub fn function(&self, pc: u32) -> Option<&Function> {
+        self.functions
+            .iter()
+            .filter(|f| f.low_pc <= pc && pc < f.high_pc)
+            .min_by_key(|f| f.high_pc.wrapping_sub(f.low_pc))
+    }
+
+    /// Finds the source location of a byte address.
+    pub fn symbolicate(&self, pc: u32) -> Option<Location> {
+        let index = self.rows.partition_point(|row| row.address <= pc);
+        let row = self.rows[..index].last()?;
+        if row.end_sequence {
+            return None;
+        }
+
+        Some(Location {
+            function: self.function(pc).map(|f| f.name.clone()),
+            file: self.files[row.file].clone(),
+            line: row.line,
+        })
+    }
+
+    /// Gets the address ranges of all source lines in the line number
+    /// table, by address. A line may have several ranges.
+    pub fn line_ranges(&self) -> impl Iterator<Item = LineRange<'_>> {
+        self.rows
+            .windows(2)
+            .filter(|rows| !rows[0].end_sequence && rows[0].address < rows[1].address)
+            .map(|rows| LineRange {
+                file: &self.files[rows[0].file],
+                line: rows[0].line,
+                low_pc: rows[0].address,
+                high_pc: rows[1].address,
+            })
+    }
+
+    fn parse_lines(&mut self, section: &[u8], strings: &Strings) -> Result<(), Error> {
+        let mut reader = Reader::new(section);
+        while !reader.is_empty() {
+            let length = reader.u32()?;
+            if length == 0xffff_ffff {
+                return Err(Error::InvalidDwarf("64-bit DWARF is not supported"));
+            }
+            let unit = reader.take(length as usize)?;
+            self.parse_line_unit(unit, strings)?;
+        }
+        Ok(())
+    }
+
+    fn parse_line_unit(&mut self, unit: &[u8], strings: &Strings) -> Result<(), Error> {
+        let mut reader = Reader::new(unit);
+        let version = reader.u16()?;
+        if !(2..=5).contains(&version) {
+            return Err(Error::InvalidDwarf("unsupported line table version"));
+        }
+        if version >= 5 {
+            reader.u8()?; // address size
+            reader.u8()?; // segment selector size
+        }
+        let header_length = reader.u32()? as usize;
+        let program = unit
+            .get(reader.pos + header_length..)
+            .ok_or(Error::InvalidDwarf("line table header out of bounds"))?;
+        let mut program = Reader::new(program);
+
+        let min_instruction_length = reader.u8()? as u32;
+        if version >= 4 {
+            reader.u8()?; // maximum operations per instruction
+        }
+        reader.u8()?; // default is_stmt
+        let line_base = reader.u8()? as i8 as i64;
+        let line_range = reader.u8()?;
+        let opcode_base = reader.u8()?;
+        let opcode_lengths = reader
+            .take(opcode_base.saturating_sub(1) as usize)?
+            .to_vec();
+        if line_range == 0 {
+            return Err(Error::InvalidDwarf("line range of zero"));
+        }
+
+        // Maps the file numbers used by the program to `self.files`.
+        let mut files = Vec::new();
+        if version >= 5 {
+            // Directories.
+            let formats = entry_formats(&mut reader)?;
+            for _ in 0..reader.uleb()? {
+                read_entry(&mut reader, &formats, strings)?;
+            }
+
+            let formats = entry_formats(&mut reader)?;
+            for _ in 0..reader.uleb()? {
+                let path = read_entry(&mut reader, &formats, strings)?;
+                files.push(self.add_file(path.unwrap_or_default()));
+            }
+        } else {
+            while !reader.cstr()?.is_empty() {} // include directories
+
+            // File numbers start at one.
+            files.push(self.add_file(String::new()));
+            loop {
+                let path = reader.cstr()?;
+                if path.is_empty() {
+                    break;
+                }
+                reader.uleb()?; // directory
+                reader.uleb()?; // modification time
+                reader.uleb()?; // length
+                files.push(self.add_file(path));
+            }
+        }
+
+        let mut address = 0u32;
+        let mut file = 1usize;
+        let mut line = 1i64;
+
+        while !program.is_empty() {
+            let opcode = program.u8()?;
+            let mut emit = false;
+            let mut end_sequence = false;
+
+            if opcode >= opcode_base {
+                let adjusted = opcode - opcode_base;
+                address =
+                    address.wrapping_add((adjusted / line_range) as u32 * min_instruction_length);
+                line = line.wrapping_add(line_base + (adjusted % line_range) as i64);
+                emit = true;
+            } else if opcode == 0 {
+                let length = program.uleb()? as usize;
+                let mut extended = Reader::new(program.take(length)?);
+                match extended.u8()? {
+                    DW_LNE_END_SEQUENCE => {
+                        emit = true;
+                        end_sequence = true;
+                    }
+                    DW_LNE_SET_ADDRESS => {
+                        address = match length.saturating_sub(1) {
+                            2 => extended.u16()? as u32,
+                            _ => extended.u32()?,
+                        };
+                    }
+                    DW_LNE_DEFINE_FILE => {
+                        let path = extended.cstr()?;
+                        files.push(self.add_file(path));
+                    }
+                    _ => (),
+                }
+            } else {
+                match opcode {
+                    DW_LNS_COPY => emit = true,
+                    DW_LNS_ADVANCE_PC => {
+                        let advance = (program.uleb()? as u32).wrapping_mul(min_instruction_length);
+                        address = address.wrapping_add(advance);
+                    }
+                    DW_LNS_ADVANCE_LINE => line = line.wrapping_add(program.sleb()?),
+                    DW_LNS_SET_FILE => file = program.uleb()? as usize,
+                    DW_LNS_CONST_ADD_PC => {
+                        let adjusted = 255 - opcode_base;
+                        address = address
+                            .wrapping_add((adjusted / line_range) as u32 * min_instruction_length);
+                    }
+                    DW_LNS_FIXED_ADVANCE_PC => {
+                        address = address.wrapping_add(program.u16()? as u32)
+                    }
+                    _ => {
+                        for _ in 0..opcode_lengths[opcode as usize - 1] {
+                            program.uleb()?;
+                        }
+                    }
+                }
+            }
+
+            if emit {
+                self.rows.push(Row {
+                    address,
+                    file: *files
+                        .get(file)
+                        .ok_or(Error::InvalidDwarf("file number out of range"))?,
+                    line: line as u32,
+                    end_sequence,
+                });
+            }
+            if end_sequence {
+                address = 0;
+                file = 1;
+                line = 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn add_file(&mut self, path: String) -> usize {
+        self.files.push(path);
+        self.files.len() - 1
+    }
+
+    fn parse_functions(
+        &mut self,
+        section: &[u8],
+        abbrev_section: &[u8],
+        strings: &Strings,
+    ) -> Result<(), Error> {
+        let mut reader = Reader::new(section);
+        while !reader.is_empty() {
+            let unit_offset = reader.pos;
+            let length = reader.u32()?;
+            if length == 0xffff_ffff {
+                return Err(Error::InvalidDwarf("64-bit DWARF is not supported"));
+            }
+            let end = reader.pos + length as usize;
+            if end > section.len() {
+                return Err(Error::InvalidDwarf("unit out of bounds"));
+            }
+
+            let version = reader.u16()?;
+            let (unit_type, address_size, abbrev_offset) = match version {
+                2..=4 => {
+                    let abbrev_offset = reader.u32()?;
+                    (DW_UT_COMPILE, reader.u8()?, abbrev_offset)
+                }
+                5 => (reader.u8()?, reader.u8()?, reader.u32()?),
+                _ => return Err(Error::InvalidDwarf("unsupported debug info version")),
+            };
+
+            if unit_type == DW_UT_COMPILE || unit_type == DW_UT_PARTIAL {
+                let abbrevs = parse_abbrevs(abbrev_section, abbrev_offset as usize)?;
+                let unit = Unit {
+                    offset: unit_offset,
+                    version,
+                    address_size,
+                };
+                let mut dies = Reader::new(&section[..end]);
+                dies.pos = reader.pos;
+                self.parse_unit_functions(&mut dies, &unit, &abbrevs, strings)?;
+            }
+            reader.pos = end;
+        }
+        Ok(())
+    }
+
+    fn parse_unit_functions(
+        &mut self,
+        reader: &mut Reader,
+        unit: &Unit,
+        abbrevs: &HashMap<u64, Abbrev>,
+        strings: &Strings,
+    ) -> Result<(), Error> {
+        // The names of the entries in the unit, for functions that refer
+        // to their declaration for one.
+        let mut names: HashMap<usize, String> = HashMap::new();
+        let mut unnamed: Vec<(usize, u32, u32)> = Vec::new();
+
+        while !reader.is_empty() {
+            let offset = reader.pos;
+            let code = reader.uleb()?;
+            if code == 0 {
+                continue;
+            }
+            let abbrev = abbrevs
+                .get(&code)
+                .ok_or(Error::InvalidDwarf("unknown abbreviation code"))?;
+
+            let mut name = None;
+            let mut low_pc = None;
+            let mut high_pc = None;
+            let mut origin = None;
+
+            for &(attribute, form, implicit) in &abbrev.attributes {
+                let value = read_value(reader, form, implicit, unit, strings)?;
+                match (attribute, value) {
+                    (DW_AT_NAME, Value::Str(s)) => name = Some(s),
+                    (DW_AT_LOW_PC, Value::Address(a)) => low_pc = Some(a as u32),
+                    (DW_AT_HIGH_PC, value) => high_pc = Some(value),
+                    (DW_AT_SPECIFICATION | DW_AT_ABSTRACT_ORIGIN, Value::Reference(r)) => {
+                        origin = Some(r)
+                    }
+                    _ => (),
+                }
+            }
+
+            if let Some(ref name) = name {
+                names.insert(offset, name.clone());
+            }
+
+            if abbrev.tag != DW_TAG_SUBPROGRAM {
+                continue;
+            }
+            let (low_pc, high_pc) = match (low_pc, high_pc) {
+                (Some(low), Some(Value::Address(high))) => (low, high as u32),
+                (Some(low), Some(Value::Constant(size))) => (low, low.wrapping_add(size as u32)),
+                _ => continue,
+            };
+
+            match (name, origin) {
+                (Some(name), _) => self.functions.push(Function {
+                    name,
+                    low_pc,
+                    high_pc,
+                }),
+                (None, Some(origin)) => unnamed.push((origin, low_pc, high_pc)),
+                (None, None) => (),
+            }
+        }
+
+        for (origin, low_pc, high_pc) in unnamed {
+            if let Some(name) = names.get(&origin) {
+                self.functions.push(Function {
+                    name: name.clone(),
+                    low_pc,
+                    high_pc,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The string sections referred to by attributes.
+struct Strings<'a> {
+    debug_str: &'a [u8],
+    debug_line_str: &'a [u8],
+}
+
+impl Strings<'_> {
+    fn get(section: &[u8], offset: u64) -> Result<String, Error> {
+        let mut reader = Reader::new(section);
+        reader.pos = offset as usize;
+        reader.cstr()
+    }
+}
+
+struct Unit {
+    /// The offset of the unit header in `.debug_info`.
+    offset: usize,
+    version: u16,
+    address_size: u8,
+}
+
+struct Abbrev {
+    tag: u64,
+    /// The attribute, form, and implicit constant of each attribute.
+    attributes: Vec<(u64, u64, i64)>,
+}
+
+enum Value {
+    Address(u64),
+    Constant(u64),
+    Str(String),
+    /// An offset into `.debug_info`.
+    Reference(usize),
+    Other,
+}
+
+fn parse_abbrevs(section: &[u8], offset: usize) -> Result<HashMap<u64, Abbrev>, Error> {
+    let mut reader = Reader::new(section);
+    reader.pos = offset;
+
+    let mut abbrevs = HashMap::new();
+    loop {
+        let code = reader.uleb()?;
+        if code == 0 {
+            return Ok(abbrevs);
+        }
+        let tag = reader.uleb()?;
+        reader.u8()?; // has children
+
+        let mut attributes = Vec::new();
+        loop {
+            let attribute = reader.uleb()?;
+            let form = reader.uleb()?;
+            if attribute == 0 && form == 0 {
+                break;
+            }
+            let implicit = if form == DW_FORM_IMPLICIT_CONST {
+                reader.sleb()?
+            } else {
+                0
+            };
+            attributes.push((attribute, form, implicit));
+        }
+        abbrevs.insert(code, Abbrev { tag, attributes });
+    }
+}
+
+fn read_value(
+    reader: &mut Reader,
+    form: u64,
+    implicit: i64,
+    unit: &Unit,
+    strings: &Strings,
+) -> Result<Value, Error> {
+    let reference = |offset: u64| Value::Reference(unit.offset.wrapping_add(offset as usize));
+
+    Ok(match form {
+        DW_FORM_ADDR => Value::Address(reader.sized(unit.address_size)?),
+        DW_FORM_DATA1 => Value::Constant(reader.u8()? as u64),
+        DW_FORM_DATA2 => Value::Constant(reader.u16()? as u64),
+        DW_FORM_DATA4 => Value::Constant(reader.u32()? as u64),
+        DW_FORM_DATA8 => Value::Constant(reader.u64()?),
+        DW_FORM_UDATA => Value::Constant(reader.uleb()?),
+        DW_FORM_SDATA => Value::Constant(reader.sleb()? as u64),
+        DW_FORM_IMPLICIT_CONST => Value::Constant(implicit as u64),
+        DW_FORM_STRING => Value::Str(reader.cstr()?),
+        DW_FORM_STRP => Value::Str(Strings::get(strings.debug_str, reader.u32()? as u64)?),
+        DW_FORM_LINE_STRP => {
+            Value::Str(Strings::get(strings.debug_line_str, reader.u32()? as u64)?)
+        }
+        DW_FORM_REF1 => reference(reader.u8()? as u64),
+        DW_FORM_REF2 => reference(reader.u16()? as u64),
+        DW_FORM_REF4 => reference(reader.u32()? as u64),
+        DW_FORM_REF8 => reference(reader.u64()?),
+        DW_FORM_REF_UDATA => reference(reader.uleb()?),
+        DW_FORM_REF_ADDR => {
+            let size = if unit.version == 2 {
+                unit.address_size
+            } else {
+                4
+            };
+            Value::Reference(reader.sized(size)? as usize)
+        }
+        DW_FORM_INDIRECT => {
+            let form = reader.uleb()?;
+            return read_value(reader, form, implicit, unit, strings);
+        }
+        DW_FORM_FLAG_PRESENT => Value::Other,
+        DW_FORM_FLAG | DW_FORM_STRX1 | DW_FORM_ADDRX1 => {
+            reader.take(1)?;
+            Value::Other
+        }
+        DW_FORM_STRX2 | DW_FORM_ADDRX2 => {
+            reader.take(2)?;
+            Value::Other
+        }
+        DW_FORM_STRX3 | DW_FORM_ADDRX3 => {
+            reader.take(3)?;
+            Value::Other
+        }
+        DW_FORM_SEC_OFFSET | DW_FORM_REF_SUP4 | DW_FORM_STRP_SUP | DW_FORM_STRX4
+        | DW_FORM_ADDRX4 => {
+            reader.take(4)?;
+            Value::Other
+        }
+        DW_FORM_REF_SIG8 | DW_FORM_REF_SUP8 => {
+            reader.take(8)?;
+            Value::Other
+        }
+        DW_FORM_DATA16 => {
+            reader.take(16)?;
+            Value::Other
+        }
+        DW_FORM_STRX | DW_FORM_ADDRX | DW_FORM_LOCLISTX | DW_FORM_RNGLISTX => {
+            reader.uleb()?;
+            Value::Other
+        }
+        DW_FORM_BLOCK1 => {
+            let length = reader.u8()? as usize;
+            reader.take(length)?;
+            Value::Other
+        }
+        DW_FORM_BLOCK2 => {
+            let length = reader.u16()? as usize;
+            reader.take(length)?;
+            Value::Other
+        }
+        DW_FORM_BLOCK4 => {
+            let length = reader.u32()? as usize;
+            reader.take(length)?;
+            Value::Other
+        }
+        DW_FORM_BLOCK | DW_FORM_EXPRLOC => {
+            let length = reader.uleb()? as usize;
+            reader.take(length)?;
+            Value::Other
+        }
+        _ => return Err(Error::InvalidDwarf("unknown attribute form")),
+    })
+}
+
+/// Reads the entry format of a DWARF 5 directory or file name table.
+fn entry_formats(reader: &mut Reader) -> Result<Vec<(u64, u64)>, Error> {
+    let count = reader.u8()?;
+    (0..count)
+        .map(|_| Ok((reader.uleb()?, reader.uleb()?)))
+        .collect()
+}
+
+/// Reads a DWARF 5 directory or file name entry, returning its path.
+fn read_entry(
+    reader: &mut Reader,
+    formats: &[(u64, u64)],
+    strings: &Strings,
+) -> Result<Option<String>, Error> {
+    // Line tables have no unit of their own, but references and addresses
+    // do not appear in them.
+    let unit = Unit {
+        offset: 0,
+        version: 5,
+        address_size: 4,
+    };
+
+    let mut path = None;
+    for &(content_type, form) in formats {
+        let value = read_value(reader, form, 0, &unit, strings)?;
+        if let (DW_LNCT_PATH, Value::Str(s)) = (content_type, value) {
+            path = Some(s);
+        }
+    }
+    Ok(path)
+}
+
+/// Reads little-endian values from a section.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes, pos: 0 }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        let bytes = self
+            .pos
+            .checked_add(len)
+            .and_then(|end| self.bytes.get(self.pos..end))
+            .ok_or(Error::InvalidDwarf("unexpected end of section"))?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn sized(&mut self, size: u8) -> Result<u64, Error> {
+        let bytes = self.take(size as usize)?;
+        Ok(bytes
+            .iter()
+            .rev()
+            .fold(0u64, |value, &b| (value << 8) | b as u64))
+    }
+
+    fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.sized(1)? as u8)
+    }
+
+    fn u16(&mut self) -> Result<u16, Error> {
+        Ok(self.sized(2)? as u16)
+    }
+
+    fn u32(&mut self) -> Result<u32, Error> {
+        Ok(self.sized(4)? as u32)
+    }
+
+    fn u64(&mut self) -> Result<u64, Error> {
+        self.sized(8)
+    }
+
+    fn uleb(&mut self) -> Result<u64, Error> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as u64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn sleb(&mut self) -> Result<i64, Error> {
+        let mut value = 0i64;
+        let mut shift = 0;
+        loop {
+            let byte = self.u8()?;
+            if shift < 64 {
+                value |= ((byte & 0x7f) as i64) << shift;
+            }
+            shift += 7;
+            if byte & 0x80 == 0 {
+                if shift < 64 && byte & 0x40 != 0 {
+                    value |= -1i64 << shift;
+                }
+                return Ok(value);
+            }
+        }
+    }
+
+    fn cstr(&mut self) -> Result<String, Error> {
+        let rest = self
+            .bytes
+            .get(self.pos..)
+            .ok_or(Error::InvalidDwarf("string out of bounds of its section"))?;
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(Error::InvalidDwarf("unterminated string"))?;
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(&rest[..len]).into_owned())
+    }
+}
@@ -0,0 +1,278 @@
+//! The ELF file format, as produced by `avr-gcc`.
+//!
+//! The AVR toolchain places each memory space at its own offset in the
+//! physical address space of the ELF file: flash at `0`, SRAM at
+//! `0x800000`, EEPROM at `0x810000`, the fuses at `0x820000` and so on.
+
+use crate::Error;
+
+const MAGIC: [u8; 4] = [0x7f, b'E', b'L', b'F'];
+const CLASS_32: u8 = 1;
+const DATA_LITTLE_ENDIAN: u8 = 1;
+const MACHINE_AVR: u16 = 83;
+
+const PT_LOAD: u32 = 1;
+
+/// The section type of the symbol table.
+pub const SHT_SYMTAB: u32 = 2;
+/// The section type of sections with no contents in the file, like `.bss`.
+pub const SHT_NOBITS: u32 = 8;
+
+const DATA_OFFSET: u32 = 0x80_0000;
+const EEPROM_OFFSET: u32 = 0x81_0000;
+const FUSE_OFFSET: u32 = 0x82_0000;
+const LOCK_OFFSET: u32 = 0x83_0000;
+const SIGNATURE_OFFSET: u32 = 0x84_0000;
+const USER_SIGNATURE_OFFSET: u32 = 0x85_0000;
+
+/// The memory a physical address in an AVR ELF file belongs to.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Region {
+    Flash,
+    Data,
+    Eeprom,
+    Fuse,
+    Lock,
+    Signature,
+    UserSignature,
+}
+
+impl Region {
+    /// Splits a physical address into its region and the offset within it.
+    pub fn of(address: u32) -> (Region, u32) {
+        match address {
+            a if a >= USER_SIGNATURE_OFFSET => (Region::UserSignature, a - USER_SIGNATURE_OFFSET),
+            a if a >= SIGNATURE_OFFSET => (Region::Signature, a - SIGNATURE_OFFSET),
+            a if a >= LOCK_OFFSET => (Region::Lock, a - LOCK_OFFSET),
+            a if a >= FUSE_OFFSET => (Region::Fuse, a - FUSE_OFFSET),
+            a if a >= EEPROM_OFFSET => (Region::Eeprom, a - EEPROM_OFFSET),
+            a if a >= DATA_OFFSET => (Region::Data, a - DATA_OFFSET),
+            a => (Region::Flash, a),
+        }
+    }
+}
+
+/// Bytes loaded into a region, taken from a `PT_LOAD` program header.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub region: Region,
+    /// The address within the region.
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+/// A section of the file, like `.text` or `.debug_info`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    pub kind: u32,
+    pub address: u32,
+    /// The index of an associated section, like the string table of a
+    /// symbol table.
+    pub link: u32,
+    /// The contents, empty for `SHT_NOBITS` sections.
+    pub data: Vec<u8>,
+}
+
+/// The kind of thing a symbol names.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum SymbolKind {
+    Function,
+    Object,
+    Other,
+}
+
+/// An entry of the symbol table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    pub region: Region,
+    /// The address within the region.
+    pub address: u32,
+    pub size: u32,
+}
+
+/// A parsed ELF file.
+#[derive(Clone, Debug)]
+pub struct Elf {
+    /// The byte address execution starts at.
+    pub entry: u32,
+    pub segments: Vec<Segment>,
+    pub sections: Vec<Section>,
+}
+
+impl Elf {
+    /// Parses a 32-bit little-endian AVR ELF file.
+    pub fn parse(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.get(0..4) != Some(&MAGIC[..]) {
+            return Err(Error::InvalidElf("not an ELF file"));
+        }
+        if bytes.get(4) != Some(&CLASS_32) || bytes.get(5) != Some(&DATA_LITTLE_ENDIAN) {
+            return Err(Error::InvalidElf("not a 32-bit little-endian ELF file"));
+        }
+        if u16_at(bytes, 18)? != MACHINE_AVR {
+            return Err(Error::InvalidElf("not an AVR ELF file"));
+        }
+
+        let entry = u32_at(bytes, 24)?;
+        let segments = Self::parse_segments(bytes)?;
+        let sections = Self::parse_sections(bytes)?;
+
+        Ok(Elf {
+            entry,
+            segments,
+            sections,
+        })
+    }
+
+    /// Gets the section with the given name.
+    pub fn section(&self, name: &str) -> Option<&Section> {
+        self.sections.iter().find(|s| s.name == name)
+    }
+
+    /// Reads the named functions and objects from the symbol table.
+    ///
+    /// Section and file symbols are skipped.
+    pub fn symbols(&self) -> Result<Vec<Symbol>, Error> {
+        const STT_OBJECT: u8 = 1;
+        const STT_FUNC: u8 = 2;
+        const STT_SECTION: u8 = 3;
+        const STT_FILE: u8 = 4;
+        const ENTRY_SIZE: usize = 16;
+
+        let table = match self.sections.iter().find(|s| s.kind == SHT_SYMTAB) {
+            Some(table) => table,
+            None => return Ok(Vec::new()),
+        };
+        let names = self
+            .sections
+            .get(table.link as usize)
+            .ok_or(Error::InvalidElf("symbol table has no string table"))?;
+
+        let mut symbols = Vec::new();
+        for entry in table.data.chunks_exact(ENTRY_SIZE) {
+            let name = string_at(&names.data, u32_at(entry, 0)? as usize)?;
+            let kind = match entry[12] & 0xf {
+                STT_SECTION | STT_FILE => continue,
+                STT_FUNC => SymbolKind::Function,
+                STT_OBJECT => SymbolKind::Object,
+                _ => SymbolKind::Other,
+            };
+            if name.is_empty() {
+                continue;
+            }
+
+            let (region, address) = Region::of(u32_at(entry, 4)?);
+            symbols.push(Symbol {
+                name,
+                kind,
+                region,
+                address,
+                size: u32_at(entry, 8)?,
+            });
+        }
+        Ok(symbols)
+    }
+
+    fn parse_segments(bytes: &[u8]) -> Result<Vec<Segment>, Error> {
+        let offset = u32_at(bytes, 28)? as usize;
+        let entry_size = u16_at(bytes, 42)? as usize;
+        let count = u16_at(bytes, 44)? as usize;
+
+        let mut segments = Vec::new();
+        for i in 0..count {
+            let header = offset + i * entry_size;
+            if u32_at(bytes, header)? != PT_LOAD {
+                continue;
+            }
+
+            let file_offset = u32_at(bytes, header + 4)? as usize;
+            let physical_address = u32_at(bytes, header + 12)?;
+            let file_size = u32_at(bytes, header + 16)? as usize;
+            if file_size == 0 {
+                continue;
+            }
+
+            let (region, address) = Region::of(physical_address);
+            segments.push(Segment {
+                region,
+                address,
+                data: slice(bytes, file_offset, file_size)?.to_vec(),
+            });
+        }
+        Ok(segments)
+    }
+
+    fn parse_sections(bytes: &[u8]) -> Result<Vec<Section>, Error> {
+        let offset = u32_at(bytes, 32)? as usize;
+        let entry_size = u16_at(bytes, 46)? as usize;
+        let count = u16_at(bytes, 48)? as usize;
+        let names_index = u16_at(bytes, 50)? as usize;
+
+        if offset == 0 || count == 0 {
+            return Ok(Vec::new());
+        }
+
+        let header = |i: usize| offset + i * entry_size;
+        let names_header = header(names_index);
+        let names = slice(
+            bytes,
+            u32_at(bytes, names_header + 16)? as usize,
+            u32_at(bytes, names_header + 20)? as usize,
+        )?;
+
+        let mut sections = Vec::with_capacity(count);
+        for i in 0..count {
+            let header = header(i);
+            let name = u32_at(bytes, header)? as usize;
+            let kind = u32_at(bytes, header + 4)?;
+            let address = u32_at(bytes, header + 12)?;
+            let link = u32_at(bytes, header + 24)?;
+            let data = if kind == SHT_NOBITS {
+                Vec::new()
+            } else {
+                slice(
+                    bytes,
+                    u32_at(bytes, header + 16)? as usize,
+                    u32_at(bytes, header + 20)? as usize,
+                )?
+                .to_vec()
+            };
+
+            sections.push(Section {
+                name: string_at(names, name)?,
+                kind,
+                address,
+                link,
+                data,
+            });
+        }
+        Ok(sections)
+    }
+}
+
+fn slice(bytes: &[u8], offset: usize, len: usize) -> Result<&[u8], Error> {
+    offset
+        .checked_add(len)
+        .and_then(|end| bytes.get(offset..end))
+        .ok_or(Error::InvalidElf("data out of bounds of the file"))
+}
+
+fn u16_at(bytes: &[u8], offset: usize) -> Result<u16, Error> {
+    let b = slice(bytes, offset, 2)?;
+    Ok(u16::from_le_bytes([b[0], b[1]]))
+}
+
+fn u32_at(bytes: &[u8], offset: usize) -> Result<u32, Error> {
+    let b = slice(bytes, offset, 4)?;
+    Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn string_at(table: &[u8], offset: usize) -> Result<String, Error> {
+    let bytes = table
+        .get(offset..)
+        .ok_or(Error::InvalidElf("string out of bounds of its table"))?;
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    Ok(String::from_utf8_lossy(&bytes[..end]).into_owned())
+}
@@ -1,9 +1,186 @@
+use crate::chips::Family;
+use crate::Instruction;
+
+/// The most recently executed instructions and their byte addresses,
+/// oldest first, as kept by `Core::pc_history`.
+pub type History = Vec<(u32, Instruction)>;
+
 /// An error on the AVR.
 #[derive(Debug)]
 pub enum Error {
     UnknownInstruction(u32),
-    StackOverflow,
-    SegmentationFault { address: usize },
+    /// The machine code ends in the middle of an instruction.
+    TruncatedInstruction,
+    /// An operand of the instruction does not fit its encoding, like `r5`
+    /// for `LDI`.
+    InvalidOperand(Instruction),
+    /// A push would write below the start of SRAM.
+    StackOverflow {
+        sp: u16,
+        pc: u32,
+        /// The instructions executed before, see `Error::history`.
+        history: History,
+    },
+    /// A pop would read past the end of SRAM.
+    StackUnderflow {
+        sp: u16,
+        pc: u32,
+        /// The instructions executed before, see `Error::history`.
+        history: History,
+    },
+    SegmentationFault {
+        address: usize,
+        /// The instructions executed before, see `Error::history`.
+        history: History,
+    },
+    /// An instruction at `pc` wrote to flash mapped into the data space.
+    /// See `Core::set_mapped_flash_writes`.
+    StrayFlashWrite {
+        address: u16,
+        value: u8,
+        pc: u32,
+        /// The instructions executed before, see `Error::history`.
+        history: History,
+    },
+    /// An access to an address protected by `Space::guard`.
+    GuardViolation {
+        address: usize,
+        /// Whether the access was a write rather than a read.
+        write: bool,
+        /// The address of the instruction that made the access, or `None`
+        /// if it was not made by an instruction.
+        pc: Option<u32>,
+        /// The instructions executed before, see `Error::history`.
+        history: History,
+    },
     RegisterDoesNotExist(u8),
     RegisterPairOdd(u8),
+    /// The chip has no such port, or the port no such pin.
+    PinDoesNotExist {
+        port: char,
+        pin: u8,
+    },
+    /// A pin name is not like `PB5`.
+    InvalidPinName(String),
+    /// The interrupt vector is not in the chip's vector table.
+    InterruptDoesNotExist(u8),
+    /// The instruction is not supported by the chip.
+    UnsupportedInstruction(Instruction),
+    /// The instruction is not in the instruction set of the core family,
+    /// like `MUL` or any use of `r0` to `r15` on a reduced AVRrc core.
+    NotInInstructionSet {
+        instruction: Instruction,
+        family: Family,
+    },
+    /// A `BREAK` instruction was executed at `pc`.
+    ///
+    /// Execution can be resumed by ticking again.
+    Break {
+        pc: u32,
+    },
+    /// A run executed its whole instruction budget without stopping,
+    /// leaving the program counter at `pc`.
+    InstructionBudgetExhausted {
+        budget: u64,
+        pc: u32,
+    },
+    /// An Intel HEX file could not be parsed.
+    InvalidIntelHex {
+        line: usize,
+        message: &'static str,
+    },
+    /// Assembly source could not be assembled.
+    Assembly {
+        line: usize,
+        message: String,
+    },
+    /// A label was defined more than once in a `Program`.
+    DuplicateLabel(String),
+    /// A relative jump or branch in a `Program` is too far from its label.
+    BranchOutOfRange {
+        label: String,
+        offset: i64,
+    },
+    /// An ELF file could not be parsed.
+    InvalidElf(&'static str),
+    /// The DWARF debug information in an ELF file could not be parsed.
+    InvalidDwarf(&'static str),
+    /// No symbol with the name exists in the loaded program's symbol table,
+    /// or it is not in program space.
+    UnknownSymbol(String),
+    /// A breakpoint condition could not be parsed.
+    InvalidCondition {
+        condition: String,
+        message: String,
+    },
+    /// An ATDF device description could not be parsed.
+    InvalidAtdf {
+        line: usize,
+        message: String,
+    },
+    /// A saved simulation state could not be loaded.
+    InvalidState(&'static str),
+    /// A chip description from a `CoreBuilder` does not fit together.
+    InvalidChip(&'static str),
+    /// A replay journal could not be parsed.
+    InvalidJournal {
+        line: usize,
+        message: String,
+    },
+    /// A stimulus was for a UART or ADC that was not registered with the
+    /// `Mcu`.
+    UnregisteredHandle {
+        kind: &'static str,
+        index: usize,
+    },
+    /// Data space addresses were mapped with `Core::map_io` when part of
+    /// them already were.
+    IoAlreadyMapped(std::ops::Range<u16>),
+    /// An IO error from a file backing the simulation.
+    Io(std::io::Error),
+}
+
+impl Error {
+    /// Gets the instructions executed up to and including the one that
+    /// failed, oldest first, for errors that record them.
+    ///
+    /// This is filled in when the error comes from `Core::tick`, with as
+    /// many instructions as `Core::set_pc_history_capacity` keeps.
+    pub fn history(&self) -> Option<&[(u32, Instruction)]> {
+        match self {
+            Error::StackOverflow { history, .. }
+            | Error::StackUnderflow { history, .. }
+            | Error::SegmentationFault { history, .. }
+            | Error::StrayFlashWrite { history, .. }
+            | Error::GuardViolation { history, .. } => Some(history),
+            _ => None,
+        }
+    }
+
+    /// Fills in the address of the instruction that failed, for errors
+    /// from a memory space that cannot know it.
+    pub(crate) fn at_pc(mut self, at: u32) -> Self {
+        if let Error::GuardViolation { pc: pc @ None, .. } = &mut self {
+            *pc = Some(at);
+        }
+        self
+    }
+
+    /// Fills in the history of errors that record it.
+    pub(crate) fn with_history<I>(mut self, instructions: I) -> Self
+    where
+        I: IntoIterator<Item = (u32, Instruction)>,
+    {
+        match &mut self {
+            Error::StackOverflow { history, .. }
+            | Error::StackUnderflow { history, .. }
+            | Error::SegmentationFault { history, .. }
+            | Error::StrayFlashWrite { history, .. }
+            | Error::GuardViolation { history, .. } => {
+                *history = instructions.into_iter().collect();
+            }
+            _ => (),
+        }
+        self
+    }
 }
@@ -0,0 +1,38 @@
+//! Events that happen as the simulation runs, for `Mcu::subscribe`.
+
+use crate::mem;
+use crate::reset::ResetCause;
+use crate::watch;
+
+/// Something that happened in the simulation.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Event {
+    /// The CPU was reset, with the cause recorded in `MCUSR`, or `None`
+    /// for `Mcu::reset`.
+    Reset { cause: Option<ResetCause> },
+    /// An interrupt vector was dispatched.
+    InterruptDispatched { vector: u8 },
+    /// An instruction wrote to an IO register.
+    IoWrite { address: mem::Address, value: u8 },
+    /// `SPM` erased or wrote a page of program space.
+    FlashWrite {
+        /// The byte address of the start of the page.
+        page: u32,
+        /// Whether the page was erased rather than written.
+        erased: bool,
+    },
+    /// An instruction at `pc` wrote to flash mapped into the data space,
+    /// outside of self-programming. See `Core::set_mapped_flash_writes`.
+    StrayFlashWrite {
+        address: mem::Address,
+        value: u8,
+        pc: u32,
+    },
+    /// The level of a GPIO pin changed, like `PB5` for port `'B'` and pin
+    /// 5.
+    PinChanged { port: char, pin: u8, high: bool },
+    /// A run stopped at a breakpoint at a byte address.
+    BreakpointHit { address: u32 },
+    /// A run stopped on a watched access.
+    WatchpointHit(watch::Hit),
+}
@@ -0,0 +1,140 @@
+//! Fuse bytes and lock bits.
+//!
+//! A fuse bit is programmed when it is zero. The bit layouts are those of
+//! the ATmega328P.
+
+/// Low fuse byte bits.
+pub mod low {
+    /// Divide the clock by 8.
+    pub const CKDIV8: u8 = 1 << 7;
+    /// Output the clock on `CLKO`.
+    pub const CKOUT: u8 = 1 << 6;
+    pub const SUT_MASK: u8 = 0b0011_0000;
+    pub const CKSEL_MASK: u8 = 0b0000_1111;
+}
+
+/// High fuse byte bits.
+pub mod high {
+    /// Disable the external reset.
+    pub const RSTDISBL: u8 = 1 << 7;
+    /// Enable debugWIRE.
+    pub const DWEN: u8 = 1 << 6;
+    /// Enable serial programming.
+    pub const SPIEN: u8 = 1 << 5;
+    /// Watchdog timer always on.
+    pub const WDTON: u8 = 1 << 4;
+    /// Preserve EEPROM through chip erase.
+    pub const EESAVE: u8 = 1 << 3;
+    /// The boot section size bits `BOOTSZ1:0`.
+    pub const BOOTSZ_MASK: u8 = 0b0000_0110;
+    /// Reset into the boot section.
+    pub const BOOTRST: u8 = 1 << 0;
+}
+
+/// Lock byte bits.
+pub mod lock {
+    /// The memory lock bits `LB2:1`, which only affect external
+    /// programming.
+    pub const LB_MASK: u8 = 0b0000_0011;
+    /// The application section lock bits `BLB02:01`.
+    pub const BLB0_MASK: u8 = 0b0000_1100;
+    /// The boot section lock bits `BLB12:11`.
+    pub const BLB1_MASK: u8 = 0b0011_0000;
+}
+
+/// A section of flash.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Section {
+    Application,
+    Boot,
+}
+
+/// What the boot lock bits allow for a section.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SectionLock {
+    /// Whether `SPM` may write to the section.
+    pub spm_write: bool,
+    /// Whether `LPM` executed from the other section may read it.
+    pub lpm_read: bool,
+}
+
+/// The fuse bytes and lock bits of a chip.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Fuses {
+    pub low: u8,
+    pub high: u8,
+    pub extended: u8,
+    pub lock: u8,
+}
+
+impl Fuses {
+    /// Everything unprogrammed.
+    pub fn unprogrammed() -> Self {
+        Fuses {
+            low: 0xff,
+            high: 0xff,
+            extended: 0xff,
+            lock: 0xff,
+        }
+    }
+
+    /// Gets the factor the clock is divided by at reset.
+    pub fn clock_divider(&self) -> u32 {
+        if self.low & low::CKDIV8 == 0 {
+            8
+        } else {
+            1
+        }
+    }
+
+    /// Checks if the reset vector is at the start of the boot section.
+    pub fn boot_reset(&self) -> bool {
+        self.high & high::BOOTRST == 0
+    }
+
+    /// Gets the size of the boot section in words, given the size selected
+    /// by `BOOTSZ1:0 = 11`. Each step down doubles it.
+    pub fn boot_section_words(&self, min_words: u32) -> u32 {
+        let bootsz = (self.high & high::BOOTSZ_MASK) >> 1;
+        min_words << (3 - bootsz)
+    }
+
+    /// Checks if the flash can not be read back by a programmer.
+    pub fn is_read_protected(&self) -> bool {
+        self.lock & lock::LB_MASK == 0
+    }
+
+    /// Gets what the boot lock bits allow for a section.
+    pub fn section_lock(&self, section: Section) -> SectionLock {
+        let bits = match section {
+            Section::Application => (self.lock & lock::BLB0_MASK) >> 2,
+            Section::Boot => (self.lock & lock::BLB1_MASK) >> 4,
+        };
+
+        match bits {
+            0b11 => SectionLock {
+                spm_write: true,
+                lpm_read: true,
+            },
+            0b10 => SectionLock {
+                spm_write: false,
+                lpm_read: true,
+            },
+            0b00 => SectionLock {
+                spm_write: false,
+                lpm_read: false,
+            },
+            _ => SectionLock {
+                spm_write: true,
+                lpm_read: false,
+            },
+        }
+    }
+}
+
+impl Default for Fuses {
+    fn default() -> Self {
+        Fuses::unprogrammed()
+    }
+}
@@ -0,0 +1,178 @@
+//! The Intel HEX file format.
+
+use crate::Error;
+use std::fmt::Write;
+
+/// Data to be loaded at an address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Segment {
+    pub address: u32,
+    pub data: Vec<u8>,
+}
+
+const DATA: u8 = 0x00;
+const END_OF_FILE: u8 = 0x01;
+const EXTENDED_SEGMENT_ADDRESS: u8 = 0x02;
+const START_SEGMENT_ADDRESS: u8 = 0x03;
+const EXTENDED_LINEAR_ADDRESS: u8 = 0x04;
+const START_LINEAR_ADDRESS: u8 = 0x05;
+
+/// Parses Intel HEX records into the segments they load.
+///
+/// Extended segment and extended linear address records are applied to the
+/// data records that follow them. Start address records are ignored, and
+/// parsing stops at the end of file record.
+pub fn parse(text: &str) -> Result<Vec<Segment>, Error> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut base = 0u32;
+
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |message| Error::InvalidIntelHex {
+            line: line_number,
+            message,
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let hex = line
+            .strip_prefix(':')
+            .ok_or_else(|| error("record does not start with ':'"))?;
+        let bytes = decode_hex(hex).ok_or_else(|| error("invalid hex digits"))?;
+
+        if bytes.len() < 5 || bytes.len() != 5 + bytes[0] as usize {
+            return Err(error("record length does not match its byte count"));
+        }
+        if bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) != 0 {
+            return Err(error("checksum mismatch"));
+        }
+
+        let offset = u16::from_be_bytes([bytes[1], bytes[2]]) as u32;
+        let data = &bytes[4..bytes.len() - 1];
+
+        match bytes[3] {
+            DATA => {
+                let address = base.wrapping_add(offset);
+                match segments.last_mut() {
+                    // Merge contiguous records into one segment.
+                    Some(last) if last.address + last.data.len() as u32 == address => {
+                        last.data.extend_from_slice(data);
+                    }
+                    _ => segments.push(Segment {
+                        address,
+                        data: data.to_vec(),
+                    }),
+                }
+            }
+            END_OF_FILE => return Ok(segments),
+            EXTENDED_SEGMENT_ADDRESS if data.len() == 2 => {
+                base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 4;
+            }
+            EXTENDED_LINEAR_ADDRESS if data.len() == 2 => {
+                base = (u16::from_be_bytes([data[0], data[1]]) as u32) << 16;
+            }
+            START_SEGMENT_ADDRESS | START_LINEAR_ADDRESS if data.len() == 4 => (),
+            EXTENDED_SEGMENT_ADDRESS
+            | EXTENDED_LINEAR_ADDRESS
+            | START_SEGMENT_ADDRESS
+            | START_LINEAR_ADDRESS => return Err(error("wrong record length for its type")),
+            _ => return Err(error("unknown record type")),
+        }
+    }
+
+    Ok(segments)
+}
+
+/// The number of data bytes written per record.
+const RECORD_SIZE: usize = 16;
+
+/// Splits memory contents into segments, leaving out records that are
+/// entirely `blank`.
+pub fn segments(data: &[u8], blank: u8) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+
+    for (index, chunk) in data.chunks(RECORD_SIZE).enumerate() {
+        if chunk.iter().all(|&b| b == blank) {
+            continue;
+        }
+
+        let address = (index * RECORD_SIZE) as u32;
+        match segments.last_mut() {
+            Some(last) if last.address + last.data.len() as u32 == address => {
+                last.data.extend_from_slice(chunk);
+            }
+            _ => segments.push(Segment {
+                address,
+                data: chunk.to_vec(),
+            }),
+        }
+    }
+    segments
+}
+
+/// Encodes segments as Intel HEX records.
+///
+/// Extended linear address records are written for data above 64KiB, and
+/// records never cross a 64KiB boundary.
+pub fn encode(segments: &[Segment]) -> String {
+    let mut text = String::new();
+    let mut base = 0u32;
+
+    for segment in segments {
+        let mut address = segment.address;
+        let mut data = &segment.data[..];
+
+        while !data.is_empty() {
+            if address & 0xffff_0000 != base {
+                base = address & 0xffff_0000;
+                write_record(
+                    &mut text,
+                    EXTENDED_LINEAR_ADDRESS,
+                    0,
+                    &((base >> 16) as u16).to_be_bytes(),
+                );
+            }
+
+            let until_boundary = 0x1_0000 - (address & 0xffff) as usize;
+            let len = data.len().min(RECORD_SIZE).min(until_boundary);
+            write_record(&mut text, DATA, address as u16, &data[..len]);
+
+            address += len as u32;
+            data = &data[len..];
+        }
+    }
+
+    write_record(&mut text, END_OF_FILE, 0, &[]);
+    text
+}
+
+fn write_record(text: &mut String, kind: u8, offset: u16, data: &[u8]) {
+    let mut bytes = vec![data.len() as u8];
+    bytes.extend_from_slice(&offset.to_be_bytes());
+    bytes.push(kind);
+    bytes.extend_from_slice(data);
+
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes.push(sum.wrapping_neg());
+
+    text.push(':');
+    for byte in bytes {
+        let _ = write!(text, "{:02X}", byte);
+    }
+    text.push('\n');
+}
+
+/// Decodes pairs of hex digits into bytes.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
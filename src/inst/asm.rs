@@ -0,0 +1,866 @@
+//! An assembler for a useful subset of AVR assembly.
+//!
+//! The syntax follows the GNU assembler, as printed by `Instruction`'s
+//! `Display` implementation:
+//!
+//! ```text
+//!         .org 0
+//!         rjmp main
+//! main:   ldi r16, 5
+//! loop:   dec r16
+//!         brne loop       ; or `brne .-4`
+//!         ldi r30, lo8(message)
+//!         ldi r31, hi8(message)
+//!         lpm r24, Z+
+//! message:
+//!         .db "hi", 0
+//! ```
+//!
+//! Supported are all instructions the emulator decodes, the aliases `clr`,
+//! `ser`, `tst`, `lsl`, `rol`, `brsh`, `brlo`, `brbs` and `brbc`, labels,
+//! comments starting with `;` or `//`, and the directives `.org`, `.db`
+//! (`.byte`), `.dw` (`.word`), `.equ` (`.set`) and `.align`.
+//!
+//! Operands are expressions of numbers (`10`, `0x0a`, `0b1010`, `'a'`),
+//! labels, constants and `.` (the current address) combined with `+`, `-`
+//! and the functions `lo8`, `hi8`, `pm`, `pm_lo8` and `pm_hi8`. Relative
+//! jumps and branches take a target address, or an offset in bytes from the
+//! next instruction written as `.+4` or `.-6`.
+
+use crate::inst::Variant;
+use crate::{Error, Instruction};
+use std::collections::HashMap;
+
+/// Assembles source code into bytes to be loaded at address zero.
+///
+/// Gaps left by `.org` and `.align` are filled with `0xff`, like erased
+/// flash.
+pub fn assemble(source: &str) -> Result<Vec<u8>, Error> {
+    let mut assembler = Assembler::default();
+    let statements = assembler.layout(source)?;
+
+    let mut bytes = Vec::new();
+    for statement in statements {
+        let error = |message: String| Error::Assembly {
+            line: statement.line,
+            message,
+        };
+
+        let encoded = match statement.kind {
+            Kind::Instruction(..) if statement.address % 2 != 0 => {
+                return Err(error("instruction at an odd address".to_owned()));
+            }
+            Kind::Instruction(ref mnemonic, ref operands) => assembler
+                .instruction(mnemonic, operands, statement.address)
+                .map_err(error)?
+                .encode()
+                .map_err(|_| error("operand out of range".to_owned()))?,
+            Kind::Bytes(ref operands) => assembler
+                .data(operands, 1, statement.address)
+                .map_err(error)?,
+            Kind::Words(ref operands) => assembler
+                .data(operands, 2, statement.address)
+                .map_err(error)?,
+        };
+
+        let start = statement.address as usize;
+        if bytes.len() < start {
+            bytes.resize(start, 0xff);
+        }
+        let end = start + encoded.len();
+        if bytes.len() < end {
+            bytes.resize(end, 0xff);
+        }
+        bytes[start..end].copy_from_slice(&encoded);
+    }
+    Ok(bytes)
+}
+
+/// A line that emits bytes, placed at an address.
+struct Statement {
+    line: usize,
+    address: u32,
+    kind: Kind,
+}
+
+enum Kind {
+    Instruction(String, Vec<String>),
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+#[derive(Default)]
+struct Assembler {
+    /// The values of labels and constants.
+    symbols: HashMap<String, i64>,
+}
+
+impl Assembler {
+    /// Parses the source and assigns addresses to labels and statements.
+    fn layout(&mut self, source: &str) -> Result<Vec<Statement>, Error> {
+        let mut statements = Vec::new();
+        let mut address = 0u32;
+
+        for (index, line) in source.lines().enumerate() {
+            let line_number = index + 1;
+            let error = |message: String| Error::Assembly {
+                line: line_number,
+                message,
+            };
+
+            let mut text = strip_comment(line).trim();
+
+            // Labels.
+            while let Some(colon) = text.find(':') {
+                let label = text[..colon].trim();
+                if !is_identifier(label) {
+                    break;
+                }
+                self.define(label, address as i64).map_err(error)?;
+                text = text[colon + 1..].trim();
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            let (mnemonic, rest) = match text.find(char::is_whitespace) {
+                Some(i) => (&text[..i], text[i..].trim()),
+                None => (text, ""),
+            };
+            let mnemonic = mnemonic.to_ascii_lowercase();
+            let operands = split_operands(rest);
+
+            let kind = match &mnemonic[..] {
+                ".org" => {
+                    let [target] = expect_operands(&operands).map_err(error)?;
+                    address = self.evaluate(target, address).map_err(error)? as u32;
+                    continue;
+                }
+                ".align" => {
+                    let [alignment] = expect_operands(&operands).map_err(error)?;
+                    let alignment = self.evaluate(alignment, address).map_err(error)? as u32;
+                    address = address.next_multiple_of(alignment.max(1));
+                    continue;
+                }
+                ".equ" | ".set" => {
+                    let [name, value] = expect_operands(&operands).map_err(error)?;
+                    let value = self.evaluate(value, address).map_err(error)?;
+                    self.define(name, value).map_err(error)?;
+                    continue;
+                }
+                ".db" | ".byte" => Kind::Bytes(operands),
+                ".dw" | ".word" => Kind::Words(operands),
+                _ if mnemonic.starts_with('.') => {
+                    return Err(error(format!("unknown directive '{}'", mnemonic)))
+                }
+                _ => Kind::Instruction(mnemonic, operands),
+            };
+
+            let size = match kind {
+                Kind::Instruction(ref mnemonic, _) => match &mnemonic[..] {
+                    "jmp" | "call" | "lds" | "sts" => 4,
+                    _ => 2,
+                },
+                Kind::Bytes(ref operands) => {
+                    let mut size = 0;
+                    for operand in operands {
+                        size += match parse_string(operand).map_err(error)? {
+                            Some(s) => s.len() as u32,
+                            None => 1,
+                        };
+                    }
+                    size
+                }
+                Kind::Words(ref operands) => operands.len() as u32 * 2,
+            };
+
+            statements.push(Statement {
+                line: line_number,
+                address,
+                kind,
+            });
+            address += size;
+        }
+        Ok(statements)
+    }
+
+    fn define(&mut self, name: &str, value: i64) -> Result<(), String> {
+        if !is_identifier(name) {
+            return Err(format!("invalid name '{}'", name));
+        }
+        if self.symbols.insert(name.to_owned(), value).is_some() {
+            return Err(format!("'{}' is defined more than once", name));
+        }
+        Ok(())
+    }
+
+    fn data(&self, operands: &[String], size: usize, address: u32) -> Result<Vec<u8>, String> {
+        let mut bytes = Vec::new();
+        for operand in operands {
+            if size == 1 {
+                if let Some(s) = parse_string(operand)? {
+                    bytes.extend_from_slice(s.as_bytes());
+                    continue;
+                }
+            }
+
+            let value = self.evaluate(operand, address)?;
+            let max = (1i64 << (size * 8)) - 1;
+            if value < -(max + 1) / 2 || value > max {
+                return Err(format!("{} does not fit in {} byte(s)", value, size));
+            }
+            bytes.extend_from_slice(&(value as u16).to_le_bytes()[..size]);
+        }
+        Ok(bytes)
+    }
+
+    fn instruction(
+        &self,
+        mnemonic: &str,
+        operands: &[String],
+        address: u32,
+    ) -> Result<Instruction, String> {
+        use crate::Instruction::*;
+
+        let ops = Operands {
+            assembler: self,
+            operands,
+            address,
+        };
+
+        let instruction = match mnemonic {
+            "inc" => Inc(ops.one(register)?),
+            "dec" => Dec(ops.one(register)?),
+            "com" => Com(ops.one(register)?),
+            "neg" => Neg(ops.one(register)?),
+            "push" => Push(ops.one(register)?),
+            "pop" => Pop(ops.one(register)?),
+            "swap" => Swap(ops.one(register)?),
+            "clr" => {
+                let r = ops.one(register)?;
+                Eor(r, r)
+            }
+            "tst" => {
+                let r = ops.one(register)?;
+                And(r, r)
+            }
+            "lsl" => {
+                let r = ops.one(register)?;
+                Add(r, r)
+            }
+            "rol" => {
+                let r = ops.one(register)?;
+                Adc(r, r)
+            }
+            "ser" => Ldi(ops.one(upper_register)?, 0xff),
+
+            "subi" => ops.register_immediate(Subi)?,
+            "sbci" => ops.register_immediate(Sbci)?,
+            "andi" => ops.register_immediate(Andi)?,
+            "ori" => ops.register_immediate(Ori)?,
+            "cpi" => ops.register_immediate(Cpi)?,
+            "ldi" => ops.register_immediate(Ldi)?,
+
+            "add" => ops.registers(Add)?,
+            "adc" => ops.registers(Adc)?,
+            "sub" => ops.registers(Sub)?,
+            "sbc" => ops.registers(Sbc)?,
+            "mul" => ops.registers(Mul)?,
+            "and" => ops.registers(And)?,
+            "or" => ops.registers(Or)?,
+            "eor" => ops.registers(Eor)?,
+            "cpse" => ops.registers(Cpse)?,
+            "cp" => ops.registers(Cp)?,
+            "cpc" => ops.registers(Cpc)?,
+            "mov" => ops.registers(Mov)?,
+            "movw" => {
+                let (d, r) = ops.two(register, register)?;
+                if d % 2 != 0 || r % 2 != 0 {
+                    return Err("movw needs even registers".to_owned());
+                }
+                Movw(d, r)
+            }
+            "adiw" | "sbiw" => {
+                let (d, k) = ops.two(register, |s| Ok(s.to_owned()))?;
+                if ![24, 26, 28, 30].contains(&d) {
+                    return Err(format!("{} needs r24, r26, r28 or r30", mnemonic));
+                }
+                let k = ops.value(&k, 0, 63)? as u8;
+                if mnemonic == "adiw" {
+                    Adiw(d, k)
+                } else {
+                    Sbiw(d, k)
+                }
+            }
+
+            "in" => {
+                let [d, a] = expect_operands(operands)?;
+                In(register(d)?, ops.value(a, 0, 63)? as u8)
+            }
+            "out" => {
+                let [a, r] = expect_operands(operands)?;
+                Out(ops.value(a, 0, 63)? as u8, register(r)?)
+            }
+            "sbi" => ops.io_bit(Sbi)?,
+            "sbis" => ops.io_bit(Sbis)?,
+            "cbi" => ops.io_bit(Cbi)?,
+            "sbrs" => {
+                let [r, b] = expect_operands(operands)?;
+                Sbrs(register(r)?, ops.value(b, 0, 7)? as u8)
+            }
+
+            "jmp" => Jmp(ops.absolute()?),
+            "call" => Call(ops.absolute()?),
+            "rjmp" => Rjmp(ops.relative(-4096, 4094)? as i16),
+            "rcall" => Rcall(ops.relative(-4096, 4094)? as i16),
+
+            "brbs" | "brbc" => {
+                let [s, target] = expect_operands(operands)?;
+                let s = ops.value(s, 0, 7)? as u8;
+                let k = ops.relative_to(target, -128, 126)? as i8;
+                if mnemonic == "brbs" {
+                    Brbs(s, k)
+                } else {
+                    Brbc(s, k)
+                }
+            }
+            "breq" => Breq(ops.branch()?),
+            "brne" => Brne(ops.branch()?),
+            "brcs" => Brcs(ops.branch()?),
+            "brcc" => Brcc(ops.branch()?),
+            "brsh" => Brsh(ops.branch()?),
+            "brlo" => Brlo(ops.branch()?),
+            "brmi" => Brmi(ops.branch()?),
+            "brpl" => Brpl(ops.branch()?),
+            "brge" => Brge(ops.branch()?),
+            "brlt" => Brlt(ops.branch()?),
+            "brhs" => Brhs(ops.branch()?),
+            "brhc" => Brhc(ops.branch()?),
+            "brts" => Brts(ops.branch()?),
+            "brtc" => Brtc(ops.branch()?),
+            "brvs" => Brvs(ops.branch()?),
+            "brvc" => Brvc(ops.branch()?),
+            "brie" => Brie(ops.branch()?),
+            "brid" => Brid(ops.branch()?),
+
+            "st" => {
+                let (pointer, r) = ops.two(pointer, register)?;
+                St(pointer.0, r, pointer.1)
+            }
+            "ld" => {
+                let (d, pointer) = ops.two(register, pointer)?;
+                Ld(d, pointer.0, pointer.1)
+            }
+            "xch" => ops.atomic(Xch)?,
+            "las" => ops.atomic(Las)?,
+            "lac" => ops.atomic(Lac)?,
+            "lat" => ops.atomic(Lat)?,
+            "std" => {
+                let [target, r] = expect_operands(operands)?;
+                let (p, q) = ops.displacement(target)?;
+                Std(p, q, register(r)?)
+            }
+            "ldd" => {
+                let [d, source] = expect_operands(operands)?;
+                let (p, q) = ops.displacement(source)?;
+                Ldd(register(d)?, p, q)
+            }
+            "sts" => {
+                let [k, r] = expect_operands(operands)?;
+                Sts(register(r)?, ops.value(k, 0, 0xffff)? as u16)
+            }
+            "lds" => {
+                let [d, k] = expect_operands(operands)?;
+                Lds(register(d)?, ops.value(k, 0, 0xffff)? as u16)
+            }
+            "lpm" if operands.is_empty() => Lpm(0, 30, false),
+            "lpm" => {
+                let (d, pointer) = ops.two(register, pointer)?;
+                match pointer {
+                    (30, Variant::Normal) => Lpm(d, 30, false),
+                    (30, Variant::Postincrement) => Lpm(d, 30, true),
+                    _ => return Err("lpm takes Z or Z+".to_owned()),
+                }
+            }
+            "elpm" if operands.is_empty() => Elpm(0, 30, false),
+            "elpm" => {
+                let (d, pointer) = ops.two(register, pointer)?;
+                match pointer {
+                    (30, Variant::Normal) => Elpm(d, 30, false),
+                    (30, Variant::Postincrement) => Elpm(d, 30, true),
+                    _ => return Err("elpm takes Z or Z+".to_owned()),
+                }
+            }
+            "spm" if operands.is_empty() => Spm(false),
+            "spm" => match ops.one(pointer)? {
+                (30, Variant::Postincrement) => Spm(true),
+                _ => return Err("spm takes Z+".to_owned()),
+            },
+
+            "ijmp" => ops.none(Ijmp)?,
+            "icall" => ops.none(Icall)?,
+            "eijmp" => ops.none(Eijmp)?,
+            "eicall" => ops.none(Eicall)?,
+
+            "nop" => ops.none(Nop)?,
+            "ret" => ops.none(Ret)?,
+            "reti" => ops.none(Reti)?,
+            "sei" => ops.none(Sei)?,
+            "cli" => ops.none(Cli)?,
+            "sleep" => ops.none(Sleep)?,
+            "break" => ops.none(Break)?,
+            "wdr" => ops.none(Wdr)?,
+            "des" => Des(ops.one(|k| ops.value(k, 0, 15))? as u8),
+
+            _ => return Err(format!("unknown instruction '{}'", mnemonic)),
+        };
+        Ok(instruction)
+    }
+
+    /// Evaluates an expression, with `.` standing for `address`.
+    fn evaluate(&self, expression: &str, address: u32) -> Result<i64, String> {
+        let mut parser = Parser {
+            assembler: self,
+            address,
+            text: expression.trim(),
+        };
+        let value = parser.expression()?;
+        if !parser.text.trim().is_empty() {
+            return Err(format!("unexpected '{}'", parser.text.trim()));
+        }
+        Ok(value)
+    }
+}
+
+/// The operands of an instruction being assembled.
+struct Operands<'a> {
+    assembler: &'a Assembler,
+    operands: &'a [String],
+    address: u32,
+}
+
+impl Operands<'_> {
+    fn none(&self, instruction: Instruction) -> Result<Instruction, String> {
+        let [] = expect_operands(self.operands)?;
+        Ok(instruction)
+    }
+
+    fn one<T, F>(&self, parse: F) -> Result<T, String>
+    where
+        F: FnOnce(&str) -> Result<T, String>,
+    {
+        let [a] = expect_operands(self.operands)?;
+        parse(a)
+    }
+
+    fn two<A, B, F, G>(&self, first: F, second: G) -> Result<(A, B), String>
+    where
+        F: FnOnce(&str) -> Result<A, String>,
+        G: FnOnce(&str) -> Result<B, String>,
+    {
+        let [a, b] = expect_operands(self.operands)?;
+        Ok((first(a)?, second(b)?))
+    }
+
+    /// Evaluates an operand, which must be between `min` and `max`.
+    fn value(&self, operand: &str, min: i64, max: i64) -> Result<i64, String> {
+        let value = self.assembler.evaluate(operand, self.address)?;
+        if value < min || value > max {
+            return Err(format!("{} is out of range ({}..={})", value, min, max));
+        }
+        Ok(value)
+    }
+
+    fn registers(&self, f: fn(u8, u8) -> Instruction) -> Result<Instruction, String> {
+        let (d, r) = self.two(register, register)?;
+        Ok(f(d, r))
+    }
+
+    fn register_immediate(&self, f: fn(u8, u8) -> Instruction) -> Result<Instruction, String> {
+        let [d, k] = expect_operands(self.operands)?;
+        // Negative immediates are stored in two's complement.
+        Ok(f(upper_register(d)?, self.value(k, -128, 255)? as u8))
+    }
+
+    fn io_bit(&self, f: fn(u8, u8) -> Instruction) -> Result<Instruction, String> {
+        let [a, b] = expect_operands(self.operands)?;
+        Ok(f(self.value(a, 0, 31)? as u8, self.value(b, 0, 7)? as u8))
+    }
+
+    fn atomic(&self, f: fn(u8, u8) -> Instruction) -> Result<Instruction, String> {
+        let (pointer, r) = self.two(pointer, register)?;
+        match pointer {
+            (30, Variant::Normal) => Ok(f(30, r)),
+            _ => Err("atomic instructions take Z".to_owned()),
+        }
+    }
+
+    fn displacement(&self, operand: &str) -> Result<(u8, u8), String> {
+        let operand = operand.trim();
+        let p = match operand.get(..1).map(|s| s.to_ascii_uppercase()) {
+            Some(ref s) if s == "Y" => 28,
+            Some(ref s) if s == "Z" => 30,
+            _ => return Err(format!("expected Y+q or Z+q, found '{}'", operand)),
+        };
+        let q = operand[1..]
+            .trim()
+            .strip_prefix('+')
+            .ok_or_else(|| format!("expected Y+q or Z+q, found '{}'", operand))?;
+        Ok((p, self.value(q, 0, 63)? as u8))
+    }
+
+    fn absolute(&self) -> Result<u32, String> {
+        let [target] = expect_operands(self.operands)?;
+        let target = self.value(target, 0, 0x7f_ffff)?;
+        if target % 2 != 0 {
+            return Err(format!("0x{:x} is not a word address", target));
+        }
+        Ok(target as u32)
+    }
+
+    fn branch(&self) -> Result<i8, String> {
+        Ok(self.relative(-128, 126)? as i8)
+    }
+
+    fn relative(&self, min: i64, max: i64) -> Result<i64, String> {
+        let [target] = expect_operands(self.operands)?;
+        self.relative_to(target, min, max)
+    }
+
+    /// Gets the offset from the next instruction to a target, which is an
+    /// address or a `.+k` offset.
+    fn relative_to(&self, target: &str, min: i64, max: i64) -> Result<i64, String> {
+        let target = target.trim();
+        let offset = match target.strip_prefix('.') {
+            Some(offset) if offset.trim().starts_with(['+', '-']) => {
+                self.assembler.evaluate(offset, self.address)?
+            }
+            _ => self.assembler.evaluate(target, self.address)? - (self.address as i64 + 2),
+        };
+
+        if offset % 2 != 0 {
+            return Err(format!("offset {} is not a whole number of words", offset));
+        }
+        if offset < min || offset > max {
+            return Err(format!("target is out of range (offset {})", offset));
+        }
+        Ok(offset)
+    }
+}
+
+/// Evaluates expressions by recursive descent.
+struct Parser<'a> {
+    assembler: &'a Assembler,
+    address: u32,
+    text: &'a str,
+}
+
+impl Parser<'_> {
+    fn expression(&mut self) -> Result<i64, String> {
+        let mut value = self.term()?;
+        loop {
+            self.text = self.text.trim_start();
+            if let Some(rest) = self.text.strip_prefix('+') {
+                self.text = rest;
+                value += self.term()?;
+            } else if let Some(rest) = self.text.strip_prefix('-') {
+                self.text = rest;
+                value -= self.term()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<i64, String> {
+        self.text = self.text.trim_start();
+
+        if let Some(rest) = self.text.strip_prefix('-') {
+            self.text = rest;
+            return Ok(-self.term()?);
+        }
+        if let Some(rest) = self.text.strip_prefix('+') {
+            self.text = rest;
+            return self.term();
+        }
+        if let Some(rest) = self.text.strip_prefix('(') {
+            self.text = rest;
+            let value = self.expression()?;
+            return self.close(value);
+        }
+        if let Some(rest) = self.text.strip_prefix('\'') {
+            let mut chars = rest.chars();
+            let c = chars.next().ok_or("unterminated character")?;
+            if chars.next() != Some('\'') {
+                return Err("unterminated character".to_owned());
+            }
+            self.text = chars.as_str();
+            return Ok(c as i64);
+        }
+
+        let end = self
+            .text
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '$'))
+            .unwrap_or(self.text.len());
+        let token = &self.text[..end];
+        self.text = &self.text[end..];
+
+        if token == "." {
+            return Ok(self.address as i64);
+        }
+        if token.is_empty() {
+            return Err("expected a value".to_owned());
+        }
+        if token.starts_with(|c: char| c.is_ascii_digit() || c == '$') {
+            return parse_number(token);
+        }
+
+        let function: Option<fn(i64) -> i64> = match &token.to_ascii_lowercase()[..] {
+            "lo8" => Some(|v| v & 0xff),
+            "hi8" => Some(|v| (v >> 8) & 0xff),
+            "pm" => Some(|v| v >> 1),
+            "pm_lo8" => Some(|v| (v >> 1) & 0xff),
+            "pm_hi8" => Some(|v| (v >> 9) & 0xff),
+            _ => None,
+        };
+        if let Some(function) = function {
+            let rest = self.text.trim_start();
+            if let Some(rest) = rest.strip_prefix('(') {
+                self.text = rest;
+                let value = self.expression()?;
+                return self.close(function(value));
+            }
+        }
+
+        self.assembler
+            .symbols
+            .get(token)
+            .copied()
+            .ok_or_else(|| format!("unknown symbol '{}'", token))
+    }
+
+    fn close(&mut self, value: i64) -> Result<i64, String> {
+        match self.text.trim_start().strip_prefix(')') {
+            Some(rest) => {
+                self.text = rest;
+                Ok(value)
+            }
+            None => Err("expected ')'".to_owned()),
+        }
+    }
+}
+
+fn expect_operands<const N: usize>(operands: &[String]) -> Result<[&str; N], String> {
+    if operands.len() != N {
+        return Err(format!(
+            "expected {} operand(s), found {}",
+            N,
+            operands.len()
+        ));
+    }
+    Ok(std::array::from_fn(|i| &operands[i][..]))
+}
+
+fn register(operand: &str) -> Result<u8, String> {
+    let operand = operand.trim();
+    operand
+        .strip_prefix(['r', 'R'])
+        .and_then(|n| n.parse::<u8>().ok())
+        .filter(|&n| n < 32)
+        .ok_or_else(|| format!("expected a register, found '{}'", operand))
+}
+
+fn upper_register(operand: &str) -> Result<u8, String> {
+    let r = register(operand)?;
+    if r < 16 {
+        return Err(format!("expected one of r16 to r31, found r{}", r));
+    }
+    Ok(r)
+}
+
+/// Parses `X`, `-X`, `X+` and the same for `Y` and `Z`.
+fn pointer(operand: &str) -> Result<(u8, Variant), String> {
+    let operand = operand.trim();
+    let (name, variant) = if let Some(name) = operand.strip_prefix('-') {
+        (name, Variant::Predecrement)
+    } else if let Some(name) = operand.strip_suffix('+') {
+        (name, Variant::Postincrement)
+    } else {
+        (operand, Variant::Normal)
+    };
+
+    let register = match &name.trim().to_ascii_uppercase()[..] {
+        "X" => 26,
+        "Y" => 28,
+        "Z" => 30,
+        _ => return Err(format!("expected X, Y or Z, found '{}'", operand)),
+    };
+    Ok((register, variant))
+}
+
+fn parse_number(token: &str) -> Result<i64, String> {
+    let lower = token.to_ascii_lowercase();
+    let result = if let Some(hex) = lower.strip_prefix("0x").or(lower.strip_prefix('$')) {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(binary) = lower.strip_prefix("0b") {
+        i64::from_str_radix(binary, 2)
+    } else {
+        lower.parse()
+    };
+    result.map_err(|_| format!("invalid number '{}'", token))
+}
+
+/// Parses a double-quoted string operand, if it is one.
+fn parse_string(operand: &str) -> Result<Option<String>, String> {
+    let operand = operand.trim();
+    let inner = match operand.strip_prefix('"') {
+        Some(inner) => inner
+            .strip_suffix('"')
+            .ok_or_else(|| "unterminated string".to_owned())?,
+        None => return Ok(None),
+    };
+
+    let mut s = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            s.push(c);
+            continue;
+        }
+        s.push(match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(c) => c,
+            None => return Err("unterminated escape".to_owned()),
+        });
+    }
+    Ok(Some(s))
+}
+
+/// Splits operands on commas outside of quotes.
+fn split_operands(text: &str) -> Vec<String> {
+    if text.is_empty() {
+        return Vec::new();
+    }
+
+    let mut operands = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+
+    for c in text.chars() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if c == '\\' {
+                    escaped = true;
+                } else if c == q {
+                    quote = None;
+                }
+                current.push(c);
+            }
+            None if c == ',' => operands.push(std::mem::take(&mut current).trim().to_owned()),
+            None => {
+                if c == '"' || c == '\'' {
+                    quote = Some(c);
+                }
+                current.push(c);
+            }
+        }
+    }
+    operands.push(current.trim().to_owned());
+    operands
+}
+
+/// Removes a `;` or `//` comment, ignoring ones inside quotes.
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let bytes = line.as_bytes();
+
+    for (i, &b) in bytes.iter().enumerate() {
+        match quote {
+            Some(q) => {
+                if escaped {
+                    escaped = false;
+                } else if b == b'\\' {
+                    escaped = true;
+                } else if b == q {
+                    quote = None;
+                }
+            }
+            None => match b {
+                b'"' | b'\'' => quote = Some(b),
+                b';' => return &line[..i],
+                b'/' if bytes.get(i + 1) == Some(&b'/') => return &line[..i],
+                _ => (),
+            },
+        }
+    }
+    line
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_' || c == '.')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.')
+        && s != "."
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::Instruction;
+
+    fn encode(instructions: &[Instruction]) -> Vec<u8> {
+        instructions
+            .iter()
+            .flat_map(|i| i.encode().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn labels() {
+        let source = "
+            start:  rjmp end
+                    nop
+            end:    rjmp start
+        ";
+        let expected = encode(&[
+            Instruction::Rjmp(2),
+            Instruction::Nop,
+            Instruction::Rjmp(-6),
+        ]);
+        assert_eq!(assemble(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn org_fills_gaps_with_erased_flash() {
+        let source = "
+                    nop
+                    .org 6
+                    nop
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0, 0, 0xff, 0xff, 0xff, 0xff, 0, 0]
+        );
+    }
+
+    #[test]
+    fn lo8_and_hi8() {
+        let source = "
+                    ldi r30, lo8(data)
+                    ldi r31, hi8(data)
+                    .org 0x1234
+            data:   .db 42
+        ";
+        let bytes = assemble(source).unwrap();
+        let expected = encode(&[Instruction::Ldi(30, 0x34), Instruction::Ldi(31, 0x12)]);
+        assert_eq!(bytes[..4], expected);
+        assert_eq!(bytes[0x1234..], [42]);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::chips::Family;
 use crate::{inst, math};
 use crate::{Error, Instruction};
 
@@ -5,8 +6,7 @@ pub fn read<I>(mut bytes: I) -> Result<Instruction, Error>
 where
     I: Iterator<Item = u8>,
 {
-    let b1 = bytes.next().unwrap();
-    let b2 = bytes.next().unwrap();
+    let (b1, b2) = self::next_word(&mut bytes)?;
 
     // must reverse endianess
     let bits16 = ((b2 as u16) << 8) | (b1 as u16);
@@ -15,8 +15,8 @@ where
         return Ok(i);
     }
 
-    let b3 = bytes.next().unwrap() as u32;
-    let b4 = bytes.next().unwrap() as u32;
+    let (b3, b4) = self::next_word(&mut bytes)?;
+    let (b3, b4) = (b3 as u32, b4 as u32);
     // must reverse endianess
     let bits32 = ((bits16 as u32) << 16) | (b4 << 8) | b3;
 
@@ -27,18 +27,303 @@ where
     Err(Error::UnknownInstruction(bits32))
 }
 
-fn try_read16(bits: u16) -> Option<Instruction> {
-    let result = match bits {
-        0 => Some(Instruction::Nop),
-        0x9508 => Some(Instruction::Ret),
-        0x9518 => Some(Instruction::Reti),
-        0x95C8 => Some(Instruction::Lpm(0, 30, false)),
-        0x9478 => Some(Instruction::Sei),
-        0x94F8 => Some(Instruction::Cli),
-        _ => None,
+/// Takes the two bytes of the next instruction word.
+fn next_word<I>(bytes: &mut I) -> Result<(u8, u8), Error>
+where
+    I: Iterator<Item = u8>,
+{
+    match (bytes.next(), bytes.next()) {
+        (Some(b1), Some(b2)) => Ok((b1, b2)),
+        _ => Err(Error::TruncatedInstruction),
+    }
+}
+
+/// Reads an instruction the way a core of a family decodes it.
+///
+/// Reduced (AVRrc) cores have no `LDD` and `STD`, and decode their
+/// encodings with a displacement of 32 or more as the one-word `LDS` and
+/// `STS` instead. Other cores decode like `read`.
+pub fn read_for<I>(mut bytes: I, family: Family) -> Result<Instruction, Error>
+where
+    I: Iterator<Item = u8>,
+{
+    let (b1, b2) = self::next_word(&mut bytes)?;
+    let bits16 = ((b2 as u16) << 8) | (b1 as u16);
+
+    if family == Family::Reduced {
+        if let Some(i) = self::try_read_lds_sts16(bits16) {
+            return Ok(i);
+        }
+    }
+    self::read([b1, b2].into_iter().chain(bytes))
+}
+
+/// Encodes an instruction as machine code, the inverse of `read`.
+///
+/// Aliases encode like the instruction they stand for, so `Brsh` reads
+/// back as `Brcc` and `Brbs`/`Brbc` read back as the named branch for their
+/// flag.
+///
+/// Operands the encoding has no room for, like `r5` for `LDI` or an odd
+/// register pair for `MOVW`, give `Error::InvalidOperand`.
+pub fn write(instruction: Instruction) -> Result<Vec<u8>, Error> {
+    use crate::inst::Variant::*;
+    use crate::Instruction::*;
+
+    if !self::operands_in_range(instruction) {
+        return Err(Error::InvalidOperand(instruction));
+    }
+
+    let d = |r: u8| (r as u16 & 0x1f) << 4;
+    let rd = |opcode: u16, r: u8| opcode | d(r);
+    let rdk = |opcode: u16, r: u8, k: u8| {
+        opcode << 12 | (k as u16 & 0xf0) << 4 | d(r - 16) | (k as u16 & 0x0f)
+    };
+    let rdrr = |opcode: u16, r1: u8, r2: u8| {
+        opcode << 10 | (r2 as u16 & 0x10) << 5 | d(r1) | (r2 as u16 & 0x0f)
+    };
+    let io = |opcode: u16, a: u8, r: u8| opcode | (a as u16 & 0x30) << 5 | d(r) | (a as u16 & 0x0f);
+    let io_ab = |opcode: u16, a: u8, b: u8| opcode | (a as u16 & 0x1f) << 3 | (b as u16 & 0x7);
+    let k12 = |opcode: u16, k: i16| opcode | ((k >> 1) as u16 & 0x0fff);
+    let k22 = |opcode: u16, k: u32| {
+        let k = k >> 1;
+        [
+            opcode | ((k >> 17) as u16 & 0x1f) << 4 | ((k >> 16) as u16 & 1),
+            k as u16,
+        ]
+    };
+    let branch = |opcode: u16, k: i8| opcode | ((k >> 1) as u16 & 0x7f) << 3;
+    let pointer = |p: u8, variant: inst::Variant| match (p, variant) {
+        (26, Normal) => (0b1001_0000_0000_0000, 0b1100),
+        (26, Postincrement) => (0b1001_0000_0000_0000, 0b1101),
+        (26, Predecrement) => (0b1001_0000_0000_0000, 0b1110),
+        (28, Normal) => (0b1000_0000_0000_0000, 0b1000),
+        (28, Postincrement) => (0b1001_0000_0000_0000, 0b1001),
+        (28, Predecrement) => (0b1001_0000_0000_0000, 0b1010),
+        (_, Normal) => (0b1000_0000_0000_0000, 0b0000),
+        (_, Postincrement) => (0b1001_0000_0000_0000, 0b0001),
+        (_, Predecrement) => (0b1001_0000_0000_0000, 0b0010),
     };
+    let displacement = |store: bool, p: u8, q: u8, r: u8| {
+        let q = q as u16;
+        0b1000_0000_0000_0000
+            | (q & 0x20) << 8
+            | (q & 0x18) << 7
+            | (store as u16) << 9
+            | d(r)
+            | ((p == 28) as u16) << 3
+            | (q & 0x7)
+    };
+
+    let words: Vec<u16> = match instruction {
+        Inc(r) => vec![rd(0x9403, r)],
+        Dec(r) => vec![rd(0x940a, r)],
+        Com(r) => vec![rd(0x9400, r)],
+        Neg(r) => vec![rd(0x9401, r)],
+        Push(r) => vec![rd(0x920f, r)],
+        Pop(r) => vec![rd(0x900f, r)],
+        Swap(r) => vec![rd(0x9402, r)],
+
+        Subi(r, k) => vec![rdk(0b0101, r, k)],
+        Sbci(r, k) => vec![rdk(0b0100, r, k)],
+        Andi(r, k) => vec![rdk(0b0111, r, k)],
+        Ori(r, k) => vec![rdk(0b0110, r, k)],
+        Cpi(r, k) => vec![rdk(0b0011, r, k)],
+        Ldi(r, k) => vec![rdk(0b1110, r, k)],
+
+        Add(r1, r2) => vec![rdrr(0b000011, r1, r2)],
+        Adc(r1, r2) => vec![rdrr(0b000111, r1, r2)],
+        Sub(r1, r2) => vec![rdrr(0b000110, r1, r2)],
+        Sbc(r1, r2) => vec![rdrr(0b000010, r1, r2)],
+        Mul(r1, r2) => vec![rdrr(0b100111, r1, r2)],
+        And(r1, r2) => vec![rdrr(0b001000, r1, r2)],
+        Or(r1, r2) => vec![rdrr(0b001010, r1, r2)],
+        Eor(r1, r2) => vec![rdrr(0b001001, r1, r2)],
+        Cpse(r1, r2) => vec![rdrr(0b000100, r1, r2)],
+        Cp(r1, r2) => vec![rdrr(0b000101, r1, r2)],
+        Cpc(r1, r2) => vec![rdrr(0b000001, r1, r2)],
+        Mov(r1, r2) => vec![rdrr(0b001011, r1, r2)],
+        Movw(r1, r2) => vec![0x0100 | (r1 as u16 >> 1) << 4 | (r2 as u16 >> 1)],
+        Adiw(r, k) | Sbiw(r, k) => {
+            let opcode = if let Adiw(..) = instruction {
+                0x9600
+            } else {
+                0x9700
+            };
+            let k = k as u16;
+            vec![opcode | (k & 0x30) << 2 | ((r as u16 - 24) >> 1) << 4 | (k & 0xf)]
+        }
+
+        In(r, a) => vec![io(0xb000, a, r)],
+        Out(a, r) => vec![io(0xb800, a, r)],
+        Sbi(a, b) => vec![io_ab(0x9a00, a, b)],
+        Sbis(a, b) => vec![io_ab(0x9b00, a, b)],
+        Cbi(a, b) => vec![io_ab(0x9800, a, b)],
+        Sbrs(r, b) => vec![0xfe00 | d(r) | (b as u16 & 0x7)],
+
+        Jmp(k) => k22(0x940c, k).to_vec(),
+        Call(k) => k22(0x940e, k).to_vec(),
+        Rjmp(k) => vec![k12(0xc000, k)],
+        Rcall(k) => vec![k12(0xd000, k)],
+
+        Brbs(s, k) => vec![branch(0xf000 | (s as u16 & 0x7), k)],
+        Brbc(s, k) => vec![branch(0xf400 | (s as u16 & 0x7), k)],
+        Brcs(k) | Brlo(k) => vec![branch(0xf000, k)],
+        Breq(k) => vec![branch(0xf001, k)],
+        Brmi(k) => vec![branch(0xf002, k)],
+        Brvs(k) => vec![branch(0xf003, k)],
+        Brlt(k) => vec![branch(0xf004, k)],
+        Brhs(k) => vec![branch(0xf005, k)],
+        Brts(k) => vec![branch(0xf006, k)],
+        Brie(k) => vec![branch(0xf007, k)],
+        Brcc(k) | Brsh(k) => vec![branch(0xf400, k)],
+        Brne(k) => vec![branch(0xf401, k)],
+        Brpl(k) => vec![branch(0xf402, k)],
+        Brvc(k) => vec![branch(0xf403, k)],
+        Brge(k) => vec![branch(0xf404, k)],
+        Brhc(k) => vec![branch(0xf405, k)],
+        Brtc(k) => vec![branch(0xf406, k)],
+        Brid(k) => vec![branch(0xf407, k)],
+
+        St(p, r, variant) => {
+            let (opcode, subop) = pointer(p, variant);
+            vec![opcode | 0x0200 | d(r) | subop]
+        }
+        Ld(r, p, variant) => {
+            let (opcode, subop) = pointer(p, variant);
+            vec![opcode | d(r) | subop]
+        }
+
+        Xch(_, r) => vec![0x9204 | d(r)],
+        Las(_, r) => vec![0x9205 | d(r)],
+        Lac(_, r) => vec![0x9206 | d(r)],
+        Lat(_, r) => vec![0x9207 | d(r)],
+
+        Std(p, q, r) => vec![displacement(true, p, q, r)],
+        Ldd(r, p, q) => vec![displacement(false, p, q, r)],
+
+        Sts(r, k) => vec![0x9200 | d(r), k],
+        Lds(r, k) => vec![0x9000 | d(r), k],
+        Sts16(r, k) | Lds16(r, k) => {
+            let store = matches!(instruction, Sts16(..)) as u16;
+            vec![0xa000 | store << 11 | (k & 0x30) << 5 | (k & 0x40) << 2 | d(r - 16) | (k & 0x0f)]
+        }
+        Lpm(0, _, false) => vec![0x95c8],
+        Lpm(r, _, false) => vec![0x9004 | d(r)],
+        Lpm(r, _, true) => vec![0x9005 | d(r)],
+        Elpm(0, _, false) => vec![0x95d8],
+        Elpm(r, _, false) => vec![0x9006 | d(r)],
+        Elpm(r, _, true) => vec![0x9007 | d(r)],
+        Spm(false) => vec![0x95e8],
+        Spm(true) => vec![0x95f8],
+
+        Ijmp => vec![0x9409],
+        Icall => vec![0x9509],
+        Eijmp => vec![0x9419],
+        Eicall => vec![0x9519],
+
+        Nop => vec![0x0000],
+        Ret => vec![0x9508],
+        Reti => vec![0x9518],
+        Sei => vec![0x9478],
+        Cli => vec![0x94f8],
+        Sleep => vec![0x9588],
+        Break => vec![0x9598],
+        Wdr => vec![0x95a8],
+        Des(k) => vec![0x940b | (k as u16 & 0xf) << 4],
+    };
+
+    Ok(words.iter().flat_map(|w| w.to_le_bytes()).collect())
+}
 
-    result
+/// Checks the operands of an instruction fit its encoding.
+fn operands_in_range(instruction: Instruction) -> bool {
+    use crate::Instruction::*;
+
+    let gpr = |r: u8| r < 32;
+    let upper = |r: u8| (16..32).contains(&r);
+    let pair = |r: u8| r < 32 && r.is_multiple_of(2);
+    let bit = |b: u8| b < 8;
+    let branch = |k: i8| k % 2 == 0;
+    let relative = |k: i16| (-4096..4096).contains(&k) && k % 2 == 0;
+    let absolute = |k: u32| k < 1 << 23 && k.is_multiple_of(2);
+    let pointer = |p: u8| matches!(p, 26 | 28 | 30);
+
+    match instruction {
+        Inc(r) | Dec(r) | Com(r) | Neg(r) | Push(r) | Pop(r) | Swap(r) => gpr(r),
+        Subi(r, _) | Sbci(r, _) | Andi(r, _) | Ori(r, _) | Cpi(r, _) | Ldi(r, _) => upper(r),
+        Add(r1, r2)
+        | Adc(r1, r2)
+        | Sub(r1, r2)
+        | Sbc(r1, r2)
+        | Mul(r1, r2)
+        | And(r1, r2)
+        | Or(r1, r2)
+        | Eor(r1, r2)
+        | Cpse(r1, r2)
+        | Cp(r1, r2)
+        | Cpc(r1, r2)
+        | Mov(r1, r2) => gpr(r1) && gpr(r2),
+        Movw(r1, r2) => pair(r1) && pair(r2),
+        Adiw(r, k) | Sbiw(r, k) => matches!(r, 24 | 26 | 28 | 30) && k < 64,
+        In(r, a) | Out(a, r) => gpr(r) && a < 64,
+        Sbi(a, b) | Sbis(a, b) | Cbi(a, b) => a < 32 && bit(b),
+        Sbrs(r, b) => gpr(r) && bit(b),
+        Jmp(k) | Call(k) => absolute(k),
+        Rjmp(k) | Rcall(k) => relative(k),
+        Brbs(s, k) | Brbc(s, k) => bit(s) && branch(k),
+        Brcs(k) | Brlo(k) | Breq(k) | Brmi(k) | Brvs(k) | Brlt(k) | Brhs(k) | Brts(k) | Brie(k)
+        | Brcc(k) | Brsh(k) | Brne(k) | Brpl(k) | Brvc(k) | Brge(k) | Brhc(k) | Brtc(k)
+        | Brid(k) => branch(k),
+        St(p, r, _) | Ld(r, p, _) => pointer(p) && gpr(r),
+        Xch(p, r) | Las(p, r) | Lac(p, r) | Lat(p, r) => p == 30 && gpr(r),
+        Std(p, q, r) | Ldd(r, p, q) => matches!(p, 28 | 30) && q < 64 && gpr(r),
+        Sts(r, _) | Lds(r, _) => gpr(r),
+        Sts16(r, k) | Lds16(r, k) => upper(r) && (0x40..=0xbf).contains(&k),
+        Lpm(r, p, _) | Elpm(r, p, _) => p == 30 && gpr(r),
+        Des(k) => k < 16,
+        Spm(_) | Ijmp | Icall | Eijmp | Eicall | Nop | Ret | Reti | Sei | Cli | Sleep | Break
+        | Wdr => true,
+    }
+}
+
+/// Decodes a single-word instruction.
+///
+/// Two-word instructions like `JMP` and `LDS` are not decoded, see `read`.
+pub fn try_decode_word(word: u16) -> Option<Instruction> {
+    self::try_read16(word)
+}
+
+/// Decodes a single-word instruction with every decoding rule, instead of
+/// stopping at the first that matches.
+///
+/// More than one result means the encoding is ambiguous and `read` picks
+/// the first. For example, `LD r0, Z` and `LDD r0, Z+0` share an encoding.
+pub fn decode_candidates(word: u16) -> Vec<Instruction> {
+    let rules: [fn(u16) -> Option<Instruction>; 16] = [
+        try_read_fixed,
+        try_read_rd,
+        try_read_rdk,
+        try_read_rdrr,
+        try_read_rda,
+        try_read_io_ab,
+        try_read_rdz,
+        try_read_k16,
+        try_read_st_ld,
+        try_read_atomic,
+        try_read_std_ldd,
+        try_read_movw,
+        try_read_relcondbr,
+        try_read_adiw,
+        try_read_sbrs,
+        try_read_des,
+    ];
+
+    rules.iter().filter_map(|rule| rule(word)).collect()
+}
+
+fn try_read16(bits: u16) -> Option<Instruction> {
+    self::try_read_fixed(bits)
         .or_else(|| self::try_read_rd(bits))
         .or_else(|| self::try_read_rdk(bits))
         .or_else(|| self::try_read_rdrr(bits))
@@ -47,11 +332,35 @@ fn try_read16(bits: u16) -> Option<Instruction> {
         .or_else(|| self::try_read_rdz(bits))
         .or_else(|| self::try_read_k16(bits))
         .or_else(|| self::try_read_st_ld(bits))
+        .or_else(|| self::try_read_atomic(bits))
         .or_else(|| self::try_read_std_ldd(bits))
         .or_else(|| self::try_read_movw(bits))
         .or_else(|| self::try_read_relcondbr(bits))
         .or_else(|| self::try_read_adiw(bits))
         .or_else(|| self::try_read_sbrs(bits))
+        .or_else(|| self::try_read_des(bits))
+}
+
+fn try_read_fixed(bits: u16) -> Option<Instruction> {
+    match bits {
+        0 => Some(Instruction::Nop),
+        0x9508 => Some(Instruction::Ret),
+        0x9518 => Some(Instruction::Reti),
+        0x95C8 => Some(Instruction::Lpm(0, 30, false)),
+        0x95D8 => Some(Instruction::Elpm(0, 30, false)),
+        0x9409 => Some(Instruction::Ijmp),
+        0x9509 => Some(Instruction::Icall),
+        0x9419 => Some(Instruction::Eijmp),
+        0x9519 => Some(Instruction::Eicall),
+        0x95E8 => Some(Instruction::Spm(false)),
+        0x95F8 => Some(Instruction::Spm(true)),
+        0x9478 => Some(Instruction::Sei),
+        0x94F8 => Some(Instruction::Cli),
+        0x9588 => Some(Instruction::Sleep),
+        0x9598 => Some(Instruction::Break),
+        0x95A8 => Some(Instruction::Wdr),
+        _ => None,
+    }
 }
 
 pub fn try_read32(bits: u32) -> Option<Instruction> {
@@ -170,9 +479,9 @@ fn try_read_sbrs(bits: u16) -> Option<Instruction> {
     }
 }
 
-/// `LPM` instructions.
-/// `<1001|000d|dddd|010f>`
-/// `f` is postincrement bit.
+/// `LPM` and `ELPM` instructions.
+/// `<1001|000d|dddd|01ef>`
+/// `e` is the extended bit and `f` the postincrement bit.
 fn try_read_rdz(bits: u16) -> Option<Instruction> {
     let opcode = ((bits >> 5) & 0b11111110000) | (bits & 0b1111);
     let register = ((bits >> 4) & 0b11111) as u8;
@@ -180,6 +489,8 @@ fn try_read_rdz(bits: u16) -> Option<Instruction> {
     match opcode {
         0b10010000100 => Some(Instruction::Lpm(register, 30, false)),
         0b10010000101 => Some(Instruction::Lpm(register, 30, true)),
+        0b10010000110 => Some(Instruction::Elpm(register, 30, false)),
+        0b10010000111 => Some(Instruction::Elpm(register, 30, true)),
         _ => None,
     }
 }
@@ -209,7 +520,7 @@ fn try_read_k32(bits: u32) -> Option<Instruction> {
     let opcode = (bits & 0xfe000000) >> 25;
     let subopcode = (bits & 0xe0000) >> 17;
 
-    let mut k = ((bits & 0x1f00000) >> 20) | (bits & 0x1ffff);
+    let mut k = ((bits & 0x1f00000) >> 3) | (bits & 0x1ffff);
 
     // un-left shift the address.
     k <<= 1;
@@ -240,6 +551,26 @@ fn try_read_lds_sts(bits: u32) -> Option<Instruction> {
     }
 }
 
+/// Attempts to read the one-word `LDS` or `STS` of reduced cores.
+/// `<1010|fkkk|dddd|kkkk>`, where `f` is set for `STS`.
+///
+/// The seven bits of `k` address `0x40..=0xbf`: bit 8 of the instruction
+/// is bit 6 of the address, and its inverse bit 7.
+fn try_read_lds_sts16(bits: u16) -> Option<Instruction> {
+    if bits & 0xf000 != 0xa000 {
+        return None;
+    }
+    let register = ((bits >> 4) & 0xf) as u8 + 16;
+    let k6 = (bits >> 8) & 1;
+    let address = (k6 ^ 1) << 7 | k6 << 6 | ((bits >> 9) & 0b11) << 4 | (bits & 0xf);
+
+    if bits & 0x0800 == 0 {
+        Some(Instruction::Lds16(register, address))
+    } else {
+        Some(Instruction::Sts16(register, address))
+    }
+}
+
 /// Attempts to read an `LD` or `ST` instruction.
 fn try_read_st_ld(bits: u16) -> Option<Instruction> {
     let opcode = (bits & 0b1111111000000000) >> 9;
@@ -272,6 +603,27 @@ fn try_read_st_ld(bits: u16) -> Option<Instruction> {
     }
 }
 
+/// Attempts to read an `XCH`, `LAS`, `LAC` or `LAT` instruction.
+/// `<1001|001r|rrrr|01ff>`
+fn try_read_atomic(bits: u16) -> Option<Instruction> {
+    let opcode = (bits & 0b1111111000000000) >> 9;
+    let subop = bits & 0xf;
+
+    let reg = ((bits & 0x1f0) >> 4) as u8;
+
+    if opcode != 0b1001001 {
+        return None;
+    }
+
+    match subop {
+        0b0100 => Some(Instruction::Xch(30, reg)),
+        0b0101 => Some(Instruction::Las(30, reg)),
+        0b0110 => Some(Instruction::Lac(30, reg)),
+        0b0111 => Some(Instruction::Lat(30, reg)),
+        _ => None,
+    }
+}
+
 /// An `STD` or `LDD` instruction.
 /// `(std|ldd) rd, y+z => 10q0 qqfr rrrr pqqq`
 /// * `f` is type (`1` for `std`, `0` for `ldd`).
@@ -282,8 +634,8 @@ fn try_read_std_ldd(bits: u16) -> Option<Instruction> {
 
     let f = (bits & 0b0000_0010_0000_0000) >> 9;
     let p = (bits & 0b1000) >> 3;
-    let q = ((bits & 0b0010_0000_0000_0000) >> 7)
-        | ((bits & 0b0000_1100_0000_0000) >> 6)
+    let q = ((bits & 0b0010_0000_0000_0000) >> 8)
+        | ((bits & 0b0000_1100_0000_0000) >> 7)
         | (bits & 0b0000_0000_0000_0111);
 
     let reg = ((bits & 0b1_1111_0000) >> 4) as u8;
@@ -330,7 +682,7 @@ fn try_read_movw(bits: u16) -> Option<Instruction> {
 fn try_read_relcondbr(bits: u16) -> Option<Instruction> {
     let opcode = bits & 0b1111_1100_0000_0111;
     let k_bits = ((0b0000_0011_1111_1000 & bits) >> 3) as i8;
-    let k = math::sign_extend(k_bits << 1, 7);
+    let k = math::sign_extend(k_bits, 7) << 1;
 
     match opcode {
         0b1111_0100_0000_0001 => Some(Instruction::Brne(k)),
@@ -357,7 +709,7 @@ fn try_read_relcondbr(bits: u16) -> Option<Instruction> {
 /// SBIW: 1001 0111 KKdd KKKK
 fn try_read_adiw(bits: u16) -> Option<Instruction> {
     let opcode = bits >> 8;
-    let k = (bits >> 6) & 0b11 | bits & 0b1111;
+    let k = ((bits >> 6) & 0b11) << 4 | bits & 0b1111;
     let k = k as u8;
     let d = (bits >> 3) & 0b110;
     let d = d as u8 + 24;
@@ -368,3 +720,14 @@ fn try_read_adiw(bits: u16) -> Option<Instruction> {
         _ => None,
     }
 }
+
+/// DES: 1001 0100 KKKK 1011
+fn try_read_des(bits: u16) -> Option<Instruction> {
+    let opcode = bits & 0xff0f;
+    let k = ((bits & 0x00f0) >> 4) as u8;
+
+    match opcode {
+        0x940b => Some(Instruction::Des(k)),
+        _ => None,
+    }
+}
@@ -0,0 +1,119 @@
+//! Static disassembly of program space.
+
+use crate::inst::binary;
+use crate::symbols::Symbols;
+use crate::Instruction;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// A decoded instruction, or a word that could not be decoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Line {
+    /// The byte address of the instruction.
+    pub address: u32,
+    pub instruction: Option<Instruction>,
+    /// The bytes of the instruction as stored in program space.
+    pub bytes: Vec<u8>,
+    /// Where the instruction jumps, calls or branches to.
+    pub target: Option<u32>,
+}
+
+/// A disassembly listing with labels for branch targets.
+#[derive(Clone, Debug, Default)]
+pub struct Listing {
+    lines: Vec<Line>,
+    labels: BTreeMap<u32, String>,
+}
+
+/// Disassembles program space bytes starting at byte address `base`.
+///
+/// Every branch target gets a generated label like `L_0068`.
+pub fn disassemble(bytes: &[u8], base: u32) -> Listing {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+
+    while offset + 2 <= bytes.len() {
+        let address = base + offset as u32;
+        let rest = &bytes[offset..];
+        // Pad so that a truncated 32-bit instruction can still be read.
+        let padded = rest.iter().copied().chain(std::iter::repeat(0)).take(4);
+
+        let instruction = binary::read(padded)
+            .ok()
+            .filter(|i| i.size() as usize <= rest.len());
+        let size = instruction.map_or(2, |i| i.size() as usize);
+
+        lines.push(Line {
+            address,
+            instruction,
+            bytes: rest[..size].to_vec(),
+            target: instruction.and_then(|i| i.branch_target(address)),
+        });
+        offset += size;
+    }
+
+    let labels = lines
+        .iter()
+        .filter_map(|line| line.target)
+        .map(|target| (target, format!("L_{:04X}", target)))
+        .collect();
+
+    Listing { lines, labels }
+}
+
+impl Listing {
+    /// Names labels after the symbols at their addresses, and adds labels
+    /// for all other symbols on instruction boundaries.
+    pub fn with_symbols(mut self, symbols: &Symbols) -> Self {
+        for line in &self.lines {
+            let symbol = symbols
+                .flash(line.address)
+                .filter(|s| s.address == line.address);
+            if let Some(symbol) = symbol {
+                self.labels.insert(line.address, symbol.name.clone());
+            }
+        }
+        self
+    }
+
+    pub fn lines(&self) -> &[Line] {
+        &self.lines
+    }
+
+    /// Gets the label at a byte address.
+    pub fn label(&self, address: u32) -> Option<&str> {
+        self.labels.get(&address).map(|s| &s[..])
+    }
+}
+
+/// Renders an `objdump`-style listing.
+impl fmt::Display for Listing {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for line in &self.lines {
+            if let Some(label) = self.label(line.address) {
+                writeln!(fmt, "\n{}:", label)?;
+            }
+
+            let bytes: Vec<String> = line.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+            write!(fmt, "{:8x}:\t{:<12}\t", line.address, bytes.join(" "))?;
+
+            match line.instruction {
+                Some(instruction) => write!(fmt, "{}", instruction)?,
+                None => write!(
+                    fmt,
+                    ".word 0x{:04x}",
+                    u16::from_le_bytes([line.bytes[0], line.bytes[1]])
+                )?,
+            }
+
+            match line.target {
+                Some(target) => match self.label(target) {
+                    Some(label) => writeln!(fmt, "\t; 0x{:x} <{}>", target, label)?,
+                    None => writeln!(fmt, "\t; 0x{:x}", target)?,
+                },
+                None => writeln!(fmt)?,
+            }
+        }
+        Ok(())
+    }
+}
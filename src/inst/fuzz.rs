@@ -0,0 +1,163 @@
+//! Random instructions for fuzzing and property testing.
+//!
+//! Only instructions in their canonical form are generated, so that every
+//! generated instruction reads back from its encoding unchanged. This
+//! leaves out the branch aliases (`Brbs`, `Brbc`, `Brsh`, `Brlo`) and `Ldd`
+//! or `Std` with a displacement of zero, which read back as `Ld` and `St`.
+//! The one-word `Lds16` and `Sts16` of reduced cores are left out as well,
+//! as they only read back with `binary::read_for`.
+
+use crate::inst::Variant;
+use crate::Instruction;
+use arbitrary::{Arbitrary, Result, Unstructured};
+
+const POINTERS: [u8; 3] = [26, 28, 30];
+const DISPLACEMENT_POINTERS: [u8; 2] = [28, 30];
+const VARIANTS: [Variant; 3] = [
+    Variant::Normal,
+    Variant::Predecrement,
+    Variant::Postincrement,
+];
+
+impl<'a> Arbitrary<'a> for Instruction {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        use crate::Instruction::*;
+
+        let gpr = |u: &mut Unstructured| u.int_in_range(0..=31u8);
+        let upper = |u: &mut Unstructured| u.int_in_range(16..=31u8);
+        let pair = |u: &mut Unstructured| Ok(u.int_in_range(0..=15u8)? * 2);
+        let bit = |u: &mut Unstructured| u.int_in_range(0..=7u8);
+        let branch = |u: &mut Unstructured| Ok(u.int_in_range(-64..=63i8)? * 2);
+
+        let unary: [fn(u8) -> Instruction; 7] = [Inc, Dec, Com, Neg, Push, Pop, Swap];
+        let immediate: [fn(u8, u8) -> Instruction; 6] = [Subi, Sbci, Andi, Ori, Cpi, Ldi];
+        let binary: [fn(u8, u8) -> Instruction; 12] =
+            [Add, Adc, Sub, Sbc, Mul, And, Or, Eor, Cpse, Cp, Cpc, Mov];
+        let io_bit: [fn(u8, u8) -> Instruction; 3] = [Sbi, Sbis, Cbi];
+        let branches: [fn(i8) -> Instruction; 16] = [
+            Breq, Brne, Brcs, Brcc, Brmi, Brpl, Brge, Brlt, Brhs, Brhc, Brts, Brtc, Brvs, Brvc,
+            Brie, Brid,
+        ];
+        let atomic: [fn(u8, u8) -> Instruction; 4] = [Xch, Las, Lac, Lat];
+        let nullary = [
+            Nop,
+            Ret,
+            Reti,
+            Sei,
+            Cli,
+            Sleep,
+            Break,
+            Wdr,
+            Spm(false),
+            Spm(true),
+            Ijmp,
+            Icall,
+            Eijmp,
+            Eicall,
+        ];
+
+        Ok(match u.int_in_range(0..=21u8)? {
+            0 => u.choose(&unary)?(gpr(u)?),
+            1 => u.choose(&immediate)?(upper(u)?, u.arbitrary()?),
+            2 => u.choose(&binary)?(gpr(u)?, gpr(u)?),
+            3 => Movw(pair(u)?, pair(u)?),
+            4 => {
+                let register = u.int_in_range(12..=15u8)? * 2;
+                let k = u.int_in_range(0..=63u8)?;
+                if u.arbitrary()? {
+                    Adiw(register, k)
+                } else {
+                    Sbiw(register, k)
+                }
+            }
+            5 => In(gpr(u)?, u.int_in_range(0..=63u8)?),
+            6 => Out(u.int_in_range(0..=63u8)?, gpr(u)?),
+            7 => u.choose(&io_bit)?(u.int_in_range(0..=31u8)?, bit(u)?),
+            8 => Sbrs(gpr(u)?, bit(u)?),
+            9 => Jmp(u.int_in_range(0..=0x3f_ffffu32)? * 2),
+            10 => Call(u.int_in_range(0..=0x3f_ffffu32)? * 2),
+            11 => Rjmp(u.int_in_range(-2048..=2047i16)? * 2),
+            12 => Rcall(u.int_in_range(-2048..=2047i16)? * 2),
+            13 => u.choose(&branches)?(branch(u)?),
+            14 => St(*u.choose(&POINTERS)?, gpr(u)?, *u.choose(&VARIANTS)?),
+            15 => Ld(gpr(u)?, *u.choose(&POINTERS)?, *u.choose(&VARIANTS)?),
+            16 => u.choose(&atomic)?(30, gpr(u)?),
+            17 => {
+                let pointer = *u.choose(&DISPLACEMENT_POINTERS)?;
+                let q = u.int_in_range(1..=63u8)?;
+                if u.arbitrary()? {
+                    Std(pointer, q, gpr(u)?)
+                } else {
+                    Ldd(gpr(u)?, pointer, q)
+                }
+            }
+            18 => {
+                if u.arbitrary()? {
+                    Sts(gpr(u)?, u.arbitrary()?)
+                } else {
+                    Lds(gpr(u)?, u.arbitrary()?)
+                }
+            }
+            19 if u.arbitrary()? => Lpm(gpr(u)?, 30, u.arbitrary()?),
+            19 => Elpm(gpr(u)?, 30, u.arbitrary()?),
+            20 => Des(u.int_in_range(0..=15u8)?),
+            _ => *u.choose(&nullary)?,
+        })
+    }
+}
+
+#[cfg(feature = "proptest")]
+mod proptest_impl {
+    use crate::Instruction;
+    use arbitrary::{Arbitrary, Unstructured};
+    use proptest::prelude::*;
+
+    /// Generates instructions from random bytes with the `arbitrary`
+    /// implementation. Bytes shrink towards zero, which gives simpler
+    /// instructions with lower operands.
+    impl proptest::arbitrary::Arbitrary for Instruction {
+        type Parameters = ();
+        type Strategy = BoxedStrategy<Instruction>;
+
+        fn arbitrary_with(_: ()) -> Self::Strategy {
+            proptest::collection::vec(any::<u8>(), 16)
+                .prop_map(|bytes| {
+                    // Running out of bytes gives the lowest values instead
+                    // of an error, so this cannot fail.
+                    <Instruction as Arbitrary>::arbitrary(&mut Unstructured::new(&bytes))
+                        .expect("generating an instruction from bytes")
+                })
+                .boxed()
+        }
+    }
+}
+
+#[cfg(all(test, feature = "proptest"))]
+mod tests {
+    use crate::inst::binary;
+    use crate::Instruction;
+    use proptest::prelude::*;
+
+    proptest! {
+        // Each case picks one of many instructions, so it takes a lot of
+        // them to cover every opcode.
+        #![proptest_config(ProptestConfig::with_cases(4096))]
+
+        #[test]
+        fn encoding_reads_back(instruction: Instruction) {
+            let bytes = instruction.encode().unwrap();
+            prop_assert_eq!(bytes.len(), instruction.size() as usize);
+            prop_assert_eq!(binary::read(bytes.into_iter()).unwrap(), instruction);
+        }
+
+        /// Whatever a word decodes to encodes to a word decoding the same,
+        /// even where several encodings mean one instruction.
+        #[test]
+        fn decoding_is_stable(word: u16) {
+            if let Some(instruction) = binary::try_decode_word(word) {
+                let bytes = instruction.encode().unwrap();
+                prop_assert_eq!(binary::read(bytes.into_iter()).unwrap(), instruction);
+            }
+        }
+    }
+}
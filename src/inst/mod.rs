@@ -1,4 +1,12 @@
+pub mod asm;
 pub mod binary;
+pub mod disasm;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
+
+use crate::chips::Family;
+use crate::Error;
+use std::fmt;
 
 pub type Gpr = u8;
 pub type GprPair = u8;
@@ -6,7 +14,7 @@ pub type Address = u32;
 pub type RelativeAddress = u32;
 pub type RelativeAddress7 = i8;
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Variant {
     Normal,
     Predecrement,
@@ -14,7 +22,7 @@ pub enum Variant {
 }
 
 /// An instruction.
-#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Instruction {
     Inc(Gpr),
     Dec(Gpr),
@@ -61,6 +69,14 @@ pub enum Instruction {
     Call(u32),
     Rjmp(i16),
     Rcall(i16),
+    /// Indirect jump to `Z`.
+    Ijmp,
+    /// Indirect call to `Z`.
+    Icall,
+    /// Indirect jump to `EIND:Z`.
+    Eijmp,
+    /// Indirect call to `EIND:Z`.
+    Eicall,
 
     Brbs(u8, RelativeAddress7),
     Brbc(u8, RelativeAddress7),
@@ -86,24 +102,70 @@ pub enum Instruction {
     St(GprPair, Gpr, Variant),
     Ld(Gpr, GprPair, Variant),
 
+    /// Exchange.
+    /// `GprPair` is always the `Z` register.
+    Xch(GprPair, Gpr),
+    /// Load and set.
+    /// `GprPair` is always the `Z` register.
+    Las(GprPair, Gpr),
+    /// Load and clear.
+    /// `GprPair` is always the `Z` register.
+    Lac(GprPair, Gpr),
+    /// Load and toggle.
+    /// `GprPair` is always the `Z` register.
+    Lat(GprPair, Gpr),
+
     Std(GprPair, u8, Gpr),
     Ldd(Gpr, GprPair, u8),
 
     Sts(Gpr, u16),
     Lds(Gpr, u16),
+    /// The one-word `STS` of reduced (AVRrc) cores, which reaches the data
+    /// space addresses `0x40..=0xbf`.
+    Sts16(Gpr, u16),
+    /// The one-word `LDS` of reduced (AVRrc) cores, which reaches the data
+    /// space addresses `0x40..=0xbf`.
+    Lds16(Gpr, u16),
     /// Load program memory.
     /// `GprPair` is always the `Z` register.
     /// The `bool` is whether to postincrement.
     Lpm(Gpr, GprPair, bool),
+    /// Extended load program memory, from `RAMPZ:Z`.
+    /// `GprPair` is always the `Z` register.
+    /// The `bool` is whether to postincrement.
+    Elpm(Gpr, GprPair, bool),
+    /// Store program memory.
+    /// The `bool` is whether to postincrement `Z`.
+    Spm(bool),
 
     Nop,
     Ret,
     Reti,
     Sei,
     Cli,
+    Sleep,
+    Break,
+    /// Watchdog reset.
+    Wdr,
+    /// A single round of DES encryption or decryption.
+    Des(u8),
 }
 
 impl Instruction {
+    /// Encodes the instruction as machine code, see `binary::write`.
+    pub fn encode(&self) -> Result<Vec<u8>, Error> {
+        binary::write(*self)
+    }
+
+    /// Gets the mnemonic the instruction is disassembled with, like `ldi`.
+    pub fn mnemonic(&self) -> String {
+        let text = self.to_string();
+        match text.split_once(' ') {
+            Some((mnemonic, _)) => mnemonic.to_owned(),
+            None => text,
+        }
+    }
+
     pub fn size(self) -> u8 {
         match self {
             Instruction::Jmp(..) => 4,
@@ -113,4 +175,490 @@ impl Instruction {
             _ => 2,
         }
     }
+
+    /// Gets the number of cycles the instruction takes on a family of
+    /// cores.
+    ///
+    /// For branches this is the time when the branch is not taken, and for
+    /// skips when nothing is skipped. Taken branches take one more cycle,
+    /// and skips one more cycle per word skipped.
+    pub fn cycles(&self, family: Family) -> u64 {
+        use self::Family::*;
+
+        match *self {
+            Instruction::Adiw(..) | Instruction::Sbiw(..) | Instruction::Mul(..) => 2,
+
+            Instruction::Sbi(..) | Instruction::Cbi(..) => match family {
+                Classic => 2,
+                Xmega | Xt | Reduced => 1,
+            },
+
+            Instruction::Jmp(..) => 3,
+            Instruction::Rjmp(..) => 2,
+            Instruction::Call(..) => match family {
+                Classic => 4,
+                Xmega | Xt | Reduced => 3,
+            },
+            Instruction::Rcall(..) => match family {
+                Classic | Reduced => 3,
+                Xmega | Xt => 2,
+            },
+            Instruction::Ijmp | Instruction::Eijmp => 2,
+            Instruction::Icall => match family {
+                Classic | Reduced => 3,
+                Xmega | Xt => 2,
+            },
+            Instruction::Eicall => match family {
+                Classic | Reduced => 3,
+                Xmega | Xt => 2,
+            },
+            Instruction::Ret | Instruction::Reti => match family {
+                Classic | Xmega | Xt => 4,
+                Reduced => 6,
+            },
+
+            Instruction::Ld(_, _, variant) => match (family, variant) {
+                (Classic, _) => 2,
+                (Xmega, Variant::Predecrement) | (Reduced, Variant::Predecrement) => 2,
+                (Xmega, _) | (Reduced, _) => 1,
+                (Xt, _) => 2,
+            },
+            Instruction::Ldd(..) => 2,
+            Instruction::St(..) | Instruction::Std(..) => match family {
+                Classic => 2,
+                Xmega | Xt | Reduced => 1,
+            },
+            Instruction::Lds(..) => match family {
+                Classic | Xmega => 2,
+                Xt => 3,
+                Reduced => 1,
+            },
+            Instruction::Lds16(..) | Instruction::Sts16(..) => 1,
+            Instruction::Sts(..) => match family {
+                Classic | Xmega | Xt => 2,
+                Reduced => 1,
+            },
+            Instruction::Push(..) => match family {
+                Classic => 2,
+                Xmega | Xt | Reduced => 1,
+            },
+            Instruction::Pop(..) => match family {
+                Classic | Xmega | Xt => 2,
+                Reduced => 3,
+            },
+            Instruction::Lpm(..) | Instruction::Elpm(..) => 3,
+            Instruction::Xch(..)
+            | Instruction::Las(..)
+            | Instruction::Lac(..)
+            | Instruction::Lat(..) => 2,
+
+            _ => 1,
+        }
+    }
+
+    /// Gets the byte address a jump, call or branch at `address` goes to
+    /// when taken.
+    pub fn branch_target(&self, address: u32) -> Option<u32> {
+        let next = address as i64 + self.size() as i64;
+        let relative = |k: i64| Some((next + k) as u32);
+
+        match *self {
+            Instruction::Jmp(k) | Instruction::Call(k) => Some(k),
+            Instruction::Rjmp(k) | Instruction::Rcall(k) => relative(k as i64),
+            Instruction::Brbs(_, k) | Instruction::Brbc(_, k) => relative(k as i64),
+            Instruction::Breq(k)
+            | Instruction::Brne(k)
+            | Instruction::Brcs(k)
+            | Instruction::Brcc(k)
+            | Instruction::Brsh(k)
+            | Instruction::Brlo(k)
+            | Instruction::Brmi(k)
+            | Instruction::Brpl(k)
+            | Instruction::Brge(k)
+            | Instruction::Brlt(k)
+            | Instruction::Brhs(k)
+            | Instruction::Brhc(k)
+            | Instruction::Brts(k)
+            | Instruction::Brtc(k)
+            | Instruction::Brvs(k)
+            | Instruction::Brvc(k)
+            | Instruction::Brie(k)
+            | Instruction::Brid(k) => relative(k as i64),
+            _ => None,
+        }
+    }
+
+    /// Checks if the instruction is a conditional branch.
+    pub fn is_branch(&self) -> bool {
+        matches!(
+            *self,
+            Instruction::Brbs(..)
+                | Instruction::Brbc(..)
+                | Instruction::Breq(..)
+                | Instruction::Brne(..)
+                | Instruction::Brcs(..)
+                | Instruction::Brcc(..)
+                | Instruction::Brsh(..)
+                | Instruction::Brlo(..)
+                | Instruction::Brmi(..)
+                | Instruction::Brpl(..)
+                | Instruction::Brge(..)
+                | Instruction::Brlt(..)
+                | Instruction::Brhs(..)
+                | Instruction::Brhc(..)
+                | Instruction::Brts(..)
+                | Instruction::Brtc(..)
+                | Instruction::Brvs(..)
+                | Instruction::Brvc(..)
+                | Instruction::Brie(..)
+                | Instruction::Brid(..)
+        )
+    }
+
+    /// Checks if the instruction may skip the next instruction.
+    pub fn is_skip(&self) -> bool {
+        matches!(
+            *self,
+            Instruction::Cpse(..) | Instruction::Sbis(..) | Instruction::Sbrs(..)
+        )
+    }
+
+    /// Gets the general purpose registers the instruction reads.
+    ///
+    /// Pointer registers count as both of their halves, and the flags in
+    /// `SREG` and the stack pointer are not counted.
+    pub fn registers_read(&self) -> Vec<Gpr> {
+        use self::Instruction::*;
+
+        match *self {
+            Inc(r) | Dec(r) | Com(r) | Neg(r) | Push(r) | Swap(r) => vec![r],
+            Subi(r, _) | Sbci(r, _) | Andi(r, _) | Ori(r, _) | Cpi(r, _) => vec![r],
+            Add(d, r)
+            | Adc(d, r)
+            | Sub(d, r)
+            | Sbc(d, r)
+            | Mul(d, r)
+            | And(d, r)
+            | Or(d, r)
+            | Eor(d, r)
+            | Cpse(d, r)
+            | Cp(d, r)
+            | Cpc(d, r) => vec![d, r],
+            Mov(_, r) => vec![r],
+            Movw(_, r) => pair(r).to_vec(),
+            Adiw(d, _) | Sbiw(d, _) => pair(d).to_vec(),
+            Out(_, r) | Sbrs(r, _) | Sts(r, _) | Sts16(r, _) => vec![r],
+            St(p, r, _) | Std(p, _, r) => vec![p, p + 1, r],
+            Ld(_, p, _) | Ldd(_, p, _) | Lpm(_, p, _) | Elpm(_, p, _) => pair(p).to_vec(),
+            Ijmp | Icall | Eijmp | Eicall => vec![30, 31],
+            Xch(p, r) | Las(p, r) | Lac(p, r) | Lat(p, r) => vec![p, p + 1, r],
+            Spm(_) => vec![0, 1, 30, 31],
+            Des(_) => (0..16).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gets the general purpose registers the instruction writes.
+    ///
+    /// Pointer registers count as both of their halves, and the flags in
+    /// `SREG` and the stack pointer are not counted.
+    pub fn registers_written(&self) -> Vec<Gpr> {
+        use self::Instruction::*;
+
+        match *self {
+            Inc(d) | Dec(d) | Com(d) | Neg(d) | Pop(d) | Swap(d) => vec![d],
+            Subi(d, _) | Sbci(d, _) | Andi(d, _) | Ori(d, _) | Ldi(d, _) => vec![d],
+            Add(d, _)
+            | Adc(d, _)
+            | Sub(d, _)
+            | Sbc(d, _)
+            | And(d, _)
+            | Or(d, _)
+            | Eor(d, _)
+            | Mov(d, _) => vec![d],
+            Mul(..) => vec![0, 1],
+            Movw(d, _) | Adiw(d, _) | Sbiw(d, _) => pair(d).to_vec(),
+            In(d, _) | Lds(d, _) | Lds16(d, _) | Ldd(d, _, _) => vec![d],
+            Ld(d, _, Variant::Normal) => vec![d],
+            Ld(d, p, _) => vec![d, p, p + 1],
+            St(_, _, Variant::Normal) => Vec::new(),
+            St(p, _, _) => pair(p).to_vec(),
+            Lpm(d, _, false) | Elpm(d, _, false) => vec![d],
+            Lpm(d, p, true) | Elpm(d, p, true) => vec![d, p, p + 1],
+            Xch(_, r) | Las(_, r) | Lac(_, r) | Lat(_, r) => vec![r],
+            Spm(true) => vec![30, 31],
+            Des(_) => (0..8).collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Checks if the instruction reads data space, including IO registers
+    /// and the stack.
+    pub fn reads_memory(&self) -> bool {
+        use self::Instruction::*;
+
+        matches!(
+            *self,
+            Pop(_)
+                | Ret
+                | Reti
+                | In(..)
+                | Sbi(..)
+                | Sbis(..)
+                | Cbi(..)
+                | Ld(..)
+                | Ldd(..)
+                | Lds(..)
+                | Lds16(..)
+                | Xch(..)
+                | Las(..)
+                | Lac(..)
+                | Lat(..)
+        )
+    }
+
+    /// Checks if the instruction writes data space, including IO registers
+    /// and the stack.
+    pub fn writes_memory(&self) -> bool {
+        use self::Instruction::*;
+
+        matches!(
+            *self,
+            Push(_)
+                | Call(_)
+                | Rcall(_)
+                | Icall
+                | Eicall
+                | Out(..)
+                | Sbi(..)
+                | Cbi(..)
+                | St(..)
+                | Std(..)
+                | Sts(..)
+                | Sts16(..)
+                | Xch(..)
+                | Las(..)
+                | Lac(..)
+                | Lat(..)
+        )
+    }
+
+    /// Checks if the instruction calls a subroutine, pushing a return
+    /// address.
+    pub fn is_call(&self) -> bool {
+        matches!(
+            *self,
+            Instruction::Call(_) | Instruction::Rcall(_) | Instruction::Icall | Instruction::Eicall
+        )
+    }
+
+    /// Checks if the instruction reads or writes data space. Program space
+    /// accesses by `lpm` and `spm` are not counted.
+    pub fn touches_memory(&self) -> bool {
+        self.reads_memory() || self.writes_memory()
+    }
+
+    /// Checks if the instruction is in the instruction set of a family of
+    /// cores.
+    ///
+    /// Reduced (AVRrc) cores only have `r16` to `r31`, and leave out the
+    /// multiplications, `ADIW`, `SBIW`, `MOVW`, `LDD`, `STD`, the two-word
+    /// instructions, and reading or writing flash with `LPM` and `SPM`. The
+    /// one-word `LDS` and `STS` are only theirs. Instructions only some
+    /// chips of a family have, like `DES` or `EIJMP`, are checked by the
+    /// core.
+    pub fn is_supported_by(&self, family: Family) -> bool {
+        use self::Instruction::*;
+
+        if family != Family::Reduced {
+            return !matches!(*self, Lds16(..) | Sts16(..));
+        }
+        let excluded = matches!(
+            *self,
+            Adiw(..)
+                | Sbiw(..)
+                | Mul(..)
+                | Movw(..)
+                | Ldd(..)
+                | Std(..)
+                | Lds(..)
+                | Sts(..)
+                | Jmp(..)
+                | Call(..)
+                | Eijmp
+                | Eicall
+                | Lpm(..)
+                | Elpm(..)
+                | Spm(..)
+                | Xch(..)
+                | Las(..)
+                | Lac(..)
+                | Lat(..)
+                | Des(..)
+        );
+        let low_register = self
+            .registers_read()
+            .into_iter()
+            .chain(self.registers_written())
+            .any(|r| r < 16);
+        !excluded && !low_register
+    }
+}
+
+/// Gets both registers of a register pair.
+fn pair(low: GprPair) -> [Gpr; 2] {
+    [low, low + 1]
+}
+
+/// Formats instructions in the assembly syntax of the GNU assembler, like
+/// `ldi r24, 0xFF`, `brne .-6` or `ld r16, X+`.
+///
+/// Relative jumps and branches are shown as offsets in bytes from the next
+/// instruction, and absolute jumps and calls as byte addresses.
+impl fmt::Display for Instruction {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        let rel = Relative;
+        match *self {
+            Inc(d) => write!(fmt, "inc r{}", d),
+            Dec(d) => write!(fmt, "dec r{}", d),
+            Com(d) => write!(fmt, "com r{}", d),
+            Neg(d) => write!(fmt, "neg r{}", d),
+            Push(d) => write!(fmt, "push r{}", d),
+            Pop(d) => write!(fmt, "pop r{}", d),
+            Swap(d) => write!(fmt, "swap r{}", d),
+
+            Subi(d, k) => write!(fmt, "subi r{}, 0x{:02X}", d, k),
+            Sbci(d, k) => write!(fmt, "sbci r{}, 0x{:02X}", d, k),
+            Andi(d, k) => write!(fmt, "andi r{}, 0x{:02X}", d, k),
+            Ori(d, k) => write!(fmt, "ori r{}, 0x{:02X}", d, k),
+            Cpi(d, k) => write!(fmt, "cpi r{}, 0x{:02X}", d, k),
+            Ldi(d, k) => write!(fmt, "ldi r{}, 0x{:02X}", d, k),
+
+            Add(d, r) => write!(fmt, "add r{}, r{}", d, r),
+            Adc(d, r) => write!(fmt, "adc r{}, r{}", d, r),
+            Adiw(d, k) => write!(fmt, "adiw r{}, 0x{:02X}", d, k),
+            Sub(d, r) => write!(fmt, "sub r{}, r{}", d, r),
+            Sbc(d, r) => write!(fmt, "sbc r{}, r{}", d, r),
+            Sbiw(d, k) => write!(fmt, "sbiw r{}, 0x{:02X}", d, k),
+            Mul(d, r) => write!(fmt, "mul r{}, r{}", d, r),
+            And(d, r) => write!(fmt, "and r{}, r{}", d, r),
+            Or(d, r) => write!(fmt, "or r{}, r{}", d, r),
+            Eor(d, r) => write!(fmt, "eor r{}, r{}", d, r),
+            Cpse(d, r) => write!(fmt, "cpse r{}, r{}", d, r),
+            Cp(d, r) => write!(fmt, "cp r{}, r{}", d, r),
+            Cpc(d, r) => write!(fmt, "cpc r{}, r{}", d, r),
+            Mov(d, r) => write!(fmt, "mov r{}, r{}", d, r),
+            Movw(d, r) => write!(fmt, "movw r{}, r{}", d, r),
+
+            In(d, a) => write!(fmt, "in r{}, 0x{:02X}", d, a),
+            Out(a, r) => write!(fmt, "out 0x{:02X}, r{}", a, r),
+            Sbi(a, b) => write!(fmt, "sbi 0x{:02X}, {}", a, b),
+            Sbis(a, b) => write!(fmt, "sbis 0x{:02X}, {}", a, b),
+            Cbi(a, b) => write!(fmt, "cbi 0x{:02X}, {}", a, b),
+            Sbrs(r, b) => write!(fmt, "sbrs r{}, {}", r, b),
+
+            Jmp(k) => write!(fmt, "jmp 0x{:X}", k),
+            Call(k) => write!(fmt, "call 0x{:X}", k),
+            Rjmp(k) => write!(fmt, "rjmp {}", rel(k as i32)),
+            Rcall(k) => write!(fmt, "rcall {}", rel(k as i32)),
+            Ijmp => write!(fmt, "ijmp"),
+            Icall => write!(fmt, "icall"),
+            Eijmp => write!(fmt, "eijmp"),
+            Eicall => write!(fmt, "eicall"),
+
+            Brbs(s, k) => write!(fmt, "brbs {}, {}", s, rel(k as i32)),
+            Brbc(s, k) => write!(fmt, "brbc {}, {}", s, rel(k as i32)),
+            Breq(k) => write!(fmt, "breq {}", rel(k as i32)),
+            Brne(k) => write!(fmt, "brne {}", rel(k as i32)),
+            Brcs(k) => write!(fmt, "brcs {}", rel(k as i32)),
+            Brcc(k) => write!(fmt, "brcc {}", rel(k as i32)),
+            Brsh(k) => write!(fmt, "brsh {}", rel(k as i32)),
+            Brlo(k) => write!(fmt, "brlo {}", rel(k as i32)),
+            Brmi(k) => write!(fmt, "brmi {}", rel(k as i32)),
+            Brpl(k) => write!(fmt, "brpl {}", rel(k as i32)),
+            Brge(k) => write!(fmt, "brge {}", rel(k as i32)),
+            Brlt(k) => write!(fmt, "brlt {}", rel(k as i32)),
+            Brhs(k) => write!(fmt, "brhs {}", rel(k as i32)),
+            Brhc(k) => write!(fmt, "brhc {}", rel(k as i32)),
+            Brts(k) => write!(fmt, "brts {}", rel(k as i32)),
+            Brtc(k) => write!(fmt, "brtc {}", rel(k as i32)),
+            Brvs(k) => write!(fmt, "brvs {}", rel(k as i32)),
+            Brvc(k) => write!(fmt, "brvc {}", rel(k as i32)),
+            Brie(k) => write!(fmt, "brie {}", rel(k as i32)),
+            Brid(k) => write!(fmt, "brid {}", rel(k as i32)),
+
+            St(p, r, variant) => write!(fmt, "st {}, r{}", Pointer(p, variant), r),
+            Ld(d, p, variant) => write!(fmt, "ld r{}, {}", d, Pointer(p, variant)),
+
+            Xch(p, r) => write!(fmt, "xch {}, r{}", Pointer(p, Variant::Normal), r),
+            Las(p, r) => write!(fmt, "las {}, r{}", Pointer(p, Variant::Normal), r),
+            Lac(p, r) => write!(fmt, "lac {}, r{}", Pointer(p, Variant::Normal), r),
+            Lat(p, r) => write!(fmt, "lat {}, r{}", Pointer(p, Variant::Normal), r),
+
+            Std(p, q, r) => write!(fmt, "std {}+{}, r{}", Pointer(p, Variant::Normal), q, r),
+            Ldd(d, p, q) => write!(fmt, "ldd r{}, {}+{}", d, Pointer(p, Variant::Normal), q),
+
+            Sts(r, k) | Sts16(r, k) => write!(fmt, "sts 0x{:04X}, r{}", k, r),
+            Lds(d, k) | Lds16(d, k) => write!(fmt, "lds r{}, 0x{:04X}", d, k),
+            Lpm(d, p, increment) => {
+                let variant = if increment {
+                    Variant::Postincrement
+                } else {
+                    Variant::Normal
+                };
+                write!(fmt, "lpm r{}, {}", d, Pointer(p, variant))
+            }
+            Elpm(d, p, increment) => {
+                let variant = if increment {
+                    Variant::Postincrement
+                } else {
+                    Variant::Normal
+                };
+                write!(fmt, "elpm r{}, {}", d, Pointer(p, variant))
+            }
+            Spm(false) => write!(fmt, "spm"),
+            Spm(true) => write!(fmt, "spm Z+"),
+
+            Nop => write!(fmt, "nop"),
+            Ret => write!(fmt, "ret"),
+            Reti => write!(fmt, "reti"),
+            Sei => write!(fmt, "sei"),
+            Cli => write!(fmt, "cli"),
+            Sleep => write!(fmt, "sleep"),
+            Break => write!(fmt, "break"),
+            Wdr => write!(fmt, "wdr"),
+            Des(k) => write!(fmt, "des {}", k),
+        }
+    }
+}
+
+/// A pointer register operand, like `X`, `-Y` or `Z+`.
+struct Pointer(GprPair, Variant);
+
+impl fmt::Display for Pointer {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self.0 {
+            26 => "X".to_owned(),
+            28 => "Y".to_owned(),
+            30 => "Z".to_owned(),
+            n => format!("r{}", n),
+        };
+
+        match self.1 {
+            Variant::Normal => write!(fmt, "{}", name),
+            Variant::Predecrement => write!(fmt, "-{}", name),
+            Variant::Postincrement => write!(fmt, "{}+", name),
+        }
+    }
+}
+
+/// A byte offset from the next instruction, like `.+4` or `.-6`.
+struct Relative(i32);
+
+impl fmt::Display for Relative {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, ".{:+}", self.0)
+    }
 }
@@ -0,0 +1,151 @@
+//! The interrupt controller.
+
+/// An entry in the interrupt vector table.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Vector {
+    /// The name of the vector, as in the datasheet (e.g. `TIMER0_OVF`).
+    pub name: String,
+    /// The address of the vector in program space.
+    pub address: u32,
+}
+
+impl Vector {
+    pub fn new<S: Into<String>>(name: S, address: u32) -> Self {
+        Vector {
+            name: name.into(),
+            address,
+        }
+    }
+}
+
+/// The priority level of an interrupt on XMEGA chips, which peripherals
+/// select for each of their interrupts in their `INTCTRL` registers.
+///
+/// A higher level interrupts the handler of a lower one. Other chips have
+/// a single level, so all their interrupts are `Low`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Level {
+    Low,
+    Medium,
+    High,
+    /// Non-maskable, for oscillator failures. It is serviced even while
+    /// the `I` flag is clear.
+    NonMaskable,
+}
+
+impl Level {
+    /// Every level, lowest first.
+    pub const ALL: [Level; 4] = [Level::Low, Level::Medium, Level::High, Level::NonMaskable];
+}
+
+/// The interrupt controller.
+///
+/// The vector number is the index into the chip's vector table, where
+/// vector `0` is always `RESET`. Higher levels have higher priority, and
+/// within a level lower vector numbers do.
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Controller {
+    vectors: Vec<Vector>,
+    /// Whether each vector has a pending request.
+    pending: Vec<bool>,
+    /// The level of each vector.
+    levels: Vec<Level>,
+}
+
+impl Controller {
+    pub fn new(vectors: Vec<Vector>) -> Self {
+        let pending = vec![false; vectors.len()];
+        let levels = vec![Level::Low; vectors.len()];
+        Controller {
+            vectors,
+            pending,
+            levels,
+        }
+    }
+
+    /// Requests service of a vector.
+    ///
+    /// Returns `false` if the vector does not exist.
+    pub fn raise(&mut self, number: u8) -> bool {
+        match self.pending.get_mut(number as usize) {
+            Some(pending) => {
+                *pending = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Withdraws a pending request.
+    pub fn clear(&mut self, number: u8) {
+        if let Some(pending) = self.pending.get_mut(number as usize) {
+            *pending = false;
+        }
+    }
+
+    /// Withdraws all pending requests.
+    pub fn clear_all(&mut self) {
+        self.pending.iter_mut().for_each(|p| *p = false);
+    }
+
+    /// Checks if a vector has a pending request.
+    pub fn is_pending(&self, number: u8) -> bool {
+        self.pending.get(number as usize).cloned().unwrap_or(false)
+    }
+
+    /// Gets the highest priority pending vector.
+    pub fn highest_pending(&self) -> Option<u8> {
+        self.highest_pending_where(|_| true)
+    }
+
+    /// Gets the highest priority pending vector of the levels `accepts`
+    /// lets through.
+    pub fn highest_pending_where<F>(&self, accepts: F) -> Option<u8>
+    where
+        F: Fn(Level) -> bool,
+    {
+        (0..self.pending.len())
+            .filter(|&n| self.pending[n] && accepts(self.levels[n]))
+            .min_by_key(|&n| (std::cmp::Reverse(self.levels[n]), n))
+            .map(|n| n as u8)
+    }
+
+    /// Sets the level of a vector.
+    ///
+    /// Returns `false` if the vector does not exist.
+    pub fn set_level(&mut self, number: u8, level: Level) -> bool {
+        match self.levels.get_mut(number as usize) {
+            Some(l) => {
+                *l = level;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gets the level of a vector.
+    pub fn level(&self, number: u8) -> Option<Level> {
+        self.levels.get(number as usize).copied()
+    }
+
+    /// Gets the vector table.
+    pub fn vectors(&self) -> &[Vector] {
+        &self.vectors
+    }
+
+    /// Gets a vector by number.
+    pub fn vector(&self, number: u8) -> Option<&Vector> {
+        self.vectors.get(number as usize)
+    }
+
+    /// Looks up a vector number by name.
+    pub fn vector_number(&self, name: &str) -> Option<u8> {
+        self.vectors
+            .iter()
+            .position(|v| v.name == name)
+            .map(|n| n as u8)
+    }
+}
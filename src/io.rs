@@ -1,10 +1,155 @@
-#[derive(Clone)]
+//! General purpose IO ports and IO registers.
+
+/// A GPIO port, with its `PINx`, `DDRx` and `PORTx` registers.
+///
+/// A pin set as an output in `DDRx` is driven to its bit in `PORTx`, or by a
+/// peripheral like a timer that has taken it over. An input reads what the
+/// outside world drives it to, or high through the pull-up resistor enabled
+/// by its bit in `PORTx`. A floating input without a pull-up reads low.
+///
+/// `PINx` always holds the levels of the pins, so it can be read like any
+/// other register.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Port {
-    pub address: u32,
+    /// The letter of the port, like `'B'` for `PORTB`.
+    pub name: char,
+    /// The IO address of `PINx`.
+    pub pin: u8,
+    /// The IO address of `DDRx`.
+    pub ddr: u8,
+    /// The IO address of `PORTx`.
+    pub port: u8,
+
+    /// The pins driven from the outside.
+    driven: u8,
+    /// The levels of the pins driven from the outside.
+    driven_levels: u8,
+    /// The pins whose output a peripheral has taken over from `PORTx`.
+    overridden: u8,
+    /// The levels peripherals drive their pins to.
+    override_levels: u8,
 }
 
 impl Port {
-    pub fn new(address: u32) -> Self {
-        Port { address }
+    pub fn new(name: char, pin: u8, ddr: u8, port: u8) -> Self {
+        Port {
+            name,
+            pin,
+            ddr,
+            port,
+            driven: 0,
+            driven_levels: 0,
+            overridden: 0,
+            override_levels: 0,
+        }
+    }
+
+    /// Checks if an IO address is one of the registers of the port.
+    pub fn has_register(&self, address: u8) -> bool {
+        address == self.pin || address == self.ddr || address == self.port
+    }
+
+    /// Gets the levels of the pins for the values of `DDRx` and `PORTx`.
+    pub fn levels(&self, ddr: u8, port: u8) -> u8 {
+        let outputs = (self.overridden & self.override_levels) | (!self.overridden & port);
+        let inputs = (self.driven & self.driven_levels) | (!self.driven & port);
+        (ddr & outputs) | (!ddr & inputs)
+    }
+
+    /// Drives a pin from the outside, or stops driving it if `level` is
+    /// `None`, leaving it floating.
+    pub fn drive(&mut self, bit: u8, level: Option<bool>) {
+        let mask = 1 << bit;
+        match level {
+            Some(high) => {
+                self.driven |= mask;
+                self.driven_levels = set(self.driven_levels, mask, high);
+            }
+            None => {
+                self.driven &= !mask;
+                self.driven_levels &= !mask;
+            }
+        }
+    }
+
+    /// Gets the level a pin is driven to from the outside, or `None` if it
+    /// is not.
+    pub fn driven(&self, bit: u8) -> Option<bool> {
+        let mask = 1 << bit;
+        (self.driven & mask != 0).then_some(self.driven_levels & mask != 0)
+    }
+
+    /// Lets a peripheral drive an output pin instead of `PORTx`, or gives
+    /// it back to `PORTx` if `level` is `None`.
+    pub fn set_override(&mut self, bit: u8, level: Option<bool>) {
+        let mask = 1 << bit;
+        match level {
+            Some(high) => {
+                self.overridden |= mask;
+                self.override_levels = set(self.override_levels, mask, high);
+            }
+            None => {
+                self.overridden &= !mask;
+                self.override_levels &= !mask;
+            }
+        }
+    }
+
+    /// Gives every pin back to `PORTx`, as a reset does.
+    pub fn clear_overrides(&mut self) {
+        self.overridden = 0;
+        self.override_levels = 0;
+    }
+
+    /// Gets the pins driven from the outside and their levels.
+    pub(crate) fn drive_state(&self) -> [u8; 4] {
+        [
+            self.driven,
+            self.driven_levels,
+            self.overridden,
+            self.override_levels,
+        ]
+    }
+
+    /// Restores what `drive_state` returned.
+    pub(crate) fn set_drive_state(&mut self, state: [u8; 4]) {
+        [
+            self.driven,
+            self.driven_levels,
+            self.overridden,
+            self.override_levels,
+        ] = state;
+    }
+}
+
+/// A named IO register.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Register {
+    /// The name of the register, as in the datasheet (e.g. `TCNT1`).
+    pub name: String,
+    /// The data space address of the register, or of its low byte.
+    pub address: u16,
+    /// The size of the register in bytes.
+    pub size: u8,
+}
+
+impl Register {
+    pub fn new<S: Into<String>>(name: S, address: u16, size: u8) -> Self {
+        Register {
+            name: name.into(),
+            address,
+            size,
+        }
+    }
+}
+
+/// Sets or clears the bits of a mask.
+fn set(value: u8, mask: u8, high: bool) -> u8 {
+    if high {
+        value | mask
+    } else {
+        value & !mask
     }
 }
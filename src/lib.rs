@@ -1,4 +1,5 @@
 pub use self::addons::Addon;
+pub use self::builder::CoreBuilder;
 pub use self::core::Core;
 pub use self::error::Error;
 pub use self::inst::Instruction;
@@ -7,15 +8,39 @@ pub use self::mem::Space;
 pub use self::regs::{Register, RegisterFile};
 pub use self::sreg::SReg;
 
+pub mod analysis;
+pub mod builder;
+pub mod condition;
 pub mod core;
+mod des;
+pub mod dwarf;
+pub mod elf;
 pub mod error;
+pub mod events;
+pub mod fuses;
+pub mod ihex;
 pub mod inst;
+pub mod interrupt;
 pub mod io;
 pub mod math;
 pub mod mcu;
 pub mod mem;
+pub mod mmio;
+pub mod pin;
+pub mod pmic;
+pub mod program;
 pub mod regs;
+pub mod replay;
+pub mod reset;
+pub mod reverse;
+pub mod sleep;
+pub mod srec;
 pub mod sreg;
+pub mod state;
+pub mod symbols;
+pub mod watch;
+mod xml;
 
 pub mod addons;
+pub mod boards;
 pub mod chips;
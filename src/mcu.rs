@@ -1,9 +1,208 @@
-use crate::addons;
-use crate::{Core, Error};
+use crate::addons::{self, adc, uart};
+use crate::analysis::{Cfg, StackAnalysis};
+use crate::condition::Condition;
+use crate::dwarf::{DebugInfo, Location};
+use crate::elf::{self, Elf, Region, SymbolKind};
+use crate::events;
+use crate::fuses::Fuses;
+use crate::ihex;
+use crate::inst::disasm::{self, Listing};
+use crate::pin::Pin;
+use crate::replay::{Event, Journal, Stimulus};
+use crate::reset::ResetCause;
+use crate::reverse::Delta;
+use crate::srec;
+use crate::state;
+use crate::symbols::Symbols;
+use crate::watch;
+use crate::{sreg, Core, Error, Instruction};
+use std::collections::VecDeque;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// A reset scheduled to happen at a point in simulated time.
+#[derive(Copy, Clone, Debug)]
+struct ScheduledReset {
+    cause: ResetCause,
+    /// The cycle at which reset is asserted.
+    at: u64,
+    /// The number of cycles reset is held for.
+    duration: u64,
+    asserted: bool,
+}
+
+/// How `Mcu::run` paces execution against wall-clock time.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pacing {
+    /// Run as fast as the host allows.
+    Unpaced,
+    /// Sleep on the host so that simulated time tracks wall-clock time.
+    ///
+    /// This is needed when the simulation talks to real programs, which
+    /// expect the firmware to take as long as it would on hardware.
+    RealTime,
+}
+
+/// The file format of a memory image.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    IntelHex,
+    SRecord,
+}
+
+impl ImageFormat {
+    /// Encodes memory contents, leaving out records that are entirely
+    /// `blank`.
+    pub fn encode(self, data: &[u8], blank: u8) -> String {
+        let segments = ihex::segments(data, blank);
+        match self {
+            ImageFormat::IntelHex => ihex::encode(&segments),
+            ImageFormat::SRecord => srec::encode(&segments),
+        }
+    }
+}
+
+/// A place runs stop before executing.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Breakpoint {
+    /// The byte address of the instruction.
+    pub address: u32,
+    /// The condition that has to hold for runs to stop, if any.
+    pub condition: Option<Condition>,
+}
+
+/// The exit status of a program, as passed to `exit`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ExitCode(pub i16);
+
+impl ExitCode {
+    pub const SUCCESS: ExitCode = ExitCode(0);
+
+    pub fn is_success(self) -> bool {
+        self == ExitCode::SUCCESS
+    }
+}
+
+/// Why a run stopped.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    /// The requested number of instructions were executed.
+    InstructionCount,
+    /// The requested number of cycles were executed.
+    CycleCount,
+    /// The program counter reached the requested address.
+    PcReached(u32),
+    /// The predicate given to `run_until` returned true.
+    Predicate,
+    /// The program counter reached a breakpoint, so that the instruction
+    /// there is the next to be executed.
+    Breakpoint(u32),
+    /// An instruction accessed a watched address, see
+    /// `Core::add_watchpoint`. The instruction has finished executing.
+    Watchpoint(watch::Hit),
+    /// A step of `step_over` or `step_out` completed.
+    Stepped,
+    /// The program exited through one of the enabled exit conventions.
+    Exited(ExitCode),
+    /// The program can make no more progress, see `Mcu::is_halted`.
+    Halted {
+        /// The address of the instruction the program halted on.
+        pc: u32,
+    },
+}
+
+/// Identifies an addon attached to an `Mcu`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct AddonId(u32);
+
+/// Identifies a subscription made with `Mcu::subscribe`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub struct SubscriptionId(u32);
+
+/// A callback events are published to.
+type Subscriber = Box<dyn FnMut(&Core, &events::Event)>;
+
+/// An addon and whether its hooks are called.
+struct Attached {
+    id: AddonId,
+    enabled: bool,
+    addon: Box<dyn addons::Addon>,
+}
+
+/// A tick recorded for reverse execution.
+struct Step {
+    delta: Delta,
+    clock_periods: u64,
+    last_cycle_count: u64,
+    earlier_nanos: u64,
+    last_executed: Option<(Instruction, u32)>,
+    scheduled_resets: Vec<ScheduledReset>,
+}
+
+/// How far simulated time may run ahead of wall-clock time before a paced
+/// run sleeps.
+const PACING_SLACK: Duration = Duration::from_millis(1);
 
 pub struct Mcu {
     pub core: Core,
-    addons: Vec<Box<dyn addons::Addon>>,
+    /// The attached addons, in the order their hooks are called.
+    addons: Vec<Attached>,
+    next_addon_id: u32,
+    /// The callbacks events are published to, in the order they subscribed.
+    subscribers: Vec<(SubscriptionId, Subscriber)>,
+    next_subscription_id: u32,
+    /// The levels of the pins of each port when changes were last
+    /// published, or empty if they were not.
+    pin_levels: Vec<u8>,
+    scheduled_resets: Vec<ScheduledReset>,
+    /// Whether `assert_reset_pin` holds the core in reset.
+    reset_pin_asserted: bool,
+    /// Whether `brown_out` holds the core in reset.
+    browned_out: bool,
+    /// The entry point of the last ELF file loaded.
+    entry_point: Option<u32>,
+    /// The debug information of the last ELF file loaded.
+    debug_info: Option<DebugInfo>,
+    /// The symbol table of the last ELF file loaded.
+    symbols: Symbols,
+    breakpoints: Vec<Breakpoint>,
+
+    /// The number of clock source periods simulated since the clock
+    /// frequency was last set, up to `last_cycle_count`.
+    clock_periods: u64,
+    /// The cycle count when `clock_periods` was last brought up to date.
+    last_cycle_count: u64,
+    /// The simulated time before the clock frequency was last set, so
+    /// that it is not rescaled by the new frequency (nanoseconds).
+    earlier_nanos: u64,
+
+    /// The last instruction executed and its address.
+    last_executed: Option<(Instruction, u32)>,
+    /// The most instructions a single run may execute.
+    instruction_budget: Option<u64>,
+    /// Whether `BREAK` exits with the code in R25:R24.
+    exit_on_break: bool,
+    /// The data space address whose writes exit with the written value.
+    exit_address: Option<u16>,
+
+    pacing: Pacing,
+    /// The wall-clock and simulated time at which pacing started.
+    pacing_origin: Option<(Instant, Duration)>,
+
+    /// The most recent ticks, newest last, for `step_back`.
+    history: VecDeque<Step>,
+    /// The most ticks kept in `history`.
+    history_capacity: usize,
+
+    /// The handles stimuli are sent through, by their index.
+    uarts: Vec<uart::Handle>,
+    adcs: Vec<adc::Handle>,
+    /// The stimuli injected so far.
+    journal: Journal,
+    /// The stimuli still to be replayed, earliest first.
+    replaying: VecDeque<Event>,
+    /// The stimuli scheduled with `schedule`, earliest first.
+    scheduled: VecDeque<Event>,
 }
 
 impl Mcu {
@@ -11,20 +210,1323 @@ impl Mcu {
         Mcu {
             core,
             addons: Vec::new(),
+            next_addon_id: 0,
+            subscribers: Vec::new(),
+            next_subscription_id: 0,
+            pin_levels: Vec::new(),
+            scheduled_resets: Vec::new(),
+            reset_pin_asserted: false,
+            browned_out: false,
+            entry_point: None,
+            debug_info: None,
+            symbols: Symbols::default(),
+            breakpoints: Vec::new(),
+            clock_periods: 0,
+            last_cycle_count: 0,
+            earlier_nanos: 0,
+            last_executed: None,
+            instruction_budget: None,
+            exit_on_break: false,
+            exit_address: None,
+            pacing: Pacing::Unpaced,
+            pacing_origin: None,
+            history: VecDeque::new(),
+            history_capacity: 0,
+            uarts: Vec::new(),
+            adcs: Vec::new(),
+            journal: Journal::new(),
+            replaying: VecDeque::new(),
+            scheduled: VecDeque::new(),
+        }
+    }
+
+    /// Attaches an addon, calling its hooks after those of the addons
+    /// attached before it.
+    pub fn attach(&mut self, mut addon: Box<dyn addons::Addon>) -> AddonId {
+        let id = AddonId(self.next_addon_id);
+        self.next_addon_id += 1;
+
+        if let Err(error) = addon.on_attach(&mut self.core) {
+            tracing::warn!(addon = id.0, ?error, "addon failed to attach");
+        }
+        self.addons.push(Attached {
+            id,
+            enabled: true,
+            addon,
+        });
+        id
+    }
+
+    /// Detaches an addon, giving it back if it was attached.
+    pub fn detach(&mut self, id: AddonId) -> Option<Box<dyn addons::Addon>> {
+        let index = self.addon_index(id)?;
+        let mut addon = self.addons.remove(index).addon;
+
+        if let Err(error) = addon.on_detach(&mut self.core) {
+            tracing::warn!(addon = id.0, ?error, "addon failed to detach");
+        }
+        Some(addon)
+    }
+
+    /// Stops or starts calling the hooks of an addon, returning whether it
+    /// is attached.
+    ///
+    /// A disabled addon misses everything that happens until it is enabled
+    /// again, including resets, but its IO mappings stay in place and it is
+    /// still part of saved states.
+    pub fn set_addon_enabled(&mut self, id: AddonId, enabled: bool) -> bool {
+        match self.addon_index(id) {
+            Some(index) => {
+                self.addons[index].enabled = enabled;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_addon_enabled(&self, id: AddonId) -> bool {
+        self.addon_index(id)
+            .is_some_and(|index| self.addons[index].enabled)
+    }
+
+    /// Moves an addon to a position in the order hooks are called, or to
+    /// the end if the position is past it. Returns whether it is attached.
+    pub fn move_addon(&mut self, id: AddonId, position: usize) -> bool {
+        match self.addon_index(id) {
+            Some(index) => {
+                let attached = self.addons.remove(index);
+                let position = position.min(self.addons.len());
+                self.addons.insert(position, attached);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Gets the attached addons in the order their hooks are called.
+    pub fn addon_ids(&self) -> impl Iterator<Item = AddonId> + '_ {
+        self.addons.iter().map(|attached| attached.id)
+    }
+
+    fn addon_index(&self, id: AddonId) -> Option<usize> {
+        self.addons.iter().position(|attached| attached.id == id)
+    }
+
+    /// Calls a function with every event from now on, along with the core
+    /// as it is after the tick the event happened in.
+    ///
+    /// Unlike addons, subscribers only watch the simulation, so any number
+    /// of them can look at it without getting in each other's way.
+    pub fn subscribe<F>(&mut self, callback: F) -> SubscriptionId
+    where
+        F: FnMut(&Core, &events::Event) + 'static,
+    {
+        let id = SubscriptionId(self.next_subscription_id);
+        self.next_subscription_id += 1;
+        self.subscribers.push((id, Box::new(callback)));
+        self.publish_pin_changes();
+        id
+    }
+
+    /// Stops calling a subscriber, returning whether it was subscribed.
+    pub fn unsubscribe(&mut self, id: SubscriptionId) -> bool {
+        let count = self.subscribers.len();
+        self.subscribers.retain(|(s, _)| *s != id);
+        self.subscribers.len() < count
+    }
+
+    fn publish(&mut self, event: &events::Event) {
+        for (_, callback) in self.subscribers.iter_mut() {
+            callback(&self.core, event);
+        }
+    }
+
+    /// Gets the first attached addon of a type, like a `Uart` to read what
+    /// it captured.
+    pub fn addon<T>(&self) -> Option<&T>
+    where
+        T: addons::Addon + 'static,
+    {
+        self.addons
+            .iter()
+            .find_map(|attached| attached.addon.as_ref().as_any().downcast_ref())
+    }
+
+    /// Gets the first attached addon of a type mutably.
+    pub fn addon_mut<T>(&mut self) -> Option<&mut T>
+    where
+        T: addons::Addon + 'static,
+    {
+        self.addons
+            .iter_mut()
+            .find_map(|attached| attached.addon.as_mut().as_any_mut().downcast_mut())
+    }
+
+    /// Loads an Intel HEX file into program space.
+    pub fn load_ihex<P>(&mut self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let text = std::fs::read_to_string(path).map_err(Error::Io)?;
+        self.load_ihex_str(&text)
+    }
+
+    /// Loads the contents of an Intel HEX file into program space.
+    pub fn load_ihex_str(&mut self, text: &str) -> Result<(), Error> {
+        for segment in ihex::parse(text)? {
+            self.core
+                .load_program_space_at(segment.address, &segment.data)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes the current contents of program space.
+    ///
+    /// This is what is actually in flash, including anything written by
+    /// the program itself through `SPM`. Program space starts out zeroed,
+    /// so zeroed records are left out.
+    pub fn flash_image(&self, format: ImageFormat) -> String {
+        let flash: Vec<u8> = self.core.program_space().bytes().collect();
+        format.encode(&flash, 0x00)
+    }
+
+    /// Encodes the current contents of the EEPROM, leaving out erased
+    /// records.
+    pub fn eeprom_image(&self, format: ImageFormat) -> String {
+        let eeprom: Vec<u8> = self.core.eeprom().bytes().collect();
+        format.encode(&eeprom, 0xff)
+    }
+
+    /// Writes the current contents of program space to a file.
+    pub fn save_flash<P>(&self, path: P, format: ImageFormat) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        std::fs::write(path, self.flash_image(format)).map_err(Error::Io)
+    }
+
+    /// Writes the current contents of the EEPROM to a file.
+    pub fn save_eeprom<P>(&self, path: P, format: ImageFormat) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        std::fs::write(path, self.eeprom_image(format)).map_err(Error::Io)
+    }
+
+    /// Loads an ELF file, as produced by `avr-gcc`.
+    ///
+    /// See `load_elf_bytes`.
+    pub fn load_elf<P>(&mut self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = std::fs::read(path).map_err(Error::Io)?;
+        self.load_elf_bytes(&bytes)
+    }
+
+    /// Loads the contents of an ELF file.
+    ///
+    /// Loadable segments are placed by their physical address: `.text` and
+    /// the initial values of `.data` go into program space, `.eeprom` into
+    /// the EEPROM, and `.fuse` and `.lock` set the fuses. SRAM and signature
+    /// segments are skipped. Execution continues at the entry point.
+    ///
+    /// Any DWARF debug information is kept for `symbolicate`, and the
+    /// symbol table for `symbols`. Debug information or symbols that cannot
+    /// be read are left out with a warning, since the program runs without
+    /// them.
+    pub fn load_elf_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let elf = Elf::parse(bytes)?;
+        let debug_info = match DebugInfo::parse(&elf) {
+            Ok(debug_info) => Some(debug_info),
+            Err(error) => {
+                tracing::warn!(?error, "ignoring unreadable debug information");
+                None
+            }
+        };
+        let symbols = Symbols::from_elf(&elf).unwrap_or_else(|error| {
+            tracing::warn!(?error, "ignoring unreadable symbol table");
+            Symbols::default()
+        });
+
+        for segment in &elf.segments {
+            self.load_elf_segment(segment)?;
+        }
+
+        self.core.pc = elf.entry;
+        self.entry_point = Some(elf.entry);
+        self.debug_info = debug_info;
+        self.symbols = symbols;
+        Ok(())
+    }
+
+    fn load_elf_segment(&mut self, segment: &elf::Segment) -> Result<(), Error> {
+        match segment.region {
+            Region::Flash => self
+                .core
+                .load_program_space_at(segment.address, &segment.data)?,
+            Region::Eeprom => {
+                for (i, &byte) in segment.data.iter().enumerate() {
+                    self.core
+                        .eeprom_mut()
+                        .set_u8(segment.address as usize + i, byte)?;
+                }
+            }
+            Region::Fuse => {
+                let mut fuses = self.core.fuses();
+                for (i, &byte) in segment.data.iter().enumerate() {
+                    match segment.address as usize + i {
+                        0 => fuses.low = byte,
+                        1 => fuses.high = byte,
+                        2 => fuses.extended = byte,
+                        _ => (),
+                    }
+                }
+                self.core.set_fuses(fuses);
+            }
+            Region::Lock => {
+                if let (0, Some(&byte)) = (segment.address, segment.data.first()) {
+                    let mut fuses = self.core.fuses();
+                    fuses.lock = byte;
+                    self.core.set_fuses(fuses);
+                }
+            }
+            Region::Data | Region::Signature | Region::UserSignature => (),
+        }
+        Ok(())
+    }
+
+    /// Gets the entry point of the last ELF file loaded.
+    pub fn entry_point(&self) -> Option<u32> {
+        self.entry_point
+    }
+
+    pub fn debug_info(&self) -> Option<&DebugInfo> {
+        self.debug_info.as_ref()
+    }
+
+    /// Finds the function and source line of a byte address, using the
+    /// debug information of the loaded ELF file.
+    pub fn symbolicate(&self, pc: u32) -> Option<Location> {
+        self.debug_info.as_ref()?.symbolicate(pc)
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    /// Disassembles program space, up to the last byte that is not zero,
+    /// labelling branch targets with the symbols of the loaded program.
+    pub fn disassemble(&self) -> Listing {
+        let flash: Vec<u8> = self.core.program_space().bytes().collect();
+        let end = flash.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        let end = end + end % 2;
+
+        disasm::disassemble(&flash[..end], 0).with_symbols(&self.symbols)
+    }
+
+    /// Builds the control-flow graph of program space, starting from the
+    /// reset vector, every interrupt vector and every function symbol of
+    /// the loaded program.
+    pub fn control_flow_graph(&self) -> Cfg {
+        let flash: Vec<u8> = self.core.program_space().bytes().collect();
+
+        let mut entries = vec![self.core.reset_vector()];
+        entries.extend(self.core.interrupts().vectors().iter().map(|v| v.address));
+        entries.extend(
+            self.symbols
+                .iter()
+                .filter(|s| s.region == Region::Flash && s.kind == SymbolKind::Function)
+                .map(|s| s.address),
+        );
+
+        Cfg::build(&flash, &entries)
+    }
+
+    /// Estimates the stack usage of every function in program space.
+    pub fn stack_analysis(&self) -> StackAnalysis {
+        StackAnalysis::new(&self.control_flow_graph())
+    }
+
+    /// Estimates the most bytes the program can have on the stack, from
+    /// the reset vector plus the deepest interrupt handler. Returns `None`
+    /// if there is recursion or a loop that keeps pushing.
+    pub fn worst_case_stack_depth(&self) -> Option<u32> {
+        // The first vector is the reset vector.
+        let interrupts: Vec<u32> = self.core.interrupts().vectors()[1..]
+            .iter()
+            .map(|v| v.address)
+            .collect();
+
+        self.stack_analysis()
+            .worst_case(&[self.core.reset_vector()], &interrupts)
+    }
+
+    /// Stops runs before executing the function or label `name`, returning
+    /// its byte address.
+    pub fn break_at(&mut self, name: &str) -> Result<u32, Error> {
+        let address = match self.symbols.get(name) {
+            Some(symbol) if symbol.region == Region::Flash => symbol.address,
+            _ => return Err(Error::UnknownSymbol(name.to_owned())),
+        };
+
+        self.add_breakpoint(address);
+        Ok(address)
+    }
+
+    /// Stops runs before executing the instruction at byte address
+    /// `address`, with `StopReason::Breakpoint`.
+    ///
+    /// A run always executes at least one instruction, so resuming from a
+    /// breakpoint does not stop there again straight away.
+    pub fn add_breakpoint(&mut self, address: u32) {
+        self.set_breakpoint(address, None);
+    }
+
+    /// Stops runs before executing the instruction at byte address
+    /// `address` if `condition` holds, like `r24 == 0x7F`.
+    ///
+    /// The condition is evaluated against the state before the instruction
+    /// executes, and failing to evaluate it fails the run.
+    pub fn add_conditional_breakpoint(&mut self, address: u32, condition: Condition) {
+        self.set_breakpoint(address, Some(condition));
+    }
+
+    /// Replaces any breakpoint at `address`.
+    fn set_breakpoint(&mut self, address: u32, condition: Option<Condition>) {
+        self.remove_breakpoint(address);
+        self.breakpoints.push(Breakpoint { address, condition });
+    }
+
+    /// Removes the breakpoint at a byte address, returning whether there
+    /// was one.
+    pub fn remove_breakpoint(&mut self, address: u32) -> bool {
+        let len = self.breakpoints.len();
+        self.breakpoints.retain(|b| b.address != address);
+        self.breakpoints.len() != len
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// Gets the breakpoints, in the order they were added.
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// Saves the state of the whole simulation to a file, see
+    /// `save_state_bytes`.
+    pub fn save_state<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        std::fs::write(path, self.save_state_bytes()).map_err(Error::Io)
+    }
+
+    /// Loads the state of the whole simulation from a file, see
+    /// `load_state_bytes`.
+    pub fn load_state<P>(&mut self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        let bytes = std::fs::read(path).map_err(Error::Io)?;
+        self.load_state_bytes(&bytes)
+    }
+
+    /// Saves the state of the core, the scheduled resets and every addon in
+    /// the format of the `state` module.
+    ///
+    /// Breakpoints, symbols and other debugging aids are not saved.
+    pub fn save_state_bytes(&self) -> Vec<u8> {
+        let mut state = state::Writer::new();
+        state.header();
+
+        let mut core = state::Writer::new();
+        self.core.save_state(&mut core);
+        state.bytes(&core.into_bytes());
+
+        state.u64(self.clock_periods);
+        state.u64(self.last_cycle_count);
+        state.u64(self.earlier_nanos);
+        // Executed instructions were decoded, so they always encode.
+        let last_executed = self
+            .last_executed
+            .and_then(|(instruction, pc)| Some((instruction.encode().ok()?, pc)));
+        match last_executed {
+            Some((bytes, pc)) => {
+                state.bool(true);
+                state.u32(pc);
+                state.bytes(&bytes);
+            }
+            None => state.bool(false),
+        }
+        state.u32(self.scheduled_resets.len() as u32);
+        for reset in &self.scheduled_resets {
+            state.u8(reset.cause.flag());
+            state.u64(reset.at);
+            state.u64(reset.duration);
+            state.bool(reset.asserted);
+        }
+        state.bool(self.reset_pin_asserted);
+        state.bool(self.browned_out);
+
+        state.u32(self.addons.len() as u32);
+        for attached in &self.addons {
+            let mut addon_state = state::Writer::new();
+            attached.addon.save_state(&mut addon_state);
+            state.bytes(&addon_state.into_bytes());
+        }
+        state.into_bytes()
+    }
+
+    /// Loads a state saved by `save_state_bytes`.
+    ///
+    /// The state has to come from the same chip with the same addons,
+    /// added in the same order.
+    /// If loading fails part of the state may already have been loaded.
+    pub fn load_state_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+        let mut state = state::Reader::new(bytes);
+        state.header()?;
+
+        self.core
+            .load_state(&mut state::Reader::new(state.bytes()?))?;
+
+        self.clock_periods = state.u64()?;
+        self.last_cycle_count = state.u64()?;
+        self.earlier_nanos = state.u64()?;
+        self.last_executed = match state.bool()? {
+            true => {
+                let pc = state.u32()?;
+                let bytes = state.bytes()?;
+                let padded = bytes.iter().copied().chain(std::iter::repeat(0)).take(4);
+                let family = self.core.family();
+                Some((crate::inst::binary::read_for(padded, family)?, pc))
+            }
+            false => None,
+        };
+        let resets = state.u32()?;
+        self.scheduled_resets = (0..resets)
+            .map(|_| {
+                Ok(ScheduledReset {
+                    cause: ResetCause::from_flag(state.u8()?)
+                        .ok_or(Error::InvalidState("invalid reset cause"))?,
+                    at: state.u64()?,
+                    duration: state.u64()?,
+                    asserted: state.bool()?,
+                })
+            })
+            .collect::<Result<_, Error>>()?;
+        self.reset_pin_asserted = state.bool()?;
+        self.browned_out = state.bool()?;
+
+        if state.u32()? as usize != self.addons.len() {
+            return Err(Error::InvalidState("addon count does not match"));
+        }
+        for attached in self.addons.iter_mut() {
+            attached
+                .addon
+                .load_state(&mut state::Reader::new(state.bytes()?))?;
+        }
+
+        self.pacing_origin = None;
+        Ok(())
+    }
+
+    /// Resets the core.
+    pub fn reset(&mut self) {
+        self.core.reset();
+        self.for_each_addon("on_reset", |addon, core| addon.on_reset(core, None));
+        self.publish(&events::Event::Reset { cause: None });
+    }
+
+    /// Resets the core, recording the cause in `MCUSR`.
+    pub fn reset_with(&mut self, cause: ResetCause) -> Result<(), Error> {
+        self.core.reset_with(cause)?;
+        self.report_events();
+        Ok(())
+    }
+
+    /// Sets the frequency of the clock source (hertz). Simulated time
+    /// stands still while the frequency is zero.
+    pub fn set_clock_frequency(&mut self, frequency: u64) {
+        self.update_clock_periods();
+        self.earlier_nanos = self.elapsed().as_nanos() as u64;
+        self.clock_periods = 0;
+        self.core.set_clock_frequency(frequency);
+    }
+
+    /// Gets the simulated time since the MCU was created.
+    ///
+    /// This is derived from the cycle counter, and the clock frequency and
+    /// clock prescaler in effect when the cycles were executed.
+    pub fn elapsed(&self) -> Duration {
+        let frequency = self.core.clock_frequency();
+        let periods = self.clock_periods();
+        let earlier = Duration::from_nanos(self.earlier_nanos);
+        if frequency == 0 {
+            return earlier;
         }
+
+        earlier
+            + Duration::new(
+                periods / frequency,
+                ((periods % frequency) * 1_000_000_000 / frequency) as u32,
+            )
+    }
+
+    /// Gets the simulated time in microseconds.
+    pub fn elapsed_micros(&self) -> u128 {
+        self.elapsed().as_micros()
     }
 
-    pub fn attach(&mut self, addon: Box<dyn addons::Addon>) {
-        self.addons.push(addon);
+    /// Gets the simulated time in nanoseconds.
+    pub fn elapsed_nanos(&self) -> u128 {
+        self.elapsed().as_nanos()
     }
 
+    /// Gets the number of clock source periods simulated so far.
+    fn clock_periods(&self) -> u64 {
+        let cycles = self.core.cycle_count - self.last_cycle_count;
+        self.clock_periods + cycles * self.core.clock_divider() as u64
+    }
+
+    /// Accounts for the cycles executed since the last update, so that
+    /// changes to the clock only affect the cycles after them.
+    fn update_clock_periods(&mut self) {
+        self.clock_periods = self.clock_periods();
+        self.last_cycle_count = self.core.cycle_count;
+    }
+
+    pub fn fuses(&self) -> Fuses {
+        self.core.fuses()
+    }
+
+    /// Sets the fuse bytes and lock bits, which mostly take effect at the
+    /// next reset.
+    pub fn set_fuses(&mut self, fuses: Fuses) {
+        self.core.set_fuses(fuses)
+    }
+
+    /// Pulls the `RESET` pin low, holding the core in reset until
+    /// `release_reset_pin`.
+    pub fn assert_reset_pin(&mut self) -> Result<(), Error> {
+        self.core.assert_reset(ResetCause::External)?;
+        self.reset_pin_asserted = true;
+        self.report_events();
+        Ok(())
+    }
+
+    /// Lets the `RESET` pin go high again. The core stays in reset while a
+    /// brown-out or a scheduled reset still holds it.
+    pub fn release_reset_pin(&mut self) {
+        self.reset_pin_asserted = false;
+        self.release_reset_if_unheld();
+    }
+
+    /// Drives pin `pin` of a port like `'B'` high or low from the outside,
+    /// as a button or another chip would.
+    ///
+    /// The pin only reads as driven while `DDRx` makes it an input.
+    pub fn set_pin_input(&mut self, port: char, pin: u8, level: bool) -> Result<(), Error> {
+        self.core.set_pin_input(port, pin, Some(level))?;
+        self.report_events();
+        Ok(())
+    }
+
+    /// Stops driving a pin from the outside, leaving it floating, so that
+    /// it reads high with its pull-up enabled and low otherwise.
+    pub fn float_pin(&mut self, port: char, pin: u8) -> Result<(), Error> {
+        self.core.set_pin_input(port, pin, None)?;
+        self.report_events();
+        Ok(())
+    }
+
+    /// Gets a pin by its name, like `PB5`.
+    pub fn pin(&mut self, name: &str) -> Result<Pin<'_>, Error> {
+        Pin::new(self, name)
+    }
+
+    /// Drops the supply voltage below the brown-out level, holding the core
+    /// in reset until `restore_supply`.
+    pub fn brown_out(&mut self) -> Result<(), Error> {
+        self.core.assert_reset(ResetCause::BrownOut)?;
+        self.browned_out = true;
+        self.report_events();
+        Ok(())
+    }
+
+    /// Brings the supply voltage back up after a brown-out. The core stays
+    /// in reset while the `RESET` pin or a scheduled reset still holds it.
+    pub fn restore_supply(&mut self) {
+        self.browned_out = false;
+        self.release_reset_if_unheld();
+    }
+
+    /// Schedules a reset at cycle `at`, holding the core in reset for
+    /// `duration` cycles.
+    pub fn schedule_reset(&mut self, cause: ResetCause, at: u64, duration: u64) {
+        self.scheduled_resets.push(ScheduledReset {
+            cause,
+            at,
+            duration,
+            asserted: false,
+        });
+    }
+
+    /// Asserts and releases scheduled resets that are due.
+    fn apply_scheduled_resets(&mut self) -> Result<(), Error> {
+        let now = self.core.cycle_count;
+
+        for scheduled in self.scheduled_resets.iter_mut() {
+            if !scheduled.asserted && now >= scheduled.at {
+                self.core.assert_reset(scheduled.cause)?;
+                scheduled.asserted = true;
+            }
+        }
+
+        let count = self.scheduled_resets.len();
+        self.scheduled_resets
+            .retain(|scheduled| now < scheduled.at + scheduled.duration);
+        let expired = self.scheduled_resets.len() < count;
+
+        if expired {
+            self.release_reset_if_unheld();
+        }
+        Ok(())
+    }
+
+    /// Lets the core out of reset, unless a scheduled reset, the `RESET`
+    /// pin or a brown-out still holds it there.
+    fn release_reset_if_unheld(&mut self) {
+        let scheduled = self.scheduled_resets.iter().any(|s| s.asserted);
+        if !scheduled && !self.reset_pin_asserted && !self.browned_out {
+            self.core.release_reset();
+        }
+    }
+
+    /// Registers a UART that `Stimulus::UartRx` can send bytes through,
+    /// returning its index.
+    pub fn register_uart(&mut self, handle: uart::Handle) -> usize {
+        self.uarts.push(handle);
+        self.uarts.len() - 1
+    }
+
+    /// Registers an ADC that `Stimulus::AdcVoltage` can set voltages on,
+    /// returning its index.
+    pub fn register_adc(&mut self, handle: adc::Handle) -> usize {
+        self.adcs.push(handle);
+        self.adcs.len() - 1
+    }
+
+    /// Applies a stimulus from the outside world now, and records it in
+    /// the journal at the current cycle.
+    ///
+    /// Stimuli only change the simulation through this method for their
+    /// runs to be replayable, so UARTs and ADCs have to be registered
+    /// rather than used through their own handles.
+    pub fn inject(&mut self, stimulus: Stimulus) -> Result<(), Error> {
+        match &stimulus {
+            Stimulus::UartRx { uart, bytes } => self
+                .uarts
+                .get(*uart)
+                .ok_or(Error::UnregisteredHandle {
+                    kind: "UART",
+                    index: *uart,
+                })?
+                .send_to_target(bytes),
+            Stimulus::Pin {
+                pin_register,
+                bit,
+                high,
+            } => self.core.drive_pin(*pin_register, *bit, *high)?,
+            Stimulus::AdcVoltage {
+                adc,
+                channel,
+                volts,
+            } => self
+                .adcs
+                .get(*adc)
+                .ok_or(Error::UnregisteredHandle {
+                    kind: "ADC",
+                    index: *adc,
+                })?
+                .set_voltage(*channel, *volts),
+            Stimulus::Interrupt(number) => self.core.raise_interrupt(*number)?,
+        }
+
+        // Nothing is sent, and the text format has no way to write it.
+        if matches!(&stimulus, Stimulus::UartRx { bytes, .. } if bytes.is_empty()) {
+            return Ok(());
+        }
+        self.journal.push(Event {
+            cycle: self.core.cycle_count,
+            stimulus,
+        });
+        Ok(())
+    }
+
+    /// Gets the stimuli injected so far, including replayed ones.
+    pub fn journal(&self) -> &Journal {
+        &self.journal
+    }
+
+    /// Takes the stimuli injected so far, leaving the journal empty.
+    pub fn take_journal(&mut self) -> Journal {
+        std::mem::take(&mut self.journal)
+    }
+
+    /// Replays a journal as the simulation runs.
+    ///
+    /// Each event is injected at the first instruction boundary at or
+    /// after its cycle, which for a journal recorded from the same firmware
+    /// and starting state is where it was originally injected, giving a
+    /// bit-identical run. The UARTs and ADCs have to be registered in the
+    /// same order as when recording. Events already due are injected on the
+    /// next tick.
+    pub fn replay(&mut self, journal: Journal) {
+        self.replaying = journal.events().iter().cloned().collect();
+    }
+
+    /// Checks if there are events of a replayed journal left to inject.
+    pub fn is_replaying(&self) -> bool {
+        !self.replaying.is_empty()
+    }
+
+    /// Schedules a stimulus to be injected at a cycle, like driving a pin
+    /// low at cycle 1 000 000, for scripted tests.
+    ///
+    /// It is injected at the first instruction boundary at or after the
+    /// cycle, after stimuli scheduled earlier for the same cycle, and is
+    /// recorded in the journal like any other.
+    pub fn schedule(&mut self, cycle: u64, stimulus: Stimulus) {
+        let index = self.scheduled.partition_point(|event| event.cycle <= cycle);
+        self.scheduled.insert(index, Event { cycle, stimulus });
+    }
+
+    /// Schedules a stimulus to be injected after an amount of simulated
+    /// time, going by the current CPU frequency.
+    pub fn schedule_after(&mut self, delay: Duration, stimulus: Stimulus) {
+        let cycles = delay.as_nanos() * self.core.cpu_frequency() as u128 / 1_000_000_000;
+        let cycle = self.core.cycle_count.saturating_add(cycles as u64);
+        self.schedule(cycle, stimulus);
+    }
+
+    /// Gets the stimuli still to be injected by `schedule`, earliest first.
+    pub fn scheduled(&self) -> impl Iterator<Item = &Event> + '_ {
+        self.scheduled.iter()
+    }
+
+    /// Forgets the stimuli still to be injected by `schedule`.
+    pub fn clear_schedule(&mut self) {
+        self.scheduled.clear();
+    }
+
+    /// Injects the replayed and scheduled stimuli that are due.
+    fn inject_due_events(&mut self) -> Result<(), Error> {
+        let now = self.core.cycle_count;
+        while let Some(event) = self.replaying.front() {
+            if event.cycle > now {
+                break;
+            }
+            let event = self.replaying.pop_front().unwrap();
+            self.inject(event.stimulus)?;
+        }
+        while let Some(event) = self.scheduled.front() {
+            if event.cycle > now {
+                break;
+            }
+            let event = self.scheduled.pop_front().unwrap();
+            self.inject(event.stimulus)?;
+        }
+        Ok(())
+    }
+
+    /// Services an interrupt vector on the core, if interrupts are enabled.
+    pub fn interrupt(&mut self, number: u8) -> Result<bool, Error> {
+        self.core.interrupt(number)
+    }
+
+    /// Gets the number of interrupt handlers currently being executed.
+    pub fn interrupt_depth(&self) -> u32 {
+        self.core.interrupt_depth()
+    }
+
+    pub fn pacing(&self) -> Pacing {
+        self.pacing
+    }
+
+    /// Sets how `run` paces execution against wall-clock time.
+    pub fn set_pacing(&mut self, pacing: Pacing) {
+        self.pacing = pacing;
+        self.pacing_origin = None;
+    }
+
+    /// Limits how many instructions a single run may execute, after which
+    /// it fails with `Error::InstructionBudgetExhausted`.
+    pub fn set_instruction_budget(&mut self, budget: Option<u64>) {
+        self.instruction_budget = budget;
+    }
+
+    /// Makes `BREAK` exit the program with the code in R25:R24, which is
+    /// where avr-gcc passes the argument of `exit`, rather than failing
+    /// with `Error::Break`.
+    pub fn set_exit_on_break(&mut self, enabled: bool) {
+        self.exit_on_break = enabled;
+    }
+
+    /// Makes a write to the data space address `address` exit the program
+    /// with the written byte as the code.
+    pub fn set_exit_address(&mut self, address: Option<u16>) {
+        self.exit_address = address;
+    }
+
+    /// Checks if the program can make no more progress.
+    ///
+    /// This is the case when, with interrupts disabled, the last instruction
+    /// jumped to itself (the `cli; rjmp .-2` idiom that ends avr-libc
+    /// programs), or the core went to sleep.
+    pub fn is_halted(&self) -> bool {
+        if self.core.register_file().sreg.is_set(sreg::INTERRUPT_FLAG)
+            || self.core.held_in_reset().is_some()
+        {
+            return false;
+        }
+
+        match self.last_executed {
+            Some((Instruction::Rjmp(..), pc)) | Some((Instruction::Jmp(..), pc)) => {
+                self.core.pc == pc
+            }
+            _ => self.core.is_sleeping(),
+        }
+    }
+
+    /// Runs until the program halts or an error occurs, paced as set by
+    /// `set_pacing`.
+    pub fn run(&mut self) -> Result<StopReason, Error> {
+        self.run_while(|_, _| None)
+    }
+
+    /// Executes `count` instructions.
+    ///
+    /// Ticks spent sleeping or held in reset count as instructions.
+    pub fn run_for_instructions(&mut self, count: u64) -> Result<StopReason, Error> {
+        if count == 0 {
+            return Ok(StopReason::InstructionCount);
+        }
+        let mut executed = 0;
+        self.run_while(|_, _| {
+            executed += 1;
+            (executed >= count).then_some(StopReason::InstructionCount)
+        })
+    }
+
+    /// Executes instructions until at least `count` cycles have passed.
+    pub fn run_for_cycles(&mut self, count: u64) -> Result<StopReason, Error> {
+        if count == 0 {
+            return Ok(StopReason::CycleCount);
+        }
+        let end = self.core.cycle_count + count;
+        self.run_while(|core, _| (core.cycle_count >= end).then_some(StopReason::CycleCount))
+    }
+
+    /// Executes instructions until the program counter is `pc`, so that the
+    /// instruction there is the next to be executed.
+    ///
+    /// At least one instruction is always executed.
+    pub fn run_until_pc(&mut self, pc: u32) -> Result<StopReason, Error> {
+        self.run_while(|core, _| (core.pc == pc).then_some(StopReason::PcReached(pc)))
+    }
+
+    /// Executes instructions until `predicate` returns true, checking it
+    /// after every instruction.
+    pub fn run_until<P>(&mut self, mut predicate: P) -> Result<StopReason, Error>
+    where
+        P: FnMut(&Core) -> bool,
+    {
+        self.run_while(|core, _| predicate(core).then_some(StopReason::Predicate))
+    }
+
+    /// Executes a single instruction, running whole calls as one step.
+    ///
+    /// For `CALL` and `RCALL` this runs until the call returns, and an
+    /// interrupt taken before the instruction is run to completion as well.
+    /// Breakpoints and watchpoints hit on the way still stop the step.
+    pub fn step_over(&mut self) -> Result<StopReason, Error> {
+        let start = self.core.pc;
+        let sp = self.core.stack_pointer()?;
+        let depth = self.core.interrupt_depth();
+
+        let reason = self.run_for_instructions(1)?;
+        if !matches!(
+            reason,
+            StopReason::InstructionCount | StopReason::Breakpoint(_)
+        ) {
+            return Ok(reason);
+        }
+
+        if self.core.interrupt_depth() > depth {
+            // Finish the handler, then step the instruction it interrupted.
+            let reason = self.run_while(|core, _| {
+                let returned = core.pc == start && core.stack_pointer().is_ok_and(|s| s >= sp);
+                returned.then_some(StopReason::Stepped)
+            })?;
+            return match reason {
+                StopReason::Stepped => self.step_over(),
+                reason => Ok(reason),
+            };
+        }
+
+        let (instruction, pc) = self.last_executed.unwrap_or((Instruction::Nop, start));
+        let return_address = pc + instruction.size() as u32;
+        if !instruction.is_call() || self.core.pc == return_address {
+            return Ok(StopReason::Stepped);
+        }
+        if let StopReason::Breakpoint(_) = reason {
+            return Ok(reason);
+        }
+
+        // Recursive calls pass the return address deeper in the stack.
+        self.run_while(|core, _| {
+            let returned = core.pc == return_address && core.stack_pointer().is_ok_and(|s| s >= sp);
+            returned.then_some(StopReason::Stepped)
+        })
+    }
+
+    /// Runs until the current function or interrupt handler returns.
+    ///
+    /// This is the first `RET` or `RETI` that leaves the stack pointer above
+    /// where it was, so returns from calls it makes and from interrupts do
+    /// not count.
+    pub fn step_out(&mut self) -> Result<StopReason, Error> {
+        let sp = self.core.stack_pointer()?;
+
+        self.run_while(|core, instruction| {
+            let returned = matches!(instruction, Instruction::Ret | Instruction::Reti)
+                && core.stack_pointer().is_ok_and(|s| s > sp);
+            returned.then_some(StopReason::Stepped)
+        })
+    }
+
+    /// Ticks, paced as set by `set_pacing`, until `stop` gives a reason to
+    /// stop or the program halts.
+    ///
+    /// `stop` is given the core and the instruction just executed.
+    fn run_while<F>(&mut self, mut stop: F) -> Result<StopReason, Error>
+    where
+        F: FnMut(&Core, Instruction) -> Option<StopReason>,
+    {
+        self.pacing_origin = None;
+        let mut executed = 0;
+
+        loop {
+            if let Some(budget) = self.instruction_budget {
+                if executed >= budget {
+                    return Err(Error::InstructionBudgetExhausted {
+                        budget,
+                        pc: self.core.pc,
+                    });
+                }
+            }
+
+            match self.tick() {
+                Ok(()) => (),
+                Err(Error::Break { .. }) if self.exit_on_break => {
+                    let code = self.core.register_file().gpr_pair_val(24)?;
+                    return Ok(StopReason::Exited(ExitCode(code as i16)));
+                }
+                Err(e) => return Err(e),
+            }
+            self.pace();
+            executed += 1;
+
+            if let Some(address) = self.exit_address {
+                if self.core.was_written(address) {
+                    let code = self.core.peek_data(address)?;
+                    return Ok(StopReason::Exited(ExitCode(code as i16)));
+                }
+            }
+            if let Some(&hit) = self.core.watchpoint_hits().first() {
+                self.publish(&events::Event::WatchpointHit(hit));
+                return Ok(StopReason::Watchpoint(hit));
+            }
+            if self.is_at_breakpoint()? {
+                let address = self.core.pc;
+                self.publish(&events::Event::BreakpointHit { address });
+                return Ok(StopReason::Breakpoint(address));
+            }
+            let instruction = self.last_executed.map_or(Instruction::Nop, |(i, _)| i);
+            if let Some(reason) = stop(&self.core, instruction) {
+                return Ok(reason);
+            }
+            if self.is_halted() {
+                let pc = self.last_executed.map_or(self.core.pc, |(_, pc)| pc);
+                return Ok(StopReason::Halted { pc });
+            }
+        }
+    }
+
+    /// Checks if there is a breakpoint at the program counter whose
+    /// condition holds.
+    fn is_at_breakpoint(&self) -> Result<bool, Error> {
+        let breakpoint = self.breakpoints.iter().find(|b| b.address == self.core.pc);
+        match breakpoint {
+            Some(Breakpoint {
+                condition: Some(condition),
+                ..
+            }) => condition.evaluate(&self.core),
+            Some(_) => Ok(true),
+            None => Ok(false),
+        }
+    }
+
+    /// Sleeps on the host if simulated time has got ahead of wall-clock
+    /// time.
+    fn pace(&mut self) {
+        if self.pacing == Pacing::Unpaced {
+            return;
+        }
+
+        let simulated = self.elapsed();
+        let (wall_start, simulated_start) = *self
+            .pacing_origin
+            .get_or_insert((Instant::now(), simulated));
+
+        let ahead = (simulated - simulated_start).checked_sub(wall_start.elapsed());
+        if let Some(ahead) = ahead {
+            if ahead > PACING_SLACK {
+                std::thread::sleep(ahead);
+            }
+        }
+    }
+
+    /// Executes a single instruction and ticks all attached addons.
+    ///
+    /// Addons are still ticked while the core is sleeping, so that
+    /// peripherals can wake it back up.
     pub fn tick(&mut self) -> Result<(), Error> {
-        let (inst, pc) = self.core.tick()?;
+        if self.history_capacity == 0 {
+            return self.tick_unrecorded();
+        }
 
-        for addon in self.addons.iter_mut() {
-            let _ = addon.tick(&mut self.core, inst, pc);
+        let checkpoint = self.core.checkpoint();
+        let clock_periods = self.clock_periods;
+        let last_cycle_count = self.last_cycle_count;
+        let earlier_nanos = self.earlier_nanos;
+        let last_executed = self.last_executed;
+        let scheduled_resets = self.scheduled_resets.clone();
+
+        // Failed ticks are recorded too, since they may have changed some
+        // of the state before failing.
+        let result = self.tick_unrecorded();
+
+        if self.history.len() == self.history_capacity {
+            self.history.pop_front();
         }
+        self.history.push_back(Step {
+            delta: self.core.delta(checkpoint),
+            clock_periods,
+            last_cycle_count,
+            earlier_nanos,
+            last_executed,
+            scheduled_resets,
+        });
+        result
+    }
+
+    /// Keeps the last `capacity` ticks so that they can be undone with
+    /// `step_back`, or stops recording if it is zero.
+    ///
+    /// Each tick records the registers and memory it changed. The internal
+    /// state of addons is not recorded, so stepping back over a tick that
+    /// changed it leaves the addon as it is; their registers in data space
+    /// are restored like any other memory. Addons are told about it with
+    /// `Addon::on_rewind`.
+    pub fn set_reverse_history(&mut self, capacity: usize) {
+        self.history_capacity = capacity;
+        self.core.set_journaling(capacity > 0);
+        while self.history.len() > capacity {
+            self.history.pop_front();
+        }
+    }
+
+    /// Gets the recorded ticks that can be undone, oldest first.
+    pub fn reverse_history(&self) -> impl Iterator<Item = &Delta> {
+        self.history.iter().map(|step| &step.delta)
+    }
+
+    /// Undoes up to `count` ticks, returning how many were undone.
+    ///
+    /// This is limited by how many ticks were recorded, see
+    /// `set_reverse_history`.
+    pub fn step_back(&mut self, count: usize) -> usize {
+        let mut undone = 0;
+        while undone < count {
+            let step = match self.history.pop_back() {
+                Some(step) => step,
+                None => break,
+            };
+
+            self.core.undo(&step.delta);
+            self.clock_periods = step.clock_periods;
+            self.last_cycle_count = step.last_cycle_count;
+            self.earlier_nanos = step.earlier_nanos;
+            self.last_executed = step.last_executed;
+            self.scheduled_resets = step.scheduled_resets;
+            undone += 1;
+        }
+
+        if undone > 0 {
+            self.for_each_addon("on_rewind", |addon, core| addon.on_rewind(core));
+        }
+        self.pacing_origin = None;
+        undone
+    }
+
+    fn tick_unrecorded(&mut self) -> Result<(), Error> {
+        self.inject_due_events()?;
+        self.update_clock_periods();
+        self.apply_scheduled_resets()?;
+        self.report_events();
+
+        let span = tracing::trace_span!("tick", pc = self.core.pc, cycle = self.core.cycle_count);
+        let _entered = span.enter();
+
+        self.for_each_addon("pre_tick", |addon, core| addon.pre_tick(core));
+
+        let (inst, pc) = self.core.tick()?;
+        self.last_executed = Some((inst, pc));
+        self.report_events();
+
+        self.for_each_addon("post_tick", |addon, core| addon.post_tick(core, inst, pc));
+        // Addons like the watchdog can reset the core.
+        self.report_events();
 
         Ok(())
     }
+
+    /// Tells the addons about the resets and interrupts since the last
+    /// time, and publishes every event to the subscribers.
+    pub(crate) fn report_events(&mut self) {
+        for event in self.core.take_events() {
+            match event {
+                events::Event::Reset { cause } => {
+                    self.for_each_addon("on_reset", |addon, core| addon.on_reset(core, cause));
+                }
+                events::Event::InterruptDispatched { vector } => {
+                    self.for_each_addon("on_interrupt", |addon, core| {
+                        addon.on_interrupt(core, vector)
+                    });
+                }
+                _ => (),
+            }
+            self.publish(&event);
+        }
+        self.publish_pin_changes();
+    }
+
+    /// Publishes the pins whose levels changed since the last time.
+    fn publish_pin_changes(&mut self) {
+        if self.subscribers.is_empty() {
+            self.pin_levels.clear();
+            return;
+        }
+
+        for index in 0..self.core.io_ports.len() {
+            let port = &self.core.io_ports[index];
+            let (name, address) = (port.name, self.core.io_offset() + port.pin as u16);
+            let levels = self.core.peek_data(address).unwrap_or(0);
+
+            match self.pin_levels.get_mut(index) {
+                Some(last) => {
+                    let changed = *last ^ levels;
+                    *last = levels;
+                    for pin in (0..8).filter(|pin| changed & (1 << pin) != 0) {
+                        self.publish(&events::Event::PinChanged {
+                            port: name,
+                            pin,
+                            high: levels & (1 << pin) != 0,
+                        });
+                    }
+                }
+                // Changes are published from the first time.
+                None => self.pin_levels.push(levels),
+            }
+        }
+    }
+
+    /// Calls a hook of every enabled addon in order.
+    ///
+    /// An addon failing does not stop the emulation, but is reported to
+    /// whoever is listening.
+    fn for_each_addon<F>(&mut self, hook: &'static str, mut call: F)
+    where
+        F: FnMut(&mut dyn addons::Addon, &mut Core) -> Result<(), Error>,
+    {
+        for attached in self.addons.iter_mut().filter(|a| a.enabled) {
+            if let Err(error) = call(attached.addon.as_mut(), &mut self.core) {
+                tracing::warn!(
+                    pc = self.core.pc,
+                    cycle = self.core.cycle_count,
+                    addon = attached.id.0,
+                    hook,
+                    ?error,
+                    "addon failed"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Mcu;
+    use crate::chips::atmega328p;
+    use crate::reset::ResetCause;
+    use crate::Core;
+    use std::time::Duration;
+
+    fn setup() -> Mcu {
+        let mut core = Core::new::<atmega328p::Chip>();
+        core.load_program_space([0; 64].into_iter());
+        Mcu::new(core)
+    }
+
+    #[test]
+    fn elapsed_with_a_stopped_clock() {
+        let mut mcu = setup();
+        let run = |mcu: &mut Mcu| {
+            for _ in 0..10 {
+                mcu.tick().unwrap();
+            }
+        };
+
+        mcu.set_clock_frequency(1_000_000);
+        run(&mut mcu);
+        let before = mcu.elapsed();
+        assert!(before > Duration::ZERO);
+
+        mcu.set_clock_frequency(0);
+        run(&mut mcu);
+        assert_eq!(mcu.elapsed(), before);
+
+        mcu.set_clock_frequency(1_000_000);
+        run(&mut mcu);
+        assert_eq!(mcu.elapsed(), before * 2);
+    }
+
+    #[test]
+    fn scheduled_reset_ends_while_the_reset_pin_is_held() {
+        let mut mcu = setup();
+        mcu.schedule_reset(ResetCause::Watchdog, 0, 4);
+        mcu.assert_reset_pin().unwrap();
+
+        for _ in 0..8 {
+            mcu.tick().unwrap();
+        }
+        assert!(mcu.core.held_in_reset().is_some());
+
+        mcu.release_reset_pin();
+        assert_eq!(mcu.core.held_in_reset(), None);
+    }
 }
@@ -1,44 +1,226 @@
+use crate::state;
 use crate::Error;
 use std;
+use std::ops::Range;
 
 pub type Address = u16;
 
+/// The storage behind a memory space.
+///
+/// `Space` checks addresses against `len` before it reads or writes, so
+/// backends only see addresses in bounds. Besides a `Vec<u8>`, a backend
+/// can be a memory-mapped file, a copy-on-write snapshot or storage that
+/// only allocates the parts of a large space that are written.
+pub trait Backend {
+    /// Gets the size of the storage in bytes.
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn get(&self, addr: usize) -> u8;
+
+    fn set(&mut self, addr: usize, val: u8);
+
+    /// Copies the storage, for cloning a `Space`. By default the contents
+    /// are copied into a `Vec<u8>`.
+    fn box_clone(&self) -> Box<dyn Backend> {
+        Box::new(
+            (0..self.len())
+                .map(|addr| self.get(addr))
+                .collect::<Vec<u8>>(),
+        )
+    }
+}
+
+impl Backend for Vec<u8> {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn get(&self, addr: usize) -> u8 {
+        self[addr]
+    }
+
+    fn set(&mut self, addr: usize, val: u8) {
+        self[addr] = val;
+    }
+
+    fn box_clone(&self) -> Box<dyn Backend> {
+        Box::new(self.clone())
+    }
+}
+
+impl<B: Backend + ?Sized> Backend for Box<B> {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn get(&self, addr: usize) -> u8 {
+        (**self).get(addr)
+    }
+
+    fn set(&mut self, addr: usize, val: u8) {
+        (**self).set(addr, val)
+    }
+
+    fn box_clone(&self) -> Box<dyn Backend> {
+        (**self).box_clone()
+    }
+}
+
+impl Clone for Box<dyn Backend> {
+    fn clone(&self) -> Self {
+        self.box_clone()
+    }
+}
+
+/// What a memory space does with accesses past its end.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum OutOfRange {
+    /// Fails with `Error::SegmentationFault`, which `Core::tick` fills in
+    /// with the instructions leading up to the access.
+    #[default]
+    Fault,
+    /// Wraps the address around the size of the space, like parts that
+    /// leave the upper address bits unconnected and mirror their memory.
+    Wrap,
+    /// Reads zero and ignores writes.
+    Ignore,
+}
+
 /// A memory space.
+///
+/// The spaces of a `Core` keep their storage behind a `Box<dyn Backend>`,
+/// so any backend can replace the default `Vec<u8>`:
+///
+/// ```text
+/// let size = core.program_space().len();
+/// *core.program_space_mut() = Space::with_backend(Box::new(MappedFile::open(path, size)?));
+/// ```
 #[derive(Clone)]
-pub struct Space {
-    data: Vec<u8>,
+pub struct Space<B = Box<dyn Backend>> {
+    data: B,
+    /// The addresses written by `set_u8` and `set_u16` and their old
+    /// values, oldest first, while journaling.
+    journal: Option<Vec<(usize, u8)>>,
+    out_of_range: OutOfRange,
+    /// The ranges of addresses that cannot be accessed.
+    guards: Vec<Range<usize>>,
 }
 
 impl Space {
+    /// Creates a space of zeros in a `Vec<u8>`.
     pub fn new(size: usize) -> Self {
-        let data = std::iter::repeat(0).take(size).collect();
-        Space { data }
+        Space::with_backend(Box::new(vec![0; size]))
+    }
+}
+
+impl<B: Backend> Space<B> {
+    pub fn with_backend(data: B) -> Self {
+        Space {
+            data,
+            journal: None,
+            out_of_range: OutOfRange::Fault,
+            guards: Vec::new(),
+        }
+    }
+
+    pub fn backend(&self) -> &B {
+        &self.data
+    }
+
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.data
+    }
+
+    pub fn out_of_range(&self) -> OutOfRange {
+        self.out_of_range
+    }
+
+    /// Sets what accesses past the end of the space do, which is to fail
+    /// by default.
+    pub fn set_out_of_range(&mut self, policy: OutOfRange) {
+        self.out_of_range = policy;
+    }
+
+    /// Protects a range of addresses, so that `get_u8` and `set_u8` fail
+    /// on them with `Error::GuardViolation`, like a canary zone between
+    /// `.bss` and the stack that catches them growing into each other.
+    pub fn guard(&mut self, range: Range<usize>) {
+        self.guards.push(range);
+    }
+
+    /// Gets the protected ranges, in the order they were added.
+    pub fn guards(&self) -> &[Range<usize>] {
+        &self.guards
+    }
+
+    pub fn clear_guards(&mut self) {
+        self.guards.clear();
     }
 
     pub fn set_u8(&mut self, addr: usize, val: u8) -> Result<(), Error> {
+        self.check_guards(addr, true)?;
         if self.is_access_in_bounds(addr, 1) {
-            self.data[addr] = val;
-            Ok(())
-        } else {
-            Err(Error::SegmentationFault { address: addr + 1 })
+            self.record(addr);
+            self.data.set(addr, val);
+            return Ok(());
+        }
+        match self.out_of_range {
+            OutOfRange::Fault => Err(Error::SegmentationFault {
+                address: addr + 1,
+                history: Vec::new(),
+            }),
+            OutOfRange::Wrap if !self.is_empty() => self.set_u8(addr % self.len(), val),
+            _ => Ok(()),
         }
     }
 
     pub fn set_u16(&mut self, addr: usize, val: u16) -> Result<(), Error> {
+        self.check_guards(addr, true)?;
+        self.check_guards(addr + 1, true)?;
         if self.is_access_in_bounds(addr, 2) {
-            self.data[addr] = ((val & 0xff00) >> 8) as u8;
-            self.data[addr + 1] = (val & 0xff) as u8;
+            self.record(addr);
+            self.record(addr + 1);
+            self.data.set(addr, ((val & 0xff00) >> 8) as u8);
+            self.data.set(addr + 1, (val & 0xff) as u8);
             Ok(())
+        } else if self.out_of_range == OutOfRange::Fault {
+            Err(Error::SegmentationFault {
+                address: addr + 2,
+                history: Vec::new(),
+            })
         } else {
-            Err(Error::SegmentationFault { address: addr + 2 })
+            self.set_u8(addr, ((val & 0xff00) >> 8) as u8)?;
+            self.set_u8(addr + 1, (val & 0xff) as u8)
         }
     }
 
     pub fn get_u8(&self, addr: usize) -> Result<u8, Error> {
-        self.data
-            .get(addr)
-            .cloned()
-            .ok_or(Error::SegmentationFault { address: addr })
+        let wraps = self.out_of_range == OutOfRange::Wrap && !self.is_empty();
+        match self.is_access_in_bounds(addr, 1) || !wraps {
+            true => self.check_guards(addr, false)?,
+            false => self.check_guards(addr % self.len(), false)?,
+        }
+        self.peek_u8(addr)
+    }
+
+    /// Reads a byte even if it is protected by `guard`, for debuggers.
+    pub fn peek_u8(&self, addr: usize) -> Result<u8, Error> {
+        if self.is_access_in_bounds(addr, 1) {
+            return Ok(self.data.get(addr));
+        }
+        match self.out_of_range {
+            OutOfRange::Fault => Err(Error::SegmentationFault {
+                address: addr,
+                history: Vec::new(),
+            }),
+            OutOfRange::Wrap if !self.is_empty() => self.peek_u8(addr % self.len()),
+            _ => Ok(0),
+        }
     }
 
     pub fn get_u16(&self, addr: usize) -> Result<u16, Error> {
@@ -48,29 +230,194 @@ impl Space {
         Ok((hi << 8) | lo)
     }
 
-    pub fn bytes(&self) -> std::slice::Iter<'_, u8> {
-        self.data.iter()
+    /// Gets the size of the space in bytes.
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn bytes(&self) -> impl ExactSizeIterator<Item = u8> + '_ {
+        (0..self.data.len()).map(|addr| self.data.get(addr))
     }
 
-    pub fn bytes_mut(&mut self) -> std::slice::IterMut<'_, u8> {
-        self.data.iter_mut()
+    /// Sets every byte of the space, without journaling.
+    pub fn fill(&mut self, val: u8) {
+        for addr in 0..self.data.len() {
+            self.data.set(addr, val);
+        }
     }
 
-    pub fn load<I>(&mut self, mut bytes: I)
+    pub fn load<I>(&mut self, bytes: I)
     where
         I: Iterator<Item = u8>,
     {
-        for byte in self.data.iter_mut() {
-            if let Some(b) = bytes.next() {
-                *byte = b;
-            } else {
-                break;
-            }
+        for (addr, b) in (0..self.data.len()).zip(bytes) {
+            self.data.set(addr, b);
         }
     }
 
+    pub fn save_state(&self, state: &mut state::Writer) {
+        state.bytes(&self.bytes().collect::<Vec<_>>());
+    }
+
+    /// Restores the contents saved by `save_state`, which must be the same
+    /// size as the space.
+    pub fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        let bytes = state.bytes()?;
+        if bytes.len() != self.len() {
+            return Err(Error::InvalidState("memory size does not match the chip"));
+        }
+        self.load(bytes.iter().copied());
+        Ok(())
+    }
+
+    /// Starts or stops recording the old values of bytes written by
+    /// `set_u8` and `set_u16`, so that the writes can be undone.
+    pub fn set_journaling(&mut self, enabled: bool) {
+        self.journal = enabled.then(Vec::new);
+    }
+
+    /// Takes the writes recorded since journaling started or the journal
+    /// was last taken, as addresses and their old values.
+    pub fn take_journal(&mut self) -> Vec<(usize, u8)> {
+        self.journal
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+
+    /// Undoes the writes in a journal taken from `take_journal`.
+    pub fn undo(&mut self, journal: &[(usize, u8)]) {
+        for &(addr, old) in journal.iter().rev() {
+            self.data.set(addr, old);
+        }
+    }
+
+    fn record(&mut self, addr: usize) {
+        if let Some(journal) = &mut self.journal {
+            journal.push((addr, self.data.get(addr)));
+        }
+    }
+
+    fn check_guards(&self, addr: usize, write: bool) -> Result<(), Error> {
+        if self.guards.iter().any(|guard| guard.contains(&addr)) {
+            return Err(Error::GuardViolation {
+                address: addr,
+                write,
+                pc: None,
+                history: Vec::new(),
+            });
+        }
+        Ok(())
+    }
+
     fn is_access_in_bounds(&self, addr: usize, byte_count: usize) -> bool {
         let end_byte_offset = addr + byte_count;
         end_byte_offset <= self.data.len()
     }
 }
+
+/// Spaces are serialized as their contents, and deserialized into a
+/// `Vec<u8>` without guards that faults on accesses out of range.
+#[cfg(feature = "serde")]
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename = "Space")]
+struct Contents {
+    data: Vec<u8>,
+}
+
+#[cfg(feature = "serde")]
+impl<B: Backend> serde::Serialize for Space<B> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let data = self.bytes().collect();
+        Contents { data }.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Space {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let Contents { data } = Contents::deserialize(deserializer)?;
+        Ok(Space::with_backend(Box::new(data)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Backend, Space};
+    use crate::chips::atmega328p;
+    use crate::inst::asm;
+    use crate::{Core, Error};
+    use std::collections::BTreeMap;
+
+    /// A backend that only stores the bytes which were written.
+    struct Sparse {
+        len: usize,
+        written: BTreeMap<usize, u8>,
+    }
+
+    impl Sparse {
+        fn new(len: usize) -> Self {
+            Sparse {
+                len,
+                written: BTreeMap::new(),
+            }
+        }
+    }
+
+    impl Backend for Sparse {
+        fn len(&self) -> usize {
+            self.len
+        }
+
+        fn get(&self, addr: usize) -> u8 {
+            self.written.get(&addr).copied().unwrap_or(0xff)
+        }
+
+        fn set(&mut self, addr: usize, val: u8) {
+            self.written.insert(addr, val);
+        }
+    }
+
+    #[test]
+    fn custom_backend() {
+        let mut space = Space::with_backend(Sparse::new(0x10000));
+        assert_eq!(space.get_u16(0x8000).unwrap(), 0xffff);
+
+        space.set_journaling(true);
+        space.set_u16(0x8000, 0x1234).unwrap();
+        assert_eq!(space.get_u16(0x8000).unwrap(), 0x1234);
+        assert_eq!(space.backend().written.len(), 2);
+
+        let journal = space.take_journal();
+        space.undo(&journal);
+        assert_eq!(space.get_u16(0x8000).unwrap(), 0xffff);
+
+        // Accesses past the end never reach the backend.
+        assert!(matches!(
+            space.set_u8(0x10000, 0),
+            Err(Error::SegmentationFault { .. })
+        ));
+    }
+
+    #[test]
+    fn custom_backend_in_a_core() {
+        let mut core = Core::new::<atmega328p::Chip>();
+        let size = core.program_space().len();
+        *core.program_space_mut() = Space::with_backend(Box::new(Sparse::new(size)));
+        core.load_program_space(asm::assemble("ldi r16, 42").unwrap().into_iter());
+
+        core.tick().unwrap();
+        assert_eq!(core.register_file().gpr(16).unwrap(), 42);
+
+        // Cloning copies the contents into a `Vec<u8>`, which is independent
+        // of the original.
+        let mut copy = core.program_space().clone();
+        copy.set_u8(0, 0).unwrap();
+        assert_eq!(copy.get_u8(2).unwrap(), 0xff);
+        assert_ne!(core.program_space().get_u8(0).unwrap(), 0);
+    }
+}
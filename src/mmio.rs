@@ -0,0 +1,42 @@
+//! Memory-mapped IO, letting peripherals see the reads and writes
+//! instructions make to their registers instead of polling data space.
+
+use crate::mem;
+use crate::Error;
+use std::cell::RefCell;
+use std::ops::Range;
+use std::rc::Rc;
+
+/// Handles the accesses instructions make to a range of data space
+/// addresses mapped with `Core::map_io`.
+///
+/// Only loads and stores by instructions like `IN`, `OUT`, `LDS`, `STS`,
+/// `LD` and `ST` are handled. Reads and writes of addons and the host, and
+/// the pushes and pops of the stack, go straight to data space.
+pub trait Handler {
+    /// Called when an instruction reads `address` at `cycle`, with the
+    /// byte stored there. Returns the byte the instruction reads.
+    fn read(&mut self, _cycle: u64, _address: mem::Address, stored: u8) -> Result<u8, Error> {
+        Ok(stored)
+    }
+
+    /// Called when an instruction writes `value` to `address` at `cycle`.
+    /// Returns the byte to store there, which later reads see.
+    fn write(&mut self, _cycle: u64, _address: mem::Address, value: u8) -> Result<u8, Error> {
+        Ok(value)
+    }
+}
+
+/// A range of data space addresses and the handler they are mapped to.
+#[derive(Clone)]
+pub struct Mapping {
+    pub range: Range<mem::Address>,
+    pub handler: Rc<RefCell<dyn Handler>>,
+}
+
+impl Mapping {
+    /// Checks if the mapping shares an address with a range.
+    pub fn overlaps(&self, range: &Range<mem::Address>) -> bool {
+        self.range.start < range.end && range.start < self.range.end
+    }
+}
@@ -0,0 +1,131 @@
+//! Handles on single GPIO pins, for connecting LEDs, buttons and probes.
+
+use crate::events::Event;
+use crate::mcu::SubscriptionId;
+use crate::replay::Stimulus;
+use crate::{Error, Mcu};
+
+/// A GPIO pin of an `Mcu`, like `PB5`, from `Mcu::pin`.
+pub struct Pin<'a> {
+    mcu: &'a mut Mcu,
+    port: char,
+    bit: u8,
+    /// The data space addresses of `PINx`, `DDRx` and `PORTx`.
+    addresses: [u16; 3],
+}
+
+impl<'a> Pin<'a> {
+    /// Gets a pin by its name, like `PB5`.
+    pub(crate) fn new(mcu: &'a mut Mcu, name: &str) -> Result<Self, Error> {
+        let invalid = || Error::InvalidPinName(name.to_owned());
+        let mut chars = name.chars();
+        if chars.next() != Some('P') {
+            return Err(invalid());
+        }
+        let port = chars.next().ok_or_else(invalid)?;
+        let bit = chars.as_str().parse::<u8>().map_err(|_| invalid())?;
+
+        let offset = mcu.core.io_offset();
+        let addresses = mcu
+            .core
+            .io_ports
+            .iter()
+            .find(|p| p.name == port)
+            .filter(|_| bit < 8)
+            .map(|p| [p.pin, p.ddr, p.port].map(|a| offset + a as u16))
+            .ok_or(Error::PinDoesNotExist { port, pin: bit })?;
+
+        Ok(Pin {
+            mcu,
+            port,
+            bit,
+            addresses,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        format!("P{}{}", self.port, self.bit)
+    }
+
+    fn bit_of(&self, register: usize) -> bool {
+        let value = self
+            .mcu
+            .core
+            .peek_data(self.addresses[register])
+            .unwrap_or(0);
+        value & (1 << self.bit) != 0
+    }
+
+    /// Gets the level of the pin, as the firmware reads it from `PINx`.
+    pub fn is_high(&self) -> bool {
+        self.bit_of(0)
+    }
+
+    /// Checks if `DDRx` makes the pin an output.
+    pub fn is_output(&self) -> bool {
+        self.bit_of(1)
+    }
+
+    /// Gets the level the chip drives the pin to, or `None` if it is an
+    /// input.
+    pub fn output(&self) -> Option<bool> {
+        self.is_output().then(|| self.is_high())
+    }
+
+    /// Checks if the pin is an input with its pull-up resistor enabled.
+    pub fn has_pull_up(&self) -> bool {
+        !self.is_output() && self.bit_of(2)
+    }
+
+    /// Gets the level the pin is driven to from the outside, or `None` if
+    /// it is not.
+    pub fn driven(&self) -> Option<bool> {
+        let port = self.mcu.core.io_ports.iter().find(|p| p.name == self.port);
+        port.and_then(|p| p.driven(self.bit))
+    }
+
+    /// Checks if nothing drives the pin or pulls it up, so that its level
+    /// is undefined. It reads low.
+    pub fn is_floating(&self) -> bool {
+        !self.is_output() && !self.has_pull_up() && self.driven().is_none()
+    }
+
+    /// Drives the pin high or low from the outside.
+    pub fn drive(&mut self, high: bool) -> Result<(), Error> {
+        self.mcu.set_pin_input(self.port, self.bit, high)
+    }
+
+    /// Stops driving the pin from the outside, leaving it tri-stated.
+    pub fn release(&mut self) -> Result<(), Error> {
+        self.mcu.float_pin(self.port, self.bit)
+    }
+
+    /// Gets the stimulus of driving the pin from the outside, to inject
+    /// or schedule it on the `Mcu`.
+    pub fn stimulus(&self, high: bool) -> Stimulus {
+        Stimulus::Pin {
+            pin_register: (self.addresses[0] - self.mcu.core.io_offset()) as u8,
+            bit: self.bit,
+            high,
+        }
+    }
+
+    /// Calls a function with the new level and the cycle count whenever
+    /// the level of the pin changes, until `Mcu::unsubscribe` is called
+    /// with the id this returns.
+    ///
+    /// Changes are seen after each tick, so they are timed to the end of
+    /// the instruction that caused them.
+    pub fn on_change<F>(&mut self, mut callback: F) -> SubscriptionId
+    where
+        F: FnMut(bool, u64) + 'static,
+    {
+        let (port, bit) = (self.port, self.bit);
+        self.mcu.subscribe(move |core, event| match *event {
+            Event::PinChanged { port: p, pin, high } if p == port && pin == bit => {
+                callback(high, core.cycle_count)
+            }
+            _ => (),
+        })
+    }
+}
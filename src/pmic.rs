@@ -0,0 +1,72 @@
+//! The programmable multilevel interrupt controller (PMIC) of XMEGA chips.
+//!
+//! Interrupts are serviced by their level, see `interrupt::Level`. The `I`
+//! flag is left set when an interrupt is serviced. Instead `STATUS` records
+//! the levels whose handlers are executing, and an interrupt is only
+//! serviced while no handler of its level or a higher one is. `RETI`
+//! clears the highest level in `STATUS`.
+//!
+//! Round-robin scheduling (`RREN`) and moving the vectors to the boot
+//! section (`IVSEL`) are not simulated.
+
+use crate::interrupt::Level;
+
+/// The data space address of `PMIC.STATUS`.
+pub const STATUS_ADDR: u16 = 0xa0;
+/// The data space address of `PMIC.INTPRI`.
+pub const INTPRI_ADDR: u16 = 0xa1;
+/// The data space address of `PMIC.CTRL`.
+pub const CTRL_ADDR: u16 = 0xa2;
+
+/// `PMIC.STATUS` bits.
+pub mod status {
+    /// A non-maskable interrupt handler is executing.
+    pub const NMIEX: u8 = 1 << 7;
+    pub const HILVLEX: u8 = 1 << 2;
+    pub const MEDLVLEX: u8 = 1 << 1;
+    pub const LOLVLEX: u8 = 1 << 0;
+}
+
+/// `PMIC.CTRL` bits.
+pub mod ctrl {
+    /// Round-robin scheduling of low level interrupts.
+    pub const RREN: u8 = 1 << 7;
+    /// Interrupt vector select.
+    pub const IVSEL: u8 = 1 << 6;
+    pub const HILVLEN: u8 = 1 << 2;
+    pub const MEDLVLEN: u8 = 1 << 1;
+    pub const LOLVLEN: u8 = 1 << 0;
+}
+
+/// Gets the `STATUS` flag recording that a handler of a level is
+/// executing.
+pub fn executing_flag(level: Level) -> u8 {
+    match level {
+        Level::Low => status::LOLVLEX,
+        Level::Medium => status::MEDLVLEX,
+        Level::High => status::HILVLEX,
+        Level::NonMaskable => status::NMIEX,
+    }
+}
+
+/// Checks if an interrupt of a level can be serviced, given `STATUS`,
+/// `CTRL` and whether the `I` flag is set.
+pub fn accepts(level: Level, status: u8, ctrl: u8, interrupts_enabled: bool) -> bool {
+    // The flags of this level and every level above it.
+    let blocking = status & !(executing_flag(level) - 1);
+    let enabled = match level {
+        Level::Low => interrupts_enabled && ctrl & ctrl::LOLVLEN != 0,
+        Level::Medium => interrupts_enabled && ctrl & ctrl::MEDLVLEN != 0,
+        Level::High => interrupts_enabled && ctrl & ctrl::HILVLEN != 0,
+        Level::NonMaskable => true,
+    };
+    enabled && blocking == 0
+}
+
+/// Gets `STATUS` after `RETI`, which clears the highest level executing.
+pub fn after_reti(status: u8) -> u8 {
+    match status {
+        0 => 0,
+        _ => status & !(1 << (7 - status.leading_zeros())),
+    }
+}
@@ -0,0 +1,428 @@
+//! A builder for constructing programs in Rust.
+
+use crate::inst::{Gpr, GprPair, Variant};
+use crate::{Error, Instruction};
+use std::collections::HashMap;
+
+/// A jump, call or branch to a label, resolved when the program is built.
+#[derive(Copy, Clone, Debug)]
+enum Target {
+    /// An absolute byte address, for `JMP` and `CALL`.
+    Absolute(fn(u32) -> Instruction),
+    /// A 12-bit word offset, for `RJMP` and `RCALL`.
+    Relative12(fn(i16) -> Instruction),
+    /// A 7-bit word offset, for conditional branches.
+    Relative7(fn(i8) -> Instruction),
+}
+
+#[derive(Clone, Debug)]
+enum Item {
+    Instruction(Instruction),
+    Jump(Target, String),
+    Data(Vec<u8>),
+}
+
+/// A program built from instructions and labels.
+///
+/// ```text
+/// let bytes = Program::new()
+///     .ldi(16, 5)
+///     .label("loop")
+///     .dec(16)
+///     .brne("loop")
+///     .ret()
+///     .build()?;
+/// ```
+///
+/// Jumps, calls and branches take the name of a label, which may be defined
+/// before or after them. The bytes can be loaded with
+/// `Core::load_program_space`.
+#[derive(Clone, Debug, Default)]
+pub struct Program {
+    items: Vec<(u32, Item)>,
+    labels: HashMap<String, u32>,
+    /// The byte address of the next item.
+    address: u32,
+    /// The first label that was defined twice.
+    duplicate_label: Option<String>,
+}
+
+impl Program {
+    pub fn new() -> Self {
+        Program::default()
+    }
+
+    /// Gets the byte address the next instruction will be placed at.
+    pub fn address(&self) -> u32 {
+        self.address
+    }
+
+    /// Gets the byte address of a label defined so far.
+    pub fn label_address(&self, name: &str) -> Option<u32> {
+        self.labels.get(name).copied()
+    }
+
+    /// Defines a label at the current address.
+    pub fn label(mut self, name: &str) -> Self {
+        let previous = self.labels.insert(name.to_owned(), self.address);
+        if previous.is_some() && self.duplicate_label.is_none() {
+            self.duplicate_label = Some(name.to_owned());
+        }
+        self
+    }
+
+    /// Appends an instruction.
+    pub fn instruction(self, instruction: Instruction) -> Self {
+        let size = instruction.size() as u32;
+        self.append(Item::Instruction(instruction), size)
+    }
+
+    /// Appends raw bytes, such as a string for `lpm` to read.
+    ///
+    /// An odd number of bytes is padded with a zero, to keep instructions
+    /// word aligned.
+    pub fn data(self, bytes: &[u8]) -> Self {
+        let mut bytes = bytes.to_vec();
+        if !bytes.len().is_multiple_of(2) {
+            bytes.push(0);
+        }
+        let size = bytes.len() as u32;
+        self.append(Item::Data(bytes), size)
+    }
+
+    /// Resolves labels and encodes the program, to be loaded at address
+    /// zero.
+    pub fn build(&self) -> Result<Vec<u8>, Error> {
+        if let Some(ref name) = self.duplicate_label {
+            return Err(Error::DuplicateLabel(name.clone()));
+        }
+
+        let mut bytes = Vec::with_capacity(self.address as usize);
+        for (address, item) in &self.items {
+            let instruction = match *item {
+                Item::Instruction(instruction) => instruction,
+                Item::Jump(target, ref label) => self.resolve(*address, target, label)?,
+                Item::Data(ref data) => {
+                    bytes.extend_from_slice(data);
+                    continue;
+                }
+            };
+            bytes.extend(instruction.encode()?);
+        }
+        Ok(bytes)
+    }
+
+    fn resolve(&self, address: u32, target: Target, label: &str) -> Result<Instruction, Error> {
+        let destination = self
+            .label_address(label)
+            .ok_or_else(|| Error::UnknownSymbol(label.to_owned()))?;
+
+        let size = match target {
+            Target::Absolute(_) => 4,
+            _ => 2,
+        };
+        let offset = destination as i64 - (address + size) as i64;
+        let out_of_range = || Error::BranchOutOfRange {
+            label: label.to_owned(),
+            offset,
+        };
+
+        Ok(match target {
+            Target::Absolute(f) => f(destination),
+            Target::Relative12(f) if (-4096..=4094).contains(&offset) => f(offset as i16),
+            Target::Relative7(f) if (-128..=126).contains(&offset) => f(offset as i8),
+            Target::Relative12(_) | Target::Relative7(_) => return Err(out_of_range()),
+        })
+    }
+
+    fn append(mut self, item: Item, size: u32) -> Self {
+        self.items.push((self.address, item));
+        self.address += size;
+        self
+    }
+
+    fn jump(self, target: Target, label: &str) -> Self {
+        let size = match target {
+            Target::Absolute(_) => 4,
+            _ => 2,
+        };
+        self.append(Item::Jump(target, label.to_owned()), size)
+    }
+
+    fn branch(self, f: fn(i8) -> Instruction, label: &str) -> Self {
+        self.jump(Target::Relative7(f), label)
+    }
+}
+
+/// One method per instruction, taking labels in place of addresses.
+impl Program {
+    pub fn inc(self, d: Gpr) -> Self {
+        self.instruction(Instruction::Inc(d))
+    }
+    pub fn dec(self, d: Gpr) -> Self {
+        self.instruction(Instruction::Dec(d))
+    }
+    pub fn com(self, d: Gpr) -> Self {
+        self.instruction(Instruction::Com(d))
+    }
+    pub fn neg(self, d: Gpr) -> Self {
+        self.instruction(Instruction::Neg(d))
+    }
+    pub fn push(self, r: Gpr) -> Self {
+        self.instruction(Instruction::Push(r))
+    }
+    pub fn pop(self, d: Gpr) -> Self {
+        self.instruction(Instruction::Pop(d))
+    }
+    pub fn swap(self, d: Gpr) -> Self {
+        self.instruction(Instruction::Swap(d))
+    }
+    /// Clears a register, with `EOR d, d`.
+    pub fn clr(self, d: Gpr) -> Self {
+        self.instruction(Instruction::Eor(d, d))
+    }
+
+    pub fn subi(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Subi(d, k))
+    }
+    pub fn sbci(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Sbci(d, k))
+    }
+    pub fn andi(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Andi(d, k))
+    }
+    pub fn ori(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Ori(d, k))
+    }
+    pub fn cpi(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Cpi(d, k))
+    }
+    pub fn ldi(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Ldi(d, k))
+    }
+
+    pub fn add(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Add(d, r))
+    }
+    pub fn adc(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Adc(d, r))
+    }
+    pub fn adiw(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Adiw(d, k))
+    }
+    pub fn sub(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Sub(d, r))
+    }
+    pub fn sbc(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Sbc(d, r))
+    }
+    pub fn sbiw(self, d: Gpr, k: u8) -> Self {
+        self.instruction(Instruction::Sbiw(d, k))
+    }
+    pub fn mul(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Mul(d, r))
+    }
+    pub fn and(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::And(d, r))
+    }
+    pub fn or(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Or(d, r))
+    }
+    pub fn eor(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Eor(d, r))
+    }
+    pub fn cpse(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Cpse(d, r))
+    }
+    pub fn cp(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Cp(d, r))
+    }
+    pub fn cpc(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Cpc(d, r))
+    }
+    pub fn mov(self, d: Gpr, r: Gpr) -> Self {
+        self.instruction(Instruction::Mov(d, r))
+    }
+    pub fn movw(self, d: GprPair, r: GprPair) -> Self {
+        self.instruction(Instruction::Movw(d, r))
+    }
+
+    pub fn r#in(self, d: Gpr, a: u8) -> Self {
+        self.instruction(Instruction::In(d, a))
+    }
+    pub fn out(self, a: u8, r: Gpr) -> Self {
+        self.instruction(Instruction::Out(a, r))
+    }
+    pub fn sbi(self, a: u8, b: u8) -> Self {
+        self.instruction(Instruction::Sbi(a, b))
+    }
+    pub fn sbis(self, a: u8, b: u8) -> Self {
+        self.instruction(Instruction::Sbis(a, b))
+    }
+    pub fn cbi(self, a: u8, b: u8) -> Self {
+        self.instruction(Instruction::Cbi(a, b))
+    }
+    pub fn sbrs(self, r: Gpr, b: u8) -> Self {
+        self.instruction(Instruction::Sbrs(r, b))
+    }
+
+    pub fn jmp(self, label: &str) -> Self {
+        self.jump(Target::Absolute(Instruction::Jmp), label)
+    }
+    pub fn call(self, label: &str) -> Self {
+        self.jump(Target::Absolute(Instruction::Call), label)
+    }
+    pub fn rjmp(self, label: &str) -> Self {
+        self.jump(Target::Relative12(Instruction::Rjmp), label)
+    }
+    pub fn rcall(self, label: &str) -> Self {
+        self.jump(Target::Relative12(Instruction::Rcall), label)
+    }
+
+    pub fn breq(self, label: &str) -> Self {
+        self.branch(Instruction::Breq, label)
+    }
+    pub fn brne(self, label: &str) -> Self {
+        self.branch(Instruction::Brne, label)
+    }
+    pub fn brcs(self, label: &str) -> Self {
+        self.branch(Instruction::Brcs, label)
+    }
+    pub fn brcc(self, label: &str) -> Self {
+        self.branch(Instruction::Brcc, label)
+    }
+    pub fn brsh(self, label: &str) -> Self {
+        self.branch(Instruction::Brsh, label)
+    }
+    pub fn brlo(self, label: &str) -> Self {
+        self.branch(Instruction::Brlo, label)
+    }
+    pub fn brmi(self, label: &str) -> Self {
+        self.branch(Instruction::Brmi, label)
+    }
+    pub fn brpl(self, label: &str) -> Self {
+        self.branch(Instruction::Brpl, label)
+    }
+    pub fn brge(self, label: &str) -> Self {
+        self.branch(Instruction::Brge, label)
+    }
+    pub fn brlt(self, label: &str) -> Self {
+        self.branch(Instruction::Brlt, label)
+    }
+    pub fn brhs(self, label: &str) -> Self {
+        self.branch(Instruction::Brhs, label)
+    }
+    pub fn brhc(self, label: &str) -> Self {
+        self.branch(Instruction::Brhc, label)
+    }
+    pub fn brts(self, label: &str) -> Self {
+        self.branch(Instruction::Brts, label)
+    }
+    pub fn brtc(self, label: &str) -> Self {
+        self.branch(Instruction::Brtc, label)
+    }
+    pub fn brvs(self, label: &str) -> Self {
+        self.branch(Instruction::Brvs, label)
+    }
+    pub fn brvc(self, label: &str) -> Self {
+        self.branch(Instruction::Brvc, label)
+    }
+    pub fn brie(self, label: &str) -> Self {
+        self.branch(Instruction::Brie, label)
+    }
+    pub fn brid(self, label: &str) -> Self {
+        self.branch(Instruction::Brid, label)
+    }
+
+    pub fn st(self, pointer: GprPair, r: Gpr, variant: Variant) -> Self {
+        self.instruction(Instruction::St(pointer, r, variant))
+    }
+    pub fn ld(self, d: Gpr, pointer: GprPair, variant: Variant) -> Self {
+        self.instruction(Instruction::Ld(d, pointer, variant))
+    }
+    pub fn std(self, pointer: GprPair, q: u8, r: Gpr) -> Self {
+        self.instruction(Instruction::Std(pointer, q, r))
+    }
+    pub fn ldd(self, d: Gpr, pointer: GprPair, q: u8) -> Self {
+        self.instruction(Instruction::Ldd(d, pointer, q))
+    }
+    pub fn xch(self, r: Gpr) -> Self {
+        self.instruction(Instruction::Xch(30, r))
+    }
+    pub fn las(self, r: Gpr) -> Self {
+        self.instruction(Instruction::Las(30, r))
+    }
+    pub fn lac(self, r: Gpr) -> Self {
+        self.instruction(Instruction::Lac(30, r))
+    }
+    pub fn lat(self, r: Gpr) -> Self {
+        self.instruction(Instruction::Lat(30, r))
+    }
+    pub fn sts(self, k: u16, r: Gpr) -> Self {
+        self.instruction(Instruction::Sts(r, k))
+    }
+    pub fn lds(self, d: Gpr, k: u16) -> Self {
+        self.instruction(Instruction::Lds(d, k))
+    }
+    /// Stores to `0x40..=0xbf` with the one-word `STS` of reduced cores.
+    pub fn sts16(self, k: u16, r: Gpr) -> Self {
+        self.instruction(Instruction::Sts16(r, k))
+    }
+    /// Loads from `0x40..=0xbf` with the one-word `LDS` of reduced cores.
+    pub fn lds16(self, d: Gpr, k: u16) -> Self {
+        self.instruction(Instruction::Lds16(d, k))
+    }
+    /// Loads from program memory at `Z`, optionally incrementing `Z`.
+    pub fn lpm(self, d: Gpr, postincrement: bool) -> Self {
+        self.instruction(Instruction::Lpm(d, 30, postincrement))
+    }
+    /// Loads from program memory at `RAMPZ:Z`, optionally incrementing
+    /// `RAMPZ:Z`.
+    pub fn elpm(self, d: Gpr, postincrement: bool) -> Self {
+        self.instruction(Instruction::Elpm(d, 30, postincrement))
+    }
+    pub fn spm(self, postincrement: bool) -> Self {
+        self.instruction(Instruction::Spm(postincrement))
+    }
+
+    pub fn ijmp(self) -> Self {
+        self.instruction(Instruction::Ijmp)
+    }
+    pub fn icall(self) -> Self {
+        self.instruction(Instruction::Icall)
+    }
+    pub fn eijmp(self) -> Self {
+        self.instruction(Instruction::Eijmp)
+    }
+    pub fn eicall(self) -> Self {
+        self.instruction(Instruction::Eicall)
+    }
+
+    pub fn nop(self) -> Self {
+        self.instruction(Instruction::Nop)
+    }
+    pub fn ret(self) -> Self {
+        self.instruction(Instruction::Ret)
+    }
+    pub fn reti(self) -> Self {
+        self.instruction(Instruction::Reti)
+    }
+    pub fn sei(self) -> Self {
+        self.instruction(Instruction::Sei)
+    }
+    pub fn cli(self) -> Self {
+        self.instruction(Instruction::Cli)
+    }
+    pub fn sleep(self) -> Self {
+        self.instruction(Instruction::Sleep)
+    }
+    pub fn brk(self) -> Self {
+        self.instruction(Instruction::Break)
+    }
+    pub fn wdr(self) -> Self {
+        self.instruction(Instruction::Wdr)
+    }
+    pub fn des(self, k: u8) -> Self {
+        self.instruction(Instruction::Des(k))
+    }
+}
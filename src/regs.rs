@@ -1,3 +1,4 @@
+use crate::state;
 use crate::{Error, SReg};
 
 // TODO: s/addr/num
@@ -8,6 +9,7 @@ pub const SP_LO_NUM: u8 = 32;
 pub const SP_HI_NUM: u8 = 33;
 
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Register {
     pub name: String,
     pub value: u8,
@@ -15,6 +17,7 @@ pub struct Register {
 
 /// The register file.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct RegisterFile {
     registers: Vec<Register>,
     pub sreg: SReg,
@@ -28,7 +31,7 @@ impl RegisterFile {
         }
     }
 
-    pub fn registers(&self) -> ::std::slice::Iter<Register> {
+    pub fn registers(&self) -> ::std::slice::Iter<'_, Register> {
         self.registers.iter()
     }
 
@@ -49,7 +52,7 @@ impl RegisterFile {
     }
 
     pub fn gpr_pair(&self, addr: u8) -> Result<(u8, u8), Error> {
-        if addr % 2 != 0 {
+        if !addr.is_multiple_of(2) {
             return Err(Error::RegisterPairOdd(addr));
         }
 
@@ -73,16 +76,38 @@ impl RegisterFile {
         *self.gpr_mut(low + 1).unwrap() = val_hi;
     }
 
+    pub fn save_state(&self, state: &mut state::Writer) {
+        let values: Vec<u8> = self.registers.iter().map(|r| r.value).collect();
+        state.bytes(&values);
+        state.u8(self.sreg.0.value);
+    }
+
+    /// Restores the values saved by `save_state`, which must be for the
+    /// same number of registers.
+    pub fn load_state(&mut self, state: &mut state::Reader) -> Result<(), Error> {
+        let values = state.bytes()?;
+        if values.len() != self.registers.len() {
+            return Err(Error::InvalidState(
+                "register count does not match the chip",
+            ));
+        }
+        for (register, &value) in self.registers.iter_mut().zip(values) {
+            register.value = value;
+        }
+        self.sreg.0.value = state.u8()?;
+        Ok(())
+    }
+
     /// Checks if a flag is set in SREG.
     pub fn sreg_flag(&self, mask: u8) -> bool {
         (self.sreg.0.value & mask) == mask
     }
 
     pub fn sreg_flag_set(&mut self, mask: u8) {
-        self.sreg.0.value |= mask;
+        self.sreg.set(mask, true);
     }
 
     pub fn sreg_flag_clear(&mut self, mask: u8) {
-        self.sreg.0.value &= !mask;
+        self.sreg.set(mask, false);
     }
 }
@@ -0,0 +1,211 @@
+//! Journals of the stimuli injected into a simulation from the outside, so
+//! that a run can be replayed exactly, see `Mcu::inject` and `Mcu::replay`.
+//!
+//! Journals are saved as text, one event per line, starting with the cycle
+//! the event happened at:
+//!
+//! ```text
+//! 1200 uart 0 48656c6c6f     bytes received by the first registered UART
+//! 1500 pin 0x03 2 1          PINB bit 2 driven high
+//! 2000 adc 0 5 2.5           2.5 V on channel 5 of the first registered ADC
+//! 2400 interrupt 1           interrupt vector 1 raised
+//! ```
+//!
+//! Empty lines and lines starting with `#` are ignored.
+
+use crate::Error;
+use std::fmt;
+use std::path::Path;
+
+/// Something the outside world does to the simulation.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Stimulus {
+    /// Bytes sent to the firmware through a UART registered with
+    /// `Mcu::register_uart`.
+    UartRx { uart: usize, bytes: Vec<u8> },
+    /// A pin driven from the outside, see `Core::drive_pin`.
+    Pin {
+        pin_register: u8,
+        bit: u8,
+        high: bool,
+    },
+    /// A voltage on a channel of an ADC registered with `Mcu::register_adc`.
+    AdcVoltage { adc: usize, channel: u8, volts: f64 },
+    /// An interrupt vector raised, see `Core::raise_interrupt`.
+    Interrupt(u8),
+}
+
+/// A stimulus and the cycle it happened at.
+#[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Event {
+    pub cycle: u64,
+    pub stimulus: Stimulus,
+}
+
+/// The stimuli of a run, in the order they happened.
+#[derive(Clone, Debug, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Journal {
+    events: Vec<Event>,
+}
+
+impl Journal {
+    pub fn new() -> Self {
+        Journal::default()
+    }
+
+    /// Parses a journal in the text format.
+    pub fn parse(text: &str) -> Result<Self, Error> {
+        let mut journal = Journal::new();
+        for (i, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let event = parse_event(line).map_err(|message| Error::InvalidJournal {
+                line: i + 1,
+                message,
+            })?;
+            journal.push(event);
+        }
+        Ok(journal)
+    }
+
+    /// Reads a journal from a file in the text format.
+    pub fn load<P>(path: P) -> Result<Self, Error>
+    where
+        P: AsRef<Path>,
+    {
+        let text = std::fs::read_to_string(path).map_err(Error::Io)?;
+        Journal::parse(&text)
+    }
+
+    /// Writes the journal to a file in the text format.
+    pub fn save<P>(&self, path: P) -> Result<(), Error>
+    where
+        P: AsRef<Path>,
+    {
+        std::fs::write(path, self.to_string()).map_err(Error::Io)
+    }
+
+    /// Adds an event, which must not be earlier than the last one.
+    pub fn push(&mut self, event: Event) {
+        debug_assert!(self.events.last().is_none_or(|e| e.cycle <= event.cycle));
+        self.events.push(event);
+    }
+
+    pub fn events(&self) -> &[Event] {
+        &self.events
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
+
+impl fmt::Display for Stimulus {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stimulus::UartRx { uart, bytes } => {
+                write!(fmt, "uart {} ", uart)?;
+                for byte in bytes {
+                    write!(fmt, "{:02x}", byte)?;
+                }
+                Ok(())
+            }
+            Stimulus::Pin {
+                pin_register,
+                bit,
+                high,
+            } => write!(fmt, "pin {:#04x} {} {}", pin_register, bit, *high as u8),
+            Stimulus::AdcVoltage {
+                adc,
+                channel,
+                volts,
+            } => write!(fmt, "adc {} {} {}", adc, channel, volts),
+            Stimulus::Interrupt(number) => write!(fmt, "interrupt {}", number),
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        write!(fmt, "{} {}", self.cycle, self.stimulus)
+    }
+}
+
+impl fmt::Display for Journal {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(fmt, "{}", event)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_event(line: &str) -> Result<Event, String> {
+    let mut fields = line.split_whitespace();
+    let mut next = |what: &str| fields.next().ok_or(format!("expected {}", what));
+
+    let cycle = parse_number(next("a cycle")?)?;
+    let stimulus = match next("a stimulus")? {
+        "uart" => Stimulus::UartRx {
+            uart: parse_number(next("a UART")?)? as usize,
+            bytes: parse_hex(next("bytes")?)?,
+        },
+        "pin" => Stimulus::Pin {
+            pin_register: parse_byte(next("a register")?)?,
+            bit: parse_byte(next("a bit")?)?,
+            high: match next("a level")? {
+                "0" => false,
+                "1" => true,
+                level => return Err(format!("invalid level '{}'", level)),
+            },
+        },
+        "adc" => Stimulus::AdcVoltage {
+            adc: parse_number(next("an ADC")?)? as usize,
+            channel: parse_byte(next("a channel")?)?,
+            volts: next("a voltage")?
+                .parse()
+                .map_err(|_| "invalid voltage".to_owned())?,
+        },
+        "interrupt" => Stimulus::Interrupt(parse_byte(next("a vector")?)?),
+        other => return Err(format!("unknown stimulus '{}'", other)),
+    };
+
+    if let Some(rest) = fields.next() {
+        return Err(format!("unexpected '{}'", rest));
+    }
+    Ok(Event { cycle, stimulus })
+}
+
+fn parse_number(token: &str) -> Result<u64, String> {
+    let result = match token.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => token.parse(),
+    };
+    result.map_err(|_| format!("invalid number '{}'", token))
+}
+
+fn parse_byte(token: &str) -> Result<u8, String> {
+    u8::try_from(parse_number(token)?).map_err(|_| format!("'{}' is out of range", token))
+}
+
+fn parse_hex(token: &str) -> Result<Vec<u8>, String> {
+    if !token.len().is_multiple_of(2) || !token.is_ascii() {
+        return Err(format!("invalid bytes '{}'", token));
+    }
+    (0..token.len())
+        .step_by(2)
+        .map(|i| {
+            u8::from_str_radix(&token[i..i + 2], 16)
+                .map_err(|_| format!("invalid bytes '{}'", token))
+        })
+        .collect()
+}
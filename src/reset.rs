@@ -0,0 +1,99 @@
+//! Reset sources and the `MCUSR` register that records them.
+
+use crate::chips::Family;
+
+/// The IO address of the `MCUSR` register.
+pub const MCUSR_ADDR: u8 = 0x34;
+
+/// Power-on reset flag.
+pub const PORF: u8 = 1 << 0;
+/// External reset flag.
+pub const EXTRF: u8 = 1 << 1;
+/// Brown-out reset flag.
+pub const BORF: u8 = 1 << 2;
+/// Watchdog system reset flag.
+pub const WDRF: u8 = 1 << 3;
+
+/// The data space address of `RSTCTRL.RSTFR`, which takes the place of
+/// `MCUSR` on AVRxt chips.
+pub const RSTFR_ADDR: u16 = 0x40;
+
+/// The IO address of `RSTFLR`, which takes the place of `MCUSR` on
+/// reduced (AVRrc) chips. Its flags are the same.
+pub const RSTFLR_ADDR: u8 = 0x3b;
+
+/// The data space address of `RST.STATUS`, which takes the place of
+/// `MCUSR` on XMEGA chips. Its power-on, external, brown-out and watchdog
+/// flags are those of `MCUSR`.
+pub const RST_STATUS_ADDR: u16 = 0x78;
+
+/// `RSTCTRL.RSTFR` bits.
+pub mod rstfr {
+    pub const PORF: u8 = 1 << 0;
+    pub const BORF: u8 = 1 << 1;
+    pub const EXTRF: u8 = 1 << 2;
+    pub const WDRF: u8 = 1 << 3;
+    /// Software reset flag.
+    pub const SWRF: u8 = 1 << 4;
+    /// UPDI reset flag.
+    pub const UPDIRF: u8 = 1 << 5;
+}
+
+/// What caused a reset.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ResetCause {
+    PowerOn,
+    /// The `RESET` pin was pulled low.
+    External,
+    BrownOut,
+    Watchdog,
+}
+
+impl ResetCause {
+    /// Gets the `MCUSR` flag set by the reset.
+    pub fn flag(self) -> u8 {
+        match self {
+            ResetCause::PowerOn => PORF,
+            ResetCause::External => EXTRF,
+            ResetCause::BrownOut => BORF,
+            ResetCause::Watchdog => WDRF,
+        }
+    }
+
+    /// Gets the reset cause from its `MCUSR` flag.
+    pub fn from_flag(flag: u8) -> Option<Self> {
+        match flag {
+            PORF => Some(ResetCause::PowerOn),
+            EXTRF => Some(ResetCause::External),
+            BORF => Some(ResetCause::BrownOut),
+            WDRF => Some(ResetCause::Watchdog),
+            _ => None,
+        }
+    }
+
+    /// Gets the value of `MCUSR` after the reset, given its value before.
+    ///
+    /// A power-on reset clears the other flags, the rest accumulate until
+    /// the firmware clears them.
+    pub fn update_mcusr(self, mcusr: u8) -> u8 {
+        match self {
+            ResetCause::PowerOn => PORF,
+            cause => mcusr | cause.flag(),
+        }
+    }
+
+    /// Gets the value of the register recording resets after the reset,
+    /// `MCUSR` or `RSTCTRL.RSTFR` depending on the family.
+    pub fn update_flags(self, family: Family, flags: u8) -> u8 {
+        if family != Family::Xt {
+            return self.update_mcusr(flags);
+        }
+        match self {
+            ResetCause::PowerOn => rstfr::PORF,
+            ResetCause::External => flags | rstfr::EXTRF,
+            ResetCause::BrownOut => flags | rstfr::BORF,
+            ResetCause::Watchdog => flags | rstfr::WDRF,
+        }
+    }
+}
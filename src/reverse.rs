@@ -0,0 +1,69 @@
+//! Reverse execution, by recording what each tick changes so that it can
+//! be undone.
+//!
+//! Writes to memory are recorded by the journals of `mem::Space`, while the
+//! registers and the rest of the CPU state are small enough to compare
+//! before and after each tick.
+
+use crate::reset::ResetCause;
+use crate::sleep::SleepMode;
+
+/// The state of the CPU outside of its memories.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct CpuState {
+    pub pc: u32,
+    pub cycle_count: u64,
+    pub sreg: u8,
+    pub pending: Vec<bool>,
+    pub interrupt_depth: u32,
+    pub interrupts_inhibited: bool,
+    pub temp: u8,
+    pub sleep_mode: Option<SleepMode>,
+    pub held_in_reset: Option<ResetCause>,
+    pub executing_pc: u32,
+    pub size_of_next_instruction: u8,
+}
+
+/// The state before a tick, to compare against after it.
+#[derive(Clone, Debug)]
+pub(crate) struct Checkpoint {
+    pub registers: Vec<u8>,
+    pub page_buffer: Vec<u8>,
+    pub cpu: CpuState,
+}
+
+/// What a single tick changed, with the old values.
+#[derive(Clone, Debug)]
+pub struct Delta {
+    /// The registers written, as register numbers and their old values.
+    pub(crate) registers: Vec<(u8, u8)>,
+    pub(crate) memory: Vec<(usize, u8)>,
+    pub(crate) eeprom: Vec<(usize, u8)>,
+    pub(crate) program_space: Vec<(usize, u8)>,
+    /// The `SPM` page buffer, if it changed.
+    pub(crate) page_buffer: Option<Vec<u8>>,
+    pub(crate) cpu: CpuState,
+}
+
+impl Delta {
+    /// Gets the byte address of the instruction the tick started at.
+    pub fn pc(&self) -> u32 {
+        self.cpu.pc
+    }
+
+    /// Gets the cycle count before the tick.
+    pub fn cycle_count(&self) -> u64 {
+        self.cpu.cycle_count
+    }
+
+    /// Gets the general purpose and stack pointer registers the tick
+    /// changed, with their old values.
+    pub fn registers(&self) -> &[(u8, u8)] {
+        &self.registers
+    }
+
+    /// Gets the data space bytes the tick wrote, with their old values.
+    pub fn memory_writes(&self) -> &[(usize, u8)] {
+        &self.memory
+    }
+}
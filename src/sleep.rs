@@ -0,0 +1,87 @@
+//! The sleep mode controller.
+
+/// The IO address of the `SMCR` register.
+pub const SMCR_ADDR: u8 = 0x33;
+
+/// The data space address of `SLPCTRL.CTRLA`, which takes the place of
+/// `SMCR` on AVRxt chips. Its sleep enable bit is bit 0 as well.
+pub const SLPCTRL_CTRLA_ADDR: u16 = 0x50;
+/// The sleep mode select bits `SMODE1:0` of `SLPCTRL.CTRLA`.
+pub const SMODE_MASK: u8 = 0b110;
+
+/// The data space address of `SLEEP.CTRL`, which takes the place of
+/// `SMCR` on XMEGA chips with the same bits.
+pub const SLEEP_CTRL_ADDR: u16 = 0x48;
+
+/// The IO address of `SMCR` on reduced (AVRrc) chips.
+pub const REDUCED_SMCR_ADDR: u8 = 0x3a;
+
+/// Sleep enable.
+pub const SE: u8 = 1 << 0;
+/// The sleep mode select bits `SM2:0`.
+pub const SM_MASK: u8 = 0b1110;
+
+/// A sleep mode, as selected by `SMCR`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum SleepMode {
+    Idle,
+    AdcNoiseReduction,
+    PowerDown,
+    PowerSave,
+    Standby,
+    ExtendedStandby,
+    /// One of the reserved `SM` encodings.
+    Reserved(u8),
+}
+
+impl SleepMode {
+    /// Decodes the sleep mode from the value of `SMCR`.
+    pub fn from_smcr(smcr: u8) -> Self {
+        match (smcr & SM_MASK) >> 1 {
+            0b000 => SleepMode::Idle,
+            0b001 => SleepMode::AdcNoiseReduction,
+            0b010 => SleepMode::PowerDown,
+            0b011 => SleepMode::PowerSave,
+            0b110 => SleepMode::Standby,
+            0b111 => SleepMode::ExtendedStandby,
+            sm => SleepMode::Reserved(sm),
+        }
+    }
+
+    /// Decodes the sleep mode from the value of `SMCR` on reduced (AVRrc)
+    /// chips, which select standby with `0b100`.
+    pub fn from_reduced_smcr(smcr: u8) -> Self {
+        match (smcr & SM_MASK) >> 1 {
+            0b000 => SleepMode::Idle,
+            0b001 => SleepMode::AdcNoiseReduction,
+            0b010 => SleepMode::PowerDown,
+            0b100 => SleepMode::Standby,
+            sm => SleepMode::Reserved(sm),
+        }
+    }
+
+    /// Decodes the sleep mode from the value of `SLPCTRL.CTRLA`.
+    pub fn from_slpctrl(ctrla: u8) -> Self {
+        match (ctrla & SMODE_MASK) >> 1 {
+            0b00 => SleepMode::Idle,
+            0b01 => SleepMode::Standby,
+            0b10 => SleepMode::PowerDown,
+            smode => SleepMode::Reserved(smode),
+        }
+    }
+
+    /// Encodes the sleep mode as the value of `SMCR`, with `SE` clear.
+    pub fn to_smcr(self) -> u8 {
+        let sm = match self {
+            SleepMode::Idle => 0b000,
+            SleepMode::AdcNoiseReduction => 0b001,
+            SleepMode::PowerDown => 0b010,
+            SleepMode::PowerSave => 0b011,
+            SleepMode::Standby => 0b110,
+            SleepMode::ExtendedStandby => 0b111,
+            SleepMode::Reserved(sm) => sm,
+        };
+        (sm << 1) & SM_MASK
+    }
+}
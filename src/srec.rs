@@ -0,0 +1,46 @@
+//! The Motorola S-record file format.
+
+use crate::ihex::Segment;
+use std::fmt::Write;
+
+/// The number of data bytes written per record.
+const RECORD_SIZE: usize = 16;
+
+/// Encodes segments as S-records.
+///
+/// 16-bit address records (`S1`) are used when all of the data is below
+/// 64KiB, and 24-bit address records (`S2`) otherwise.
+pub fn encode(segments: &[Segment]) -> String {
+    let wide = segments
+        .iter()
+        .any(|s| s.address as usize + s.data.len() > 0x1_0000);
+    let (data_kind, end_kind, address_size) = if wide { (2, 8, 3) } else { (1, 9, 2) };
+
+    let mut text = String::new();
+    write_record(&mut text, 0, 0, 2, &[]);
+
+    for segment in segments {
+        for (index, chunk) in segment.data.chunks(RECORD_SIZE).enumerate() {
+            let address = segment.address + (index * RECORD_SIZE) as u32;
+            write_record(&mut text, data_kind, address, address_size, chunk);
+        }
+    }
+
+    write_record(&mut text, end_kind, 0, address_size, &[]);
+    text
+}
+
+fn write_record(text: &mut String, kind: u8, address: u32, address_size: usize, data: &[u8]) {
+    let mut bytes = vec![(address_size + data.len() + 1) as u8];
+    bytes.extend_from_slice(&address.to_be_bytes()[4 - address_size..]);
+    bytes.extend_from_slice(data);
+
+    let sum = bytes.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+    bytes.push(!sum);
+
+    let _ = write!(text, "S{}", kind);
+    for byte in bytes {
+        let _ = write!(text, "{:02X}", byte);
+    }
+    text.push('\n');
+}
@@ -1,24 +1,42 @@
 use crate::regs::Register;
 
+/// The bit number of `C`.
+pub const CARRY_BIT: u8 = 0;
+/// The bit number of `Z`.
+pub const ZERO_BIT: u8 = 1;
+/// The bit number of `N`.
+pub const NEGATIVE_BIT: u8 = 2;
+/// The bit number of `V`.
+pub const OVERFLOW_BIT: u8 = 3;
+/// The bit number of `S`.
+pub const S_BIT: u8 = 4;
+/// The bit number of `H`.
+pub const HALF_CARRY_BIT: u8 = 5;
+/// The bit number of `T`.
+pub const TRANSFER_BIT: u8 = 6;
+/// The bit number of `I`.
+pub const INTERRUPT_BIT: u8 = 7;
+
 /// C
-pub const CARRY_FLAG: u8 = 1 << 0;
+pub const CARRY_FLAG: u8 = 1 << CARRY_BIT;
 /// Z
-pub const ZERO_FLAG: u8 = 1 << 1;
+pub const ZERO_FLAG: u8 = 1 << ZERO_BIT;
 /// N
-pub const NEGATIVE_FLAG: u8 = 1 << 2;
+pub const NEGATIVE_FLAG: u8 = 1 << NEGATIVE_BIT;
 /// V
-pub const OVERFLOW_FLAG: u8 = 1 << 3;
+pub const OVERFLOW_FLAG: u8 = 1 << OVERFLOW_BIT;
 /// S
-pub const S_FLAG: u8 = 1 << 4;
+pub const S_FLAG: u8 = 1 << S_BIT;
 /// H
-pub const HALF_CARRY_FLAG: u8 = 1 << 5;
+pub const HALF_CARRY_FLAG: u8 = 1 << HALF_CARRY_BIT;
 /// T
-pub const TRANSFER_FLAG: u8 = 1 << 6;
+pub const TRANSFER_FLAG: u8 = 1 << TRANSFER_BIT;
 /// I
-pub const INTERRUPT_FLAG: u8 = 1 << 7;
+pub const INTERRUPT_FLAG: u8 = 1 << INTERRUPT_BIT;
 
 /// The AVR status register.
 #[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SReg(pub Register);
 
 impl SReg {
@@ -29,14 +47,25 @@ impl SReg {
         })
     }
 
+    /// Sets or clears a flag.
+    ///
+    /// Changing `N` or `V` also updates `S`, which is always `N xor V`.
     pub fn set(&mut self, flag: u8, state: bool) {
-        // TODO: update S flag. should be `N xor V`.
-
         if state {
             self.0.value |= flag
         } else {
             self.0.value &= !flag
         };
+
+        if flag & (NEGATIVE_FLAG | OVERFLOW_FLAG) != 0 {
+            let s = self.get(NEGATIVE_FLAG) ^ self.get(OVERFLOW_FLAG);
+
+            if s {
+                self.0.value |= S_FLAG
+            } else {
+                self.0.value &= !S_FLAG
+            };
+        }
     }
 
     pub fn get(&self, flag: u8) -> bool {
@@ -0,0 +1,150 @@
+//! The binary format of saved simulation states, see `Mcu::save_state`.
+//!
+//! A state file starts with `MAGIC` and the format `VERSION` as a
+//! little-endian `u32`, followed by the state of the core, the `Mcu` and
+//! then each addon in the order they were added. All integers are little
+//! endian, and byte strings are prefixed with their length as a `u32`.
+
+use crate::Error;
+
+/// The bytes every state file starts with.
+pub const MAGIC: &[u8; 8] = b"AVRSTATE";
+/// The version of the format, which is increased whenever it changes.
+pub const VERSION: u32 = 3;
+
+/// Writes state in the binary format.
+#[derive(Clone, Debug, Default)]
+pub struct Writer {
+    bytes: Vec<u8>,
+}
+
+/// Reads state in the binary format.
+#[derive(Clone, Debug)]
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl Writer {
+    pub fn new() -> Self {
+        Writer::default()
+    }
+
+    /// Writes `MAGIC` and `VERSION`.
+    pub fn header(&mut self) {
+        self.bytes.extend_from_slice(MAGIC);
+        self.u32(VERSION);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    pub fn bool(&mut self, value: bool) {
+        self.u8(value as u8);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes.extend(value.to_le_bytes());
+    }
+
+    pub fn f64(&mut self, value: f64) {
+        self.u64(value.to_bits());
+    }
+
+    /// Writes a byte string prefixed with its length.
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.u32(bytes.len() as u32);
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.bytes
+    }
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Reader { bytes }
+    }
+
+    /// Checks for `MAGIC` and a supported `VERSION`.
+    pub fn header(&mut self) -> Result<(), Error> {
+        if self.take(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(Error::InvalidState("not a saved state"));
+        }
+        if self.u32()? != VERSION {
+            return Err(Error::InvalidState("unsupported version"));
+        }
+        Ok(())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, Error> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn bool(&mut self) -> Result<bool, Error> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(Error::InvalidState("invalid boolean")),
+        }
+    }
+
+    pub fn u16(&mut self) -> Result<u16, Error> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, Error> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, Error> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub fn f64(&mut self) -> Result<f64, Error> {
+        Ok(f64::from_bits(self.u64()?))
+    }
+
+    /// Reads a byte string prefixed with its length.
+    pub fn bytes(&mut self) -> Result<&'a [u8], Error> {
+        let len = self.u32()? as usize;
+        self.take(len)
+    }
+
+    /// Reads a byte string into `buffer`, which it must fill exactly.
+    pub fn bytes_into(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+        let bytes = self.bytes()?;
+        if bytes.len() != buffer.len() {
+            return Err(Error::InvalidState("memory size does not match the chip"));
+        }
+        buffer.copy_from_slice(bytes);
+        Ok(())
+    }
+
+    /// Checks if everything has been read.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], Error> {
+        Ok(self.take(N)?.try_into().unwrap())
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], Error> {
+        if len > self.bytes.len() {
+            return Err(Error::InvalidState("unexpected end of state"));
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+}
@@ -0,0 +1,79 @@
+//! Lookups in the symbol table of a program.
+
+use crate::elf::{Elf, Region, Symbol};
+use crate::Error;
+use std::collections::HashMap;
+
+/// The symbols of a program, looked up by name or by address.
+#[derive(Clone, Debug, Default)]
+pub struct Symbols {
+    /// Sorted by region and address.
+    symbols: Vec<Symbol>,
+    /// Indices into `symbols`, of the first symbol with each name.
+    by_name: HashMap<String, usize>,
+}
+
+impl Symbols {
+    pub fn new(mut symbols: Vec<Symbol>) -> Self {
+        symbols.sort_by_key(|s| (s.region as u8, s.address));
+
+        let mut by_name = HashMap::new();
+        for (index, symbol) in symbols.iter().enumerate() {
+            by_name.entry(symbol.name.clone()).or_insert(index);
+        }
+
+        Symbols { symbols, by_name }
+    }
+
+    /// Reads the symbol table of an ELF file.
+    pub fn from_elf(elf: &Elf) -> Result<Self, Error> {
+        Ok(Symbols::new(elf.symbols()?))
+    }
+
+    pub fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    pub fn iter(&self) -> std::slice::Iter<'_, Symbol> {
+        self.symbols.iter()
+    }
+
+    /// Gets the symbol with the given name.
+    pub fn get(&self, name: &str) -> Option<&Symbol> {
+        self.by_name.get(name).map(|&index| &self.symbols[index])
+    }
+
+    /// Gets the address of a symbol within its region.
+    pub fn address(&self, name: &str) -> Option<u32> {
+        self.get(name).map(|s| s.address)
+    }
+
+    /// Gets the symbol covering an address in a region.
+    ///
+    /// A symbol without a size only covers its own address.
+    pub fn at(&self, region: Region, address: u32) -> Option<&Symbol> {
+        let end = self
+            .symbols
+            .partition_point(|s| (s.region as u8, s.address) <= (region as u8, address));
+
+        self.symbols[..end]
+            .iter()
+            .rev()
+            .take_while(|s| s.region == region)
+            .find(|s| address < s.address + s.size.max(1))
+    }
+
+    /// Gets the symbol covering a byte address in program space.
+    pub fn flash(&self, address: u32) -> Option<&Symbol> {
+        self.at(Region::Flash, address)
+    }
+
+    /// Gets the symbol covering an address in data space.
+    pub fn data(&self, address: u16) -> Option<&Symbol> {
+        self.at(Region::Data, address as u32)
+    }
+}
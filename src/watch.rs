@@ -0,0 +1,44 @@
+//! Watchpoints on data space accesses.
+
+use crate::core::Access;
+use crate::mem;
+use std::ops::Range;
+
+/// Which accesses a watchpoint stops on.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Kind {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+/// A range of data space addresses to watch.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub range: Range<mem::Address>,
+    pub kind: Kind,
+}
+
+/// An access that hit a watchpoint.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Hit {
+    /// The byte address of the instruction that made the access.
+    pub pc: u32,
+    pub access: Access,
+    /// The value before the access.
+    pub old: u8,
+    /// The value after the access, which for reads is the same as `old`.
+    pub new: u8,
+}
+
+impl Watchpoint {
+    /// Checks if the watchpoint stops on an access.
+    pub fn matches(&self, access: Access) -> bool {
+        let (address, kind_matches) = match access {
+            Access::Read(address) => (address, self.kind != Kind::Write),
+            Access::Write(address) => (address, self.kind != Kind::Read),
+        };
+
+        kind_matches && self.range.contains(&address)
+    }
+}
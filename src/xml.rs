@@ -0,0 +1,249 @@
+//! A small XML reader, for the subset of XML in device description files.
+//!
+//! Elements and their attributes are kept. Text, comments, processing
+//! instructions, `CDATA` sections and the document type are skipped.
+
+/// An element with its attributes and child elements.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Element {
+    pub name: String,
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Element>,
+    /// The line the start tag is on, counting from 1.
+    pub line: usize,
+}
+
+impl Element {
+    fn new(name: String, attributes: Vec<(String, String)>, line: usize) -> Self {
+        Element {
+            name,
+            attributes,
+            children: Vec::new(),
+            line,
+        }
+    }
+
+    /// Gets the value of an attribute.
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    /// Gets the first child element with a name.
+    pub fn child<'a>(&'a self, name: &'a str) -> Option<&'a Element> {
+        self.children(name).next()
+    }
+
+    /// Gets the child elements with a name.
+    pub fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> + 'a {
+        self.children.iter().filter(move |c| c.name == name)
+    }
+}
+
+/// A document that is not well-formed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub line: usize,
+    pub message: &'static str,
+}
+
+/// Parses a document, returning its root element.
+pub fn parse(text: &str) -> Result<Element, SyntaxError> {
+    let mut parser = Parser {
+        text,
+        pos: 0,
+        line: 1,
+    };
+    let mut open: Vec<Element> = Vec::new();
+    let mut root = None;
+
+    while let Some(start) = parser.rest().find('<') {
+        parser.advance(start);
+        let rest = parser.rest();
+
+        if rest.starts_with("<?") {
+            parser.skip_past("?>")?;
+        } else if rest.starts_with("<!--") {
+            parser.skip_past("-->")?;
+        } else if rest.starts_with("<![CDATA[") {
+            parser.skip_past("]]>")?;
+        } else if rest.starts_with("<!") {
+            parser.skip_past(">")?;
+        } else if rest.starts_with("</") {
+            parser.advance(2);
+            let name = parser.name()?;
+            parser.skip_whitespace();
+            parser.expect(">")?;
+
+            let element = match open.pop() {
+                Some(element) if element.name == name => element,
+                _ => return Err(parser.error("mismatched end tag")),
+            };
+            parser.close(element, &mut open, &mut root)?;
+        } else {
+            parser.advance(1);
+            let (element, empty) = parser.start_tag()?;
+            if empty {
+                parser.close(element, &mut open, &mut root)?;
+            } else {
+                open.push(element);
+            }
+        }
+    }
+
+    if !open.is_empty() {
+        return Err(parser.error("unclosed element"));
+    }
+    root.ok_or_else(|| parser.error("no root element"))
+}
+
+struct Parser<'a> {
+    text: &'a str,
+    pos: usize,
+    line: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn rest(&self) -> &'a str {
+        &self.text[self.pos..]
+    }
+
+    fn error(&self, message: &'static str) -> SyntaxError {
+        SyntaxError {
+            line: self.line,
+            message,
+        }
+    }
+
+    /// Moves forward a number of bytes, counting the lines passed.
+    fn advance(&mut self, count: usize) {
+        let skipped = &self.text[self.pos..self.pos + count];
+        self.line += skipped.bytes().filter(|&b| b == b'\n').count();
+        self.pos += count;
+    }
+
+    fn skip_past(&mut self, end: &'static str) -> Result<(), SyntaxError> {
+        match self.rest().find(end) {
+            Some(index) => {
+                self.advance(index + end.len());
+                Ok(())
+            }
+            None => Err(self.error("unterminated markup")),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        let count = self.rest().len() - self.rest().trim_start().len();
+        self.advance(count);
+    }
+
+    fn expect(&mut self, text: &'static str) -> Result<(), SyntaxError> {
+        if self.rest().starts_with(text) {
+            self.advance(text.len());
+            Ok(())
+        } else {
+            Err(self.error("unexpected character in tag"))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, SyntaxError> {
+        let rest = self.rest();
+        let end = rest
+            .find(|c: char| c.is_whitespace() || "/>=".contains(c))
+            .unwrap_or(rest.len());
+        if end == 0 {
+            return Err(self.error("expected a name"));
+        }
+        self.advance(end);
+        Ok(rest[..end].to_owned())
+    }
+
+    /// Parses a start tag after its `<`, returning the element and whether
+    /// it is an empty element tag like `<a/>`.
+    fn start_tag(&mut self) -> Result<(Element, bool), SyntaxError> {
+        let line = self.line;
+        let name = self.name()?;
+        let mut attributes = Vec::new();
+
+        loop {
+            self.skip_whitespace();
+            if self.rest().starts_with("/>") {
+                self.advance(2);
+                break Ok((Element::new(name, attributes, line), true));
+            }
+            if self.rest().starts_with('>') {
+                self.advance(1);
+                break Ok((Element::new(name, attributes, line), false));
+            }
+
+            let attribute = self.name()?;
+            self.skip_whitespace();
+            self.expect("=")?;
+            self.skip_whitespace();
+            let value = self.quoted()?;
+            attributes.push((attribute, value));
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, SyntaxError> {
+        let quote = match self.rest().chars().next() {
+            Some(quote @ ('"' | '\'')) => quote,
+            _ => return Err(self.error("expected a quoted attribute value")),
+        };
+        self.advance(1);
+        let end = self
+            .rest()
+            .find(quote)
+            .ok_or_else(|| self.error("unterminated attribute value"))?;
+        let value = unescape(&self.rest()[..end]).ok_or_else(|| self.error("unknown entity"))?;
+        self.advance(end + 1);
+        Ok(value)
+    }
+
+    /// Adds a finished element to its parent, or makes it the root.
+    fn close(
+        &self,
+        element: Element,
+        open: &mut [Element],
+        root: &mut Option<Element>,
+    ) -> Result<(), SyntaxError> {
+        match open.last_mut() {
+            Some(parent) => parent.children.push(element),
+            None if root.is_none() => *root = Some(element),
+            None => return Err(self.error("more than one root element")),
+        }
+        Ok(())
+    }
+}
+
+/// Replaces entity and character references, or returns `None` if one is
+/// not known.
+fn unescape(text: &str) -> Option<String> {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        let end = rest[start..].find(';')? + start;
+        let entity = &rest[start + 1..end];
+        let c = match entity {
+            "lt" => '<',
+            "gt" => '>',
+            "amp" => '&',
+            "quot" => '"',
+            "apos" => '\'',
+            _ => {
+                let code = match entity.strip_prefix("#x") {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => entity.strip_prefix('#')?.parse().ok()?,
+                };
+                char::from_u32(code)?
+            }
+        };
+        result.push(c);
+        rest = &rest[end + 1..];
+    }
+    result.push_str(rest);
+    Some(result)
+}
@@ -3,28 +3,40 @@ extern crate avr;
 fn main() {
     use std::io::Read;
 
-    let mut core = avr::Core::new::<avr::chips::atmega328p::Chip>();
+    let mut core = avr::Core::power_on::<avr::chips::atmega328p::Chip>();
 
     let mut args = std::env::args();
     args.next(); // eat the program name.
-    let program_path = args.next().expect("expected a '.bin' program path");
-
-    let program_file = std::fs::File::open(program_path).unwrap();
-    let program_bytes = program_file.bytes().map(|a| a.unwrap());
-    core.load_program_space(program_bytes);
+    let program_path = args
+        .next()
+        .expect("expected a '.bin', '.hex' or '.elf' program path");
+
+    let is_hex = program_path.ends_with(".hex") || program_path.ends_with(".ihex");
+    let is_elf = program_path.ends_with(".elf");
+    if !is_hex && !is_elf {
+        let program_file = std::io::BufReader::new(std::fs::File::open(&program_path).unwrap());
+        let program_bytes = program_file.bytes().map(|a| a.unwrap());
+        core.load_program_space(program_bytes);
+    }
 
     let mut mcu = avr::Mcu::new(core);
 
-    let uart = avr::addons::Uart::new(
-        16000000,                 // CPU frequency
-        187000,                   // Baud rate
-        avr::io::Port::new(0x24), // Tx
-        avr::io::Port::new(0x25), // Rx
-    );
+    if is_hex {
+        mcu.load_ihex(&program_path)
+            .expect("failed to load Intel HEX file");
+    } else if is_elf {
+        mcu.load_elf(&program_path)
+            .expect("failed to load ELF file");
+    }
 
-    mcu.attach(Box::new(uart));
+    mcu.attach(Box::new(avr::addons::Uart::atmega328p()));
 
     for _ in 0..70 {
-        mcu.tick().expect("failed while ticking");
+        if let Err(e) = mcu.tick() {
+            match mcu.symbolicate(mcu.core.pc) {
+                Some(location) => panic!("failed while ticking in {}: {:?}", location, e),
+                None => panic!("failed while ticking: {:?}", e),
+            }
+        }
     }
 }